@@ -83,7 +83,7 @@ async function processTiff(buffer: ArrayBuffer) {
     console.log(hierarchy);
 
     console.log('Cutting hierarchy...');
-    const labels = cut_hierarchy_wasm(hierarchy, 0);
+    const { labels } = cut_hierarchy_wasm(hierarchy, 0);
 
     console.log('Displaying labels...');
     const bitmapResult = display_labels_wasm(tiff.data, tiff.width, tiff.height, labels);
@@ -121,7 +121,8 @@ async function handleSlider(value: number) {
     const level = Math.pow(2, logValue);
 
     console.log('Cutting hierarchy...');
-    const labels = cut_hierarchy_wasm(hierarchy, level);
+    const { labels, n_regions } = cut_hierarchy_wasm(hierarchy, level);
+    console.log(`${n_regions} regions`);
 
     console.log('Displaying labels...');
     const bitmapResult = display_labels_wasm(tiff.data, tiff.width, tiff.height, labels);
@@ -0,0 +1,58 @@
+//! Build a hierarchy from a random image, then cut it at a random level.
+
+#![no_main]
+
+use std::panic::{self, UnwindSafe};
+
+use arbitrary::Arbitrary;
+use hierarchy_labellisation::{build_hierarchy_wasm, cut_hierarchy_wasm};
+use libfuzzer_sys::fuzz_target;
+
+#[derive(Debug, Arbitrary)]
+struct Input {
+    width: usize,
+    height: usize,
+    channels: usize,
+    n_clusters: usize,
+    level: f64,
+    data: Vec<u8>,
+}
+
+/// Whether `f` throws, i.e. panics without the WebAssembly bindings, without
+/// aborting the fuzzer.
+fn throws<T>(f: impl FnOnce() -> T + UnwindSafe) -> bool {
+    let hook = panic::take_hook();
+    panic::set_hook(Box::new(|_| {}));
+    let result = panic::catch_unwind(f);
+    panic::set_hook(hook);
+
+    result.is_err()
+}
+
+fuzz_target!(|input: Input| {
+    let Input {
+        width,
+        height,
+        channels,
+        n_clusters,
+        level,
+        data,
+    } = input;
+
+    let size = width
+        .checked_mul(height)
+        .and_then(|pixels| pixels.checked_mul(channels));
+    if width == 0 || height == 0 || channels == 0 || size != Some(data.len()) {
+        // Inconsistent dimensions must be reported, not turned into a hierarchy
+        assert!(throws(|| build_hierarchy_wasm(
+            &data, width, height, channels, n_clusters
+        )));
+        return;
+    }
+
+    let hierarchy = build_hierarchy_wasm(&data, width, height, channels, n_clusters);
+    let labels = cut_hierarchy_wasm(&hierarchy, level).labels;
+
+    assert_eq!(labels.len(), width * height);
+    assert!(labels.iter().all(|label| *label < hierarchy.parents.len()));
+});
@@ -0,0 +1,51 @@
+//! Draw random label borders over a random image.
+
+#![no_main]
+
+use std::panic::{self, UnwindSafe};
+
+use arbitrary::Arbitrary;
+use hierarchy_labellisation::display_labels_wasm;
+use libfuzzer_sys::fuzz_target;
+
+#[derive(Debug, Arbitrary)]
+struct Input {
+    width: usize,
+    height: usize,
+    data: Vec<u8>,
+    labels: Vec<usize>,
+}
+
+/// Whether `f` throws, i.e. panics without the WebAssembly bindings, without
+/// aborting the fuzzer.
+fn throws<T>(f: impl FnOnce() -> T + UnwindSafe) -> bool {
+    let hook = panic::take_hook();
+    panic::set_hook(Box::new(|_| {}));
+    let result = panic::catch_unwind(f);
+    panic::set_hook(hook);
+
+    result.is_err()
+}
+
+fuzz_target!(|input: Input| {
+    let Input {
+        width,
+        height,
+        data,
+        labels,
+    } = input;
+
+    // The band count is deduced from the data length by the wrapper
+    let pixels = width.checked_mul(height).filter(|pixels| *pixels > 0);
+    let valid = pixels.is_some_and(|pixels| {
+        !data.is_empty() && data.len() % pixels == 0 && labels.len() == pixels
+    });
+    if !valid {
+        assert!(throws(|| display_labels_wasm(data, width, height, labels)));
+        return;
+    }
+
+    let bitmap = display_labels_wasm(data, width, height, labels);
+
+    assert_eq!(bitmap.len(), width * height * 4);
+});
@@ -0,0 +1,37 @@
+/* C interface of hierarchy_labellisation, built with the `ffi` feature. */
+
+#ifndef HIERARCHY_LABELLISATION_H
+#define HIERARCHY_LABELLISATION_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef struct Hierarchy Hierarchy;
+
+/* Build the hierarchy of an image given as width * height * channels bytes in
+ * planar order, segmented into n_clusters superpixels. Returns NULL on failure.
+ * The hierarchy must be released with hl_hierarchy_free. */
+Hierarchy *hl_hierarchy_build(const uint8_t *data, size_t width, size_t height,
+                              size_t channels, size_t n_clusters);
+
+/* Number of nodes of the hierarchy, leaves included. */
+size_t hl_hierarchy_node_count(const Hierarchy *hierarchy);
+
+/* Write the labels of the len pixels in the cut of the hierarchy at level, each
+ * region being labelled by its tree node. Returns 0, or -1 if len is not the
+ * pixel count of the image. */
+int32_t hl_hierarchy_cut(const Hierarchy *hierarchy, double level,
+                         size_t *labels, size_t len);
+
+/* Release a hierarchy. Does nothing on NULL. */
+void hl_hierarchy_free(Hierarchy *hierarchy);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
@@ -0,0 +1,149 @@
+//! Annotation sessions, saved to stop and resume the work on a hierarchy.
+//!
+//! The format is little-endian: the magic bytes, the format version as a `u32`,
+//! the hierarchy fingerprint as a `u64`, the cut level as a `f64`, then each list
+//! as a `u64` length followed by its `u64` values.
+
+use std::{error::Error, fmt};
+
+#[cfg(feature = "wasm")]
+use wasm_bindgen::prelude::wasm_bindgen;
+
+use crate::binary::{write_usizes, Reader};
+
+const MAGIC: &[u8; 4] = b"HLAS";
+/// Version of the format written by [`AnnotationSession::to_bytes`].
+pub const SESSION_VERSION: u32 = 1;
+
+/// State of the annotation of a hierarchy.
+#[cfg_attr(feature = "wasm", wasm_bindgen(getter_with_clone))]
+#[derive(Clone, Debug, PartialEq)]
+pub struct AnnotationSession {
+    /// Fingerprint of the annotated hierarchy, see [`Hierarchy::fingerprint`](crate::Hierarchy::fingerprint)
+    pub hierarchy_fingerprint: u64,
+    /// Current cut level
+    pub level: f64,
+    /// Tree nodes selected by hand
+    pub selection: Vec<usize>,
+    /// Rectangles segmented again by hand, as `[x, y, width, height, ...]`
+    pub edited_rects: Vec<usize>,
+    /// Tree nodes with a class assigned, the class of `class_nodes[i]` being `classes[i]`
+    pub class_nodes: Vec<usize>,
+    pub classes: Vec<usize>,
+}
+
+/// Reason why an annotation session could not be loaded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SessionError {
+    /// Not a session file, or a truncated one
+    Invalid,
+    /// Written by a newer version of the library
+    UnsupportedVersion(u32),
+    /// Made on another hierarchy
+    HierarchyMismatch,
+}
+
+impl fmt::Display for SessionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SessionError::Invalid => write!(f, "Invalid annotation session"),
+            SessionError::UnsupportedVersion(version) => write!(
+                f,
+                "Annotation session version {version} is not supported, the latest is {SESSION_VERSION}"
+            ),
+            SessionError::HierarchyMismatch => {
+                write!(f, "Annotation session made on another hierarchy")
+            }
+        }
+    }
+}
+
+impl Error for SessionError {}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+impl AnnotationSession {
+    /// Empty session on the hierarchy with the given fingerprint.
+    #[cfg_attr(feature = "wasm", wasm_bindgen(constructor))]
+    pub fn new(hierarchy_fingerprint: u64, level: f64) -> Self {
+        Self {
+            hierarchy_fingerprint,
+            level,
+            selection: Vec::new(),
+            edited_rects: Vec::new(),
+            class_nodes: Vec::new(),
+            classes: Vec::new(),
+        }
+    }
+}
+
+impl AnnotationSession {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend(SESSION_VERSION.to_le_bytes());
+        bytes.extend(self.hierarchy_fingerprint.to_le_bytes());
+        bytes.extend(self.level.to_le_bytes());
+
+        for list in [
+            &self.selection,
+            &self.edited_rects,
+            &self.class_nodes,
+            &self.classes,
+        ] {
+            write_usizes(&mut bytes, list);
+        }
+
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, SessionError> {
+        let mut reader = Reader::new(bytes);
+        if reader.take(4) != Some(MAGIC) {
+            return Err(SessionError::Invalid);
+        }
+        let version = reader.u32().ok_or(SessionError::Invalid)?;
+        if version > SESSION_VERSION {
+            return Err(SessionError::UnsupportedVersion(version));
+        }
+
+        let mut read = || {
+            let mut session = Self::new(reader.u64()?, reader.f64()?);
+            session.selection = reader.usizes()?;
+            session.edited_rects = reader.usizes()?;
+            session.class_nodes = reader.usizes()?;
+            session.classes = reader.usizes()?;
+            Some(session).filter(|session| {
+                reader.is_empty() && session.class_nodes.len() == session.classes.len()
+            })
+        };
+
+        read().ok_or(SessionError::Invalid)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_session_round_trip() {
+        let mut session = AnnotationSession::new(42, 1.5);
+        session.selection = vec![3, 7];
+        session.edited_rects = vec![10, 20, 5, 5];
+        session.class_nodes = vec![7];
+        session.classes = vec![2];
+
+        let bytes = session.to_bytes();
+        assert_eq!(AnnotationSession::from_bytes(&bytes), Ok(session));
+
+        assert_eq!(
+            AnnotationSession::from_bytes(&bytes[..bytes.len() - 1]),
+            Err(SessionError::Invalid)
+        );
+        let mut newer = bytes.clone();
+        newer[4] = 2;
+        assert_eq!(
+            AnnotationSession::from_bytes(&newer),
+            Err(SessionError::UnsupportedVersion(2))
+        );
+    }
+}
@@ -0,0 +1,67 @@
+use js_sys::{Function, Promise};
+use wasm_bindgen::{prelude::*, throw_str};
+use wasm_bindgen_futures::JsFuture;
+
+use crate::{
+    console_log,
+    fingerprint::input_fingerprint,
+    hierarchy::{PartialPartitionTree, StopCondition},
+    params::SegmentationParams,
+    superpixel_graph,
+    utils::array_from_planar,
+    Hierarchy,
+};
+
+#[wasm_bindgen]
+extern "C" {
+    // Available both in windows and in workers
+    #[wasm_bindgen(js_name = setTimeout)]
+    fn set_timeout(handler: &Function, timeout: i32) -> i32;
+}
+
+/// Give control back to the JS event loop until the next macrotask.
+async fn yield_to_event_loop() {
+    let promise = Promise::new(&mut |resolve, _| {
+        set_timeout(&resolve, 0);
+    });
+    JsFuture::from(promise)
+        .await
+        .expect_throw("Failed to yield to the event loop");
+}
+
+/// Same as [`build_hierarchy_with_params_wasm`](crate::build_hierarchy_with_params_wasm),
+/// yielding to the event loop between chunks of `merges_per_chunk` merges so the
+/// page stays responsive without a worker.
+///
+/// SLIC and the graph creation run in a single chunk. The parameters are consumed.
+#[wasm_bindgen]
+pub async fn build_hierarchy_async_wasm(
+    data: Vec<u8>,
+    width: usize,
+    height: usize,
+    channels: usize,
+    params: SegmentationParams,
+    merges_per_chunk: usize,
+) -> Hierarchy {
+    let array = array_from_planar(&data, width, height, channels);
+    drop(data);
+    let fingerprint = input_fingerprint(&array, &params);
+
+    let (labels, graph) = superpixel_graph(&array, &params);
+    drop(array);
+
+    let mut tree = PartialPartitionTree::new(graph).with_options(params.criterion_options());
+    let merges_per_chunk = merges_per_chunk.max(1);
+
+    while !tree.is_complete() {
+        yield_to_event_loop().await;
+
+        let target = tree.merge_operations() + merges_per_chunk;
+        tree.merge_until(Some(StopCondition::MergeCount(target)))
+            .unwrap_or_else(|error| throw_str(&error.to_string()));
+    }
+
+    console_log!("Merge operations: {:?}", tree.merge_operations());
+
+    Hierarchy::new(&labels, tree.into_partition_tree()).with_input_fingerprint(fingerprint)
+}
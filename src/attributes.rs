@@ -0,0 +1,218 @@
+use ndarray::{Array2, Array3};
+#[cfg(feature = "wasm")]
+use wasm_bindgen::prelude::wasm_bindgen;
+
+use crate::pixels::LeafPixels;
+
+/// Attributes of the nodes of a partition tree, indexed by node.
+#[derive(Debug, Clone, PartialEq)]
+pub struct NodeAttributes {
+    /// Number of pixels of each node
+    pub areas: Vec<usize>,
+    /// Level of the parent minus the level of the node, 0 for the roots
+    pub lifetimes: Vec<f64>,
+}
+
+impl NodeAttributes {
+    pub fn new(parents: &[usize], levels: &[f64], leaf_pixels: &LeafPixels) -> Self {
+        let mut areas = vec![0; parents.len()];
+        for (leaf, area) in areas.iter_mut().enumerate().take(leaf_pixels.leaf_count()) {
+            *area = leaf_pixels.area(leaf);
+        }
+
+        // Parents always come after their children
+        for node in 0..parents.len() {
+            let parent = parents[node];
+            if parent != node {
+                areas[parent] += areas[node];
+            }
+        }
+
+        let lifetimes = parents
+            .iter()
+            .enumerate()
+            .map(|(node, &parent)| levels[parent] - levels[node])
+            .collect();
+
+        Self { areas, lifetimes }
+    }
+}
+
+/// Saliency of a region, ranking the most obvious objects of a hierarchy.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SaliencyScore {
+    Lifetime,
+    Area,
+    /// Regions both large and persistent
+    #[default]
+    LifetimeArea,
+}
+
+impl SaliencyScore {
+    pub fn score(&self, attributes: &NodeAttributes, node: usize) -> f64 {
+        let lifetime = attributes.lifetimes[node];
+        let area = attributes.areas[node] as f64;
+
+        match self {
+            SaliencyScore::Lifetime => lifetime,
+            SaliencyScore::Area => area,
+            SaliencyScore::LifetimeArea => lifetime * area,
+        }
+    }
+}
+
+/// The `k` nodes with the highest score, with their score, best first. The roots,
+/// covering the whole image, are excluded.
+pub fn top_regions(
+    parents: &[usize],
+    attributes: &NodeAttributes,
+    k: usize,
+    score: SaliencyScore,
+) -> Vec<(usize, f64)> {
+    let mut regions = (0..parents.len())
+        .filter(|node| parents[*node] != *node)
+        .map(|node| (node, score.score(attributes, node)))
+        .collect::<Vec<_>>();
+
+    regions.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap().then(a.0.cmp(&b.0)));
+    regions.truncate(k);
+
+    regions
+}
+
+/// Variance of the pixel values of each node of a partition tree on a
+/// `(height, width, channels)` image, averaged over the bands.
+pub fn node_variances(parents: &[usize], leaf_pixels: &LeafPixels, img: &Array3<u8>) -> Vec<f64> {
+    let (_, width, channels) = img.dim();
+
+    // Pixel count, then sum and sum of squares of each band
+    let mut sums = Array2::<f64>::zeros((parents.len(), 1 + 2 * channels));
+    for leaf in 0..leaf_pixels.leaf_count() {
+        for pixel in leaf_pixels.pixels(leaf) {
+            let (y, x) = (pixel / width, pixel % width);
+            sums[[leaf, 0]] += 1.;
+            for c in 0..channels {
+                let value = f64::from(img[[y, x, c]]);
+                sums[[leaf, 1 + c]] += value;
+                sums[[leaf, 1 + channels + c]] += value * value;
+            }
+        }
+    }
+
+    // Parents always come after their children
+    for node in 0..parents.len() {
+        let parent = parents[node];
+        if parent != node {
+            for i in 0..sums.ncols() {
+                sums[[parent, i]] += sums[[node, i]];
+            }
+        }
+    }
+
+    sums.outer_iter()
+        .map(|sums| {
+            let count = sums[0];
+            if count == 0. {
+                return 0.;
+            }
+
+            let variance = (0..channels)
+                .map(|c| {
+                    let mean = sums[1 + c] / count;
+                    (sums[1 + channels + c] / count - mean * mean).max(0.)
+                })
+                .sum::<f64>();
+            variance / channels as f64
+        })
+        .collect()
+}
+
+/// Grow a region from each seed leaf: the largest ancestor reached by going up
+/// the tree while the variance stays lower or equal to `tolerance`. The seed leaf
+/// itself is returned when even its parent is too heterogeneous.
+pub fn grow_from_seeds(
+    parents: &[usize],
+    variances: &[f64],
+    seeds: &[usize],
+    tolerance: f64,
+) -> Vec<usize> {
+    seeds
+        .iter()
+        .map(|&seed| {
+            let mut node = seed;
+            while parents[node] != node && variances[parents[node]] <= tolerance {
+                node = parents[node];
+            }
+            node
+        })
+        .collect()
+}
+
+/// Largest ancestor of a leaf whose level is below `tolerance`: the region of the
+/// pixels within an ultrametric distance `tolerance` of the leaf, for a magic wand
+/// selection. The leaf itself is returned when even its parent is above.
+pub fn flood_select(parents: &[usize], levels: &[f64], leaf: usize, tolerance: f64) -> usize {
+    let mut node = leaf;
+    while parents[node] != node && levels[parents[node]] < tolerance {
+        node = parents[node];
+    }
+
+    node
+}
+
+#[cfg(test)]
+mod tests {
+    use ndarray::Array2;
+
+    use super::*;
+
+    #[test]
+    fn test_top_regions() {
+        // 0 0 1
+        // 0 0 1
+        // 2 2 2
+        let labels = Array2::from_shape_vec((3, 3), vec![0, 0, 1, 0, 0, 1, 2, 2, 2]).unwrap();
+        let parents = vec![3, 3, 4, 4, 4];
+        let levels = vec![0., 0., 0., 1., 3.];
+
+        let attributes = NodeAttributes::new(&parents, &levels, &LeafPixels::new(&labels));
+        assert_eq!(attributes.areas, vec![4, 2, 3, 6, 9]);
+        assert_eq!(attributes.lifetimes, vec![1., 1., 3., 2., 0.]);
+
+        let top = top_regions(&parents, &attributes, 2, SaliencyScore::LifetimeArea);
+        assert_eq!(top, vec![(3, 12.), (2, 9.)]);
+    }
+
+    #[test]
+    fn test_grow_from_seeds() {
+        // 0 0 1
+        // 0 0 1
+        // 2 2 2
+        // with leaves 0 and 1 dark and leaf 2 bright
+        let labels = Array2::from_shape_vec((3, 3), vec![0, 0, 1, 0, 0, 1, 2, 2, 2]).unwrap();
+        let img = labels.mapv(|leaf| if leaf == 2 { 200 } else { 10 });
+        let img = img.insert_axis(ndarray::Axis(2));
+        let parents = vec![3, 3, 4, 4, 4];
+
+        let variances = node_variances(&parents, &LeafPixels::new(&labels), &img);
+        assert_eq!(&variances[..4], &[0., 0., 0., 0.]);
+        assert!(variances[4] > 0.);
+
+        assert_eq!(
+            grow_from_seeds(&parents, &variances, &[0, 2], 1.),
+            vec![3, 2]
+        );
+    }
+
+    #[test]
+    fn test_flood_select() {
+        let parents = vec![3, 3, 4, 4, 4];
+        let levels = vec![0., 0., 0., 1., 3.];
+
+        assert_eq!(flood_select(&parents, &levels, 0, 1.), 0);
+        assert_eq!(flood_select(&parents, &levels, 0, 2.), 3);
+        assert_eq!(flood_select(&parents, &levels, 2, 2.), 2);
+        assert_eq!(flood_select(&parents, &levels, 1, 10.), 4);
+    }
+}
@@ -0,0 +1,99 @@
+use ndarray::Array2;
+
+use crate::graph::SuperpixelGraph;
+
+/// Split the superpixels along a barrier raster (e.g. a rasterized road network or
+/// cadastre): each new region is a 4-connected component of the pixels of a
+/// superpixel that are all on a barrier or all off it.
+///
+/// Returns the new labels, and whether each new region is made of barrier pixels.
+/// A barrier at least one pixel wide and 8-connected separates the regions on each
+/// of its sides.
+pub fn split_by_barrier(
+    labels: &Array2<usize>,
+    barrier: &Array2<bool>,
+) -> (Array2<usize>, Vec<bool>) {
+    split_by_raster(labels, barrier)
+}
+
+/// Split the superpixels into the 4-connected components of their pixels sharing
+/// the same raster value. Returns the new labels and the raster value of each region.
+pub(crate) fn split_by_raster<T: Copy + PartialEq>(
+    labels: &Array2<usize>,
+    raster: &Array2<T>,
+) -> (Array2<usize>, Vec<T>) {
+    let (height, width) = labels.dim();
+    let mut new_labels = Array2::from_elem((height, width), usize::MAX);
+    let mut region_values = Vec::new();
+    let mut stack = Vec::new();
+
+    for ((y, x), &label) in labels.indexed_iter() {
+        if new_labels[[y, x]] != usize::MAX {
+            continue;
+        }
+
+        let region = region_values.len();
+        let value = raster[[y, x]];
+        region_values.push(value);
+
+        new_labels[[y, x]] = region;
+        stack.push((y, x));
+        while let Some((y, x)) = stack.pop() {
+            let neighbors = [
+                (y.wrapping_sub(1), x),
+                (y + 1, x),
+                (y, x.wrapping_sub(1)),
+                (y, x + 1),
+            ];
+            for (ny, nx) in neighbors {
+                if ny < height
+                    && nx < width
+                    && new_labels[[ny, nx]] == usize::MAX
+                    && labels[[ny, nx]] == label
+                    && raster[[ny, nx]] == value
+                {
+                    new_labels[[ny, nx]] = region;
+                    stack.push((ny, nx));
+                }
+            }
+        }
+    }
+
+    (new_labels, region_values)
+}
+
+/// Remove the edges of the graph touching a barrier region, so that the hierarchy
+/// never merges across a barrier. Barrier regions become isolated roots.
+pub fn remove_barrier_edges(graph: &mut SuperpixelGraph, barrier_regions: &[bool]) {
+    graph.retain_edges(|graph, edge| {
+        let (a, b) = graph.edge_endpoints(edge).unwrap();
+        !barrier_regions[a.index()] && !barrier_regions[b.index()]
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use ndarray::Array3;
+
+    use super::*;
+    use crate::graph::graph_from_labels;
+
+    #[test]
+    fn test_split_by_barrier() {
+        // A single superpixel crossed by a vertical barrier
+        let labels = Array2::zeros((3, 3));
+        let barrier = Array2::from_shape_fn((3, 3), |(_, x)| x == 1);
+
+        let (labels, barrier_regions) = split_by_barrier(&labels, &barrier);
+
+        assert_eq!(
+            labels,
+            Array2::from_shape_vec((3, 3), vec![0, 1, 2, 0, 1, 2, 0, 1, 2]).unwrap()
+        );
+        assert_eq!(barrier_regions, vec![false, true, false]);
+
+        let mut graph = graph_from_labels(&Array3::zeros((3, 3, 1)), &labels).unwrap();
+        remove_barrier_edges(&mut graph, &barrier_regions);
+        assert_eq!(graph.edge_count(), 0);
+    }
+}
@@ -0,0 +1,79 @@
+//! Little-endian encoding shared by the files saved by the library: fixed-size
+//! values, and lists as a `u64` length followed by their values.
+
+/// Append a list of values, each stored as a `u64`.
+pub(crate) fn write_usizes(bytes: &mut Vec<u8>, values: &[usize]) {
+    bytes.extend((values.len() as u64).to_le_bytes());
+    for value in values {
+        bytes.extend((*value as u64).to_le_bytes());
+    }
+}
+
+/// Append a list of floats.
+pub(crate) fn write_f64s(bytes: &mut Vec<u8>, values: &[f64]) {
+    bytes.extend((values.len() as u64).to_le_bytes());
+    for value in values {
+        bytes.extend(value.to_le_bytes());
+    }
+}
+
+/// Remaining bytes of a file, consumed by the reads. Reads return `None` past the end.
+pub(crate) struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    pub fn new(bytes: &'a [u8]) -> Self {
+        Self(bytes)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn take(&mut self, len: usize) -> Option<&'a [u8]> {
+        if self.0.len() < len {
+            return None;
+        }
+        let (taken, rest) = self.0.split_at(len);
+        self.0 = rest;
+        Some(taken)
+    }
+
+    pub fn u32(&mut self) -> Option<u32> {
+        self.array().map(u32::from_le_bytes)
+    }
+
+    pub fn u64(&mut self) -> Option<u64> {
+        self.array().map(u64::from_le_bytes)
+    }
+
+    pub fn f64(&mut self) -> Option<f64> {
+        self.array().map(f64::from_le_bytes)
+    }
+
+    pub fn usize(&mut self) -> Option<usize> {
+        usize::try_from(self.u64()?).ok()
+    }
+
+    pub fn usizes(&mut self) -> Option<Vec<usize>> {
+        self.list(Self::usize)
+    }
+
+    pub fn f64s(&mut self) -> Option<Vec<f64>> {
+        self.list(Self::f64)
+    }
+
+    fn array<const N: usize>(&mut self) -> Option<[u8; N]> {
+        self.take(N)?.try_into().ok()
+    }
+
+    fn list<T>(&mut self, read: impl Fn(&mut Self) -> Option<T>) -> Option<Vec<T>> {
+        let len = self.usize()?;
+        // Bounded by the remaining bytes, not to allocate for a corrupted length
+        let mut values = Vec::with_capacity(len.min(self.0.len() / 8));
+        for _ in 0..len {
+            values.push(read(self)?);
+        }
+
+        Some(values)
+    }
+}
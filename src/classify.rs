@@ -0,0 +1,241 @@
+use std::collections::{BTreeMap, HashMap};
+
+use ndarray::{s, Array1, Array2, ArrayView3, Zip};
+
+use crate::cut::leaf_count;
+
+/// Class assigned to a region of a cut from a per-pixel classification.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RegionClass {
+    pub region: usize,
+    /// Most frequent class inside the region, the smallest one in case of tie
+    pub class: usize,
+    /// Fraction of the pixels of the region having that class
+    pub purity: f64,
+}
+
+/// Assign to each region of a cut its majority class in a per-pixel classification.
+///
+/// Results are sorted by region.
+pub fn majority_classes(labels: &Array2<usize>, classes: &Array2<usize>) -> Vec<RegionClass> {
+    let mut histograms = BTreeMap::<usize, HashMap<usize, usize>>::new();
+
+    Zip::from(labels).and(classes).for_each(|&label, &class| {
+        *histograms
+            .entry(label)
+            .or_default()
+            .entry(class)
+            .or_insert(0) += 1;
+    });
+
+    histograms
+        .into_iter()
+        .map(|(region, histogram)| {
+            let (class, purity) = majority(&histogram);
+            RegionClass {
+                region,
+                class,
+                purity,
+            }
+        })
+        .collect()
+}
+
+/// Most frequent class of a non-empty histogram, the smallest one in case of tie,
+/// with its frequency.
+fn majority(histogram: &HashMap<usize, usize>) -> (usize, f64) {
+    let area = histogram.values().sum::<usize>();
+    let (class, count) = histogram
+        .iter()
+        .max_by(|(class_a, count_a), (class_b, count_b)| {
+            count_a.cmp(count_b).then(class_b.cmp(class_a))
+        })
+        .unwrap();
+
+    (*class, *count as f64 / area as f64)
+}
+
+/// Snap a per-pixel classification (e.g. a CNN semantic map) to a hierarchy.
+///
+/// Each region of the cut at `level`, made of the nodes created below that level,
+/// gets its majority class. Regions whose purity is below `min_purity` are replaced
+/// by their children, recursively, down to the leaves.
+pub fn snap_classes(
+    leaf_labels: &Array2<usize>,
+    parents: &[usize],
+    levels: &[f64],
+    level: f64,
+    classes: &Array2<usize>,
+    min_purity: f64,
+) -> Array2<usize> {
+    let leaves = leaf_count(parents);
+    let below_cut = |node: usize| node < leaves || levels[node] < level;
+
+    let mut histograms = vec![HashMap::<usize, usize>::new(); parents.len()];
+    Zip::from(leaf_labels)
+        .and(classes)
+        .for_each(|&leaf, &class| {
+            *histograms[leaf].entry(class).or_insert(0) += 1;
+        });
+
+    // Parents always come after their children
+    let mut children = vec![Vec::new(); parents.len()];
+    for node in 0..parents.len() {
+        let parent = parents[node];
+        if parent != node && below_cut(parent) {
+            for (class, count) in histograms[node].clone() {
+                *histograms[parent].entry(class).or_insert(0) += count;
+            }
+            children[parent].push(node);
+        }
+    }
+
+    let mut node_classes = vec![None; parents.len()];
+    let mut stack = (0..parents.len())
+        .filter(|&node| below_cut(node) && (parents[node] == node || !below_cut(parents[node])))
+        .collect::<Vec<_>>();
+
+    while let Some(node) = stack.pop() {
+        if histograms[node].is_empty() {
+            continue;
+        }
+
+        let (class, purity) = majority(&histograms[node]);
+        if purity < min_purity && !children[node].is_empty() {
+            stack.extend(&children[node]);
+        } else {
+            node_classes[node] = Some(class);
+        }
+    }
+
+    // Descendants of a snapped node inherit its class
+    for node in (0..parents.len()).rev() {
+        let parent = parents[node];
+        if node_classes[node].is_none() && parent != node && below_cut(parent) {
+            node_classes[node] = node_classes[parent];
+        }
+    }
+
+    leaf_labels.mapv(|leaf| node_classes[leaf].expect("Every leaf has a class"))
+}
+
+/// Class probabilities of a region of a cut, averaged over its pixels.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RegionProbabilities {
+    pub region: usize,
+    pub probabilities: Array1<f64>,
+    /// Entropy of the averaged probabilities, in nats. Low values mean a confident class.
+    pub entropy: f64,
+}
+
+/// Average per-pixel class probability maps, of shape `(classes, height, width)`,
+/// over each region of a cut.
+///
+/// Results are sorted by region.
+pub fn mean_probabilities(
+    labels: &Array2<usize>,
+    probabilities: ArrayView3<f32>,
+) -> Vec<RegionProbabilities> {
+    let n_classes = probabilities.dim().0;
+    let mut sums = BTreeMap::<usize, (Array1<f64>, usize)>::new();
+
+    for ((y, x), &label) in labels.indexed_iter() {
+        let (sum, area) = sums
+            .entry(label)
+            .or_insert_with(|| (Array1::zeros(n_classes), 0));
+
+        Zip::from(&mut *sum)
+            .and(probabilities.slice(s![.., y, x]))
+            .for_each(|s, &p| *s += p as f64);
+        *area += 1;
+    }
+
+    sums.into_iter()
+        .map(|(region, (sum, area))| {
+            let probabilities = sum / area as f64;
+
+            let total = probabilities.sum();
+            let entropy = if total > 0. {
+                -probabilities
+                    .iter()
+                    .map(|p| p / total)
+                    .filter(|p| *p > 0.)
+                    .map(|p| p * p.ln())
+                    .sum::<f64>()
+            } else {
+                0.
+            };
+
+            RegionProbabilities {
+                region,
+                probabilities,
+                entropy,
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_majority_classes() {
+        let labels = Array2::from_shape_vec((2, 3), vec![0, 0, 1, 0, 0, 1]).unwrap();
+        let classes = Array2::from_shape_vec((2, 3), vec![5, 5, 2, 5, 3, 3]).unwrap();
+
+        let regions = majority_classes(&labels, &classes);
+
+        assert_eq!(
+            regions,
+            vec![
+                RegionClass {
+                    region: 0,
+                    class: 5,
+                    purity: 0.75
+                },
+                RegionClass {
+                    region: 1,
+                    class: 2,
+                    purity: 0.5
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_mean_probabilities() {
+        let labels = Array2::from_shape_vec((1, 3), vec![0, 0, 1]).unwrap();
+        // Two classes: region 0 is split between them, region 1 is certain
+        let probabilities =
+            ndarray::Array3::from_shape_vec((2, 1, 3), vec![1., 0., 1., 0., 1., 0.]).unwrap();
+
+        let regions = mean_probabilities(&labels, probabilities.view());
+
+        assert_eq!(regions.len(), 2);
+        assert_eq!(regions[0].probabilities, ndarray::array![0.5, 0.5]);
+        assert!((regions[0].entropy - 2f64.ln()).abs() < 1e-9);
+        assert_eq!(regions[1].probabilities, ndarray::array![1., 0.]);
+        assert_eq!(regions[1].entropy, 0.);
+    }
+
+    #[test]
+    fn test_snap_classes() {
+        // 0 0 1
+        // 0 0 1
+        // 2 2 2
+        let leaf_labels = Array2::from_shape_vec((3, 3), vec![0, 0, 1, 0, 0, 1, 2, 2, 2]).unwrap();
+        // 0 and 1 merge at level 1, then with 2 at level 5
+        let parents = vec![3, 3, 4, 4, 4];
+        let levels = vec![0., 0., 0., 1., 5.];
+        let classes = Array2::from_shape_vec((3, 3), vec![7, 7, 8, 7, 7, 8, 7, 7, 7]).unwrap();
+
+        // Node 3 (6 pixels of 7, 2 of 8) is kept at low purity thresholds
+        let coarse = snap_classes(&leaf_labels, &parents, &levels, 2., &classes, 0.5);
+        assert_eq!(coarse, Array2::from_elem((3, 3), 7));
+
+        // and split into its children otherwise
+        let fine = snap_classes(&leaf_labels, &parents, &levels, 2., &classes, 0.9);
+        assert_eq!(fine, classes);
+    }
+}
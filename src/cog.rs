@@ -0,0 +1,205 @@
+use std::io::{Read, Seek};
+#[cfg(not(target_arch = "wasm32"))]
+use std::{fs::File, io::BufReader, path::Path};
+
+use ndarray::{s, Array3};
+use tiff::{
+    decoder::{Decoder, DecodingResult},
+    tags::{PlanarConfiguration, Tag},
+    ColorType, TiffError, TiffResult, TiffUnsupportedError,
+};
+
+use crate::{geo::GeoTransform, slic_helpers::div_ceil, utils::Rect};
+
+/// Windowed reader of a GeoTIFF, e.g. a Cloud-Optimized GeoTIFF of a national
+/// mosaic: reading a window only decodes the tiles or strips intersecting it, so
+/// the image never has to fit in memory.
+///
+/// The source can be any seekable reader, a local file or a reader issuing HTTP
+/// range requests. Samples of more than 8 bits are converted to 8 bits, like
+/// [`decode_image`](crate::utils::decode_image) does.
+pub struct GeoTiffReader<R: Read + Seek> {
+    decoder: Decoder<R>,
+    width: usize,
+    height: usize,
+    channels: usize,
+    geo_transform: Option<GeoTransform>,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl GeoTiffReader<BufReader<File>> {
+    pub fn open(path: impl AsRef<Path>) -> TiffResult<Self> {
+        Self::new(BufReader::new(File::open(path)?))
+    }
+}
+
+impl<R: Read + Seek> GeoTiffReader<R> {
+    pub fn new(reader: R) -> TiffResult<Self> {
+        let mut decoder = Decoder::new(reader)?;
+
+        let planar = decoder.find_tag_unsigned::<u16>(Tag::PlanarConfiguration)?;
+        if planar == Some(PlanarConfiguration::Planar.to_u16()) {
+            return Err(TiffError::UnsupportedError(
+                TiffUnsupportedError::UnsupportedPlanarConfig(Some(PlanarConfiguration::Planar)),
+            ));
+        }
+
+        let channels = match decoder.colortype()? {
+            ColorType::Gray(_) => 1,
+            ColorType::GrayA(_) => 2,
+            ColorType::RGB(_) => 3,
+            ColorType::RGBA(_) => 4,
+            color => {
+                return Err(TiffError::UnsupportedError(
+                    TiffUnsupportedError::UnsupportedColorType(color),
+                ))
+            }
+        };
+        let (width, height) = decoder.dimensions()?;
+        let geo_transform = GeoTransform::from_tiff_decoder(&mut decoder);
+
+        Ok(Self {
+            decoder,
+            width: width as usize,
+            height: height as usize,
+            channels,
+            geo_transform,
+        })
+    }
+
+    /// Size of the image, as `(width, height)`.
+    pub fn dimensions(&self) -> (usize, usize) {
+        (self.width, self.height)
+    }
+
+    pub fn channels(&self) -> usize {
+        self.channels
+    }
+
+    /// Georeferencing of the whole image. The one of a window is offset by its
+    /// top-left corner.
+    pub fn geo_transform(&self) -> Option<GeoTransform> {
+        self.geo_transform
+    }
+
+    /// Read a window of the image into a `(height, width, channels)` array. The
+    /// window is clipped to the image.
+    pub fn read_window(&mut self, window: Rect) -> TiffResult<Array3<u8>> {
+        let window = window.expanded(0, self.width, self.height);
+        let mut output = Array3::zeros((window.height, window.width, self.channels));
+        if window.width == 0 || window.height == 0 {
+            return Ok(output);
+        }
+
+        // Tiles, or strips as wide as the image
+        let (chunk_width, chunk_height) = self.decoder.chunk_dimensions();
+        let (chunk_width, chunk_height) = (chunk_width as usize, chunk_height as usize);
+        let chunks_across = div_ceil(self.width, chunk_width);
+
+        let (x_end, y_end) = (window.x + window.width, window.y + window.height);
+        for chunk_y in window.y / chunk_height..div_ceil(y_end, chunk_height) {
+            for chunk_x in window.x / chunk_width..div_ceil(x_end, chunk_width) {
+                let index = (chunk_y * chunks_across + chunk_x) as u32;
+                let (data_width, data_height) = self.decoder.chunk_data_dimensions(index);
+                let samples = to_u8(self.decoder.read_chunk(index)?)?;
+                let chunk = Array3::from_shape_vec(
+                    (data_height as usize, data_width as usize, self.channels),
+                    samples,
+                )
+                .map_err(|_| {
+                    TiffError::UnsupportedError(TiffUnsupportedError::UnsupportedDataType)
+                })?;
+
+                // Intersection of the chunk and the window, in image coordinates
+                let (x0, y0) = (chunk_x * chunk_width, chunk_y * chunk_height);
+                let (left, top) = (x0.max(window.x), y0.max(window.y));
+                let right = (x0 + data_width as usize).min(x_end);
+                let bottom = (y0 + data_height as usize).min(y_end);
+
+                output
+                    .slice_mut(s![
+                        top - window.y..bottom - window.y,
+                        left - window.x..right - window.x,
+                        ..
+                    ])
+                    .assign(&chunk.slice(s![top - y0..bottom - y0, left - x0..right - x0, ..]));
+            }
+        }
+
+        Ok(output)
+    }
+}
+
+/// Samples converted to 8 bits.
+fn to_u8(samples: DecodingResult) -> TiffResult<Vec<u8>> {
+    match samples {
+        DecodingResult::U8(samples) => Ok(samples),
+        DecodingResult::U16(samples) => Ok(samples
+            .into_iter()
+            .map(|sample| ((u32::from(sample) + 128) / 257) as u8)
+            .collect()),
+        _ => Err(TiffError::UnsupportedError(
+            TiffUnsupportedError::UnsupportedDataType,
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use tiff::encoder::{colortype::RGB8, TiffEncoder};
+
+    use super::*;
+
+    #[test]
+    fn test_read_window() {
+        let (width, height) = (5, 7);
+        let data = (0..width * height * 3).map(|i| i as u8).collect::<Vec<_>>();
+
+        // Strips of 2 rows, georeferenced in Lambert-93 with 50cm pixels
+        let mut bytes = Cursor::new(Vec::new());
+        let mut encoder = TiffEncoder::new(&mut bytes).unwrap();
+        let mut image = encoder.new_image::<RGB8>(width, height).unwrap();
+        image.rows_per_strip(2).unwrap();
+        let tags = image.encoder();
+        tags.write_tag(Tag::ModelPixelScaleTag, &[0.5, 0.5, 0.][..])
+            .unwrap();
+        tags.write_tag(
+            Tag::ModelTiepointTag,
+            &[0., 0., 0., 650_000., 6_860_000., 0.][..],
+        )
+        .unwrap();
+        tags.write_tag(
+            Tag::GeoKeyDirectoryTag,
+            &[1u16, 1, 0, 1, 3072, 0, 1, 2154][..],
+        )
+        .unwrap();
+        image.write_data(&data).unwrap();
+
+        let mut reader = GeoTiffReader::new(Cursor::new(bytes.into_inner())).unwrap();
+        assert_eq!(reader.dimensions(), (5, 7));
+        assert_eq!(
+            reader.geo_transform(),
+            Some(GeoTransform::new(
+                650_000.,
+                6_860_000.,
+                0.5,
+                0.5,
+                Some(2154)
+            ))
+        );
+
+        // Window over 3 strips, overflowing the image on the right
+        let window = Rect {
+            x: 3,
+            y: 1,
+            width: 4,
+            height: 4,
+        };
+        let pixels = reader.read_window(window).unwrap();
+
+        let full = Array3::from_shape_vec((7, 5, 3), data).unwrap();
+        assert_eq!(pixels, full.slice(s![1..5, 3..5, ..]));
+    }
+}
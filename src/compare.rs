@@ -0,0 +1,562 @@
+use std::collections::{HashMap, HashSet};
+
+use ndarray::{Array2, Array3, Zip};
+use petgraph::unionfind::UnionFind;
+
+use crate::cut::leaf_count;
+
+/// Intersection over union below which two regions are not matched.
+pub const MIN_MATCH_IOU: f64 = 0.1;
+
+/// Comparison of two partitions of the same image.
+#[derive(Debug, Clone)]
+pub struct PartitionComparison {
+    /// `1` for the pixels whose regions do not match in the two partitions
+    pub disagreement: Array2<u8>,
+    pub variation_of_information: f64,
+    pub rand_index: f64,
+}
+
+/// Number of pixels in each pair of overlapping regions, and in each region of both partitions.
+struct Contingency {
+    joint: HashMap<(usize, usize), usize>,
+    a: HashMap<usize, usize>,
+    b: HashMap<usize, usize>,
+    total: usize,
+}
+
+impl Contingency {
+    fn new(a: &Array2<usize>, b: &Array2<usize>) -> Self {
+        let mut contingency = Self {
+            joint: HashMap::new(),
+            a: HashMap::new(),
+            b: HashMap::new(),
+            total: a.len(),
+        };
+
+        Zip::from(a).and(b).for_each(|&label_a, &label_b| {
+            *contingency.joint.entry((label_a, label_b)).or_insert(0) += 1;
+            *contingency.a.entry(label_a).or_insert(0) += 1;
+            *contingency.b.entry(label_b).or_insert(0) += 1;
+        });
+
+        contingency
+    }
+
+    /// `sum(n * log(n))` of the given counts, in nats.
+    fn sum_n_log_n<'a>(counts: impl Iterator<Item = &'a usize>) -> f64 {
+        counts.map(|n| n_log_n(*n)).sum()
+    }
+
+    fn variation_of_information(&self) -> f64 {
+        let n = self.total as f64;
+        // VI = H(A|B) + H(B|A) = (sum(a log a) + sum(b log b) - 2 sum(n_ab log n_ab)) / N
+        let vi = (Self::sum_n_log_n(self.a.values()) + Self::sum_n_log_n(self.b.values())
+            - 2. * Self::sum_n_log_n(self.joint.values()))
+            / n;
+        vi.max(0.)
+    }
+
+    fn rand_index(&self) -> f64 {
+        let pairs = |n: usize| (n as f64) * (n as f64 - 1.) / 2.;
+        let total_pairs = pairs(self.total);
+        if total_pairs == 0. {
+            return 1.;
+        }
+
+        let joint = self.joint.values().map(|n| pairs(*n)).sum::<f64>();
+        let a = self.a.values().map(|n| pairs(*n)).sum::<f64>();
+        let b = self.b.values().map(|n| pairs(*n)).sum::<f64>();
+
+        (total_pairs + 2. * joint - a - b) / total_pairs
+    }
+
+    /// Region of the other partition overlapping the most each region.
+    fn best_matches(&self) -> (HashMap<usize, usize>, HashMap<usize, usize>) {
+        let mut a_to_b = HashMap::<usize, (usize, usize)>::new();
+        let mut b_to_a = HashMap::<usize, (usize, usize)>::new();
+
+        for (&(label_a, label_b), &count) in &self.joint {
+            let best = a_to_b.entry(label_a).or_insert((label_b, count));
+            if count > best.1 || (count == best.1 && label_b < best.0) {
+                *best = (label_b, count);
+            }
+
+            let best = b_to_a.entry(label_b).or_insert((label_a, count));
+            if count > best.1 || (count == best.1 && label_a < best.0) {
+                *best = (label_a, count);
+            }
+        }
+
+        (
+            a_to_b.into_iter().map(|(k, (v, _))| (k, v)).collect(),
+            b_to_a.into_iter().map(|(k, (v, _))| (k, v)).collect(),
+        )
+    }
+}
+
+/// Compare two partitions. A pixel disagrees when its region in one partition is
+/// not the best matching region of its region in the other partition.
+pub fn compare_partitions(a: &Array2<usize>, b: &Array2<usize>) -> PartitionComparison {
+    let contingency = Contingency::new(a, b);
+    let (a_to_b, b_to_a) = contingency.best_matches();
+
+    let disagreement = Zip::from(a).and(b).map_collect(|label_a, label_b| {
+        u8::from(a_to_b[label_a] != *label_b || b_to_a[label_b] != *label_a)
+    });
+
+    PartitionComparison {
+        disagreement,
+        variation_of_information: contingency.variation_of_information(),
+        rand_index: contingency.rand_index(),
+    }
+}
+
+/// Matching between the regions of two partitions of the same area, e.g. at two dates.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RegionMatching {
+    /// Matched regions as `(region_a, region_b, iou)`, sorted by region of `a`
+    pub matches: Vec<(usize, usize, f64)>,
+    /// Regions of `a` matched with no region of `b`, sorted
+    pub disappeared: Vec<usize>,
+    /// Regions of `b` matched with no region of `a`, sorted
+    pub appeared: Vec<usize>,
+}
+
+/// Match each region of `a` with at most one region of `b`, maximizing the sum of the
+/// intersections over union of the matched regions. Regions overlapping by less
+/// than [`MIN_MATCH_IOU`] are never matched.
+///
+/// The assignment is solved with the Hungarian algorithm on each group of regions
+/// linked by overlaps, so the cost stays low for partitions with many regions.
+pub fn match_regions(a: &Array2<usize>, b: &Array2<usize>) -> RegionMatching {
+    let contingency = Contingency::new(a, b);
+
+    let mut regions_a = contingency.a.keys().copied().collect::<Vec<_>>();
+    let mut regions_b = contingency.b.keys().copied().collect::<Vec<_>>();
+    regions_a.sort_unstable();
+    regions_b.sort_unstable();
+    let index_a = index_of(&regions_a);
+    let index_b = index_of(&regions_b);
+
+    // Overlaps as `(index in a, index in b, iou)`
+    let mut overlaps = contingency
+        .joint
+        .iter()
+        .map(|(&(label_a, label_b), &count)| {
+            let union = contingency.a[&label_a] + contingency.b[&label_b] - count;
+            (
+                index_a[&label_a],
+                index_b[&label_b],
+                count as f64 / union as f64,
+            )
+        })
+        .filter(|(_, _, iou)| *iou >= MIN_MATCH_IOU)
+        .collect::<Vec<_>>();
+    overlaps.sort_unstable_by_key(|(i, j, _)| (*i, *j));
+
+    // Groups of regions linked by overlaps, regions of b after the ones of a
+    let mut components = UnionFind::<usize>::new(regions_a.len() + regions_b.len());
+    for (i, j, _) in &overlaps {
+        components.union(*i, regions_a.len() + j);
+    }
+    let mut groups = HashMap::<usize, Vec<(usize, usize, f64)>>::new();
+    for overlap in overlaps {
+        groups
+            .entry(components.find(overlap.0))
+            .or_default()
+            .push(overlap);
+    }
+
+    let mut matches = Vec::new();
+    for group in groups.into_values() {
+        let mut rows = group.iter().map(|(i, _, _)| *i).collect::<Vec<_>>();
+        let mut columns = group.iter().map(|(_, j, _)| *j).collect::<Vec<_>>();
+        for indices in [&mut rows, &mut columns] {
+            indices.sort_unstable();
+            indices.dedup();
+        }
+        let (row_of, column_of) = (index_of(&rows), index_of(&columns));
+
+        // The assignment needs at most as many rows as columns
+        let transposed = rows.len() > columns.len();
+        let (n_rows, n_columns) = if transposed {
+            (columns.len(), rows.len())
+        } else {
+            (rows.len(), columns.len())
+        };
+        let mut ious = vec![vec![0.; n_columns]; n_rows];
+        for (i, j, iou) in &group {
+            let (row, column) = (row_of[i], column_of[j]);
+            if transposed {
+                ious[column][row] = *iou;
+            } else {
+                ious[row][column] = *iou;
+            }
+        }
+
+        let costs = ious
+            .iter()
+            .map(|row| row.iter().map(|iou| -iou).collect())
+            .collect::<Vec<_>>();
+        for (row, column) in min_cost_assignment(&costs).into_iter().enumerate() {
+            let iou = ious[row][column];
+            // Pairs of regions not overlapping enough complete the assignment
+            if iou > 0. {
+                let (i, j) = if transposed {
+                    (rows[column], columns[row])
+                } else {
+                    (rows[row], columns[column])
+                };
+                matches.push((regions_a[i], regions_b[j], iou));
+            }
+        }
+    }
+    matches.sort_unstable_by_key(|(label_a, _, _)| *label_a);
+
+    let matched_a = matches.iter().map(|m| m.0).collect::<HashSet<_>>();
+    let matched_b = matches.iter().map(|m| m.1).collect::<HashSet<_>>();
+
+    RegionMatching {
+        disappeared: regions_a
+            .into_iter()
+            .filter(|label| !matched_a.contains(label))
+            .collect(),
+        appeared: regions_b
+            .into_iter()
+            .filter(|label| !matched_b.contains(label))
+            .collect(),
+        matches,
+    }
+}
+
+/// Changes between the cuts of an area at two dates, see [`change_map`].
+#[derive(Debug, Clone)]
+pub struct ChangeMap {
+    /// Change score of each region of the second cut: the spectral distance to its
+    /// matched region of the first cut, or `f64::INFINITY` for the appeared regions
+    pub scores: HashMap<usize, f64>,
+    /// `1` for the changed pixels: in a region of the second cut scoring above the
+    /// threshold, or in a region of the first cut that disappeared
+    pub mask: Array2<u8>,
+}
+
+/// Mean of each band of a `(height, width, channels)` image in each region of a cut.
+pub fn region_means(cut: &Array2<usize>, img: &Array3<u8>) -> HashMap<usize, Vec<f64>> {
+    let channels = img.dim().2;
+    let mut sums = HashMap::<usize, (usize, Vec<f64>)>::new();
+
+    for ((y, x), label) in cut.indexed_iter() {
+        let (count, sum) = sums
+            .entry(*label)
+            .or_insert_with(|| (0, vec![0.; channels]));
+        *count += 1;
+        for (c, sum) in sum.iter_mut().enumerate() {
+            *sum += f64::from(img[[y, x, c]]);
+        }
+    }
+
+    sums.into_iter()
+        .map(|(label, (count, sum))| (label, sum.into_iter().map(|s| s / count as f64).collect()))
+        .collect()
+}
+
+/// Change detection between the cuts of an area at two dates, with the band means
+/// of their regions, see [`region_means`]. The regions are matched with
+/// [`match_regions`], and the score of a matched region is the root mean square
+/// of the differences of its band means, in pixel values.
+pub fn change_map(
+    cut_t0: &Array2<usize>,
+    cut_t1: &Array2<usize>,
+    stats_t0: &HashMap<usize, Vec<f64>>,
+    stats_t1: &HashMap<usize, Vec<f64>>,
+    threshold: f64,
+) -> ChangeMap {
+    let matching = match_regions(cut_t0, cut_t1);
+
+    let mut scores = matching
+        .appeared
+        .iter()
+        .map(|label| (*label, f64::INFINITY))
+        .collect::<HashMap<_, _>>();
+    for (label_t0, label_t1, _) in &matching.matches {
+        let (means_t0, means_t1) = (&stats_t0[label_t0], &stats_t1[label_t1]);
+        let squares = means_t0
+            .iter()
+            .zip(means_t1)
+            .map(|(a, b)| (a - b) * (a - b))
+            .sum::<f64>();
+        let score = (squares / means_t0.len().max(1) as f64).sqrt();
+        scores.insert(*label_t1, score);
+    }
+
+    let disappeared = matching.disappeared.into_iter().collect::<HashSet<_>>();
+    let mask = Zip::from(cut_t0)
+        .and(cut_t1)
+        .map_collect(|label_t0, label_t1| {
+            u8::from(scores[label_t1] > threshold || disappeared.contains(label_t0))
+        });
+
+    ChangeMap { scores, mask }
+}
+
+fn index_of(values: &[usize]) -> HashMap<usize, usize> {
+    values.iter().enumerate().map(|(i, v)| (*v, i)).collect()
+}
+
+/// Column assigned to each row minimizing the total cost, with at most as many rows
+/// as columns (Hungarian algorithm with potentials, in `O(rows² * columns)`).
+fn min_cost_assignment(costs: &[Vec<f64>]) -> Vec<usize> {
+    let n_rows = costs.len();
+    let n_columns = costs.first().map_or(0, Vec::len);
+
+    // 1-based rows and columns, column 0 holding the row being assigned
+    let mut u = vec![0.; n_rows + 1];
+    let mut v = vec![0.; n_columns + 1];
+    let mut row_of = vec![0; n_columns + 1];
+    let mut way = vec![0; n_columns + 1];
+
+    for row in 1..=n_rows {
+        row_of[0] = row;
+        let mut column = 0;
+        let mut min_slack = vec![f64::INFINITY; n_columns + 1];
+        let mut used = vec![false; n_columns + 1];
+
+        // Find an augmenting path from the row to a free column
+        loop {
+            used[column] = true;
+            let current = row_of[column];
+            let mut delta = f64::INFINITY;
+            let mut next = 0;
+            for j in 1..=n_columns {
+                if !used[j] {
+                    let slack = costs[current - 1][j - 1] - u[current] - v[j];
+                    if slack < min_slack[j] {
+                        min_slack[j] = slack;
+                        way[j] = column;
+                    }
+                    if min_slack[j] < delta {
+                        delta = min_slack[j];
+                        next = j;
+                    }
+                }
+            }
+            for j in 0..=n_columns {
+                if used[j] {
+                    u[row_of[j]] += delta;
+                    v[j] -= delta;
+                } else {
+                    min_slack[j] -= delta;
+                }
+            }
+            column = next;
+            if row_of[column] == 0 {
+                break;
+            }
+        }
+
+        // Flip the assignments along the path
+        while column != 0 {
+            let previous = way[column];
+            row_of[column] = row_of[previous];
+            column = previous;
+        }
+    }
+
+    let mut assignment = vec![0; n_rows];
+    for (column, row) in row_of.iter().enumerate().skip(1) {
+        if *row != 0 {
+            assignment[row - 1] = column - 1;
+        }
+    }
+
+    assignment
+}
+
+/// `n * log(n)`, `0` for `0`.
+fn n_log_n(n: usize) -> f64 {
+    if n == 0 {
+        0.
+    } else {
+        n as f64 * (n as f64).ln()
+    }
+}
+
+/// Variation of information between each horizontal cut of a partition tree and a
+/// reference partition, as `(level, vi)` pairs sorted by level. The first pair
+/// is for the leaves.
+///
+/// The contingency table is updated at each merge, so the whole curve costs
+/// about as much as a single comparison.
+pub fn vi_curve(
+    leaf_labels: &Array2<usize>,
+    parents: &[usize],
+    levels: &[f64],
+    reference: &Array2<usize>,
+) -> Vec<(f64, f64)> {
+    let n = leaf_labels.len() as f64;
+
+    // Overlap of each node of the tree with the regions of the reference
+    let mut rows = vec![HashMap::<usize, usize>::new(); parents.len()];
+    let mut areas = vec![0; parents.len()];
+    let mut reference_areas = HashMap::<usize, usize>::new();
+
+    Zip::from(leaf_labels)
+        .and(reference)
+        .for_each(|&leaf, &reference_label| {
+            *rows[leaf].entry(reference_label).or_insert(0) += 1;
+            areas[leaf] += 1;
+            *reference_areas.entry(reference_label).or_insert(0) += 1;
+        });
+
+    let reference_sum = reference_areas.values().map(|a| n_log_n(*a)).sum::<f64>();
+    let mut node_sum = areas.iter().map(|a| n_log_n(*a)).sum::<f64>();
+    let mut joint_sum = rows
+        .iter()
+        .flat_map(|row| row.values())
+        .map(|c| n_log_n(*c))
+        .sum::<f64>();
+
+    let mut children = vec![Vec::new(); parents.len()];
+    for (node, parent) in parents.iter().enumerate() {
+        if *parent != node {
+            children[*parent].push(node);
+        }
+    }
+
+    let vi =
+        |node_sum: f64, joint_sum: f64| ((node_sum + reference_sum - 2. * joint_sum) / n).max(0.);
+
+    let mut curve = vec![(0., vi(node_sum, joint_sum))];
+
+    for node in leaf_count(parents)..parents.len() {
+        for &child in &children[node] {
+            let child_row = std::mem::take(&mut rows[child]);
+
+            // Merge the smaller table into the larger one
+            let (mut row, other) = if child_row.len() > rows[node].len() {
+                (child_row, std::mem::take(&mut rows[node]))
+            } else {
+                (std::mem::take(&mut rows[node]), child_row)
+            };
+
+            for (reference_label, count) in other {
+                let entry = row.entry(reference_label).or_insert(0);
+                joint_sum += n_log_n(*entry + count) - n_log_n(*entry) - n_log_n(count);
+                *entry += count;
+            }
+            rows[node] = row;
+
+            node_sum +=
+                n_log_n(areas[node] + areas[child]) - n_log_n(areas[node]) - n_log_n(areas[child]);
+            areas[node] += areas[child];
+        }
+
+        let point = (levels[node], vi(node_sum, joint_sum));
+        match curve.last_mut() {
+            // Merges at the same level belong to the same cut
+            Some(last) if last.0 == point.0 => *last = point,
+            _ => curve.push(point),
+        }
+    }
+
+    curve
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compare_partitions() {
+        let a = Array2::from_shape_vec((2, 3), vec![0, 0, 1, 0, 0, 1]).unwrap();
+        let same = a.mapv(|l| l + 10);
+        let split = Array2::from_shape_vec((2, 3), vec![0, 2, 1, 0, 2, 1]).unwrap();
+
+        let comparison = compare_partitions(&a, &same);
+        assert_eq!(comparison.variation_of_information, 0.);
+        assert_eq!(comparison.rand_index, 1.);
+        assert!(comparison.disagreement.iter().all(|d| *d == 0));
+
+        let comparison = compare_partitions(&a, &split);
+        assert!(comparison.variation_of_information > 0.);
+        assert!(comparison.rand_index < 1.);
+        assert_eq!(
+            comparison.disagreement.iter().filter(|d| **d == 1).count(),
+            2
+        );
+    }
+
+    #[test]
+    fn test_vi_curve() {
+        // 0 0 1
+        // 0 0 1
+        // 2 2 2
+        let labels = Array2::from_shape_vec((3, 3), vec![0, 0, 1, 0, 0, 1, 2, 2, 2]).unwrap();
+        let parents = vec![3, 3, 4, 4, 4];
+        let levels = vec![0., 0., 0., 1., 5.];
+        let reference = labels.mapv(|l| usize::from(l == 2));
+
+        let curve = vi_curve(&labels, &parents, &levels, &reference);
+
+        assert_eq!(curve.len(), 3);
+        assert!(curve[0].1 > 0.);
+        assert_eq!(curve[1], (1., 0.));
+        assert!(curve[2].1 > 0.);
+
+        let cut = labels.mapv(|l| if l == 2 { 2 } else { 3 });
+        let expected = Contingency::new(&cut, &reference).variation_of_information();
+        assert!((curve[1].1 - expected).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_match_regions() {
+        // 0 0 1 1      5 5 6 6
+        // 0 0 1 1      5 5 6 6
+        // 2 2 2 2      7 7 7 8
+        let a = Array2::from_shape_vec((3, 4), vec![0, 0, 1, 1, 0, 0, 1, 1, 2, 2, 2, 2]).unwrap();
+        let b = Array2::from_shape_vec((3, 4), vec![5, 5, 6, 6, 5, 5, 6, 6, 7, 7, 7, 8]).unwrap();
+
+        let matching = match_regions(&a, &b);
+        assert_eq!(matching.matches, vec![(0, 5, 1.), (1, 6, 1.), (2, 7, 0.75)]);
+        assert!(matching.disappeared.is_empty());
+        assert_eq!(matching.appeared, vec![8]);
+
+        // One region split in two: only the best half is matched
+        let split = Array2::from_shape_vec((1, 5), vec![0, 0, 0, 1, 1]).unwrap();
+        let whole = Array2::from_elem((1, 5), 3);
+        let matching = match_regions(&whole, &split);
+        assert_eq!(matching.matches, vec![(3, 0, 0.6)]);
+        assert_eq!(matching.appeared, vec![1]);
+    }
+
+    #[test]
+    fn test_min_cost_assignment() {
+        // The greedy choice of the smallest cost, 1, is not optimal
+        let costs = vec![vec![1., 2., 9.], vec![2., 9., 9.]];
+        assert_eq!(min_cost_assignment(&costs), vec![1, 0]);
+    }
+
+    #[test]
+    fn test_change_map() {
+        // Two fields, the right one harvested and a new building in the left one
+        let cut_t0 = Array2::from_shape_vec((2, 4), vec![0, 0, 1, 1, 0, 0, 1, 1]).unwrap();
+        let cut_t1 = Array2::from_shape_vec((2, 4), vec![0, 0, 1, 1, 0, 2, 1, 1]).unwrap();
+        let img_t0 = Array3::from_shape_fn((2, 4, 1), |(_, x, _)| if x < 2 { 100 } else { 50 });
+        let mut img_t1 = img_t0.mapv(|v| if v == 50 { 150 } else { v });
+        img_t1[[1, 1, 0]] = 250;
+
+        let stats_t0 = region_means(&cut_t0, &img_t0);
+        let stats_t1 = region_means(&cut_t1, &img_t1);
+        assert_eq!(stats_t1[&1], vec![150.]);
+
+        let changes = change_map(&cut_t0, &cut_t1, &stats_t0, &stats_t1, 10.);
+        assert_eq!(changes.scores[&0], 0.);
+        assert_eq!(changes.scores[&1], 100.);
+        assert_eq!(changes.scores[&2], f64::INFINITY);
+        assert_eq!(
+            changes.mask,
+            Array2::from_shape_vec((2, 4), vec![0, 0, 1, 1, 0, 1, 1, 1]).unwrap()
+        );
+    }
+}
@@ -0,0 +1,153 @@
+use ndarray::{Array2, ArrayView2, ArrayView3};
+use petgraph::unionfind::UnionFind;
+
+use crate::hierarchy::PartitionTree;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ComponentTreeKind {
+    /// Components of the lower level sets, extracts dark structures.
+    MinTree,
+    /// Components of the upper level sets, extracts bright structures.
+    MaxTree,
+}
+
+/// Build the component tree of a single band with 4-connectivity.
+///
+/// Leaves are the pixels, in row-major order. For a min-tree, the regions at
+/// level `t` are the connected components of the pixels whose value is lower or
+/// equal to `t`. For a max-tree the band is inverted first: the regions at level
+/// `t` are the components of the pixels whose value is greater or equal to `255 - t`.
+///
+/// As for the alpha-tree, components appearing at the same level are chained
+/// binary nodes sharing that level.
+pub fn component_tree(band: ArrayView2<u8>, kind: ComponentTreeKind) -> PartitionTree {
+    let (height, width) = band.dim();
+    let num_points = height * width;
+
+    let value = |y: usize, x: usize| match kind {
+        ComponentTreeKind::MinTree => band[[y, x]],
+        ComponentTreeKind::MaxTree => u8::MAX - band[[y, x]],
+    };
+
+    // An edge between two pixels appears when both of them are in the level set
+    let mut edges = Vec::with_capacity(2 * num_points);
+    for y in 0..height {
+        for x in 0..width {
+            let i = y * width + x;
+            if x + 1 < width {
+                edges.push((value(y, x).max(value(y, x + 1)), i, i + 1));
+            }
+            if y + 1 < height {
+                edges.push((value(y, x).max(value(y + 1, x)), i, i + width));
+            }
+        }
+    }
+    edges.sort_by_key(|(level, _, _)| *level);
+
+    let mut parents = (0..num_points).collect::<Vec<_>>();
+    let mut levels = vec![0.0; num_points];
+
+    let mut components = UnionFind::<usize>::new(num_points);
+    // Tree node representing each component, indexed by the component representative
+    let mut roots = (0..num_points).collect::<Vec<_>>();
+    let mut merges = Vec::with_capacity(num_points.saturating_sub(1));
+
+    for (level, a, b) in edges {
+        let root_a = roots[components.find(a)];
+        let root_b = roots[components.find(b)];
+
+        if root_a == root_b {
+            continue;
+        }
+
+        let new_node = parents.len();
+        parents.push(new_node);
+        levels.push(f64::from(level));
+
+        parents[root_a] = new_node;
+        parents[root_b] = new_node;
+        merges.push((root_a, root_b, 1));
+
+        components.union(a, b);
+        roots[components.find(a)] = new_node;
+    }
+
+    PartitionTree {
+        parents,
+        levels,
+        merges,
+    }
+}
+
+/// Normalized difference `(a - b) / (a + b)` of two bands of a `(height, width,
+/// channels)` image, e.g. the NDVI from the near infrared and red bands, mapped from
+/// `-1..=1` to `0..=255` for [`component_tree`]. Pixels where both bands are 0 are
+/// mapped to the middle value, 128.
+pub fn normalized_difference(img: ArrayView3<u8>, a: usize, b: usize) -> Array2<u8> {
+    let (height, width, _) = img.dim();
+
+    Array2::from_shape_fn((height, width), |(y, x)| {
+        let (a, b) = (f64::from(img[[y, x, a]]), f64::from(img[[y, x, b]]));
+        let difference = if a + b == 0. { 0. } else { (a - b) / (a + b) };
+
+        ((difference + 1.) * 127.5).round() as u8
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use ndarray::{array, Array3};
+
+    use super::*;
+
+    #[test]
+    fn test_min_tree() {
+        // Single-pixel minimum at 1, plateau at 3-4
+        let band = array![[2, 0, 2, 7, 7]];
+        let tree = component_tree(band.view(), ComponentTreeKind::MinTree);
+
+        assert_eq!(tree.parents, vec![5, 5, 6, 7, 8, 6, 7, 8, 8]);
+        assert_eq!(tree.levels, vec![0., 0., 0., 0., 0., 2., 2., 7., 7.]);
+    }
+
+    #[test]
+    fn test_max_tree() {
+        // The plateau at 3-4 is the brightest component, the minimum joins last
+        let band = array![[2, 0, 2, 7, 7]];
+        let tree = component_tree(band.view(), ComponentTreeKind::MaxTree);
+
+        assert_eq!(tree.parents, vec![7, 7, 6, 5, 5, 6, 8, 8, 8]);
+        assert_eq!(
+            tree.levels,
+            vec![0., 0., 0., 0., 0., 248., 253., 255., 255.]
+        );
+    }
+
+    #[test]
+    fn test_flat_band() {
+        // A single plateau merges at its value, whatever the kind
+        let band = Array2::from_elem((2, 2), 9);
+        for (kind, level) in [
+            (ComponentTreeKind::MinTree, 9.),
+            (ComponentTreeKind::MaxTree, 246.),
+        ] {
+            let tree = component_tree(band.view(), kind);
+            assert_eq!(tree.parents.len(), 7);
+            assert!(tree.levels[4..].iter().all(|l| *l == level));
+        }
+
+        let tree = component_tree(
+            Array2::from_elem((1, 1), 3).view(),
+            ComponentTreeKind::MinTree,
+        );
+        assert_eq!((tree.parents, tree.levels), (vec![0], vec![0.]));
+    }
+
+    #[test]
+    fn test_normalized_difference() {
+        let img = Array3::from_shape_vec((1, 2, 2), vec![200, 50, 0, 0]).unwrap();
+
+        assert_eq!(normalized_difference(img.view(), 0, 1), array![[204, 128]]);
+        assert_eq!(normalized_difference(img.view(), 1, 0), array![[51, 128]]);
+    }
+}
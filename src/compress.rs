@@ -0,0 +1,132 @@
+use std::collections::HashMap;
+
+#[cfg(feature = "wasm")]
+use wasm_bindgen::prelude::wasm_bindgen;
+
+/// Run-length encoded label map. Labels are replaced by their index in a palette
+/// of the distinct labels, then consecutive equal indices, in row-major order,
+/// are stored as runs.
+#[cfg_attr(feature = "wasm", wasm_bindgen(getter_with_clone))]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CompressedLabels {
+    pub width: usize,
+    pub height: usize,
+    /// Distinct labels, sorted
+    pub palette: Vec<usize>,
+    /// Palette index of each run
+    pub values: Vec<u32>,
+    /// Number of pixels of each run
+    pub lengths: Vec<u32>,
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+impl CompressedLabels {
+    /// Compressed labels from their arrays, e.g. sent back from JS; they are
+    /// checked by [`decompress_labels`].
+    #[cfg_attr(feature = "wasm", wasm_bindgen(constructor))]
+    pub fn new(
+        width: usize,
+        height: usize,
+        palette: Vec<usize>,
+        values: Vec<u32>,
+        lengths: Vec<u32>,
+    ) -> Self {
+        Self {
+            width,
+            height,
+            palette,
+            values,
+            lengths,
+        }
+    }
+}
+
+/// Compress a row-major label map of `width * height` labels.
+pub fn compress_labels(labels: &[usize], width: usize, height: usize) -> CompressedLabels {
+    let mut palette = labels.to_vec();
+    palette.sort_unstable();
+    palette.dedup();
+
+    let indices = palette
+        .iter()
+        .enumerate()
+        .map(|(i, label)| (*label, i as u32))
+        .collect::<HashMap<_, _>>();
+
+    let mut values = Vec::new();
+    let mut lengths = Vec::new();
+    for label in labels {
+        let value = indices[label];
+        match (values.last(), lengths.last_mut()) {
+            (Some(&last), Some(length)) if last == value && *length < u32::MAX => *length += 1,
+            _ => {
+                values.push(value);
+                lengths.push(1);
+            }
+        }
+    }
+
+    CompressedLabels {
+        width,
+        height,
+        palette,
+        values,
+        lengths,
+    }
+}
+
+/// Expand a compressed label map back to row-major labels, or `None` if a value is
+/// out of the palette, or the runs do not cover exactly `width * height` pixels.
+pub fn decompress_labels(compressed: &CompressedLabels) -> Option<Vec<usize>> {
+    let size = compressed.width.checked_mul(compressed.height)?;
+    if compressed.values.len() != compressed.lengths.len() {
+        return None;
+    }
+    let total = compressed
+        .lengths
+        .iter()
+        .try_fold(0usize, |total, length| total.checked_add(*length as usize))?;
+    if total != size {
+        return None;
+    }
+
+    let mut labels = Vec::with_capacity(size);
+    for (value, length) in compressed.values.iter().zip(&compressed.lengths) {
+        let label = *compressed.palette.get(*value as usize)?;
+        labels.resize(labels.len() + *length as usize, label);
+    }
+
+    Some(labels)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compress_labels() {
+        let labels = vec![7, 7, 7, 3, 3, 7, 42, 42];
+
+        let compressed = compress_labels(&labels, 4, 2);
+
+        assert_eq!(compressed.palette, vec![3, 7, 42]);
+        assert_eq!(compressed.values, vec![1, 0, 1, 2]);
+        assert_eq!(compressed.lengths, vec![3, 2, 1, 2]);
+        assert_eq!(decompress_labels(&compressed), Some(labels));
+    }
+
+    #[test]
+    fn test_decompress_invalid() {
+        let valid = CompressedLabels::new(2, 2, vec![3, 7], vec![0, 1], vec![1, 3]);
+        assert_eq!(decompress_labels(&valid), Some(vec![3, 7, 7, 7]));
+
+        let out_of_palette = CompressedLabels::new(2, 2, vec![3, 7], vec![0, 2], vec![1, 3]);
+        assert_eq!(decompress_labels(&out_of_palette), None);
+        let too_short = CompressedLabels::new(2, 2, vec![3, 7], vec![0, 1], vec![1, 2]);
+        assert_eq!(decompress_labels(&too_short), None);
+        let too_long = CompressedLabels::new(2, 2, vec![3, 7], vec![0, 1], vec![1, 4]);
+        assert_eq!(decompress_labels(&too_long), None);
+        let unpaired = CompressedLabels::new(2, 2, vec![3, 7], vec![0, 1], vec![4]);
+        assert_eq!(decompress_labels(&unpaired), None);
+    }
+}
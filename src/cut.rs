@@ -0,0 +1,566 @@
+use std::{
+    cmp::Reverse,
+    collections::{BTreeMap, BinaryHeap, HashMap, HashSet},
+};
+
+use ndarray::{Array2, Array3};
+
+use crate::geo::GeoTransform;
+
+/// Shared boundary length, in pixel sides, between each pair of adjacent regions
+/// of a label map with 4-connectivity.
+///
+/// Pairs are given as `(a, b)` with `a < b` and sorted.
+pub fn region_adjacency(labels: &Array2<usize>) -> Vec<((usize, usize), u32)> {
+    let mut lengths = BTreeMap::<(usize, usize), u32>::new();
+
+    for ((y, x), &label) in labels.indexed_iter() {
+        // Loop over the neighbors (right and bottom)
+        for (dy, dx) in [(0, 1), (1, 0)] {
+            if let Some(&n_label) = labels.get((y + dy, x + dx)) {
+                if n_label != label {
+                    let pair = (label.min(n_label), label.max(n_label));
+                    *lengths.entry(pair).or_insert(0) += 1;
+                }
+            }
+        }
+    }
+
+    lengths.into_iter().collect()
+}
+
+/// Number of leaves of a partition tree. Internal nodes are stored after the leaves.
+pub fn leaf_count(parents: &[usize]) -> usize {
+    parents
+        .iter()
+        .enumerate()
+        .filter(|(i, parent)| *i != **parent)
+        .map(|(_, parent)| *parent)
+        .min()
+        .unwrap_or(parents.len())
+}
+
+/// Cut a partition tree after its `merges` first merges. Each pixel is labelled
+/// with the index of its highest ancestor created by these merges.
+///
+/// With a single tree, the cut has `leaf_count(parents) - merges` regions.
+pub fn cut_by_merge_count(
+    leaf_labels: &Array2<usize>,
+    parents: &[usize],
+    merges: usize,
+) -> Array2<usize> {
+    let limit = leaf_count(parents) + merges;
+
+    // Parents always come after their children
+    let mut representatives = (0..parents.len()).collect::<Vec<_>>();
+    for node in (0..parents.len()).rev() {
+        let parent = parents[node];
+        if parent != node && parent < limit {
+            representatives[node] = representatives[parent];
+        }
+    }
+
+    leaf_labels.mapv(|leaf| representatives[leaf])
+}
+
+/// Level at which a hierarchy can be cut, with the number of regions of the cut.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScaleCandidate {
+    pub level: f64,
+    pub region_count: usize,
+    /// Length of the range of levels giving the same cut, in `log(1 + level)` scale
+    pub lifetime: f64,
+}
+
+/// Find the most stable cuts of a partition tree: the plateaus of the region count
+/// with respect to the level, ranked by decreasing lifetime.
+///
+/// Lifetimes are measured in `log(1 + level)` scale, as levels span several
+/// orders of magnitude. The level of each candidate is the middle of its plateau
+/// in that scale. The leaves and the root are not candidates.
+pub fn stable_scales(
+    parents: &[usize],
+    levels: &[f64],
+    max_candidates: usize,
+) -> Vec<ScaleCandidate> {
+    let leaves = leaf_count(parents);
+    let merge_levels = &levels[leaves..];
+
+    let mut candidates = merge_levels
+        .windows(2)
+        .enumerate()
+        .filter_map(|(i, window)| {
+            let start = window[0].ln_1p();
+            let end = window[1].ln_1p();
+
+            // Merges at the same level are part of the same cut
+            if end <= start {
+                return None;
+            }
+
+            Some(ScaleCandidate {
+                level: ((start + end) / 2.).exp_m1(),
+                region_count: leaves - (i + 1),
+                lifetime: end - start,
+            })
+        })
+        .collect::<Vec<_>>();
+
+    candidates.sort_by(|a, b| b.lifetime.partial_cmp(&a.lifetime).unwrap());
+    candidates.truncate(max_candidates);
+
+    candidates
+}
+
+/// A region of a cut merged into the region enclosing it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HoleFill {
+    pub hole: usize,
+    pub container: usize,
+    /// Area of the hole in pixels
+    pub area: usize,
+}
+
+/// Merge the regions entirely enclosed in another region into it, when their
+/// area is lower or equal to `max_area` pixels.
+///
+/// A region is enclosed when it does not touch the image border and has a single
+/// neighbor. Nested holes are filled as well. Returns the repairs done.
+pub fn fill_enclosed_regions(labels: &mut Array2<usize>, max_area: usize) -> Vec<HoleFill> {
+    let (height, width) = labels.dim();
+    let mut repairs = Vec::new();
+
+    loop {
+        let mut areas = HashMap::<usize, usize>::new();
+        for label in labels.iter() {
+            *areas.entry(*label).or_insert(0) += 1;
+        }
+
+        let mut on_border = HashSet::new();
+        for ((y, x), label) in labels.indexed_iter() {
+            if y == 0 || x == 0 || y == height - 1 || x == width - 1 {
+                on_border.insert(*label);
+            }
+        }
+
+        let mut neighbors = HashMap::<usize, Vec<usize>>::new();
+        for ((a, b), _) in region_adjacency(labels) {
+            neighbors.entry(a).or_default().push(b);
+            neighbors.entry(b).or_default().push(a);
+        }
+
+        // Sorted by hole, so that the repairs are reported in a stable order
+        let mut fills = BTreeMap::new();
+        for (label, region_neighbors) in &neighbors {
+            let area = areas[label];
+            if region_neighbors.len() == 1 && area <= max_area && !on_border.contains(label) {
+                fills.insert(*label, region_neighbors[0]);
+            }
+        }
+
+        if fills.is_empty() {
+            break;
+        }
+
+        // A container may itself be a hole filled in this pass
+        let resolve = |mut label: usize| {
+            while let Some(container) = fills.get(&label) {
+                label = *container;
+            }
+            label
+        };
+
+        for (hole, container) in &fills {
+            repairs.push(HoleFill {
+                hole: *hole,
+                container: *container,
+                area: areas[hole],
+            });
+        }
+
+        labels.mapv_inplace(resolve);
+    }
+
+    repairs
+}
+
+/// A region of a cut merged into one of its neighbors.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RegionMerge {
+    pub region: usize,
+    pub target: usize,
+    /// Area of the region when merged, in the units of the geotransform
+    pub area: f64,
+}
+
+/// Enforce a minimum mapping unit: merge the regions of a cut with an area lower
+/// than `min_area`, in squared units of the geotransform (e.g. m²), into their
+/// neighbor with the closest mean color, smallest regions first.
+///
+/// The merged regions take the label of the neighbor. A region keeps merging until
+/// it reaches the minimum area or has no neighbor left. Returns the merges done, in
+/// order.
+pub fn merge_small_regions(
+    labels: &mut Array2<usize>,
+    img: &Array3<u8>,
+    min_area: f64,
+    transform: &GeoTransform,
+) -> Vec<RegionMerge> {
+    let channels = img.dim().2;
+    let min_pixels = min_area / transform.pixel_area();
+
+    // Pixel count and sum of each band
+    let mut stats = HashMap::<usize, (usize, Vec<f64>)>::new();
+    for ((y, x), label) in labels.indexed_iter() {
+        let (count, sums) = stats
+            .entry(*label)
+            .or_insert_with(|| (0, vec![0.; channels]));
+        *count += 1;
+        for (c, sum) in sums.iter_mut().enumerate() {
+            *sum += f64::from(img[[y, x, c]]);
+        }
+    }
+
+    let mut neighbors = HashMap::<usize, HashSet<usize>>::new();
+    for ((a, b), _) in region_adjacency(labels) {
+        neighbors.entry(a).or_default().insert(b);
+        neighbors.entry(b).or_default().insert(a);
+    }
+
+    let mut queue = stats
+        .iter()
+        .filter(|(_, (count, _))| (*count as f64) < min_pixels)
+        .map(|(label, (count, _))| Reverse((*count, *label)))
+        .collect::<BinaryHeap<_>>();
+
+    let mut targets = HashMap::new();
+    let mut merges = Vec::new();
+    while let Some(Reverse((count, region))) = queue.pop() {
+        // Outdated entry of a merged or grown region
+        if targets.contains_key(&region) || stats[&region].0 != count {
+            continue;
+        }
+
+        let mean = |label: &usize| {
+            let (count, sums) = &stats[label];
+            sums.iter()
+                .map(|sum| sum / *count as f64)
+                .collect::<Vec<_>>()
+        };
+        let region_mean = mean(&region);
+        let distance = |label: &usize| {
+            mean(label)
+                .iter()
+                .zip(&region_mean)
+                .map(|(a, b)| (a - b).powi(2))
+                .sum::<f64>()
+        };
+        let region_neighbors = neighbors.remove(&region).unwrap_or_default();
+        let target = match region_neighbors
+            .iter()
+            .min_by(|a, b| distance(a).total_cmp(&distance(b)).then(a.cmp(b)))
+        {
+            Some(target) => *target,
+            None => continue,
+        };
+
+        let (_, sums) = stats.remove(&region).unwrap();
+        let (target_count, target_sums) = stats.get_mut(&target).unwrap();
+        *target_count += count;
+        for (target_sum, sum) in target_sums.iter_mut().zip(sums) {
+            *target_sum += sum;
+        }
+        let target_count = *target_count;
+
+        for neighbor in region_neighbors {
+            let others = neighbors.get_mut(&neighbor).unwrap();
+            others.remove(&region);
+            if neighbor != target {
+                others.insert(target);
+                neighbors.get_mut(&target).unwrap().insert(neighbor);
+            }
+        }
+
+        if (target_count as f64) < min_pixels {
+            queue.push(Reverse((target_count, target)));
+        }
+        targets.insert(region, target);
+        merges.push(RegionMerge {
+            region,
+            target,
+            area: count as f64 * transform.pixel_area(),
+        });
+    }
+
+    // A target may itself have been merged afterwards
+    labels.mapv_inplace(|mut label| {
+        while let Some(target) = targets.get(&label) {
+            label = *target;
+        }
+        label
+    });
+
+    merges
+}
+
+/// Point of a region far from its border, where a label can be placed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RegionAnchor {
+    pub region: usize,
+    pub x: usize,
+    pub y: usize,
+    /// Approximate distance from the anchor to the border of the region, in pixels
+    pub radius: f64,
+}
+
+/// Approximate the pole of inaccessibility of each region: the center of the
+/// largest disc inscribed in the region, found with a chamfer distance transform.
+/// The image border counts as a region border.
+///
+/// Anchors are sorted by region.
+pub fn region_label_anchors(labels: &Array2<usize>) -> Vec<RegionAnchor> {
+    let (height, width) = labels.dim();
+
+    // Pixels on the border of their region are at distance 1 from the outside
+    let mut distances = Array2::from_shape_fn((height, width), |(y, x)| {
+        let label = labels[[y, x]];
+        let on_border = y == 0
+            || x == 0
+            || y == height - 1
+            || x == width - 1
+            || labels[[y - 1, x]] != label
+            || labels[[y + 1, x]] != label
+            || labels[[y, x - 1]] != label
+            || labels[[y, x + 1]] != label;
+
+        if on_border {
+            1.
+        } else {
+            f64::INFINITY
+        }
+    });
+
+    let diagonal = std::f64::consts::SQRT_2;
+    let forward = [
+        (-1, -1, diagonal),
+        (-1, 0, 1.),
+        (-1, 1, diagonal),
+        (0, -1, 1.),
+    ];
+    let backward = [(1, 1, diagonal), (1, 0, 1.), (1, -1, diagonal), (0, 1, 1.)];
+
+    let mut relax = |y: usize, x: usize, mask: &[(i64, i64, f64)]| {
+        for &(dy, dx, cost) in mask {
+            let ny = y as i64 + dy;
+            let nx = x as i64 + dx;
+            if ny < 0 || nx < 0 || ny >= height as i64 || nx >= width as i64 {
+                continue;
+            }
+
+            let candidate = distances[[ny as usize, nx as usize]] + cost;
+            if candidate < distances[[y, x]] {
+                distances[[y, x]] = candidate;
+            }
+        }
+    };
+
+    for y in 0..height {
+        for x in 0..width {
+            relax(y, x, &forward);
+        }
+    }
+    for y in (0..height).rev() {
+        for x in (0..width).rev() {
+            relax(y, x, &backward);
+        }
+    }
+
+    let mut anchors = BTreeMap::<usize, RegionAnchor>::new();
+    for ((y, x), &label) in labels.indexed_iter() {
+        let radius = distances[[y, x]];
+        let anchor = anchors.entry(label).or_insert(RegionAnchor {
+            region: label,
+            x,
+            y,
+            radius,
+        });
+
+        if radius > anchor.radius {
+            *anchor = RegionAnchor {
+                region: label,
+                x,
+                y,
+                radius,
+            };
+        }
+    }
+
+    anchors.into_values().collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_region_adjacency() {
+        // 0 0 1
+        // 0 0 1
+        // 2 2 2
+        let labels = Array2::from_shape_vec((3, 3), vec![0, 0, 1, 0, 0, 1, 2, 2, 2]).unwrap();
+
+        let adjacency = region_adjacency(&labels);
+
+        assert_eq!(adjacency, vec![((0, 1), 2), ((0, 2), 2), ((1, 2), 1)]);
+    }
+
+    #[test]
+    fn test_merge_small_regions() {
+        // 0 0 1 2
+        // 0 0 1 2
+        // 3 3 3 3
+        #[rustfmt::skip]
+        let mut labels = Array2::from_shape_vec((3, 4), vec![
+            0, 0, 1, 2,
+            0, 0, 1, 2,
+            3, 3, 3, 3,
+        ])
+        .unwrap();
+        // Region 1 is dark like region 0, region 2 bright like region 3
+        let img = Array3::from_shape_fn((3, 4, 1), |(y, x, _)| match labels[[y, x]] {
+            0 => 10,
+            1 => 20,
+            2 => 240,
+            _ => 250,
+        });
+        // Pixels of 10m x 5m
+        let transform = GeoTransform::new(0., 0., 10., 5., Some(2154));
+
+        // Regions smaller than 3 pixels
+        let merges = merge_small_regions(&mut labels, &img, 150., &transform);
+
+        assert_eq!(
+            merges,
+            vec![
+                RegionMerge {
+                    region: 1,
+                    target: 0,
+                    area: 100.
+                },
+                RegionMerge {
+                    region: 2,
+                    target: 3,
+                    area: 100.
+                },
+            ]
+        );
+        assert_eq!(labels.column(2).to_vec(), vec![0, 0, 3]);
+        assert_eq!(labels.column(3).to_vec(), vec![3, 3, 3]);
+    }
+
+    #[test]
+    fn test_fill_enclosed_regions() {
+        // 0 0 0 0 0 3
+        // 0 1 1 1 0 3
+        // 0 1 2 1 0 3
+        // 0 1 1 1 0 3
+        // 0 0 0 0 0 3
+        #[rustfmt::skip]
+        let labels = Array2::from_shape_vec((5, 6), vec![
+            0, 0, 0, 0, 0, 3,
+            0, 1, 1, 1, 0, 3,
+            0, 1, 2, 1, 0, 3,
+            0, 1, 1, 1, 0, 3,
+            0, 0, 0, 0, 0, 3,
+        ])
+        .unwrap();
+
+        let mut small_holes = labels.clone();
+        let repairs = fill_enclosed_regions(&mut small_holes, 3);
+        assert_eq!(
+            repairs,
+            vec![HoleFill {
+                hole: 2,
+                container: 1,
+                area: 1
+            }]
+        );
+        assert_eq!(small_holes[[2, 2]], 1);
+
+        let mut all_holes = labels;
+        let repairs = fill_enclosed_regions(&mut all_holes, 9);
+        assert_eq!(
+            repairs,
+            vec![
+                HoleFill {
+                    hole: 2,
+                    container: 1,
+                    area: 1
+                },
+                HoleFill {
+                    hole: 1,
+                    container: 0,
+                    area: 9
+                }
+            ]
+        );
+        assert!(all_holes.iter().all(|l| *l == 0 || *l == 3));
+
+        // Holes filled in the same pass are reported by label
+        let mut labels = Array2::zeros((5, 7));
+        labels[[1, 1]] = 6;
+        labels[[3, 5]] = 4;
+        let repairs = fill_enclosed_regions(&mut labels, 1);
+        let holes = repairs.iter().map(|fill| fill.hole).collect::<Vec<_>>();
+        assert_eq!(holes, vec![4, 6]);
+        assert!(labels.iter().all(|l| *l == 0));
+    }
+
+    #[test]
+    fn test_region_label_anchors() {
+        // A 5x5 square region next to a thin vertical region
+        let labels = Array2::from_shape_fn((5, 6), |(_, x)| usize::from(x == 5));
+
+        let anchors = region_label_anchors(&labels);
+
+        assert_eq!(anchors.len(), 2);
+        assert_eq!((anchors[0].x, anchors[0].y), (2, 2));
+        assert_eq!(anchors[0].radius, 3.);
+        assert_eq!(anchors[1].x, 5);
+        assert_eq!(anchors[1].radius, 1.);
+    }
+
+    #[test]
+    fn test_cut_by_merge_count() {
+        // 0 0 1
+        // 0 0 1
+        // 2 2 2
+        let labels = Array2::from_shape_vec((3, 3), vec![0, 0, 1, 0, 0, 1, 2, 2, 2]).unwrap();
+        let parents = vec![3, 3, 4, 4, 4];
+
+        assert_eq!(leaf_count(&parents), 3);
+        assert_eq!(cut_by_merge_count(&labels, &parents, 0), labels);
+        assert_eq!(
+            cut_by_merge_count(&labels, &parents, 1),
+            labels.mapv(|l| if l == 2 { 2 } else { 3 })
+        );
+        assert!(cut_by_merge_count(&labels, &parents, 2)
+            .iter()
+            .all(|l| *l == 4));
+    }
+
+    #[test]
+    fn test_stable_scales() {
+        // 4 leaves merged at levels 1, 2 and 100
+        let parents = vec![4, 4, 5, 6, 5, 6, 6];
+        let levels = vec![0., 0., 0., 0., 1., 2., 100.];
+
+        let candidates = stable_scales(&parents, &levels, 5);
+
+        assert_eq!(candidates.len(), 2);
+        assert_eq!(candidates[0].region_count, 2);
+        assert!(candidates[0].level > 2. && candidates[0].level < 100.);
+        assert_eq!(candidates[1].region_count, 3);
+        assert!(candidates[0].lifetime > candidates[1].lifetime);
+    }
+}
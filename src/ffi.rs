@@ -0,0 +1,130 @@
+//! C bindings, declared in `include/hierarchy_labellisation.h`.
+//!
+//! Functions never unwind into C: a panic is reported as a null pointer or a
+//! negative status.
+
+use std::{panic::catch_unwind, ptr, slice};
+
+use crate::{cut_node_labels, hierarchical_segmentation, utils::array_from_planar, Hierarchy};
+
+/// Build the hierarchy of an image given as `width * height * channels` bytes in
+/// planar order, segmented into `n_clusters` superpixels. Returns null on failure,
+/// e.g. if the byte count overflows.
+/// The hierarchy must be released with [`hl_hierarchy_free`].
+///
+/// # Safety
+///
+/// `data` must point to `width * height * channels` readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn hl_hierarchy_build(
+    data: *const u8,
+    width: usize,
+    height: usize,
+    channels: usize,
+    n_clusters: usize,
+) -> *mut Hierarchy {
+    let len = match width
+        .checked_mul(height)
+        .and_then(|pixels| pixels.checked_mul(channels))
+    {
+        Some(len) if !data.is_null() && len <= isize::MAX as usize => len,
+        _ => return ptr::null_mut(),
+    };
+    let data = slice::from_raw_parts(data, len);
+
+    catch_unwind(|| {
+        let array = array_from_planar(data, width, height, channels);
+        let (labels, tree) = hierarchical_segmentation(array, n_clusters);
+
+        Box::into_raw(Box::new(Hierarchy::new(&labels, tree)))
+    })
+    .unwrap_or(ptr::null_mut())
+}
+
+/// Number of nodes of the hierarchy, leaves included.
+///
+/// # Safety
+///
+/// `hierarchy` must come from [`hl_hierarchy_build`] and not be freed.
+#[no_mangle]
+pub unsafe extern "C" fn hl_hierarchy_node_count(hierarchy: *const Hierarchy) -> usize {
+    hierarchy
+        .as_ref()
+        .map_or(0, |hierarchy| hierarchy.parents.len())
+}
+
+/// Write the labels of the `len` pixels in the cut of the hierarchy at `level`,
+/// each region being labelled by its tree node. Returns 0, or -1 if `len` is not
+/// the pixel count of the image.
+///
+/// # Safety
+///
+/// `hierarchy` must come from [`hl_hierarchy_build`] and not be freed, and `labels`
+/// must point to `len` writable values.
+#[no_mangle]
+pub unsafe extern "C" fn hl_hierarchy_cut(
+    hierarchy: *const Hierarchy,
+    level: f64,
+    labels: *mut usize,
+    len: usize,
+) -> i32 {
+    let hierarchy = match hierarchy.as_ref() {
+        Some(hierarchy) if !labels.is_null() && len == hierarchy.labels.len() => hierarchy,
+        _ => return -1,
+    };
+    let labels = slice::from_raw_parts_mut(labels, len);
+
+    let node_labels = cut_node_labels(&hierarchy.parents, &hierarchy.levels, level);
+    for (label, leaf) in labels.iter_mut().zip(&hierarchy.labels) {
+        *label = node_labels[*leaf];
+    }
+
+    0
+}
+
+/// Release a hierarchy. Does nothing on null.
+///
+/// # Safety
+///
+/// `hierarchy` must come from [`hl_hierarchy_build`] and not be freed already.
+#[no_mangle]
+pub unsafe extern "C" fn hl_hierarchy_free(hierarchy: *mut Hierarchy) {
+    if !hierarchy.is_null() {
+        drop(Box::from_raw(hierarchy));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ndarray::array;
+
+    use super::*;
+    use crate::PartitionTree;
+
+    #[test]
+    fn test_hierarchy_cut() {
+        // Node 3 is created at level 1 from leaves 0 and 1, node 4 at level 2 from node 3
+        // and leaf 2
+        let tree = PartitionTree {
+            parents: vec![3, 3, 4, 4, 4],
+            levels: vec![0., 0., 0., 1., 2.],
+            merges: Vec::new(),
+        };
+        let hierarchy = Box::into_raw(Box::new(Hierarchy::new(&array![[0, 1, 2]], tree)));
+
+        unsafe {
+            assert_eq!(hl_hierarchy_node_count(hierarchy), 5);
+
+            let mut labels = [0; 3];
+            assert_eq!(hl_hierarchy_cut(hierarchy, 0.5, labels.as_mut_ptr(), 3), 0);
+            assert_eq!(labels, [3, 3, 4]);
+            assert_eq!(hl_hierarchy_cut(hierarchy, 0.5, labels.as_mut_ptr(), 2), -1);
+
+            hl_hierarchy_free(hierarchy);
+
+            let data = [0u8; 4];
+            let overflowing = hl_hierarchy_build(data.as_ptr(), usize::MAX, 2, 1, 1);
+            assert!(overflowing.is_null());
+        }
+    }
+}
@@ -0,0 +1,175 @@
+use ndarray::Array3;
+use xxhash_rust::xxh3::Xxh3;
+
+use crate::params::SegmentationParams;
+
+/// Values hashed per update, to avoid hashing them one by one.
+const CHUNK: usize = 512;
+
+/// Fingerprint of a hierarchy, from its leaf labels and its tree. Stable across
+/// targets and versions, so it can be stored in files.
+pub fn hierarchy_fingerprint(labels: &[usize], parents: &[usize], levels: &[f64]) -> u64 {
+    let mut hasher = Xxh3::new();
+    hash_values(&mut hasher, labels.iter().map(|label| *label as u64));
+    hash_values(&mut hasher, parents.iter().map(|parent| *parent as u64));
+    hash_values(&mut hasher, levels.iter().map(|level| level.to_bits()));
+
+    hasher.digest()
+}
+
+/// Fingerprint of the inputs of a segmentation, the image and the parameters, to
+/// check that a cached hierarchy belongs to an image. Never 0, which marks
+/// hierarchies of unknown inputs.
+pub fn input_fingerprint(image: &Array3<u8>, params: &SegmentationParams) -> u64 {
+    params_fingerprint(image_fingerprint(image), params)
+}
+
+/// Input fingerprint extended with the auxiliary input of a builder, e.g. a
+/// boundary map or a barrier raster, as little-endian bytes. Never 0.
+pub fn auxiliary_fingerprint(input_fingerprint: u64, auxiliary: &[u8]) -> u64 {
+    let mut hasher = Xxh3::new();
+    hash_values(&mut hasher, [input_fingerprint].into_iter());
+    hash_values(&mut hasher, [auxiliary.len() as u64].into_iter());
+    hasher.update(auxiliary);
+
+    hasher.digest().max(1)
+}
+
+/// Fingerprint of an image, its dimensions and pixels.
+pub(crate) fn image_fingerprint(image: &Array3<u8>) -> u64 {
+    let mut hasher = Xxh3::new();
+    let (height, width, channels) = image.dim();
+    hash_values(
+        &mut hasher,
+        [height, width, channels].into_iter().map(|dim| dim as u64),
+    );
+    match image.as_slice() {
+        Some(pixels) => hasher.update(pixels),
+        None => hasher.update(&image.iter().copied().collect::<Vec<_>>()),
+    }
+
+    hasher.digest()
+}
+
+/// Input fingerprint from the fingerprint of the image, see [`input_fingerprint`].
+pub(crate) fn params_fingerprint(image_fingerprint: u64, params: &SegmentationParams) -> u64 {
+    // Destructured so that a new parameter does not go unhashed
+    let SegmentationParams {
+        n_clusters,
+        compactness,
+        slic_iterations,
+        slic_threshold,
+        seed_grid,
+        adherence_sweeps,
+        fidelity,
+        elevation_channel,
+        nir_channel,
+        color_weight,
+        height_weight,
+        shape_weight,
+        normalization,
+        boundary_weight,
+        shadow_weight,
+        normalize_by_boundary,
+        prune_tolerance,
+        fixed_point,
+        fine_area,
+        pixel_aspect,
+        wrap,
+    } = *params;
+
+    let mut hasher = Xxh3::new();
+    hash_values(
+        &mut hasher,
+        [
+            image_fingerprint,
+            n_clusters as u64,
+            compactness as u64,
+            slic_iterations as u64,
+            slic_threshold.is_some() as u64,
+            slic_threshold.unwrap_or_default().to_bits(),
+            seed_grid as u64,
+            adherence_sweeps as u64,
+            fidelity as u64,
+            elevation_channel.is_some() as u64,
+            elevation_channel.unwrap_or_default() as u64,
+            nir_channel.is_some() as u64,
+            nir_channel.unwrap_or_default() as u64,
+            color_weight.to_bits(),
+            height_weight.to_bits(),
+            shape_weight.to_bits(),
+            normalization as u64,
+            boundary_weight.to_bits(),
+            shadow_weight.to_bits(),
+            normalize_by_boundary as u64,
+            prune_tolerance.to_bits(),
+            fixed_point as u64,
+            fine_area as u64,
+            pixel_aspect.to_bits(),
+            wrap as u64,
+        ]
+        .into_iter(),
+    );
+
+    hasher.digest().max(1)
+}
+
+/// Hash values as little-endian bytes, after their count so that consecutive
+/// sequences cannot be confused.
+fn hash_values(hasher: &mut Xxh3, values: impl ExactSizeIterator<Item = u64>) {
+    hasher.update(&(values.len() as u64).to_le_bytes());
+
+    let mut bytes = Vec::with_capacity(CHUNK * 8);
+    for value in values {
+        bytes.extend(value.to_le_bytes());
+        if bytes.len() == bytes.capacity() {
+            hasher.update(&bytes);
+            bytes.clear();
+        }
+    }
+    hasher.update(&bytes);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hierarchy_fingerprint() {
+        let fingerprint = hierarchy_fingerprint(&[0, 1], &[2, 2, 2], &[0., 0., 1.]);
+
+        assert_eq!(
+            fingerprint,
+            hierarchy_fingerprint(&[0, 1], &[2, 2, 2], &[0., 0., 1.])
+        );
+        assert_ne!(
+            fingerprint,
+            hierarchy_fingerprint(&[0, 1], &[2, 2, 2], &[0., 0., 2.])
+        );
+        // Moving a value from one sequence to the next changes the fingerprint
+        assert_ne!(
+            fingerprint,
+            hierarchy_fingerprint(&[0, 1, 2], &[2, 2], &[0., 0., 1.])
+        );
+    }
+
+    #[test]
+    fn test_input_fingerprint() {
+        let image = Array3::from_shape_fn((4, 6, 3), |(y, x, c)| (y * 18 + x * 3 + c) as u8);
+        let params = SegmentationParams::new(4);
+        let fingerprint = input_fingerprint(&image, &params);
+
+        assert_eq!(fingerprint, input_fingerprint(&image.clone(), &params));
+        // Same pixels in another shape
+        let reshaped = image.clone().into_shape((6, 4, 3)).unwrap();
+        assert_ne!(fingerprint, input_fingerprint(&reshaped, &params));
+        let mut changed = params;
+        changed.slic_threshold = Some(0.);
+        assert_ne!(fingerprint, input_fingerprint(&image, &changed));
+
+        let with_raster = auxiliary_fingerprint(fingerprint, &[0, 1, 2]);
+        assert_ne!(with_raster, fingerprint);
+        assert_eq!(with_raster, auxiliary_fingerprint(fingerprint, &[0, 1, 2]));
+        assert_ne!(with_raster, auxiliary_fingerprint(fingerprint, &[0, 1, 3]));
+    }
+}
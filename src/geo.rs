@@ -0,0 +1,204 @@
+use std::io::{Cursor, Read, Seek};
+
+use tiff::{decoder::Decoder, tags::Tag};
+#[cfg(feature = "wasm")]
+use wasm_bindgen::prelude::wasm_bindgen;
+
+use crate::render::Viewport;
+
+// GeoTIFF keys
+const RASTER_TYPE_KEY: u16 = 1025;
+const GEOGRAPHIC_TYPE_KEY: u16 = 2048;
+const PROJECTED_CS_TYPE_KEY: u16 = 3072;
+const RASTER_PIXEL_IS_POINT: u16 = 2;
+const USER_DEFINED: u16 = 32767;
+
+/// Georeferencing of an image: the coordinates of the top-left corner of its
+/// top-left pixel, and the size of its pixels, in the units of its coordinate
+/// reference system. The Y coordinate decreases with the rows (north-up image).
+///
+/// The default transform maps pixels to themselves, with the Y axis pointing up.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GeoTransform {
+    pub origin_x: f64,
+    pub origin_y: f64,
+    pub pixel_width: f64,
+    pub pixel_height: f64,
+    /// EPSG code of the coordinate reference system, if known
+    pub crs_epsg: Option<u32>,
+}
+
+impl Default for GeoTransform {
+    fn default() -> Self {
+        Self::new(0., 0., 1., 1., None)
+    }
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+impl GeoTransform {
+    #[cfg_attr(feature = "wasm", wasm_bindgen(constructor))]
+    pub fn new(
+        origin_x: f64,
+        origin_y: f64,
+        pixel_width: f64,
+        pixel_height: f64,
+        crs_epsg: Option<u32>,
+    ) -> Self {
+        Self {
+            origin_x,
+            origin_y,
+            pixel_width,
+            pixel_height,
+            crs_epsg,
+        }
+    }
+
+    /// Height of the pixels over their width, see
+    /// [`SegmentationParams::pixel_aspect`](crate::SegmentationParams::pixel_aspect).
+    pub fn pixel_aspect(&self) -> f64 {
+        (self.pixel_height / self.pixel_width).abs()
+    }
+
+    /// Parse a world file (e.g. `.tfw`, `.pgw`, `.jgw`). Returns `None` if it is
+    /// malformed or has rotation terms.
+    pub fn from_world_file(text: &str, crs_epsg: Option<u32>) -> Option<GeoTransform> {
+        let values = text
+            .split_whitespace()
+            .map(|value| value.parse::<f64>().ok())
+            .collect::<Option<Vec<_>>>()?;
+        let [a, d, b, e, c, f] = <[f64; 6]>::try_from(values).ok()?;
+        if d != 0. || b != 0. {
+            return None;
+        }
+
+        // World files give the center of the top-left pixel
+        Some(Self::new(c - a / 2., f - e / 2., a, -e, crs_epsg))
+    }
+
+    /// Content of the world file of a raster export georeferenced by this transform.
+    pub fn to_world_file(&self) -> String {
+        let (x, y) = self.apply(0.5, 0.5);
+        format!(
+            "{}\n0\n0\n{}\n{x}\n{y}\n",
+            self.pixel_width, -self.pixel_height
+        )
+    }
+
+    /// Transform of a window rendered with
+    /// [`render_cut_window`](crate::render_cut_window).
+    pub fn for_viewport(&self, viewport: &Viewport) -> GeoTransform {
+        let (origin_x, origin_y) = self.apply(viewport.x, viewport.y);
+
+        Self::new(
+            origin_x,
+            origin_y,
+            self.pixel_width / viewport.scale,
+            self.pixel_height / viewport.scale,
+            self.crs_epsg,
+        )
+    }
+}
+
+impl GeoTransform {
+    /// Coordinates of a point given in pixels, `(0, 0)` being the top-left corner
+    /// of the image.
+    pub fn apply(&self, x: f64, y: f64) -> (f64, f64) {
+        (
+            self.origin_x + x * self.pixel_width,
+            self.origin_y - y * self.pixel_height,
+        )
+    }
+
+    /// Position in pixels of a point given by its coordinates, the inverse of
+    /// [`apply`](Self::apply).
+    pub fn invert(&self, x: f64, y: f64) -> (f64, f64) {
+        (
+            (x - self.origin_x) / self.pixel_width,
+            (self.origin_y - y) / self.pixel_height,
+        )
+    }
+
+    /// Area of a pixel, in squared units of the coordinate reference system.
+    pub fn pixel_area(&self) -> f64 {
+        (self.pixel_width * self.pixel_height).abs()
+    }
+
+    /// Read the georeferencing of a GeoTIFF. Returns `None` for other images, and
+    /// for GeoTIFFs that are not north-up.
+    pub fn from_geotiff(bytes: &[u8]) -> Option<Self> {
+        let mut decoder = Decoder::new(Cursor::new(bytes)).ok()?;
+        Self::from_tiff_decoder(&mut decoder)
+    }
+
+    /// Read the georeferencing of the current image of a TIFF decoder.
+    pub(crate) fn from_tiff_decoder<R: Read + Seek>(decoder: &mut Decoder<R>) -> Option<Self> {
+        let scale = decoder.get_tag_f64_vec(Tag::ModelPixelScaleTag).ok()?;
+        let tiepoint = decoder.get_tag_f64_vec(Tag::ModelTiepointTag).ok()?;
+        if scale.len() < 2 || tiepoint.len() < 6 {
+            return None;
+        }
+
+        let keys = decoder
+            .get_tag_u16_vec(Tag::GeoKeyDirectoryTag)
+            .unwrap_or_default();
+        // Entries of 4 values after the header, with their value inline when located in no tag
+        let key = |id: u16| {
+            keys.chunks_exact(4)
+                .skip(1)
+                .find(|entry| entry[0] == id && entry[1] == 0)
+                .map(|entry| entry[3])
+        };
+
+        let (mut origin_x, mut origin_y) = (
+            tiepoint[3] - tiepoint[0] * scale[0],
+            tiepoint[4] + tiepoint[1] * scale[1],
+        );
+        // The tie point is the center of the pixel instead of its corner
+        if key(RASTER_TYPE_KEY) == Some(RASTER_PIXEL_IS_POINT) {
+            origin_x -= scale[0] / 2.;
+            origin_y += scale[1] / 2.;
+        }
+
+        let crs_epsg = key(PROJECTED_CS_TYPE_KEY)
+            .or_else(|| key(GEOGRAPHIC_TYPE_KEY))
+            .filter(|code| *code != USER_DEFINED)
+            .map(u32::from);
+
+        Some(Self::new(origin_x, origin_y, scale[0], scale[1], crs_epsg))
+    }
+
+    /// Affine transform in the GDAL order `[x0, dx, rx, y0, ry, dy]`.
+    pub fn to_gdal(&self) -> [f64; 6] {
+        [
+            self.origin_x,
+            self.pixel_width,
+            0.,
+            self.origin_y,
+            0.,
+            -self.pixel_height,
+        ]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_world_file() {
+        // Lambert-93 ortho-image with 50cm pixels
+        let text = "0.5\n0\n0\n-0.5\n650000.25\n6860000.75\n";
+
+        let transform = GeoTransform::from_world_file(text, Some(2154)).unwrap();
+
+        assert_eq!(transform.apply(0., 0.), (650000., 6860001.));
+        assert_eq!(transform.to_world_file(), text);
+        assert!(GeoTransform::from_world_file("1 2 3", None).is_none());
+
+        let viewport = Viewport::new(10., 20., 100., 100., 2.);
+        let window = transform.for_viewport(&viewport);
+        assert_eq!(window.apply(0., 0.), transform.apply(10., 20.));
+        assert_eq!(window.pixel_width, 0.25);
+    }
+}
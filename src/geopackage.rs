@@ -0,0 +1,340 @@
+use ndarray::Array2;
+
+use crate::{
+    attributes::NodeAttributes,
+    geo::GeoTransform,
+    smooth::{smooth_outlines, Smoothing},
+    sqlite::{Database, Value},
+    vector::region_measures,
+    Hierarchy,
+};
+
+// "GPKG" application id and user version of GeoPackage 1.2
+const APPLICATION_ID: u32 = 0x4750_4b47;
+const USER_VERSION: u32 = 10200;
+
+const WGS84_WKT: &str = r#"GEOGCS["WGS 84",DATUM["WGS_1984",SPHEROID["WGS 84",6378137,298.257223563,AUTHORITY["EPSG","7030"]],AUTHORITY["EPSG","6326"]],PRIMEM["Greenwich",0,AUTHORITY["EPSG","8901"]],UNIT["degree",0.0174532925199433,AUTHORITY["EPSG","9122"]],AUTHORITY["EPSG","4326"]]"#;
+
+/// Write a cut as a GeoPackage, with a `regions` feature table holding one polygon
+/// per region with the tree `node`, area and perimeter of the region, and a `nodes`
+/// attribute table with the parent, level, area and lifetime of every node of the
+/// hierarchy. Areas and perimeters are in the units of the geotransform.
+///
+/// Pixel corners are mapped to coordinates with the geotransform, in its coordinate
+/// reference system or in undefined cartesian coordinates if its EPSG code is
+/// unknown. `last_change` is an ISO 8601 timestamp.
+pub fn cut_to_geopackage(
+    labels: &Array2<usize>,
+    hierarchy: &Hierarchy,
+    transform: &GeoTransform,
+    smoothing: Smoothing,
+    last_change: &str,
+) -> Vec<u8> {
+    let project = |(x, y): (f64, f64)| transform.apply(x, y);
+    let srs_id = transform.crs_epsg.map_or(-1, |code| code as i32);
+
+    let mut bbox = [
+        f64::INFINITY,
+        f64::INFINITY,
+        f64::NEG_INFINITY,
+        f64::NEG_INFINITY,
+    ];
+    let mut regions = Vec::new();
+    let outlines = smooth_outlines(labels, smoothing);
+    let measures = region_measures(labels, transform);
+    for (i, ((label, rings), (_, measures))) in outlines.into_iter().zip(measures).enumerate() {
+        let rings = rings
+            .iter()
+            .map(|ring| {
+                let mut points = ring
+                    .iter()
+                    .map(|vertex| project(*vertex))
+                    .collect::<Vec<_>>();
+                points.push(points[0]);
+                points
+            })
+            .collect::<Vec<_>>();
+        let geometry = geometry_blob(&rings, srs_id, &mut bbox);
+
+        let fid = i as i64 + 1;
+        regions.push((
+            fid,
+            vec![
+                Value::Null,
+                Value::Blob(geometry),
+                Value::Integer(label as i64),
+                Value::Real(measures.area),
+                Value::Real(measures.perimeter),
+            ],
+        ));
+    }
+    let region_count = regions.len() as i64;
+
+    let attributes = NodeAttributes::new(
+        &hierarchy.parents,
+        &hierarchy.levels,
+        &hierarchy.leaf_pixels,
+    );
+    let nodes = (0..hierarchy.parents.len())
+        .map(|node| {
+            let values = vec![
+                Value::Null,
+                Value::Integer(node as i64),
+                Value::Integer(hierarchy.parents[node] as i64),
+                Value::Real(hierarchy.levels[node]),
+                Value::Real(attributes.areas[node] as f64 * transform.pixel_area()),
+                Value::Real(attributes.lifetimes[node]),
+            ];
+            (node as i64 + 1, values)
+        })
+        .collect::<Vec<_>>();
+    let node_count = nodes.len() as i64;
+
+    let mut database = Database::new(APPLICATION_ID, USER_VERSION);
+
+    database.create_table(
+        "gpkg_spatial_ref_sys",
+        "CREATE TABLE gpkg_spatial_ref_sys (srs_name TEXT NOT NULL, srs_id INTEGER NOT NULL PRIMARY KEY, organization TEXT NOT NULL, organization_coordsys_id INTEGER NOT NULL, definition TEXT NOT NULL, description TEXT)",
+        &spatial_ref_sys_rows(srs_id),
+    );
+
+    let contents = [
+        ("nodes", "attributes", Value::Null),
+        ("regions", "features", Value::Integer(srs_id.into())),
+    ];
+    let contents_rows = contents
+        .iter()
+        .enumerate()
+        .map(|(i, (name, data_type, srs))| {
+            let extent = if *name == "regions" && region_count > 0 {
+                bbox.map(Value::Real)
+            } else {
+                [Value::Null, Value::Null, Value::Null, Value::Null]
+            };
+            let [min_x, min_y, max_x, max_y] = extent;
+            let values = vec![
+                Value::from(*name),
+                Value::from(*data_type),
+                Value::from(*name),
+                Value::from(""),
+                Value::from(last_change),
+                min_x,
+                min_y,
+                max_x,
+                max_y,
+                srs.clone(),
+            ];
+            (i as i64 + 1, values)
+        })
+        .collect::<Vec<_>>();
+    database.create_table(
+        "gpkg_contents",
+        "CREATE TABLE gpkg_contents (table_name TEXT NOT NULL PRIMARY KEY, data_type TEXT NOT NULL, identifier TEXT UNIQUE, description TEXT DEFAULT '', last_change DATETIME NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%fZ','now')), min_x DOUBLE, min_y DOUBLE, max_x DOUBLE, max_y DOUBLE, srs_id INTEGER, CONSTRAINT fk_gc_r_srs_id FOREIGN KEY (srs_id) REFERENCES gpkg_spatial_ref_sys(srs_id))",
+        &contents_rows,
+    );
+    // Both the table name and the identifier are unique
+    for number in 1..=2 {
+        let entries = contents
+            .iter()
+            .enumerate()
+            .map(|(i, (name, ..))| vec![Value::from(*name), Value::Integer(i as i64 + 1)])
+            .collect();
+        database.create_auto_index("gpkg_contents", number, entries);
+    }
+
+    database.create_table(
+        "gpkg_geometry_columns",
+        "CREATE TABLE gpkg_geometry_columns (table_name TEXT NOT NULL, column_name TEXT NOT NULL, geometry_type_name TEXT NOT NULL, srs_id INTEGER NOT NULL, z TINYINT NOT NULL, m TINYINT NOT NULL, CONSTRAINT pk_geom_cols PRIMARY KEY (table_name, column_name), CONSTRAINT uk_gc_table_name UNIQUE (table_name), CONSTRAINT fk_gc_tn FOREIGN KEY (table_name) REFERENCES gpkg_contents(table_name), CONSTRAINT fk_gc_srs FOREIGN KEY (srs_id) REFERENCES gpkg_spatial_ref_sys (srs_id))",
+        &[(
+            1,
+            vec![
+                Value::from("regions"),
+                Value::from("geom"),
+                Value::from("POLYGON"),
+                Value::Integer(srs_id.into()),
+                Value::Integer(0),
+                Value::Integer(0),
+            ],
+        )],
+    );
+    database.create_auto_index(
+        "gpkg_geometry_columns",
+        1,
+        vec![vec![
+            Value::from("regions"),
+            Value::from("geom"),
+            Value::Integer(1),
+        ]],
+    );
+    database.create_auto_index(
+        "gpkg_geometry_columns",
+        2,
+        vec![vec![Value::from("regions"), Value::Integer(1)]],
+    );
+
+    database.create_table(
+        "regions",
+        "CREATE TABLE \"regions\" (fid INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL, geom POLYGON, node INTEGER, area REAL, perimeter REAL)",
+        &regions,
+    );
+    database.create_table(
+        "sqlite_sequence",
+        "CREATE TABLE sqlite_sequence(name,seq)",
+        &[
+            (
+                1,
+                vec![Value::from("regions"), Value::Integer(region_count)],
+            ),
+            (2, vec![Value::from("nodes"), Value::Integer(node_count)]),
+        ],
+    );
+    database.create_table(
+        "nodes",
+        "CREATE TABLE \"nodes\" (fid INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL, node INTEGER, parent INTEGER, level REAL, area REAL, lifetime REAL)",
+        &nodes,
+    );
+
+    database.into_bytes()
+}
+
+/// Rows of the spatial reference systems table: the ones required by the standard,
+/// and the one of the features.
+fn spatial_ref_sys_rows(srs_id: i32) -> Vec<(i64, Vec<Value>)> {
+    let mut rows = vec![
+        (
+            -1,
+            "Undefined cartesian SRS",
+            "NONE",
+            "undefined",
+            "undefined cartesian coordinate reference system",
+        ),
+        (
+            0,
+            "Undefined geographic SRS",
+            "NONE",
+            "undefined",
+            "undefined geographic coordinate reference system",
+        ),
+        (
+            4326,
+            "WGS 84 geodetic",
+            "EPSG",
+            WGS84_WKT,
+            "longitude/latitude coordinates in decimal degrees on the WGS 84 spheroid",
+        ),
+    ];
+    let srs_name = format!("EPSG:{srs_id}");
+    if ![-1, 0, 4326].contains(&srs_id) {
+        rows.push((srs_id, &srs_name, "EPSG", "undefined", ""));
+    }
+    rows.sort_by_key(|row| row.0);
+
+    rows.into_iter()
+        .map(|(id, name, organization, definition, description)| {
+            let values = vec![
+                Value::from(name),
+                Value::Null,
+                Value::from(organization),
+                Value::Integer(id.into()),
+                Value::from(definition),
+                Value::from(description),
+            ];
+            (id.into(), values)
+        })
+        .collect()
+}
+
+/// GeoPackage binary geometry of a polygon: a header with the envelope of the
+/// polygon, followed by its little-endian WKB. The envelope extends `bbox`,
+/// as `[min_x, min_y, max_x, max_y]`.
+fn geometry_blob(rings: &[Vec<(f64, f64)>], srs_id: i32, bbox: &mut [f64; 4]) -> Vec<u8> {
+    let mut envelope = [
+        f64::INFINITY,
+        f64::NEG_INFINITY,
+        f64::INFINITY,
+        f64::NEG_INFINITY,
+    ];
+    for (x, y) in rings.iter().flatten() {
+        envelope = [
+            envelope[0].min(*x),
+            envelope[1].max(*x),
+            envelope[2].min(*y),
+            envelope[3].max(*y),
+        ];
+    }
+    *bbox = [
+        bbox[0].min(envelope[0]),
+        bbox[1].min(envelope[2]),
+        bbox[2].max(envelope[1]),
+        bbox[3].max(envelope[3]),
+    ];
+
+    // Version 0, little-endian with a XY envelope
+    let mut blob = vec![b'G', b'P', 0, 0b0000_0011];
+    blob.extend(srs_id.to_le_bytes());
+    for value in envelope {
+        blob.extend(value.to_le_bytes());
+    }
+
+    // WKB polygon
+    blob.push(1);
+    blob.extend(3u32.to_le_bytes());
+    blob.extend((rings.len() as u32).to_le_bytes());
+    for ring in rings {
+        blob.extend((ring.len() as u32).to_le_bytes());
+        for (x, y) in ring {
+            blob.extend(x.to_le_bytes());
+            blob.extend(y.to_le_bytes());
+        }
+    }
+
+    blob
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hierarchy::PartitionTree;
+
+    #[test]
+    fn test_cut_to_geopackage() {
+        // Region 1 is a single pixel hole in region 0
+        let mut labels = Array2::zeros((3, 3));
+        labels[[1, 1]] = 1;
+        let tree = PartitionTree {
+            parents: vec![2, 2, 2],
+            levels: vec![0., 0., 1.],
+            merges: vec![(0, 1, 4)],
+        };
+        let hierarchy = Hierarchy::new(&labels, tree);
+
+        let bytes = cut_to_geopackage(
+            &labels,
+            &hierarchy,
+            &GeoTransform::default(),
+            Smoothing::none(),
+            "2024-01-01T00:00:00.000Z",
+        );
+
+        assert_eq!(&bytes[..16], b"SQLite format 3\0");
+        assert_eq!(&bytes[68..72], b"GPKG");
+        assert_eq!(bytes.len() % 4096, 0);
+    }
+
+    #[test]
+    fn test_geometry_blob() {
+        let mut bbox = [
+            f64::INFINITY,
+            f64::INFINITY,
+            f64::NEG_INFINITY,
+            f64::NEG_INFINITY,
+        ];
+        let ring = vec![(0., 0.), (1., 0.), (1., -1.), (0., 0.)];
+
+        let blob = geometry_blob(&[ring], 2154, &mut bbox);
+
+        assert_eq!(&blob[..4], &[b'G', b'P', 0, 3]);
+        assert_eq!(blob.len(), 8 + 32 + 9 + 4 + 4 * 16);
+        assert_eq!(bbox, [0., -1., 1., 0.]);
+    }
+}
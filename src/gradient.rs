@@ -0,0 +1,76 @@
+use ndarray::{Array2, Array3};
+#[cfg(feature = "wasm")]
+use wasm_bindgen::prelude::wasm_bindgen;
+
+/// 3x3 derivative filter used for the gradient.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum GradientOperator {
+    #[default]
+    Sobel,
+    /// More rotation invariant than Sobel
+    Scharr,
+}
+
+impl GradientOperator {
+    /// Smoothing weights across the derivative direction.
+    fn weights(self) -> [f32; 3] {
+        match self {
+            GradientOperator::Sobel => [1., 2., 1.],
+            GradientOperator::Scharr => [3., 10., 3.],
+        }
+    }
+}
+
+/// Gradient magnitude of each pixel of a `(height, width, channels)` image, the
+/// maximum over its bands. Values are in band units per pixel, so that a ramp of
+/// slope 1 has a magnitude of 1, and the borders are replicated.
+pub fn gradient_magnitude<T: Copy + Into<f32>>(
+    img: &Array3<T>,
+    operator: GradientOperator,
+) -> Array2<f32> {
+    let (height, width, channels) = img.dim();
+    let weights = operator.weights();
+    // Central difference over 2 pixels, smoothed by the weights
+    let norm = 2. * weights.iter().sum::<f32>();
+
+    Array2::from_shape_fn((height, width), |(y, x)| {
+        let rows = [y.saturating_sub(1), y, (y + 1).min(height - 1)];
+        let cols = [x.saturating_sub(1), x, (x + 1).min(width - 1)];
+        let value = |y: usize, x: usize, c: usize| -> f32 { img[[y, x, c]].into() };
+
+        (0..channels)
+            .map(|c| {
+                let (mut gx, mut gy) = (0., 0.);
+                for i in 0..3 {
+                    gx += weights[i] * (value(rows[i], cols[2], c) - value(rows[i], cols[0], c));
+                    gy += weights[i] * (value(rows[2], cols[i], c) - value(rows[0], cols[i], c));
+                }
+                (gx * gx + gy * gy).sqrt() / norm
+            })
+            .fold(0., f32::max)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_gradient_magnitude() {
+        // Ramp of slope 2 along x in the second band, flat first band
+        let img = Array3::from_shape_fn((4, 5, 2), |(_, x, c)| (c * x * 2) as u16);
+
+        for operator in [GradientOperator::Sobel, GradientOperator::Scharr] {
+            let gradient = gradient_magnitude(&img, operator);
+            assert_eq!(gradient[[1, 2]], 2.);
+            // One-sided difference on the replicated border
+            assert_eq!(gradient[[1, 0]], 1.);
+        }
+
+        let flat = Array3::<f32>::from_elem((3, 3, 1), 0.5);
+        assert!(gradient_magnitude(&flat, GradientOperator::Sobel)
+            .iter()
+            .all(|g| *g == 0.));
+    }
+}
@@ -1,52 +1,304 @@
+use std::{collections::HashMap, error::Error, fmt, ops::Add, sync::Arc};
+
 use ndarray::{s, Array1, Array2, Array3, Zip};
-use petgraph::{graph::NodeIndex, prelude::UnGraph};
+use petgraph::{
+    graph::{EdgeIndex, IndexType, NodeIndex},
+    prelude::UnGraph,
+    unionfind::UnionFind,
+    visit::EdgeRef,
+};
+#[cfg(feature = "wasm")]
+use wasm_bindgen::prelude::wasm_bindgen;
+
+use crate::{
+    plef::{Plef, PlefPiece},
+    statistics::StatisticsBuffer,
+    utils::Rect,
+};
+
+/// Label of a superpixel, also used for the nodes of the partition trees.
+pub type Label = usize;
+
+/// Integer type of the node and edge indices of the superpixel graph.
+#[cfg(not(feature = "u64-index"))]
+pub type GraphIndex = u32;
+/// Integer type of the node and edge indices of the superpixel graph. Pointer-sized
+/// with the `u64-index` feature, i.e. 64 bits on native targets.
+#[cfg(feature = "u64-index")]
+pub type GraphIndex = usize;
+
+pub type SuperpixelGraph = UnGraph<SuperpixelNode, SuperpixelEdge, GraphIndex>;
+pub type SuperpixelNodeIndex = NodeIndex<GraphIndex>;
+pub type SuperpixelEdgeIndex = EdgeIndex<GraphIndex>;
+
+/// A label too large to be represented by a [`GraphIndex`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LabelOverflow {
+    pub label: Label,
+}
+
+impl fmt::Display for LabelOverflow {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Label {} does not fit in the graph index type, enable the `u64-index` feature",
+            self.label
+        )
+    }
+}
+
+impl Error for LabelOverflow {}
+
+/// Index of the graph node of a label, checking that it fits in [`GraphIndex`].
+pub fn node_index(label: Label) -> Result<SuperpixelNodeIndex, LabelOverflow> {
+    // The maximum value is reserved by petgraph
+    if label >= <GraphIndex as IndexType>::max().index() {
+        return Err(LabelOverflow { label });
+    }
+
+    Ok(NodeIndex::new(label))
+}
+
+/// Model used to approximate the values inside a region when computing its data fidelity.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FidelityModel {
+    /// Constant value per channel (Mumford-Shah piecewise-constant model)
+    #[default]
+    PiecewiseConstant,
+    /// Plane fitted on each channel, better suited to elevation rasters
+    PiecewiseLinear,
+}
+
+/// Edges of the image treated as adjacent, so that regions continue across them
+/// without a border in their perimeter. SLIC superpixels stop at the edges, but
+/// are neighbors of the superpixels on the other side in the graph.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Wrap {
+    #[default]
+    None,
+    /// Left and right edges adjacent, e.g. for 360° panoramas
+    Horizontal,
+    /// Top and bottom edges adjacent
+    Vertical,
+    /// Both pairs of edges adjacent, e.g. for wrap-around mosaics
+    Both,
+}
+
+impl Wrap {
+    pub fn horizontal(self) -> bool {
+        matches!(self, Wrap::Horizontal | Wrap::Both)
+    }
+
+    pub fn vertical(self) -> bool {
+        matches!(self, Wrap::Vertical | Wrap::Both)
+    }
+
+    /// Right and bottom neighbors of a pixel of an image of the given shape, with
+    /// whether the pixel side they share is vertical.
+    pub(crate) fn forward_neighbors(
+        self,
+        (y, x): (usize, usize),
+        (height, width): (usize, usize),
+    ) -> impl Iterator<Item = ((usize, usize), bool)> {
+        let right = match x + 1 {
+            x2 if x2 < width => Some((y, x2)),
+            _ if self.horizontal() => Some((y, 0)),
+            _ => None,
+        };
+        let bottom = match y + 1 {
+            y2 if y2 < height => Some((y2, x)),
+            _ if self.vertical() => Some((0, x)),
+            _ => None,
+        };
 
-use crate::plef::{Plef, PlefPiece};
+        [(right, true), (bottom, false)]
+            .into_iter()
+            .filter_map(|(neighbor, vertical)| Some((neighbor?, vertical)))
+    }
+}
 
-pub type SuperpixelGraph = UnGraph<SuperpixelNode, SuperpixelEdge>;
+/// Data fidelity settings shared by all the regions of a graph.
+#[derive(Debug, Clone)]
+pub struct FidelityParams {
+    pub model: FidelityModel,
+    /// Weight of each channel in the data fidelity
+    pub channel_weights: Array1<f64>,
+    /// Weight λ_shape of the perimeter against the data fidelity: higher values give
+    /// smoother boundaries, lower ones follow the data more closely
+    pub shape_weight: f64,
+    /// Height of the pixels over their width, the length of their vertical sides in
+    /// the perimeter, e.g. for scans sampled at different resolutions along each axis
+    pub pixel_aspect: f64,
+    /// Edges of the image treated as adjacent
+    pub wrap: Wrap,
+}
+
+impl FidelityParams {
+    /// All channels weighted equally.
+    pub fn new(model: FidelityModel, channels: usize) -> Self {
+        Self {
+            model,
+            channel_weights: Array1::ones(channels),
+            shape_weight: 1.,
+            pixel_aspect: 1.,
+            wrap: Wrap::None,
+        }
+    }
+}
+
+/// Spatial moments of a superpixel, needed to fit a plane on its values.
+#[derive(Debug, Clone)]
+pub struct SpatialMoments {
+    pub sum_x: u64,
+    pub sum_y: u64,
+    pub sum_xx: u64,
+    pub sum_xy: u64,
+    pub sum_yy: u64,
+    pub values_x: Array1<u64>, // sum of values weighted by their x coordinate
+    pub values_y: Array1<u64>, // sum of values weighted by their y coordinate
+}
+
+impl Add for &SpatialMoments {
+    type Output = SpatialMoments;
+
+    fn add(self, other: Self) -> SpatialMoments {
+        SpatialMoments {
+            sum_x: self.sum_x + other.sum_x,
+            sum_y: self.sum_y + other.sum_y,
+            sum_xx: self.sum_xx + other.sum_xx,
+            sum_xy: self.sum_xy + other.sum_xy,
+            sum_yy: self.sum_yy + other.sum_yy,
+            values_x: &self.values_x + &other.values_x,
+            values_y: &self.values_y + &other.values_y,
+        }
+    }
+}
 
 #[derive(Debug, Clone)]
 pub struct SuperpixelNode {
-    pub area: u32,      // number of pixels in the superpixel
-    pub perimeter: u32, // perimiter of the superpixel
-    pub values: Array1<u64>,       // sum of values inside the superpixel
-    pub values_sq: Array1<u64>,    // sum of squared values inside the superpixel
-    pub optimal_energy: Plef<f64>, // optimal energy of the superpixel
+    pub area: u32,                     // number of pixels in the superpixel
+    pub perimeter: u32,                // perimiter of the superpixel
+    pub vertical_perimeter: u32,       // part of the perimeter along vertical pixel sides
+    pub values: Array1<u64>,           // sum of values inside the superpixel
+    pub values_sq: Array1<u64>,        // sum of squared values inside the superpixel
+    pub moments: SpatialMoments,       // spatial moments of the superpixel
+    pub fidelity: Arc<FidelityParams>, // data fidelity settings of the superpixel
+    pub optimal_energy: Plef<f64>,     // optimal energy of the superpixel
 }
 
 impl SuperpixelNode {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         area: u32,
         perimeter: u32,
+        vertical_perimeter: u32,
         values: Array1<u64>,
         values_sq: Array1<u64>,
+        moments: SpatialMoments,
+        fidelity: Arc<FidelityParams>,
         optimal_energy: Plef<f64>,
     ) -> Self {
         Self {
             area,
             perimeter,
+            vertical_perimeter,
             values,
             values_sq,
+            moments,
+            fidelity,
             optimal_energy,
         }
     }
 
-    fn init(channels: usize) -> Self {
+    /// Add a pixel to the area, values and moments of the region.
+    fn add_pixel(&mut self, pixel: &Array1<u64>, x: usize, y: usize) {
+        let (x, y) = (x as u64, y as u64);
+        self.area += 1;
+        self.values += pixel;
+        self.values_sq += &pixel.mapv(|v| v * v);
+
+        let moments = &mut self.moments;
+        moments.sum_x += x;
+        moments.sum_y += y;
+        moments.sum_xx += x * x;
+        moments.sum_xy += x * y;
+        moments.sum_yy += y * y;
+        moments.values_x += &pixel.mapv(|v| v * x);
+        moments.values_y += &pixel.mapv(|v| v * y);
+    }
+
+    /// Remove a pixel previously added with [`Self::add_pixel`].
+    fn remove_pixel(&mut self, pixel: &Array1<u64>, x: usize, y: usize) {
+        let (x, y) = (x as u64, y as u64);
+        self.area -= 1;
+        self.values -= pixel;
+        self.values_sq -= &pixel.mapv(|v| v * v);
+
+        let moments = &mut self.moments;
+        moments.sum_x -= x;
+        moments.sum_y -= y;
+        moments.sum_xx -= x * x;
+        moments.sum_xy -= x * y;
+        moments.sum_yy -= y * y;
+        moments.values_x -= &pixel.mapv(|v| v * x);
+        moments.values_y -= &pixel.mapv(|v| v * y);
+    }
+
+    /// Statistics of the region resulting from the fusion of two regions sharing
+    /// the border `edge`. The optimal energy is left empty.
+    pub fn merged(&self, other: &Self, edge: &SuperpixelEdge) -> Self {
         Self::new(
-            0,
-            0,
-            Array1::zeros(channels),
-            Array1::zeros(channels),
+            self.area + other.area,
+            self.perimeter + other.perimeter - 2 * edge.length,
+            self.vertical_perimeter + other.vertical_perimeter - 2 * edge.vertical_length,
+            &self.values + &other.values,
+            &self.values_sq + &other.values_sq,
+            &self.moments + &other.moments,
+            self.fidelity.clone(),
             Plef::init(),
         )
     }
+
+    /// Data fidelity of the region under its fidelity model.
+    pub fn data_fidelity(&self) -> f64 {
+        let weights = &self.fidelity.channel_weights;
+        match self.fidelity.model {
+            FidelityModel::PiecewiseConstant => {
+                data_fidelity(&self.values, &self.values_sq, self.area, weights)
+            }
+            FidelityModel::PiecewiseLinear => plane_fidelity(
+                &self.values,
+                &self.values_sq,
+                &self.moments,
+                self.area,
+                weights,
+            ),
+        }
+    }
+
+    /// Perimeter of the region in pixel widths, the vertical sides of the pixels
+    /// being [`FidelityParams::pixel_aspect`] long.
+    pub fn scaled_perimeter(&self) -> f64 {
+        let vertical = self.vertical_perimeter as f64;
+        self.perimeter as f64 - vertical + self.fidelity.pixel_aspect * vertical
+    }
+
+    /// Slope of the energy of the region alone with the scale, its weighted perimeter.
+    pub fn shape_energy(&self) -> f64 {
+        self.fidelity.shape_weight * self.scaled_perimeter()
+    }
 }
 
 #[derive(PartialEq, Debug, Clone)]
 pub struct SuperpixelEdge {
     pub weight: f64,
     pub length: u32,
-    pub active: bool, // Maybe move into an array in the hierarchy algorithm
+    pub vertical_length: u32, // Part of the length along vertical pixel sides
+    pub boundary: f64,        // Sum of the boundary probability along the border
+    pub shadow: u32,          // Pairs of pixels inside shadows along the border
+    pub active: bool,         // Maybe move into an array in the hierarchy algorithm
 }
 
 impl SuperpixelEdge {
@@ -54,102 +306,247 @@ impl SuperpixelEdge {
         Self {
             weight,
             length,
+            vertical_length: 0,
+            boundary: 0.,
+            shadow: 0,
             active: true,
         }
     }
 
+    /// Mean boundary probability along the border, `0` without a boundary map.
+    pub fn mean_boundary(&self) -> f64 {
+        if self.length == 0 {
+            0.
+        } else {
+            self.boundary / self.length as f64
+        }
+    }
+
+    /// Fraction of the border inside shadows, `0` without a shadow mask.
+    pub fn mean_shadow(&self) -> f64 {
+        if self.length == 0 {
+            0.
+        } else {
+            self.shadow as f64 / self.length as f64
+        }
+    }
+
     fn init() -> Self {
         Self::new(0., 0)
     }
 }
 
-pub fn data_fidelity(values: &Array1<u64>, values_sq: &Array1<u64>, area: u32) -> f64 {
+pub fn data_fidelity(
+    values: &Array1<u64>,
+    values_sq: &Array1<u64>,
+    area: u32,
+    weights: &Array1<f64>,
+) -> f64 {
     Zip::from(values_sq)
         .and(values)
-        .fold(0., |acc, &value_sq, &value| {
-            acc + value_sq as f64 - (value as f64).powi(2) / area as f64
+        .and(weights)
+        .fold(0., |acc, &value_sq, &value, &weight| {
+            acc + weight * (value_sq as f64 - (value as f64).powi(2) / area as f64)
         })
 }
 
-pub fn apparition_scale(source: &SuperpixelNode, target: &SuperpixelNode, edge_length: u32) -> f64 {
-    let mut e = source.optimal_energy.sum(&target.optimal_energy, None);
+/// Residual sum of squares of the least squares fit of a plane `a + b * x + c * y`
+/// on each channel of the region.
+///
+/// Falls back to [`data_fidelity`] when the pixels are collinear, as the plane
+/// is not defined.
+pub fn plane_fidelity(
+    values: &Array1<u64>,
+    values_sq: &Array1<u64>,
+    moments: &SpatialMoments,
+    area: u32,
+    weights: &Array1<f64>,
+) -> f64 {
+    let n = area as f64;
+    let sum_x = moments.sum_x as f64;
+    let sum_y = moments.sum_y as f64;
 
-    let values = &source.values + &target.values;
-    let values_sq = &source.values_sq + &target.values_sq;
-    let a = source.area + target.area;
+    // Centered second order moments of the coordinates
+    let sxx = moments.sum_xx as f64 - sum_x * sum_x / n;
+    let syy = moments.sum_yy as f64 - sum_y * sum_y / n;
+    let sxy = moments.sum_xy as f64 - sum_x * sum_y / n;
 
-    let data_fidelity = data_fidelity(&values, &values_sq, a);
+    let det = sxx * syy - sxy * sxy;
+    if det <= f64::EPSILON * sxx * syy {
+        return data_fidelity(values, values_sq, area, weights);
+    }
+
+    Zip::from(values)
+        .and(values_sq)
+        .and(&moments.values_x)
+        .and(&moments.values_y)
+        .and(weights)
+        .fold(0., |acc, &value, &value_sq, &value_x, &value_y, &weight| {
+            let value = value as f64;
+            let svv = value_sq as f64 - value * value / n;
+            let sxv = value_x as f64 - sum_x * value / n;
+            let syv = value_y as f64 - sum_y * value / n;
+
+            // Variance explained by the slopes of the plane
+            let explained = (syy * sxv * sxv - 2. * sxy * sxv * syv + sxx * syv * syv) / det;
+
+            acc + weight * (svv - explained).max(0.)
+        })
+}
 
-    let merge_perimeter = source.perimeter + target.perimeter - 2 * edge_length;
+pub fn apparition_scale(
+    source: &SuperpixelNode,
+    target: &SuperpixelNode,
+    edge: &SuperpixelEdge,
+) -> f64 {
+    let mut e = source.optimal_energy.sum(&target.optimal_energy, None);
+
+    let merged = source.merged(target, edge);
+    let data_fidelity = merged.data_fidelity();
 
     e.infimum(PlefPiece {
         start_x: 0.0,
         start_y: data_fidelity,
-        slope: merge_perimeter as f64,
+        slope: merged.shape_energy(),
     })
 }
 
-pub fn graph_from_labels(img: &Array3<u8>, labels: &Array2<usize>) -> SuperpixelGraph {
+/// Apparition scale divided by the length of the shared border, so that large
+/// regions with long borders are not merged too late.
+pub fn normalized_apparition_scale(
+    source: &SuperpixelNode,
+    target: &SuperpixelNode,
+    edge: &SuperpixelEdge,
+) -> f64 {
+    let vertical = edge.vertical_length as f64;
+    let length = edge.length as f64 - vertical + source.fidelity.pixel_aspect * vertical;
+    apparition_scale(source, target, edge) / length.max(1.)
+}
+
+/// Ward's criterion: increase of the sum of squared errors caused by merging the two regions.
+pub fn ward_distance(
+    source: &SuperpixelNode,
+    target: &SuperpixelNode,
+    _edge: &SuperpixelEdge,
+) -> f64 {
+    let source_area = source.area as f64;
+    let target_area = target.area as f64;
+
+    let mean_distance = Zip::from(&source.values)
+        .and(&target.values)
+        .and(&source.fidelity.channel_weights)
+        .fold(0., |acc, &source_value, &target_value, &weight| {
+            acc + weight
+                * (source_value as f64 / source_area - target_value as f64 / target_area).powi(2)
+        });
+
+    source_area * target_area / (source_area + target_area) * mean_distance
+}
+
+pub fn graph_from_labels(
+    img: &Array3<u8>,
+    labels: &Array2<usize>,
+) -> Result<SuperpixelGraph, LabelOverflow> {
+    let fidelity = FidelityParams::new(FidelityModel::default(), img.dim().2);
+    graph_from_labels_with_fidelity(img, labels, fidelity)
+}
+
+pub fn graph_from_labels_with_fidelity(
+    img: &Array3<u8>,
+    labels: &Array2<usize>,
+    fidelity: FidelityParams,
+) -> Result<SuperpixelGraph, LabelOverflow> {
+    graph_from_labels_with_buffer(img, labels, fidelity, &mut StatisticsBuffer::default())
+}
+
+/// Build the superpixel graph, accumulating the statistics of the superpixels in a
+/// buffer that can be reused from one image to the next.
+pub fn graph_from_labels_with_buffer(
+    img: &Array3<u8>,
+    labels: &Array2<usize>,
+    fidelity: FidelityParams,
+    statistics: &mut StatisticsBuffer,
+) -> Result<SuperpixelGraph, LabelOverflow> {
     let (height, width, channels) = img.dim();
     let num_vertex = *labels.iter().max().unwrap() + 1;
 
-    let mut graph = SuperpixelGraph::new_undirected();
-    for _ in 0..num_vertex {
-        graph.add_node(SuperpixelNode::init(channels));
-    }
+    // A binary partition tree of the graph has up to `2 * num_vertex - 1` nodes
+    node_index(2 * num_vertex - 2)?;
 
+    let wrap = fidelity.wrap;
+    let fidelity = Arc::new(fidelity);
+
+    // Update superpixel areas and values
+    statistics.reset(num_vertex, channels);
     for ((y, x), label) in labels.indexed_iter() {
-        let i = NodeIndex::from(*label as u32);
+        statistics.add_pixel(*label, img.slice(s![y, x, ..]), x, y);
+    }
+
+    let mut graph = SuperpixelGraph::default();
+    for label in 0..num_vertex {
+        graph.add_node(statistics.node(label, fidelity.clone()));
+    }
 
-        // Update superpixel area and values
-        let node_i = &mut graph[i];
-        node_i.area += 1;
-        let pixel = img.slice(s![y, x, ..]).mapv(u64::from);
-        node_i.values += &pixel;
-        node_i.values_sq += &pixel.mapv(|x| x * x);
+    for ((y, x), label) in labels.indexed_iter() {
+        let i = NodeIndex::new(*label);
 
         // Loop over the neighbors (right and bottom)
-        for (dy, dx) in [(0, 1), (1, 0)].iter() {
-            let y2 = y + dy;
-            let x2 = x + dx;
-
-            if let Some(n_label) = labels.get((y2, x2)) {
-                let j = NodeIndex::from(*n_label as u32);
-                if n_label != label {
-                    // We are on the border of the superpixel
-                    // Update superpixel perimeters
-                    let (node_i, node_j) = graph.index_twice_mut(i, j);
-                    node_i.perimeter += 1;
-                    node_j.perimeter += 1;
-
-                    if !graph.contains_edge(i, j) {
-                        graph.add_edge(i, j, SuperpixelEdge::init());
-                    }
-
-                    // Update superpixel edge length
-                    let edge = graph.find_edge(i, j).unwrap();
-                    let edge = &mut graph[edge];
-                    edge.length += 1;
+        for (neighbor, vertical) in wrap.forward_neighbors((y, x), (height, width)) {
+            let n_label = &labels[neighbor];
+            if n_label != label {
+                let j = NodeIndex::new(*n_label);
+                // We are on the border of the superpixel, along a vertical pixel
+                // side between horizontal neighbors
+                let vertical = u32::from(vertical);
+                // Update superpixel perimeters
+                let (node_i, node_j) = graph.index_twice_mut(i, j);
+                node_i.perimeter += 1;
+                node_j.perimeter += 1;
+                node_i.vertical_perimeter += vertical;
+                node_j.vertical_perimeter += vertical;
+
+                if !graph.contains_edge(i, j) {
+                    graph.add_edge(i, j, SuperpixelEdge::init());
                 }
+
+                // Update superpixel edge length
+                let edge = graph.find_edge(i, j).unwrap();
+                let edge = &mut graph[edge];
+                edge.length += 1;
+                edge.vertical_length += vertical;
             }
         }
     }
 
     // Take into account superpixels that are on the edge of the image
-    for x in 0..width {
-        graph[NodeIndex::from(labels[[0, x]] as u32)].perimeter += 1;
-        graph[NodeIndex::from(labels[[height - 1, x]] as u32)].perimeter += 1;
+    if !wrap.vertical() {
+        for x in 0..width {
+            graph[NodeIndex::new(labels[[0, x]])].perimeter += 1;
+            graph[NodeIndex::new(labels[[height - 1, x]])].perimeter += 1;
+        }
     }
-    for y in 0..height {
-        graph[NodeIndex::from(labels[[y, 0]] as u32)].perimeter += 1;
-        graph[NodeIndex::from(labels[[y, width - 1]] as u32)].perimeter += 1;
+    if !wrap.horizontal() {
+        for y in 0..height {
+            for x in [0, width - 1] {
+                let node = &mut graph[NodeIndex::new(labels[[y, x]])];
+                node.perimeter += 1;
+                node.vertical_perimeter += 1;
+            }
+        }
     }
 
+    init_energies_and_weights(&mut graph);
+
+    Ok(graph)
+}
+
+/// Initialize the optimal energy of the nodes and the weight of the edges.
+fn init_energies_and_weights(graph: &mut SuperpixelGraph) {
     // Initialize optimal energy
     for node in graph.node_weights_mut() {
-        let data_fidelity = data_fidelity(&node.values, &node.values_sq, node.area);
+        let data_fidelity = node.data_fidelity();
 
-        let plef = Plef::from(PlefPiece::new(0., data_fidelity, node.perimeter as f64));
+        let plef = Plef::from(PlefPiece::new(0., data_fidelity, node.shape_energy()));
         node.optimal_energy = plef;
     }
 
@@ -160,10 +557,214 @@ pub fn graph_from_labels(img: &Array3<u8>, labels: &Array2<usize>) -> Superpixel
         let t_node = &graph[t_i];
         let edge = &graph[edge_i];
 
-        graph[edge_i].weight = apparition_scale(s_node, t_node, edge.length);
+        graph[edge_i].weight = apparition_scale(s_node, t_node, edge);
     }
+}
+
+/// Update the graph after the labels of the pixels inside `window` changed from
+/// `old_labels` to `labels`, e.g. after a local refinement of the superpixels.
+///
+/// The labels outside of the window must be unchanged, and no superpixel may be
+/// left without pixels. Boundary probabilities are not updated.
+pub fn patch_graph(
+    graph: &mut SuperpixelGraph,
+    img: &Array3<u8>,
+    old_labels: &Array2<usize>,
+    labels: &Array2<usize>,
+    window: Rect,
+) {
+    let (height, width, _) = img.dim();
+    let wrap = graph_wrap(graph);
+
+    for y in window.y..window.y + window.height {
+        for x in window.x..window.x + window.width {
+            let (old, new) = (old_labels[[y, x]], labels[[y, x]]);
+            if old == new {
+                continue;
+            }
+
+            let pixel = img.slice(s![y, x, ..]).mapv(u64::from);
+            graph[NodeIndex::new(old)].remove_pixel(&pixel, x, y);
+            graph[NodeIndex::new(new)].add_pixel(&pixel, x, y);
 
+            // Borders of the image, unless they wrap
+            let (h, v) = (!wrap.horizontal(), !wrap.vertical());
+            let on_border = [
+                h && x == 0,
+                v && y == 0,
+                h && x == width - 1,
+                v && y == height - 1,
+            ];
+            let border = on_border.iter().filter(|b| **b).count() as u32;
+            let vertical = u32::from(on_border[0]) + u32::from(on_border[2]);
+            graph[NodeIndex::new(old)].perimeter -= border;
+            graph[NodeIndex::new(new)].perimeter += border;
+            graph[NodeIndex::new(old)].vertical_perimeter -= vertical;
+            graph[NodeIndex::new(new)].vertical_perimeter += vertical;
+        }
+    }
+
+    // Pairs of neighboring pixels (right and bottom) touching the window, starting
+    // from the row and column before it, on the other side of the image if it wraps
+    let before = |start: usize, len: usize, wraps: bool| match start {
+        0 if wraps => Some(len - 1),
+        0 => None,
+        _ => Some(start - 1),
+    };
+    let rows = before(window.y, height, wrap.vertical())
+        .filter(|y| !(window.y..window.y + window.height).contains(y))
+        .into_iter()
+        .chain(window.y..window.y + window.height);
+    let columns = before(window.x, width, wrap.horizontal())
+        .filter(|x| !(window.x..window.x + window.width).contains(x))
+        .into_iter()
+        .chain(window.x..window.x + window.width)
+        .collect::<Vec<_>>();
+    let window_pairs = rows
+        .flat_map(|y| columns.iter().map(move |x| (y, *x)))
+        .flat_map(|a| {
+            wrap.forward_neighbors(a, (height, width))
+                .map(move |(b, vertical)| (a, b, u32::from(vertical)))
+        })
+        .filter(|((y, x), (y2, x2), _)| window.contains(*x, *y) || window.contains(*x2, *y2))
+        .collect::<Vec<_>>();
+
+    for &(a, b, vertical) in &window_pairs {
+        let (label_a, label_b) = (old_labels[a], old_labels[b]);
+        if label_a != label_b {
+            let (i, j) = (NodeIndex::new(label_a), NodeIndex::new(label_b));
+            graph[i].perimeter -= 1;
+            graph[j].perimeter -= 1;
+            graph[i].vertical_perimeter -= vertical;
+            graph[j].vertical_perimeter -= vertical;
+
+            let edge = graph.find_edge(i, j).unwrap();
+            graph[edge].length -= 1;
+            graph[edge].vertical_length -= vertical;
+        }
+    }
+
+    for &(a, b, vertical) in &window_pairs {
+        let (label_a, label_b) = (labels[a], labels[b]);
+        if label_a != label_b {
+            let (i, j) = (NodeIndex::new(label_a), NodeIndex::new(label_b));
+            graph[i].perimeter += 1;
+            graph[j].perimeter += 1;
+            graph[i].vertical_perimeter += vertical;
+            graph[j].vertical_perimeter += vertical;
+
+            let edge = match graph.find_edge(i, j) {
+                Some(edge) => edge,
+                None => graph.add_edge(i, j, SuperpixelEdge::init()),
+            };
+            graph[edge].length += 1;
+            graph[edge].vertical_length += vertical;
+        }
+    }
+
+    graph.retain_edges(|graph, edge| graph[edge].length > 0);
+
+    init_energies_and_weights(graph);
+}
+
+/// Pre-merge the neighboring superpixels whose edge weight is below `tolerance`,
+/// so that trivially identical regions do not go through the priority queue.
+///
+/// Returns the relabelled superpixels and their graph. A tolerance of `0` keeps
+/// the graph unchanged.
+pub fn prune_graph(
+    graph: &SuperpixelGraph,
+    labels: &Array2<usize>,
+    tolerance: f64,
+) -> (Array2<usize>, SuperpixelGraph) {
+    let mut components = UnionFind::<usize>::new(graph.node_count());
+    for edge in graph.edge_references() {
+        if edge.weight().weight < tolerance {
+            components.union(edge.source().index(), edge.target().index());
+        }
+    }
+
+    // New index of each superpixel, in the order of their first member
+    let mut pruned = SuperpixelGraph::default();
+    let mut roots = HashMap::<usize, SuperpixelNodeIndex>::new();
+    let mut mapping = Vec::with_capacity(graph.node_count());
+    for node in graph.node_indices() {
+        let root = components.find(node.index());
+        let new_node = match roots.get(&root) {
+            Some(&new_node) => {
+                pruned[new_node] = pruned[new_node].merged(&graph[node], &SuperpixelEdge::init());
+                new_node
+            }
+            None => {
+                let new_node = pruned.add_node(graph[node].clone());
+                roots.insert(root, new_node);
+                new_node
+            }
+        };
+        mapping.push(new_node);
+    }
+
+    for edge in graph.edge_references() {
+        let a = mapping[edge.source().index()];
+        let b = mapping[edge.target().index()];
+        let (length, vertical_length) = (edge.weight().length, edge.weight().vertical_length);
+
+        if a == b {
+            // The border is now inside the region
+            pruned[a].perimeter -= 2 * length;
+            pruned[a].vertical_perimeter -= 2 * vertical_length;
+            continue;
+        }
+
+        let edge_id = match pruned.find_edge(a, b) {
+            Some(edge_id) => edge_id,
+            None => pruned.add_edge(a, b, SuperpixelEdge::init()),
+        };
+        pruned[edge_id].length += length;
+        pruned[edge_id].vertical_length += vertical_length;
+        pruned[edge_id].boundary += edge.weight().boundary;
+        pruned[edge_id].shadow += edge.weight().shadow;
+    }
+
+    init_energies_and_weights(&mut pruned);
+
+    let labels = labels.mapv(|label| mapping[label].index());
+
+    (labels, pruned)
+}
+
+/// Accumulate a per-pixel boundary probability map (e.g. from an edge detection
+/// network) along the borders of the superpixels, see [`SuperpixelEdge::mean_boundary`].
+///
+/// Each pair of neighboring pixels on a border contributes the mean probability
+/// of its two pixels.
+pub fn add_boundary_probabilities(
+    graph: &mut SuperpixelGraph,
+    labels: &Array2<usize>,
+    boundary: &Array2<f32>,
+) {
+    let wrap = graph_wrap(graph);
+    for ((y, x), label) in labels.indexed_iter() {
+        let i = NodeIndex::new(*label);
+
+        // Loop over the neighbors (right and bottom)
+        for (neighbor, _) in wrap.forward_neighbors((y, x), labels.dim()) {
+            let n_label = &labels[neighbor];
+            if n_label != label {
+                let j = NodeIndex::new(*n_label);
+                let edge = graph.find_edge(i, j).unwrap();
+                graph[edge].boundary += (boundary[[y, x]] as f64 + boundary[neighbor] as f64) / 2.;
+            }
+        }
+    }
+}
+
+/// Edges of the image of a graph treated as adjacent, from the settings of its regions.
+pub(crate) fn graph_wrap(graph: &SuperpixelGraph) -> Wrap {
     graph
+        .node_weights()
+        .next()
+        .map_or(Wrap::None, |node| node.fidelity.wrap)
 }
 
 #[cfg(test)]
@@ -171,6 +772,7 @@ mod tests {
     use ndarray::array;
 
     use super::*;
+    use crate::params::SegmentationParams;
 
     #[test]
     fn test_graph_from_labels() {
@@ -181,7 +783,7 @@ mod tests {
         // Pixel values are from 0 to 27 (3 channels)
         let img = Array3::from_shape_vec((3, 3, 3), (0..27).into_iter().collect()).unwrap();
 
-        let graph = graph_from_labels(&img, &labels);
+        let graph = graph_from_labels(&img, &labels).unwrap();
 
         assert_eq!(graph.node_count(), 3);
 
@@ -217,4 +819,249 @@ mod tests {
             .unwrap()];
         assert_eq!(edge_1_2_weight.length, 1);
     }
+
+    #[test]
+    fn test_plane_fidelity() {
+        // A single region whose values are the plane 10 + 2x + 3y
+        let labels = Array2::zeros((4, 5));
+        let img = Array3::from_shape_fn((4, 5, 1), |(y, x, _)| (10 + 2 * x + 3 * y) as u8);
+
+        let fidelity = FidelityParams::new(FidelityModel::PiecewiseLinear, 1);
+        let graph = graph_from_labels_with_fidelity(&img, &labels, fidelity).unwrap();
+        let node = graph.node_weight(NodeIndex::from(0)).unwrap();
+
+        assert!(node.data_fidelity().abs() < 1e-6);
+        assert!(data_fidelity(&node.values, &node.values_sq, node.area, &array![1.]) > 1.);
+    }
+
+    #[test]
+    fn test_boundary_probabilities() {
+        let img = Array3::zeros((1, 3, 1));
+        let labels = array![[0, 0, 1]];
+        let boundary = array![[0., 0.5, 1.]];
+
+        let mut graph = graph_from_labels(&img, &labels).unwrap();
+        add_boundary_probabilities(&mut graph, &labels, &boundary);
+
+        let edge = graph.edge_weights().next().unwrap();
+        assert_eq!(edge.mean_boundary(), 0.75);
+    }
+
+    #[test]
+    fn test_prune_graph() {
+        // 0 0 1
+        // 0 0 1
+        // 2 2 2
+        // with regions 0 and 1 having the same value
+        let labels = Array2::from_shape_vec((3, 3), vec![0, 0, 1, 0, 0, 1, 2, 2, 2]).unwrap();
+        let img = labels
+            .mapv(|l| if l == 2 { 200 } else { 10 })
+            .insert_axis(ndarray::Axis(2));
+
+        let graph = graph_from_labels(&img, &labels).unwrap();
+        let (pruned_labels, pruned) = prune_graph(&graph, &labels, 1e-9);
+
+        assert_eq!(pruned.node_count(), 2);
+        assert_eq!(pruned.edge_count(), 1);
+        assert_eq!(pruned_labels, labels.mapv(|l| usize::from(l == 2)));
+
+        let merged = &pruned[NodeIndex::new(0)];
+        assert_eq!(merged.area, 6);
+        assert_eq!(merged.perimeter, 10);
+        assert_eq!(merged.vertical_perimeter, 4);
+        assert_eq!(pruned.edge_weights().next().unwrap().length, 3);
+    }
+
+    #[test]
+    fn test_node_index_overflow() {
+        assert_eq!(node_index(3).unwrap().index(), 3);
+
+        let label = GraphIndex::MAX as Label;
+        assert_eq!(node_index(label), Err(LabelOverflow { label }));
+    }
+
+    #[test]
+    fn test_normalized_apparition_scale() {
+        let labels = array![[0, 0, 1], [0, 0, 1]];
+        let img = labels
+            .mapv(|l| (l * 100) as u8)
+            .insert_axis(ndarray::Axis(2));
+        let graph = graph_from_labels(&img, &labels).unwrap();
+
+        let (a, b) = (&graph[NodeIndex::new(0)], &graph[NodeIndex::new(1)]);
+        let edge = graph.edge_weights().next().unwrap();
+        assert_eq!(edge.length, 2);
+        let scale = apparition_scale(a, b, edge);
+        assert!(scale > 0.);
+        assert_eq!(normalized_apparition_scale(a, b, edge), scale / 2.);
+    }
+
+    #[test]
+    fn test_shape_weight() {
+        let labels = array![[0, 0, 1], [0, 0, 1]];
+        let img = labels
+            .mapv(|l| (l * 100) as u8)
+            .insert_axis(ndarray::Axis(2));
+        let mut fidelity = FidelityParams::new(FidelityModel::default(), 1);
+        let graph = graph_from_labels_with_fidelity(&img, &labels, fidelity.clone()).unwrap();
+        let scale = graph[EdgeIndex::new(0)].weight;
+
+        // Doubling the perimeter weight halves the scale at which regions merge
+        fidelity.shape_weight = 2.;
+        let graph = graph_from_labels_with_fidelity(&img, &labels, fidelity).unwrap();
+        assert_eq!(graph[EdgeIndex::new(0)].weight, scale / 2.);
+    }
+
+    #[test]
+    fn test_height_weight() {
+        // Equal colors on band 0, a height step between the regions on band 1
+        let labels = array![[0, 0, 1], [0, 0, 1]];
+        let img = Array3::from_shape_fn((2, 3, 2), |(y, x, c)| {
+            if c == 0 {
+                50
+            } else {
+                (labels[[y, x]] * 100) as u8
+            }
+        });
+        let weight = |img: &Array3<u8>, height_weight: f64| {
+            let params = SegmentationParams {
+                elevation_channel: Some(1),
+                height_weight,
+                ..SegmentationParams::new(2)
+            };
+            let graph =
+                graph_from_labels_with_fidelity(img, &labels, params.fidelity_params(2)).unwrap();
+            graph[EdgeIndex::new(0)].weight
+        };
+
+        let scale = weight(&img, 1.);
+        assert!(scale > 0.);
+        assert_eq!(weight(&img, 2.), 2. * scale);
+        assert_eq!(weight(&img, 0.5), 0.5 * scale);
+        // Without height weight, the step is ignored
+        let flat = img.mapv(|_| 50);
+        assert_eq!(weight(&img, 0.), weight(&flat, 1.));
+    }
+
+    #[test]
+    fn test_pixel_aspect() {
+        // 0 0 1
+        // 0 0 1
+        let labels = array![[0, 0, 1], [0, 0, 1]];
+        let img = labels
+            .mapv(|l| (l * 100) as u8)
+            .insert_axis(ndarray::Axis(2));
+        let mut fidelity = FidelityParams::new(FidelityModel::default(), 1);
+        fidelity.pixel_aspect = 2.;
+        let graph = graph_from_labels_with_fidelity(&img, &labels, fidelity).unwrap();
+
+        let (a, b) = (&graph[NodeIndex::new(0)], &graph[NodeIndex::new(1)]);
+        assert_eq!((a.perimeter, a.vertical_perimeter), (8, 4));
+        assert_eq!((b.perimeter, b.vertical_perimeter), (6, 4));
+        // Vertical pixel sides twice as long as the horizontal ones
+        assert_eq!(a.scaled_perimeter(), 12.);
+        assert_eq!(a.shape_energy(), 12.);
+
+        let edge = graph.edge_weights().next().unwrap();
+        assert_eq!((edge.length, edge.vertical_length), (2, 2));
+        let merged = a.merged(b, edge);
+        assert_eq!((merged.perimeter, merged.vertical_perimeter), (10, 4));
+        assert_eq!(merged.scaled_perimeter(), 14.);
+        assert_eq!(
+            normalized_apparition_scale(a, b, edge),
+            apparition_scale(a, b, edge) / 4.
+        );
+    }
+
+    #[test]
+    fn test_wrap() {
+        // 0 0 1 1
+        // 2 2 2 2
+        let labels = array![[0, 0, 1, 1], [2, 2, 2, 2]];
+        let img = Array3::from_shape_fn((2, 4, 1), |(y, x, _)| (y * 40 + x * 10) as u8);
+        let graph = |labels: &Array2<usize>, wrap: Wrap| {
+            let mut fidelity = FidelityParams::new(FidelityModel::default(), 1);
+            fidelity.wrap = wrap;
+            graph_from_labels_with_fidelity(&img, labels, fidelity).unwrap()
+        };
+        let perimeters = |graph: &SuperpixelGraph| {
+            graph
+                .node_weights()
+                .map(|node| (node.perimeter, node.vertical_perimeter))
+                .collect::<Vec<_>>()
+        };
+        let length = |graph: &SuperpixelGraph, a: usize, b: usize| {
+            let edge = graph.find_edge(NodeIndex::new(a), NodeIndex::new(b));
+            edge.map(|edge| graph[edge].length)
+        };
+
+        let bounded = graph(&labels, Wrap::None);
+        assert_eq!(perimeters(&bounded), [(6, 2), (6, 2), (10, 2)]);
+        assert_eq!(length(&bounded, 0, 1), Some(1));
+
+        // Regions 0 and 1 also meet across the left and right edges
+        let horizontal = graph(&labels, Wrap::Horizontal);
+        assert_eq!(perimeters(&horizontal), [(6, 2), (6, 2), (8, 0)]);
+        assert_eq!(length(&horizontal, 0, 1), Some(2));
+
+        // Region 2 is below and above the others
+        let both = graph(&labels, Wrap::Both);
+        assert_eq!(perimeters(&both), [(6, 2), (6, 2), (8, 0)]);
+        assert_eq!(length(&both, 0, 2), Some(4));
+
+        // Patching across the edges
+        let mut patched = graph(&labels, Wrap::Both);
+        let new_labels = array![[0, 0, 1, 2], [2, 2, 2, 0]];
+        let window = Rect {
+            x: 3,
+            y: 0,
+            width: 1,
+            height: 2,
+        };
+        patch_graph(&mut patched, &img, &labels, &new_labels, window);
+        let expected = graph(&new_labels, Wrap::Both);
+        assert_eq!(perimeters(&patched), perimeters(&expected));
+        for (a, b) in [(0, 1), (0, 2), (1, 2)] {
+            assert_eq!(length(&patched, a, b), length(&expected, a, b));
+        }
+    }
+
+    #[test]
+    fn test_patch_graph() {
+        // 0 0 1 1
+        // 0 0 1 1
+        // 2 2 2 2
+        let old_labels = array![[0, 0, 1, 1], [0, 0, 1, 1], [2, 2, 2, 2]];
+        let img = Array3::from_shape_fn((3, 4, 2), |(y, x, c)| (y * 40 + x * 10 + c) as u8);
+        let mut graph = graph_from_labels(&img, &old_labels).unwrap();
+
+        // Region 1 takes over the second column, region 2 a pixel of region 1
+        let labels = array![[0, 1, 1, 1], [0, 1, 2, 1], [2, 2, 2, 2]];
+        let window = Rect {
+            x: 1,
+            y: 0,
+            width: 2,
+            height: 2,
+        };
+        patch_graph(&mut graph, &img, &old_labels, &labels, window);
+
+        let expected = graph_from_labels(&img, &labels).unwrap();
+        for (node, expected) in graph.node_weights().zip(expected.node_weights()) {
+            assert_eq!(node.area, expected.area);
+            assert_eq!(node.perimeter, expected.perimeter);
+            assert_eq!(node.vertical_perimeter, expected.vertical_perimeter);
+            assert_eq!(node.values, expected.values);
+            assert_eq!(node.moments.sum_xy, expected.moments.sum_xy);
+        }
+        assert_eq!(graph.edge_count(), expected.edge_count());
+        for edge in expected.edge_references() {
+            let patched = graph.find_edge(edge.source(), edge.target()).unwrap();
+            assert_eq!(graph[patched].length, edge.weight().length);
+            assert_eq!(
+                graph[patched].vertical_length,
+                edge.weight().vertical_length
+            );
+            assert_eq!(graph[patched].weight, edge.weight().weight);
+        }
+    }
 }
@@ -1,20 +1,29 @@
 use std::{
     cmp::Ordering,
-    collections::{BinaryHeap, HashMap},
+    collections::{BTreeMap, BinaryHeap},
+    error::Error,
+    fmt,
 };
 
-use petgraph::prelude::{EdgeIndex, NodeIndex};
+use petgraph::unionfind::UnionFind;
 
 use crate::{
     console_log,
-    graph::{apparition_scale, data_fidelity, SuperpixelEdge, SuperpixelGraph, SuperpixelNode},
+    graph::{
+        apparition_scale, normalized_apparition_scale, ward_distance, SuperpixelEdge,
+        SuperpixelEdgeIndex, SuperpixelGraph, SuperpixelNode, SuperpixelNodeIndex,
+    },
+    lca::LcaIndex,
+    logger::Span,
+    mst::minimum_spanning_tree,
     plef::PlefPiece,
 };
 
 #[derive(Debug, PartialEq)]
 struct EdgeWrapper {
-    index: EdgeIndex,
+    index: SuperpixelEdgeIndex,
     weight: f64,
+    tie: u64, // Merge order between edges of equal weight, see `TieBreak`
 }
 
 impl Eq for EdgeWrapper {}
@@ -28,55 +37,476 @@ impl PartialOrd for EdgeWrapper {
 impl Ord for EdgeWrapper {
     fn cmp(&self, other: &Self) -> Ordering {
         // We want the smallest weight to be the first element
-        other.weight.partial_cmp(&self.weight).unwrap()
+        other
+            .weight
+            .partial_cmp(&self.weight)
+            .unwrap()
+            .then_with(|| other.tie.cmp(&self.tie))
+            .then_with(|| other.index.cmp(&self.index))
     }
 }
 
 pub struct PartitionTree {
     pub parents: Vec<usize>,
     pub levels: Vec<f64>,
+    /// Merge history: the two children of each internal node, in creation order,
+    /// and the length of their shared border at merge time. The alpha-tree and
+    /// component trees only record the length of the edge triggering the merge.
+    /// Node ids are `usize`, i.e. 32 bits in WebAssembly, so that they are never
+    /// truncated with the `u64-index` feature.
+    pub merges: Vec<(usize, usize, u32)>,
 }
 
-pub(crate) fn binary_partition_tree(mut graph: SuperpixelGraph) -> PartitionTree {
-    let num_points = graph.node_count();
-    let mut parents = (0..num_points).collect::<Vec<_>>();
-    let mut levels = vec![0.0; num_points];
+impl PartitionTree {
+    /// Index of the lowest common ancestors of the nodes, answering at which level
+    /// two regions merge in constant time.
+    pub fn lca_index(&self) -> LcaIndex {
+        LcaIndex::new(&self.parents, &self.levels)
+    }
+}
+
+/// Broken invariant of the tree construction, e.g. on a corrupted graph. The
+/// partial tree is left in an inconsistent state and must be dropped.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum InternalError {
+    /// The weight of the edge at the top of the heap differs from its weight in the graph
+    HeapConsistency {
+        edge: usize,
+        heap_weight: f64,
+        graph_weight: f64,
+    },
+    /// An edge between the two merged regions is still active
+    ActiveEdge { edge: usize, weight: f64 },
+}
+
+impl fmt::Display for InternalError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InternalError::HeapConsistency {
+                edge,
+                heap_weight,
+                graph_weight,
+            } => write!(
+                f,
+                "Heap consistency: edge {edge} has weight {heap_weight} in the heap and {graph_weight} in the graph"
+            ),
+            InternalError::ActiveEdge { edge, weight } => write!(
+                f,
+                "Active edge: edge {edge} of weight {weight} between merged regions"
+            ),
+        }
+    }
+}
+
+impl Error for InternalError {}
+
+/// Function computing the weight of the edge between two regions sharing the
+/// given border. Lower weights are merged first.
+pub type MergeCriterion = fn(&SuperpixelNode, &SuperpixelNode, &SuperpixelEdge) -> f64;
 
-    let mut heap: BinaryHeap<EdgeWrapper> = BinaryHeap::new();
+/// Order of the merges between edges of equal weight, e.g. in flat areas.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TieBreak {
+    /// Edges created first are merged first
+    #[default]
+    EdgeId,
+    /// Edges whose merge creates the smallest region are merged first, then by edge id
+    SmallestArea,
+}
 
-    // Iterate over all edges
-    for edge_id in graph.edge_indices() {
-        let edge = graph.edge_weight(edge_id).unwrap();
-        let weight = edge.weight;
+impl TieBreak {
+    fn key(&self, graph: &SuperpixelGraph, edge_id: SuperpixelEdgeIndex) -> u64 {
+        match self {
+            TieBreak::EdgeId => 0,
+            TieBreak::SmallestArea => {
+                let (a, b) = graph.edge_endpoints(edge_id).unwrap();
+                graph[a].area as u64 + graph[b].area as u64
+            }
+        }
+    }
+}
+
+/// Options of the default merge criterion, the apparition scale.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct CriterionOptions {
+    /// Divide the apparition scale by the length of the shared border, see
+    /// [`normalized_apparition_scale`]
+    pub normalize_by_boundary: bool,
+    /// Round the edge weights to fixed point, see [`PartialPartitionTree::with_fixed_point`]
+    pub fixed_point: bool,
+    /// Area below which regions are merged by color only, 0 for none, see
+    /// [`PartialPartitionTree::with_fine_stage`]
+    pub fine_area: u32,
+    /// Reduction of the weights of the edges inside shadows, see
+    /// [`PartialPartitionTree::with_shadow_weight`]
+    pub shadow_weight: f64,
+}
 
-        let wrapper = EdgeWrapper {
-            index: edge_id,
-            weight,
+impl CriterionOptions {
+    pub fn criterion(&self) -> MergeCriterion {
+        if self.normalize_by_boundary {
+            normalized_apparition_scale
+        } else {
+            apparition_scale
+        }
+    }
+}
+
+/// Fractional bits of the fixed-point edge weights.
+const FIXED_POINT_BITS: u32 = 10;
+
+/// Edge weight rounded to a multiple of `2^-FIXED_POINT_BITS`, through a scaled
+/// 64-bit integer. Infinite weights are kept.
+fn to_fixed_point(weight: f64) -> f64 {
+    if !weight.is_finite() {
+        return weight;
+    }
+
+    let scale = (1u64 << FIXED_POINT_BITS) as f64;
+    let ticks = (weight * scale).round() as i64;
+    ticks as f64 / scale
+}
+
+/// Parcel of the regions of the tree, for [`PartialPartitionTree::with_parcels`].
+struct ParcelConstraint {
+    // Parcel of each node, `usize::MAX` for nodes spanning several parcels
+    parcels: Vec<usize>,
+    level: f64,
+}
+
+impl ParcelConstraint {
+    fn snap(&self, a: usize, b: usize, weight: f64) -> f64 {
+        if self.parcels[a] == self.parcels[b] {
+            weight
+        } else {
+            weight.max(self.level)
+        }
+    }
+}
+
+/// Hook called before each merge with the indices of the two merged regions,
+/// the node resulting from their fusion and the level of the merge.
+///
+/// Returning `false` vetoes the merge: the two regions are then never merged
+/// together, and the construction may end with several roots.
+pub type MergeHook = Box<dyn FnMut(usize, usize, &SuperpixelNode, f64) -> bool>;
+
+/// Condition at which [`PartialPartitionTree::merge_until`] stops merging.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StopCondition {
+    /// Stop before the first merge whose level is greater or equal to this one.
+    Level(f64),
+    /// Stop once this many merges have been performed since the construction started.
+    MergeCount(usize),
+}
+
+/// A binary partition tree under construction.
+///
+/// It keeps the remaining region adjacency graph and the priority queue so the
+/// construction can be stopped at a given level and resumed later, possibly
+/// with a different merge criterion.
+pub struct PartialPartitionTree {
+    graph: SuperpixelGraph,
+    heap: BinaryHeap<EdgeWrapper>,
+    parents: Vec<usize>,
+    levels: Vec<f64>,
+    merges: Vec<(usize, usize, u32)>,
+    criterion: MergeCriterion,
+    boundary_weight: f64,
+    shadow_weight: f64,
+    fixed_point: bool,
+    // Area limit of the fine stage while it lasts, and first node of the coarse stage
+    fine_area: Option<u32>,
+    coarse_from: Option<usize>,
+    tie_break: TieBreak,
+    merge_operations: usize,
+    on_merge: Option<MergeHook>,
+    parcels: Option<ParcelConstraint>,
+    // Used to store the neighbors of a fused node and its edges, ordered so that the
+    // new edges, and thus the ties between them, are the same on every run
+    neighors: BTreeMap<SuperpixelNodeIndex, Vec<SuperpixelEdgeIndex>>,
+}
+
+impl PartialPartitionTree {
+    /// Start the construction from a graph whose edge weights were computed with
+    /// [`apparition_scale`], which stays the merge criterion.
+    pub fn new(graph: SuperpixelGraph) -> Self {
+        let num_points = graph.node_count();
+        let parents = (0..num_points).collect::<Vec<_>>();
+        let levels = vec![0.0; num_points];
+
+        let mut tree = Self {
+            graph,
+            heap: BinaryHeap::new(),
+            parents,
+            levels,
+            merges: Vec::new(),
+            criterion: apparition_scale,
+            boundary_weight: 0.,
+            shadow_weight: 0.,
+            fixed_point: false,
+            fine_area: None,
+            coarse_from: None,
+            tie_break: TieBreak::default(),
+            merge_operations: 0,
+            on_merge: None,
+            parcels: None,
+            neighors: BTreeMap::new(),
         };
+        tree.rebuild_heap();
 
-        heap.push(wrapper);
+        tree
     }
 
-    let mut merge_operations = 0;
+    /// Change the merge criterion. The weights of the remaining edges are
+    /// recomputed with the new criterion.
+    pub fn with_criterion(mut self, criterion: MergeCriterion) -> Self {
+        self.criterion = criterion;
+        self.reweight();
 
-    // Used to store the neighbors of a fused node and its edges (allows to avoid re-allocating)
-    let mut neighors = HashMap::<NodeIndex, Vec<EdgeIndex>>::new();
+        self
+    }
+
+    /// Set the merge criterion, the fixed-point rounding and the fine stage from
+    /// the options.
+    pub fn with_options(mut self, options: CriterionOptions) -> Self {
+        self.criterion = options.criterion();
+        self.fixed_point = options.fixed_point;
+        self.shadow_weight = options.shadow_weight.clamp(0., 1.);
+
+        self.with_fine_stage(options.fine_area)
+    }
+
+    /// Build the bottom of the tree with [`ward_distance`], a color-only criterion,
+    /// as long as the merged regions are smaller than `area` pixels. The merge
+    /// criterion, with its perimeter term, then takes over for the larger regions,
+    /// as the shape matters more at the scale of objects than of superpixels.
+    ///
+    /// Once the merge criterion took over, the levels of the fine stage are rescaled
+    /// below its lowest level, so that the levels still increase toward the root.
+    /// Until then, stop levels are compared to the Ward distances. An `area` of 0
+    /// disables the fine stage.
+    pub fn with_fine_stage(mut self, area: u32) -> Self {
+        self.fine_area = (area > 0).then_some(area);
+        self.coarse_from = None;
+        self.reweight();
+
+        self
+    }
 
-    while !heap.is_empty() {
-        let top = heap.pop().unwrap();
+    /// Round the edge weights, and thus the levels, to multiples of `2^-10`.
+    ///
+    /// Floating-point rounding differs slightly between WebAssembly, native and SIMD
+    /// builds, which can change the levels and even the merge order. The weights
+    /// are still computed in floating point and only rounded afterwards, so most of
+    /// these differences vanish, and near ties are broken by the
+    /// [`TieBreak`] rather than by rounding noise. A weight falling within rounding
+    /// noise of the middle of two multiples can still round differently across
+    /// targets: hierarchies are much more stable, not guaranteed bit-identical.
+    pub fn with_fixed_point(mut self, fixed_point: bool) -> Self {
+        self.fixed_point = fixed_point;
+        self.reweight();
+
+        self
+    }
+
+    /// Take the boundary probabilities of the edges into account, see
+    /// [`add_boundary_probabilities`](crate::graph::add_boundary_probabilities):
+    /// edge weights are multiplied by `1 + weight * mean_boundary`, so that
+    /// regions separated by a likely boundary are merged later.
+    pub fn with_boundary_weight(mut self, weight: f64) -> Self {
+        self.boundary_weight = weight;
+        self.reweight();
+
+        self
+    }
+
+    /// Take the shadows along the borders into account, see
+    /// [`add_shadows`](crate::add_shadows): edge weights are multiplied by
+    /// `1 - weight * mean_shadow`, so that shadowed roofs and tree crowns, whose
+    /// contrast is compressed, are not fragmented. `weight` is between 0 and 1.
+    pub fn with_shadow_weight(mut self, weight: f64) -> Self {
+        self.shadow_weight = weight.clamp(0., 1.);
+        self.reweight();
+
+        self
+    }
+
+    /// Snap the tree to existing parcels (e.g. a land registry), given the parcel of
+    /// each node of the graph: regions spanning several parcels are not created below
+    /// `level`, edges between parcels having their weight raised to it. Above `level`,
+    /// the merges ignore the parcels. Callers check that there is one parcel per node.
+    pub fn with_parcels(mut self, parcels: Vec<usize>, level: f64) -> Self {
+        debug_assert_eq!(
+            parcels.len(),
+            self.parents.len(),
+            "One parcel per node expected"
+        );
+        self.parcels = Some(ParcelConstraint { parcels, level });
+        self.reweight();
+
+        self
+    }
+
+    fn reweight(&mut self) {
+        for edge_id in self.graph.edge_indices() {
+            if !self.graph[edge_id].active {
+                continue;
+            }
+
+            let (a, b) = self.graph.edge_endpoints(edge_id).unwrap();
+            let mut weight = edge_weight(
+                self.criterion,
+                self.fine_area,
+                self.boundary_weight,
+                self.shadow_weight,
+                &self.graph[a],
+                &self.graph[b],
+                &self.graph[edge_id],
+            );
+            if let Some(parcels) = &self.parcels {
+                weight = parcels.snap(a.index(), b.index(), weight);
+            }
+            if self.fixed_point {
+                weight = to_fixed_point(weight);
+            }
+            self.graph[edge_id].weight = weight;
+        }
+
+        self.rebuild_heap();
+    }
+
+    /// Change the order of the merges between edges of equal weight.
+    pub fn with_tie_break(mut self, tie_break: TieBreak) -> Self {
+        self.tie_break = tie_break;
+        self.rebuild_heap();
+
+        self
+    }
+
+    /// Set a hook called before each merge, see [`MergeHook`].
+    pub fn on_merge<F>(mut self, hook: F) -> Self
+    where
+        F: FnMut(usize, usize, &SuperpixelNode, f64) -> bool + 'static,
+    {
+        self.on_merge = Some(Box::new(hook));
+        self
+    }
+
+    fn rebuild_heap(&mut self) {
+        self.heap.clear();
+
+        for edge_id in self.graph.edge_indices() {
+            let edge = &self.graph[edge_id];
+            if !edge.active {
+                continue;
+            }
+
+            self.heap.push(EdgeWrapper {
+                index: edge_id,
+                weight: edge.weight,
+                tie: self.tie_break.key(&self.graph, edge_id),
+            });
+        }
+    }
+
+    /// Remaining region adjacency graph. Nodes that have not been merged yet are
+    /// the roots of the partial tree, and only the edges flagged as active
+    /// connect them.
+    pub fn graph(&self) -> &SuperpixelGraph {
+        &self.graph
+    }
+
+    /// Number of merges performed since the construction started.
+    pub fn merge_operations(&self) -> usize {
+        self.merge_operations
+    }
+
+    /// Returns `true` when no merge is left to perform.
+    pub fn is_complete(&mut self) -> bool {
+        self.peek_active().is_none()
+    }
+
+    /// Drop inactive edges from the top of the heap and return the next edge to merge.
+    fn peek_active(&mut self) -> Option<&EdgeWrapper> {
+        while let Some(top) = self.heap.peek() {
+            if self.graph[top.index].active {
+                break;
+            }
+            self.heap.pop();
+        }
+
+        self.heap.peek()
+    }
+
+    /// Merge regions until the stop condition is met, or until a single region
+    /// is left if `stop` is `None`. Returns the number of merges performed,
+    /// vetoed merges excluded.
+    pub fn merge_until(&mut self, stop: Option<StopCondition>) -> Result<usize, InternalError> {
+        let mut merges = 0;
+
+        while let Some(weight) = self.peek_active().map(|top| top.weight) {
+            if weight == f64::INFINITY && self.fine_area.is_some() {
+                // Only merges above the fine area are left
+                self.fine_area = None;
+                self.coarse_from = Some(self.parents.len());
+                self.reweight();
+                continue;
+            }
+
+            match stop {
+                Some(StopCondition::Level(level)) if weight >= level => break,
+                Some(StopCondition::MergeCount(count)) if self.merge_operations >= count => break,
+                _ => {}
+            }
+
+            let top = self.heap.pop().unwrap();
+            if self.merge(top)? {
+                merges += 1;
+            }
+        }
+
+        Ok(merges)
+    }
+
+    fn merge(&mut self, top: EdgeWrapper) -> Result<bool, InternalError> {
+        let graph = &mut self.graph;
+        let neighors = &mut self.neighors;
 
         let fusion_edge_index = top.index;
+        let (a, b) = graph.edge_endpoints(fusion_edge_index).unwrap();
         let fusion_edge = graph.edge_weight_mut(fusion_edge_index).unwrap();
 
-        if !fusion_edge.active {
-            continue;
+        if fusion_edge.weight != top.weight {
+            return Err(InternalError::HeapConsistency {
+                edge: fusion_edge_index.index(),
+                heap_weight: top.weight,
+                graph_weight: fusion_edge.weight,
+            });
         }
 
-        assert!(fusion_edge.weight == top.weight, "Heap consistency assert");
-
         fusion_edge.active = false;
 
-        let (a, b) = graph.edge_endpoints(fusion_edge_index).unwrap();
+        let fusion_weight = fusion_edge.weight;
+        let fusion_length = fusion_edge.length;
+        let fusion_edge = fusion_edge.clone();
+
+        // Fuse the two nodes
+        let node_a = graph.node_weight(a).unwrap();
+        let node_b = graph.node_weight(b).unwrap();
+
+        let mut new_node = node_a.merged(node_b, &fusion_edge);
+        let data_fidelity = new_node.data_fidelity();
+        let mut plef = node_a.optimal_energy.sum(&node_b.optimal_energy, None);
+        plef.infimum(PlefPiece::new(0., data_fidelity, new_node.shape_energy()));
+        new_node.optimal_energy = plef;
+
+        if let Some(on_merge) = &mut self.on_merge {
+            if !on_merge(a.index(), b.index(), &new_node, fusion_weight) {
+                // The fusion edge stays inactive, so these two regions are never merged
+                return Ok(false);
+            }
+        }
 
         neighors.clear();
         // Find all neighbors of a and b
@@ -85,8 +515,13 @@ pub(crate) fn binary_partition_tree(mut graph: SuperpixelGraph) -> PartitionTree
                 let edge_id = graph.find_edge(node, neighbor).unwrap();
                 let edge = graph.edge_weight(edge_id).unwrap();
 
+                if neighbor == other && edge.active {
+                    return Err(InternalError::ActiveEdge {
+                        edge: edge_id.index(),
+                        weight: edge.weight,
+                    });
+                }
                 if neighbor == other || !edge.active {
-                    assert!(!(neighbor == other && edge.active), "Active edge assert");
                     continue;
                 }
 
@@ -98,68 +533,449 @@ pub(crate) fn binary_partition_tree(mut graph: SuperpixelGraph) -> PartitionTree
             }
         }
 
-        // Fuse the two nodes
-        let node_a = graph.node_weight(a).unwrap();
-        let node_b = graph.node_weight(b).unwrap();
-
-        let fusion_edge = graph.edge_weight(fusion_edge_index).unwrap();
-
-        let new_node = {
-            let area = node_a.area + node_b.area;
-            let perimeter = node_a.perimeter + node_b.perimeter - 2 * fusion_edge.length;
-            let values = &node_a.values + &node_b.values;
-            let values_sq = &node_a.values_sq + &node_b.values_sq;
-
-            let data_fidelity = data_fidelity(&values, &values_sq, area);
-            let mut plef = node_a.optimal_energy.sum(&node_b.optimal_energy, None);
-            plef.infimum(PlefPiece::new(0., data_fidelity, perimeter as f64));
-
-            SuperpixelNode::new(
-                node_a.area + node_b.area,
-                node_a.perimeter + node_b.perimeter - 2 * fusion_edge.length,
-                &node_a.values + &node_b.values,
-                &node_a.values_sq + &node_b.values_sq,
-                plef,
-            )
-        };
-
-        let fusion_weight = fusion_edge.weight;
         let new_node_id = graph.add_node(new_node);
 
-        assert!(parents.len() == new_node_id.index());
-        assert!(levels.len() == new_node_id.index());
-
-        parents.push(new_node_id.index());
-        levels.push(fusion_weight);
-
-        parents[a.index()] = new_node_id.index();
-        parents[b.index()] = new_node_id.index();
+        // Tree nodes and graph nodes are created together
+        debug_assert_eq!(self.parents.len(), new_node_id.index());
+        debug_assert_eq!(self.levels.len(), new_node_id.index());
+
+        self.parents.push(new_node_id.index());
+        self.levels.push(fusion_weight);
+
+        self.parents[a.index()] = new_node_id.index();
+        self.parents[b.index()] = new_node_id.index();
+        self.merges.push((a.index(), b.index(), fusion_length));
+
+        if let Some(constraint) = &mut self.parcels {
+            let (parcel_a, parcel_b) =
+                (constraint.parcels[a.index()], constraint.parcels[b.index()]);
+            let parcel = if parcel_a == parcel_b {
+                parcel_a
+            } else {
+                usize::MAX
+            };
+            constraint.parcels.push(parcel);
+        }
 
-        for (neighbor_id, old_edges) in &neighors {
+        for (neighbor_id, old_edges) in neighors.iter() {
             let neighbor_id = *neighbor_id;
-            let mut length = 0;
+            let mut new_edge = SuperpixelEdge::new(0., 0);
 
             for edge_id in old_edges {
                 let edge = graph.edge_weight_mut(*edge_id).unwrap();
-                length += edge.length;
+                new_edge.length += edge.length;
+                new_edge.vertical_length += edge.vertical_length;
+                new_edge.boundary += edge.boundary;
+                new_edge.shadow += edge.shadow;
                 edge.active = false;
             }
 
-            let neighbor_node = graph.node_weight(neighbor_id).unwrap();
-
-            let weight = apparition_scale(&graph[new_node_id], neighbor_node, length);
-            let new_edge = SuperpixelEdge::new(weight, length);
+            let mut weight = edge_weight(
+                self.criterion,
+                self.fine_area,
+                self.boundary_weight,
+                self.shadow_weight,
+                &graph[new_node_id],
+                &graph[neighbor_id],
+                &new_edge,
+            );
+            if let Some(parcels) = &self.parcels {
+                weight = parcels.snap(new_node_id.index(), neighbor_id.index(), weight);
+            }
+            if self.fixed_point {
+                weight = to_fixed_point(weight);
+            }
+            new_edge.weight = weight;
             let new_edge_id = graph.add_edge(new_node_id, neighbor_id, new_edge);
-            heap.push(EdgeWrapper {
+            self.heap.push(EdgeWrapper {
                 index: new_edge_id,
                 weight,
+                tie: self.tie_break.key(graph, new_edge_id),
             });
         }
 
-        merge_operations += 1;
+        self.merge_operations += 1;
+
+        Ok(true)
+    }
+
+    /// Current state of the tree. Regions that have not been merged yet are their own parent.
+    pub fn partition_tree(&self) -> PartitionTree {
+        let mut levels = self.levels.clone();
+        self.rescale_fine_levels(&mut levels);
+
+        PartitionTree {
+            parents: self.parents.clone(),
+            levels,
+            merges: self.merges.clone(),
+        }
+    }
+
+    pub fn into_partition_tree(mut self) -> PartitionTree {
+        let mut levels = std::mem::take(&mut self.levels);
+        self.rescale_fine_levels(&mut levels);
+
+        PartitionTree {
+            parents: self.parents,
+            levels,
+            merges: self.merges,
+        }
+    }
+
+    /// Scale the levels of the fine stage so that the highest one is the lowest level
+    /// of the coarse stage, see [`PartialPartitionTree::with_fine_stage`].
+    fn rescale_fine_levels(&self, levels: &mut [f64]) {
+        let Some(coarse_from) = self.coarse_from else {
+            return;
+        };
+        let (fine, coarse) = levels.split_at_mut(coarse_from);
+
+        let lowest = coarse.iter().copied().fold(f64::INFINITY, f64::min);
+        let highest = fine.iter().copied().fold(0., f64::max);
+        if lowest == f64::INFINITY || highest <= 0. {
+            return;
+        }
+
+        for level in fine {
+            *level *= lowest / highest;
+            if self.fixed_point {
+                *level = to_fixed_point(*level);
+            }
+        }
+    }
+}
+
+/// Weight of an edge with the merge criterion, increased by the boundary probability
+/// along the border and decreased by the shadows.
+fn edge_weight(
+    criterion: MergeCriterion,
+    fine_area: Option<u32>,
+    boundary_weight: f64,
+    shadow_weight: f64,
+    a: &SuperpixelNode,
+    b: &SuperpixelNode,
+    edge: &SuperpixelEdge,
+) -> f64 {
+    // Merges above the fine area wait for the end of the fine stage
+    let criterion = match fine_area {
+        Some(area) if a.area + b.area >= area => return f64::INFINITY,
+        Some(_) => ward_distance,
+        None => criterion,
+    };
+
+    criterion(a, b, edge)
+        * (1. + boundary_weight * edge.mean_boundary())
+        * (1. - shadow_weight * edge.mean_shadow())
+}
+
+pub(crate) fn binary_partition_tree(
+    graph: SuperpixelGraph,
+    options: CriterionOptions,
+) -> Result<PartitionTree, InternalError> {
+    let _span = Span::enter("merge");
+    let mut tree = PartialPartitionTree::new(graph).with_options(options);
+    tree.merge_until(None)?;
+
+    console_log!("Merge operations: {:?}", tree.merge_operations());
+
+    Ok(tree.into_partition_tree())
+}
+
+/// Build the alpha-tree (quasi-flat zones hierarchy) of the graph from its edge weights.
+///
+/// Regions at level `α` are the connected components of the edges whose weight
+/// is lower or equal to `α`. The tree is the single-linkage dendrogram of the
+/// minimum spanning tree: zones merging at the same level are chained binary
+/// nodes sharing that level.
+pub(crate) fn alpha_tree(graph: &SuperpixelGraph) -> PartitionTree {
+    let num_points = graph.node_count();
+    let mut parents = (0..num_points).collect::<Vec<_>>();
+    let mut levels = vec![0.0; num_points];
+
+    let mut components = UnionFind::<usize>::new(num_points);
+    // Tree node representing each component, indexed by the component representative
+    let mut roots = (0..num_points).collect::<Vec<_>>();
+    let mut merges = Vec::with_capacity(num_points.saturating_sub(1));
+
+    for edge_id in minimum_spanning_tree(graph) {
+        let (a, b) = graph.edge_endpoints(edge_id).unwrap();
+        let root_a = roots[components.find(a.index())];
+        let root_b = roots[components.find(b.index())];
+
+        let new_node = parents.len();
+        parents.push(new_node);
+        levels.push(graph[edge_id].weight);
+
+        parents[root_a] = new_node;
+        parents[root_b] = new_node;
+        merges.push((root_a, root_b, graph[edge_id].length));
+
+        components.union(a.index(), b.index());
+        roots[components.find(a.index())] = new_node;
+    }
+
+    PartitionTree {
+        parents,
+        levels,
+        merges,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ndarray::{Array2, Array3};
+
+    use super::*;
+    use crate::graph::{graph_from_labels, ward_distance};
+
+    fn test_graph() -> SuperpixelGraph {
+        // 0 0 1
+        // 0 0 1
+        // 2 2 2
+        let labels = Array2::from_shape_vec((3, 3), vec![0, 0, 1, 0, 0, 1, 2, 2, 2]).unwrap();
+        let img = Array3::from_shape_vec((3, 3, 3), (0..27).collect()).unwrap();
+
+        graph_from_labels(&img, &labels).unwrap()
+    }
+
+    #[test]
+    fn test_resume_partition_tree() {
+        let mut full = PartialPartitionTree::new(test_graph());
+        assert_eq!(full.merge_until(None).unwrap(), 2);
+        assert!(full.is_complete());
+        let full = full.into_partition_tree();
+
+        let mut partial = PartialPartitionTree::new(test_graph());
+        assert_eq!(
+            partial
+                .merge_until(Some(StopCondition::MergeCount(1)))
+                .unwrap(),
+            1
+        );
+        assert!(!partial.is_complete());
+
+        let tree = partial.partition_tree();
+        assert_eq!(tree.parents.len(), 4);
+        assert_eq!(tree.parents[3], 3);
+
+        assert_eq!(partial.merge_until(None).unwrap(), 1);
+        let resumed = partial.into_partition_tree();
+
+        assert_eq!(resumed.parents, full.parents);
+        assert_eq!(resumed.levels, full.levels);
+    }
+
+    #[test]
+    fn test_resume_with_other_criterion() {
+        let mut tree = PartialPartitionTree::new(test_graph());
+        tree.merge_until(Some(StopCondition::Level(0.0))).unwrap();
+        assert_eq!(tree.merge_operations(), 0);
+
+        let mut tree = tree.with_criterion(ward_distance);
+        tree.merge_until(None).unwrap();
+
+        let tree = tree.into_partition_tree();
+        assert_eq!(tree.parents.len(), 5);
+        assert_eq!(tree.parents[4], 4);
+    }
+
+    #[test]
+    fn test_fine_stage() {
+        // Leaves 0 and 1 are merged by color first, being below the fine area
+        let mut tree = PartialPartitionTree::new(test_graph()).with_fine_stage(7);
+        tree.merge_until(Some(StopCondition::MergeCount(1)))
+            .unwrap();
+        assert_eq!(tree.partition_tree().parents, vec![3, 3, 2, 3]);
+
+        tree.merge_until(None).unwrap();
+        let tree = tree.into_partition_tree();
+        assert_eq!(tree.parents, vec![3, 3, 4, 4, 4]);
+        // The color-only level is rescaled to the lowest level of the coarse stage
+        assert_eq!(tree.levels[3], tree.levels[4]);
+        assert!(tree.levels[4] > 0.);
+
+        let mut full = PartialPartitionTree::new(test_graph()).with_fine_stage(100);
+        full.merge_until(None).unwrap();
+        let full = full.into_partition_tree();
+        let mut ward = PartialPartitionTree::new(test_graph()).with_criterion(ward_distance);
+        ward.merge_until(None).unwrap();
+        assert_eq!(full.levels, ward.into_partition_tree().levels);
+    }
+
+    #[test]
+    fn test_veto_merge() {
+        let mut tree =
+            PartialPartitionTree::new(test_graph()).on_merge(|a, b, _, _| !(a == 2 || b == 2));
+
+        assert_eq!(tree.merge_until(None).unwrap(), 1);
+        assert!(tree.is_complete());
+
+        let tree = tree.into_partition_tree();
+        assert_eq!(tree.parents, vec![3, 3, 2, 3]);
+    }
+
+    #[test]
+    fn test_shadow_weight_clamped() {
+        // The border between regions 0 and 1 is fully shadowed
+        let mut graph = test_graph();
+        let edge = graph
+            .find_edge(SuperpixelNodeIndex::new(0), SuperpixelNodeIndex::new(1))
+            .unwrap();
+        graph[edge].shadow = graph[edge].length;
+
+        let options = CriterionOptions {
+            shadow_weight: 2.,
+            ..Default::default()
+        };
+        let mut tree = PartialPartitionTree::new(graph).with_options(options);
+        tree.merge_until(None).unwrap();
+        let tree = tree.into_partition_tree();
+
+        assert!(tree.levels.iter().all(|level| *level >= 0.));
+        for (node, parent) in tree.parents.iter().enumerate() {
+            assert!(tree.levels[node] <= tree.levels[*parent]);
+        }
+        assert_eq!(tree.levels[3], 0.);
+    }
+
+    #[test]
+    fn test_parcels() {
+        let mut tree = PartialPartitionTree::new(test_graph()).with_parcels(vec![0, 0, 1], 1e9);
+        tree.merge_until(None).unwrap();
+
+        // Regions 0 and 1 share a parcel, so they are merged before reaching region 2
+        let tree = tree.into_partition_tree();
+        assert_eq!(tree.parents, vec![3, 3, 4, 4, 4]);
+        assert!(tree.levels[3] < 1e9);
+        assert!(tree.levels[4] >= 1e9);
+    }
+
+    #[test]
+    fn test_merge_history() {
+        let mut tree = PartialPartitionTree::new(test_graph()).with_parcels(vec![0, 0, 1], 1e9);
+        tree.merge_until(None).unwrap();
+        let tree = tree.into_partition_tree();
+
+        let (a, b, length) = tree.merges[0];
+        assert_eq!((a.min(b), a.max(b), length), (0, 1, 2));
+        // The border of the merged region with region 2 sums both borders
+        let (a, b, length) = tree.merges[1];
+        assert_eq!((a.min(b), a.max(b), length), (2, 3, 3));
     }
 
-    console_log!("Merge operations: {:?}", merge_operations);
+    #[test]
+    fn test_internal_error() {
+        let mut tree = PartialPartitionTree::new(test_graph());
+        // Corrupt the weight of an edge after it was pushed on the heap
+        let top = tree.peek_active().unwrap().index;
+        tree.graph[top].weight += 1.;
+
+        let error = tree.merge_until(None).unwrap_err();
+        assert!(matches!(
+            error,
+            InternalError::HeapConsistency { edge, .. } if edge == top.index()
+        ));
+    }
+
+    #[test]
+    fn test_fixed_point() {
+        fn perturbed(
+            source: &SuperpixelNode,
+            target: &SuperpixelNode,
+            edge: &SuperpixelEdge,
+        ) -> f64 {
+            apparition_scale(source, target, edge) * (1. + 1e-12)
+        }
 
-    PartitionTree { parents, levels }
+        let build = |criterion: MergeCriterion| {
+            let mut tree = PartialPartitionTree::new(test_graph())
+                .with_fixed_point(true)
+                .with_criterion(criterion);
+            tree.merge_until(None).unwrap();
+            tree.into_partition_tree()
+        };
+        let (exact, perturbed) = (build(apparition_scale), build(perturbed));
+
+        assert_eq!(exact.parents, perturbed.parents);
+        assert_eq!(exact.levels, perturbed.levels);
+        assert!(exact
+            .levels
+            .iter()
+            .all(|level| (level * 1024.).fract() == 0.));
+    }
+
+    #[test]
+    fn test_fixed_point_ties() {
+        // Regions 0 and 2 are symmetric around region 1, so both edges have the
+        // same weight
+        let labels = Array2::from_shape_vec((1, 3), vec![0, 1, 2]).unwrap();
+        let img = Array3::from_shape_vec((1, 3, 1), vec![0, 10, 20]).unwrap();
+        // Weight of the edge between regions 1 and 2 slightly lowered, as rounding
+        // noise on another target could
+        fn perturbed(
+            source: &SuperpixelNode,
+            target: &SuperpixelNode,
+            edge: &SuperpixelEdge,
+        ) -> f64 {
+            let noise = if source.values[0] + target.values[0] == 30 {
+                1. - 1e-12
+            } else {
+                1.
+            };
+            apparition_scale(source, target, edge) * noise
+        }
+        let first_merge = |criterion: MergeCriterion, fixed_point: bool| {
+            let mut tree = PartialPartitionTree::new(graph_from_labels(&img, &labels).unwrap())
+                .with_criterion(criterion)
+                .with_fixed_point(fixed_point);
+            tree.merge_until(Some(StopCondition::MergeCount(1)))
+                .unwrap();
+            tree.partition_tree().parents[..3].to_vec()
+        };
+
+        assert_eq!(first_merge(apparition_scale, false), [3, 3, 2]);
+        assert_eq!(first_merge(perturbed, false), [0, 3, 3]);
+        // The tie survives the noise, and is broken by edge id
+        assert_eq!(first_merge(perturbed, true), [3, 3, 2]);
+
+        // Noise across the middle of two multiples is not absorbed
+        let middle = 0.5 / 1024.;
+        assert_ne!(
+            to_fixed_point(middle * (1. - 1e-12)),
+            to_fixed_point(middle * (1. + 1e-12))
+        );
+    }
+
+    #[test]
+    fn test_alpha_tree() {
+        let graph = test_graph();
+        let tree = alpha_tree(&graph);
+
+        assert_eq!(tree.parents.len(), 5);
+        assert_eq!(tree.parents[4], 4);
+        assert!(tree.levels.windows(2).all(|w| w[0] <= w[1]));
+        assert!(tree.parents[..4].iter().all(|&p| p > 2));
+    }
+
+    #[test]
+    fn test_tie_break() {
+        // 0 0 1
+        // 0 0 1
+        // 2 2 2
+        // on a flat image, so that all the edges have the same weight
+        let labels = Array2::from_shape_vec((3, 3), vec![0, 0, 1, 0, 0, 1, 2, 2, 2]).unwrap();
+        let img = Array3::zeros((3, 3, 1));
+        let graph = || graph_from_labels(&img, &labels).unwrap();
+
+        let mut tree = PartialPartitionTree::new(graph());
+        tree.merge_until(Some(StopCondition::MergeCount(1)))
+            .unwrap();
+        assert_eq!(&tree.partition_tree().parents[..3], &[3, 3, 2]);
+
+        // Regions 1 and 2 have the smallest total area
+        let mut tree = PartialPartitionTree::new(graph()).with_tie_break(TieBreak::SmallestArea);
+        tree.merge_until(Some(StopCondition::MergeCount(1)))
+            .unwrap();
+        assert_eq!(&tree.partition_tree().parents[..3], &[0, 3, 3]);
+    }
 }
@@ -0,0 +1,130 @@
+use std::mem::size_of;
+
+use ndarray::{s, Array1, Array3};
+#[cfg(feature = "wasm")]
+use wasm_bindgen::prelude::wasm_bindgen;
+
+use crate::utils::{array_from_planar, Rect};
+
+/// Summed-area tables of the bands of an image and of their squares, giving the
+/// statistics of any rectangle in constant time, e.g. for interactive queries.
+///
+/// Holds 16 bytes per pixel and band.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Clone, Debug)]
+pub struct IntegralImage {
+    /// Sums over `[0, y) x [0, x)`, with a leading row and column of zeros
+    sums: Array3<f64>,
+    squares: Array3<f64>,
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+impl IntegralImage {
+    #[cfg_attr(feature = "wasm", wasm_bindgen(constructor))]
+    pub fn new(data: &[u8], width: usize, height: usize, channels: usize) -> Self {
+        Self::from_array(&array_from_planar(data, width, height, channels))
+    }
+
+    /// Number of pixels of the rectangle, clipped to the image.
+    pub fn count(&self, x: usize, y: usize, width: usize, height: usize) -> usize {
+        let rect = self.clip(x, y, width, height);
+        rect.width * rect.height
+    }
+
+    /// Mean of each band over the rectangle, clipped to the image. Zero when empty.
+    pub fn mean(&self, x: usize, y: usize, width: usize, height: usize) -> Vec<f64> {
+        let rect = self.clip(x, y, width, height);
+        let count = (rect.width * rect.height).max(1) as f64;
+
+        (self.rect_sums(&self.sums, rect) / count).into_raw_vec()
+    }
+
+    /// Variance of the pixel values over the rectangle, clipped to the image,
+    /// averaged over the bands as in [`node_variances`](crate::node_variances).
+    pub fn variance(&self, x: usize, y: usize, width: usize, height: usize) -> f64 {
+        let rect = self.clip(x, y, width, height);
+        let channels = self.sums.dim().2;
+
+        self.squared_errors(rect).sum() / ((rect.width * rect.height).max(1) * channels) as f64
+    }
+
+    /// Sum of the squared deviations from the band means over the rectangle, clipped
+    /// to the image, i.e. the Ward fidelity of a region filling it, summed over the bands.
+    pub fn squared_error(&self, x: usize, y: usize, width: usize, height: usize) -> f64 {
+        self.squared_errors(self.clip(x, y, width, height)).sum()
+    }
+
+    /// Memory held by the tables, in bytes.
+    pub fn byte_size(&self) -> usize {
+        size_of::<Self>() + (self.sums.len() + self.squares.len()) * size_of::<f64>()
+    }
+}
+
+impl IntegralImage {
+    pub fn from_array(img: &Array3<u8>) -> Self {
+        let (height, width, channels) = img.dim();
+        let mut sums = Array3::zeros((height + 1, width + 1, channels));
+        let mut squares = Array3::zeros((height + 1, width + 1, channels));
+
+        for ((y, x, c), value) in img.indexed_iter() {
+            let value = f64::from(*value);
+            for (table, value) in [(&mut sums, value), (&mut squares, value * value)] {
+                table[[y + 1, x + 1, c]] =
+                    value + table[[y, x + 1, c]] + table[[y + 1, x, c]] - table[[y, x, c]];
+            }
+        }
+
+        Self { sums, squares }
+    }
+
+    fn clip(&self, x: usize, y: usize, width: usize, height: usize) -> Rect {
+        let (rows, cols, _) = self.sums.dim();
+        Rect {
+            x,
+            y,
+            width,
+            height,
+        }
+        .expanded(0, cols - 1, rows - 1)
+    }
+
+    /// Sum of each band of a table over a clipped rectangle.
+    fn rect_sums(&self, table: &Array3<f64>, rect: Rect) -> Array1<f64> {
+        let (bottom, right) = (rect.y + rect.height, rect.x + rect.width);
+        let corner = |y: usize, x: usize| table.slice(s![y, x, ..]);
+
+        &corner(bottom, right) - &corner(rect.y, right) - corner(bottom, rect.x)
+            + corner(rect.y, rect.x)
+    }
+
+    /// Sum of the squared deviations of each band over a clipped rectangle.
+    fn squared_errors(&self, rect: Rect) -> Array1<f64> {
+        let count = (rect.width * rect.height).max(1) as f64;
+        let sums = self.rect_sums(&self.sums, rect);
+        let squares = self.rect_sums(&self.squares, rect);
+
+        (squares - &sums * &sums / count).mapv(|error| error.max(0.))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_integral_image() {
+        // Band 0 is x, band 1 is constant
+        let img = Array3::from_shape_fn((4, 6, 2), |(_, x, c)| if c == 0 { x as u8 } else { 7 });
+        let integral = IntegralImage::from_array(&img);
+
+        assert_eq!(integral.count(1, 1, 3, 2), 6);
+        assert_eq!(integral.mean(1, 1, 3, 2), vec![2., 7.]);
+        // Values 1, 2 and 3 twice each
+        assert_eq!(integral.squared_error(1, 1, 3, 2), 4.);
+        assert_eq!(integral.variance(1, 1, 3, 2), 4. / 12.);
+
+        // Clipped to the image
+        assert_eq!(integral.count(4, 2, 10, 10), 4);
+        assert_eq!(integral.mean(10, 10, 2, 2), vec![0., 0.]);
+    }
+}
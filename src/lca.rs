@@ -0,0 +1,181 @@
+use std::mem::size_of;
+
+#[cfg(feature = "wasm")]
+use wasm_bindgen::prelude::wasm_bindgen;
+
+/// Lowest common ancestor of two nodes of a partition tree, `None` if they are in
+/// different trees. Walks up the tree, so [`LcaIndex`] is faster for many queries.
+pub fn lowest_common_ancestor(parents: &[usize], mut a: usize, mut b: usize) -> Option<usize> {
+    // Parents always come after their children, so the lower node is never the ancestor
+    while a != b {
+        let lower = if a < b { &mut a } else { &mut b };
+        let parent = *parents.get(*lower)?;
+        if parent == *lower {
+            return None;
+        }
+        *lower = parent;
+    }
+
+    Some(a)
+}
+
+/// Lowest common ancestor queries on a partition tree in constant time, from an
+/// Euler tour of the tree and a sparse table of its minimum depths.
+///
+/// Answers at which level two regions, e.g. clicked by the user, merge.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Clone, Debug)]
+pub struct LcaIndex {
+    levels: Vec<f64>,
+    depths: Vec<usize>,
+    roots: Vec<usize>,
+    /// Position of the first visit of each node in the tour
+    first: Vec<usize>,
+    /// Shallowest node of the tour in `[i, i + 2^k)`, at `table[k][i]`
+    table: Vec<Vec<usize>>,
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+impl LcaIndex {
+    /// Lowest common ancestor of two nodes, `None` if they are in different trees,
+    /// e.g. on both sides of a barrier.
+    pub fn lca(&self, a: usize, b: usize) -> Option<usize> {
+        if self.roots.get(a)? != self.roots.get(b)? {
+            return None;
+        }
+
+        let (start, end) = if self.first[a] <= self.first[b] {
+            (self.first[a], self.first[b] + 1)
+        } else {
+            (self.first[b], self.first[a] + 1)
+        };
+        let k = (end - start).ilog2() as usize;
+
+        Some(self.shallowest(self.table[k][start], self.table[k][end - (1 << k)]))
+    }
+
+    /// Level at which two nodes are merged, the level of their lowest common ancestor.
+    pub fn merge_level_of(&self, a: usize, b: usize) -> Option<f64> {
+        self.lca(a, b).map(|node| self.levels[node])
+    }
+
+    /// Memory held by the index, in bytes.
+    pub fn byte_size(&self) -> usize {
+        size_of::<Self>()
+            + self.levels.len() * size_of::<f64>()
+            + (self.depths.len() + self.roots.len() + self.first.len()) * size_of::<usize>()
+            + self
+                .table
+                .iter()
+                .map(|row| size_of::<Vec<usize>>() + row.len() * size_of::<usize>())
+                .sum::<usize>()
+    }
+}
+
+impl LcaIndex {
+    pub fn new(parents: &[usize], levels: &[f64]) -> Self {
+        let n_nodes = parents.len();
+
+        let mut children = vec![Vec::new(); n_nodes];
+        for (node, &parent) in parents.iter().enumerate() {
+            if parent != node {
+                children[parent].push(node);
+            }
+        }
+
+        // Parents always come after their children
+        let mut depths = vec![0; n_nodes];
+        let mut roots = (0..n_nodes).collect::<Vec<_>>();
+        for node in (0..n_nodes).rev() {
+            let parent = parents[node];
+            if parent != node {
+                depths[node] = depths[parent] + 1;
+                roots[node] = roots[parent];
+            }
+        }
+
+        // Nodes are visited on the way down, and again after each of their children
+        let mut tour = Vec::with_capacity(2 * n_nodes);
+        let mut first = vec![0; n_nodes];
+        for root in (0..n_nodes).filter(|node| parents[*node] == *node) {
+            first[root] = tour.len();
+            tour.push(root);
+
+            let mut stack = vec![(root, 0)];
+            while let Some((node, next)) = stack.last_mut() {
+                if let Some(&child) = children[*node].get(*next) {
+                    *next += 1;
+                    first[child] = tour.len();
+                    tour.push(child);
+                    stack.push((child, 0));
+                } else {
+                    stack.pop();
+                    if let Some((parent, _)) = stack.last() {
+                        tour.push(*parent);
+                    }
+                }
+            }
+        }
+
+        let mut index = Self {
+            levels: levels.to_vec(),
+            depths,
+            roots,
+            first,
+            table: vec![tour],
+        };
+        while let Some(previous) = index.table.last() {
+            let half = 1 << (index.table.len() - 1);
+            if previous.len() <= half {
+                break;
+            }
+
+            let row = (0..previous.len() - half)
+                .map(|i| index.shallowest(previous[i], previous[i + half]))
+                .collect();
+            index.table.push(row);
+        }
+
+        index
+    }
+
+    fn shallowest(&self, a: usize, b: usize) -> usize {
+        if self.depths[a] <= self.depths[b] {
+            a
+        } else {
+            b
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lca() {
+        // Node 5 merges leaves 0 and 1, node 6 merges node 5 and leaf 2; leaf 3 is
+        // alone in its tree, e.g. behind a barrier
+        let parents = vec![5, 5, 6, 3, 7, 6, 7, 7];
+        let levels = vec![0., 0., 0., 0., 0., 1., 2., 4.];
+        let index = LcaIndex::new(&parents, &levels);
+
+        assert_eq!(index.lca(0, 1), Some(5));
+        assert_eq!(index.lca(1, 2), Some(6));
+        assert_eq!(index.lca(2, 5), Some(6));
+        assert_eq!(index.lca(0, 4), Some(7));
+        assert_eq!(index.lca(5, 0), Some(5));
+        assert_eq!(index.lca(2, 2), Some(2));
+        assert_eq!(index.lca(0, 3), None);
+        assert_eq!(index.lca(0, 8), None);
+
+        assert_eq!(index.merge_level_of(0, 2), Some(2.));
+        assert_eq!(index.merge_level_of(4, 1), Some(4.));
+
+        for a in 0..parents.len() {
+            for b in 0..parents.len() {
+                assert_eq!(lowest_common_ancestor(&parents, a, b), index.lca(a, b));
+            }
+        }
+    }
+}
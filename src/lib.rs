@@ -1,37 +1,273 @@
+mod annotation;
+#[cfg(feature = "wasm")]
+mod async_build;
+mod attributes;
+mod barrier;
+mod binary;
+mod classify;
+mod cog;
+mod compare;
+mod component_tree;
+mod compress;
+mod cut;
+#[cfg(feature = "ffi")]
+mod ffi;
+mod fingerprint;
+mod geo;
+#[cfg(feature = "geopackage")]
+mod geopackage;
+mod gradient;
 mod graph;
 mod hierarchy;
+mod integral;
+mod lca;
 mod logger;
+#[cfg(feature = "wasm")]
+mod memory;
+mod mst;
+mod mvt;
+mod normalize;
+mod params;
+mod pixels;
 mod plef;
+mod polygon;
+mod prelude;
+mod preset;
+#[cfg(feature = "proj")]
+mod proj;
+mod pyramid;
+#[cfg(feature = "python")]
+mod python;
+mod render;
+mod resample;
+mod saliency;
 mod seed;
+mod selection;
+mod session;
+mod shadow;
+#[cfg(feature = "shapefile")]
+mod shapefile;
+#[cfg(feature = "simd")]
+mod simd;
+mod simplify;
 mod slic;
 mod slic_helpers;
+mod smooth;
+#[cfg(feature = "geopackage")]
+mod sqlite;
+mod statistics;
+mod summary;
+mod temporal;
+mod thumbnail;
+mod tiles;
 mod utils;
+mod vector;
 
-use graph::graph_from_labels;
-use hierarchy::PartitionTree;
-use slic::slic;
+use barrier::split_by_raster;
+use binary::{write_f64s, write_usizes, Reader};
+use classify::{majority_classes, mean_probabilities, snap_classes};
+use compare::{compare_partitions, vi_curve};
+use compress::{compress_labels, decompress_labels};
+use cut::{
+    cut_by_merge_count, fill_enclosed_regions, leaf_count, merge_small_regions, region_adjacency,
+    region_label_anchors, stable_scales,
+};
+use logger::Span;
+use mst::minimum_spanning_tree;
+use normalize::normalize_bands;
+use saliency::{saliency_map, saliency_to_png};
+use slic::{slic_with_info, Seeding, SlicInfo};
+use thumbnail::{descendants, node_thumbnail};
 
-use hierarchy::binary_partition_tree;
-use ndarray::{Array2, Array3};
-use std::{collections::HashMap, panic};
-use utils::array_to_rgba_bitmap;
-use wasm_bindgen::prelude::*;
+pub use annotation::{AnnotationSession, SessionError, SESSION_VERSION};
+#[cfg(feature = "wasm")]
+pub use async_build::build_hierarchy_async_wasm;
+pub use attributes::{
+    flood_select, grow_from_seeds, node_variances, top_regions, NodeAttributes, SaliencyScore,
+};
+pub use barrier::{remove_barrier_edges, split_by_barrier};
+pub use cog::GeoTiffReader;
+pub use compare::{
+    change_map, match_regions, region_means, ChangeMap, RegionMatching, MIN_MATCH_IOU,
+};
+pub use component_tree::{component_tree, normalized_difference, ComponentTreeKind};
+pub use compress::CompressedLabels;
+pub use fingerprint::{auxiliary_fingerprint, hierarchy_fingerprint, input_fingerprint};
+pub use geo::GeoTransform;
+#[cfg(feature = "geopackage")]
+pub use geopackage::cut_to_geopackage;
+pub use gradient::{gradient_magnitude, GradientOperator};
+pub use graph::{
+    add_boundary_probabilities, apparition_scale, graph_from_labels, graph_from_labels_with_buffer,
+    graph_from_labels_with_fidelity, node_index, normalized_apparition_scale, patch_graph,
+    prune_graph, ward_distance, FidelityModel, FidelityParams, GraphIndex, Label, LabelOverflow,
+    SpatialMoments, SuperpixelEdge, SuperpixelEdgeIndex, SuperpixelGraph, SuperpixelNode,
+    SuperpixelNodeIndex, Wrap,
+};
+pub use hierarchy::{
+    CriterionOptions, InternalError, MergeCriterion, MergeHook, PartialPartitionTree,
+    PartitionTree, StopCondition, TieBreak,
+};
+pub use integral::IntegralImage;
+pub use lca::{lowest_common_ancestor, LcaIndex};
+pub use logger::{set_log_level, LogLevel};
+#[cfg(feature = "wasm")]
+pub use memory::wasm_memory_bytes;
+pub use mvt::{cut_to_vector_tiles, VectorTile, MVT_EXTENT};
+pub use normalize::Normalization;
+pub use params::{auto_n_clusters, SegmentationParams};
+pub use pixels::LeafPixels;
+pub use polygon::{rasterize_polygon, regions_in_polygon};
+pub use preset::Preset;
+#[cfg(feature = "proj")]
+pub use proj::transformer;
+pub use pyramid::{HierarchyPyramid, PyramidLevel};
+pub use render::{
+    render_class_map, render_cut_sprite_sheet, render_cut_window, render_legend_strip, CutStyle,
+    FillPattern, Legend, Palette, Viewport,
+};
+pub use resample::{resample_labels, LabelOverviews};
+pub use saliency::Colormap;
+pub use seed::SeedGrid;
+pub use selection::{
+    selected_nodes, selection_difference, selection_intersection, selection_union,
+    split_selection_by_level,
+};
+pub use session::{SegmentationSession, SlicReport};
+pub use shadow::{add_shadows, shadow_mask};
+#[cfg(feature = "shapefile")]
+pub use shapefile::{cut_to_shapefile, Shapefile};
+pub use simplify::{compact, simplify_by_lifetime};
+pub use slic::{
+    refine_boundaries, refine_superpixels_local, slic, slic_with_density, slic_with_distances,
+};
+pub use smooth::{smooth_outlines, SmoothRing, Smoothing};
+pub use statistics::StatisticsBuffer;
+pub use summary::{hierarchy_summary, HierarchySummary};
+pub use temporal::TileSequence;
+pub use thumbnail::Thumbnail;
+pub use tiles::{cut_to_xyz_tiles, XyzTile, MAX_ZOOM};
+pub use utils::Rect;
+#[cfg(feature = "proj")]
+pub use vector::cut_to_geojson_reprojected;
+pub use vector::{cut_to_geojson, cut_to_svg, region_outlines, Ring};
 
+use crate::prelude::*;
+use hierarchy::{alpha_tree, binary_partition_tree};
+use ndarray::{s, Array2, Array3, ArrayView2, Axis};
+use std::{
+    collections::HashMap,
+    mem::{size_of, size_of_val},
+};
+use utils::{
+    array_from_planar, array_to_planar, array_to_rgba_bitmap, band_count, decode_image,
+    labels_to_vec, rgba_to_png,
+};
+
+#[cfg(feature = "wasm")]
 #[wasm_bindgen(start)]
 pub fn init() {
-    panic::set_hook(Box::new(console_error_panic_hook::hook));
+    std::panic::set_hook(Box::new(console_error_panic_hook::hook));
 }
 
-pub fn hierarchical_segmentation(
-    img: Array3<u8>,
-    n_clusters: usize,
-) -> (Array2<usize>, PartitionTree) {
+/// Compute the SLIC superpixels of the image and their region adjacency graph.
+///
+/// The graph can be used to build a [`PartialPartitionTree`] when the
+/// construction has to be stopped and resumed.
+pub fn superpixel_graph(
+    img: &Array3<u8>,
+    params: &SegmentationParams,
+) -> (Array2<usize>, SuperpixelGraph) {
+    let img = normalize_bands(img, params.normalization);
+
+    build_superpixel_graph(
+        img.as_ref(),
+        params,
+        &mut SlicInfo::new(),
+        &mut StatisticsBuffer::default(),
+        None,
+        None,
+    )
+}
+
+/// Same as [`superpixel_graph`], with the superpixels split along a barrier raster
+/// (e.g. a rasterized road network) and the edges crossing it removed, so that the
+/// hierarchy never merges across the barriers. See [`split_by_barrier`].
+pub fn superpixel_graph_with_barriers(
+    img: &Array3<u8>,
+    barrier: &Array2<bool>,
+    params: &SegmentationParams,
+) -> (Array2<usize>, SuperpixelGraph) {
+    let img = normalize_bands(img, params.normalization);
+
+    build_superpixel_graph(
+        img.as_ref(),
+        params,
+        &mut SlicInfo::new(),
+        &mut StatisticsBuffer::default(),
+        Some(barrier),
+        None,
+    )
+}
+
+/// Same as [`superpixel_graph`] on an already normalized image, reusing the SLIC and
+/// superpixel statistics buffers. SLIC seeds follow `density` when given, see
+/// [`slic_with_density`].
+pub(crate) fn build_superpixel_graph(
+    img: &Array3<u8>,
+    params: &SegmentationParams,
+    slic_info: &mut SlicInfo<f64, usize>,
+    statistics: &mut StatisticsBuffer,
+    barrier: Option<&Array2<bool>>,
+    seeding: Option<Seeding<'_>>,
+) -> (Array2<usize>, SuperpixelGraph) {
+    let params = &params.resolved(img);
+    if !(params.pixel_aspect > 0. && params.pixel_aspect.is_finite()) {
+        throw_str("Pixel aspect must be positive");
+    }
+
+    let span = Span::enter("slic");
     console_log!("Running SLIC...");
-    let labels = slic(n_clusters as u32, 1, Some(1), &img).expect_throw("SLIC failed");
+    let mut labels = slic_with_info(
+        params.n_clusters as u32,
+        params.compactness,
+        Some(params.slic_iterations),
+        params.slic_threshold,
+        seeding.unwrap_or(Seeding::Grid(params.seed_grid)),
+        params.pixel_aspect,
+        img,
+        slic_info,
+    )
+    .expect_throw("SLIC failed");
+    console_log!(
+        "SLIC: {} iterations, residual {:.3}",
+        slic_info.iterations,
+        slic_info.residual
+    );
+    refine_boundaries(img, &mut labels, params.adherence_sweeps);
+    drop(span);
 
-    console_log!("Creating graph from segmentation...");
+    let _span = Span::enter("graph");
+    let mut barrier_regions = None;
+    if let Some(barrier) = barrier {
+        let (split_labels, regions) = split_by_barrier(&labels, barrier);
+        labels = split_labels;
+        barrier_regions = Some(regions);
+    }
+
+    let mut graph = graph_with_params(img, &labels, params, statistics);
+    if params.shadow_weight > 0. {
+        let shadows = shadow_mask(img, params.nir_channel, params.elevation_channel);
+        add_shadows(&mut graph, &labels, &shadows);
+    }
+
+    if let Some(barrier_regions) = barrier_regions {
+        remove_barrier_edges(&mut graph, &barrier_regions);
+    }
 
-    let graph = graph_from_labels(&img, &labels);
+    if params.prune_tolerance > 0. {
+        (labels, graph) = prune_graph(&graph, &labels, params.prune_tolerance);
+    }
 
     console_log!(
         "Nodes: {},  Edges: {}",
@@ -39,21 +275,373 @@ pub fn hierarchical_segmentation(
         graph.edge_count()
     );
 
-    let partition_tree = binary_partition_tree(graph);
+    (labels, graph)
+}
+
+/// Region adjacency graph of a segmentation, with the fidelity model of the parameters.
+fn graph_with_params(
+    img: &Array3<u8>,
+    labels: &Array2<usize>,
+    params: &SegmentationParams,
+    statistics: &mut StatisticsBuffer,
+) -> SuperpixelGraph {
+    console_log!("Creating graph from segmentation...");
+
+    let channels = img.dim().2;
+    if matches!(params.elevation_channel, Some(c) if c >= channels) {
+        throw_str("Elevation channel out of range");
+    }
+    if matches!(params.nir_channel, Some(c) if c >= channels) {
+        throw_str("Near infrared channel out of range");
+    }
+
+    let fidelity = params.fidelity_params(channels);
+    graph_from_labels_with_buffer(img, labels, fidelity, statistics)
+        .unwrap_or_else(|error| throw_str(&error.to_string()))
+}
+
+pub fn hierarchical_segmentation(
+    img: Array3<u8>,
+    n_clusters: usize,
+) -> (Array2<usize>, PartitionTree) {
+    hierarchical_segmentation_with_params(img, &SegmentationParams::new(n_clusters))
+}
+
+pub fn hierarchical_segmentation_with_params(
+    img: Array3<u8>,
+    params: &SegmentationParams,
+) -> (Array2<usize>, PartitionTree) {
+    let _span = Span::enter("segmentation");
+    let (labels, graph) = superpixel_graph(&img, params);
+
+    let partition_tree = binary_partition_tree(graph, params.criterion_options())
+        .unwrap_or_else(|error| throw_str(&error.to_string()));
+
+    (labels, partition_tree)
+}
+
+/// Same as [`hierarchical_segmentation_with_params`], accumulating the statistics of
+/// the superpixels in a caller-provided buffer, e.g. reused across the tiles
+/// processed by a worker.
+pub fn hierarchical_segmentation_with_buffer(
+    img: Array3<u8>,
+    params: &SegmentationParams,
+    statistics: &mut StatisticsBuffer,
+) -> (Array2<usize>, PartitionTree) {
+    let _span = Span::enter("segmentation");
+    let img = normalize_bands(&img, params.normalization);
+    let (labels, graph) = build_superpixel_graph(
+        img.as_ref(),
+        params,
+        &mut SlicInfo::new(),
+        statistics,
+        None,
+        None,
+    );
+
+    let partition_tree = binary_partition_tree(graph, params.criterion_options())
+        .unwrap_or_else(|error| throw_str(&error.to_string()));
+
+    (labels, partition_tree)
+}
+
+/// Same as [`hierarchical_segmentation_with_params`], with a per-pixel boundary
+/// probability map guiding the merges, see [`PartialPartitionTree::with_boundary_weight`].
+pub fn hierarchical_segmentation_with_boundary(
+    img: Array3<u8>,
+    boundary: &Array2<f32>,
+    params: &SegmentationParams,
+) -> (Array2<usize>, PartitionTree) {
+    let (labels, mut graph) = superpixel_graph(&img, params);
+    add_boundary_probabilities(&mut graph, &labels, boundary);
+
+    let mut tree = PartialPartitionTree::new(graph)
+        .with_options(params.criterion_options())
+        .with_boundary_weight(params.boundary_weight);
+    tree.merge_until(None)
+        .unwrap_or_else(|error| throw_str(&error.to_string()));
+
+    (labels, tree.into_partition_tree())
+}
+
+/// Same as [`hierarchical_segmentation_with_params`], never merging across the
+/// barriers, see [`superpixel_graph_with_barriers`]. The tree has several roots
+/// when barriers split the image.
+pub fn hierarchical_segmentation_with_barriers(
+    img: Array3<u8>,
+    barrier: &Array2<bool>,
+    params: &SegmentationParams,
+) -> (Array2<usize>, PartitionTree) {
+    let (labels, graph) = superpixel_graph_with_barriers(&img, barrier, params);
+
+    let partition_tree = binary_partition_tree(graph, params.criterion_options())
+        .unwrap_or_else(|error| throw_str(&error.to_string()));
+
+    (labels, partition_tree)
+}
+
+/// Same as [`hierarchical_segmentation_with_params`], with superpixels smaller where
+/// `density` is high, see [`slic_with_density`].
+pub fn hierarchical_segmentation_with_density(
+    img: Array3<u8>,
+    density: &Array2<f32>,
+    params: &SegmentationParams,
+) -> (Array2<usize>, PartitionTree) {
+    let img = normalize_bands(&img, params.normalization);
+    let (labels, graph) = build_superpixel_graph(
+        img.as_ref(),
+        params,
+        &mut SlicInfo::new(),
+        &mut StatisticsBuffer::default(),
+        None,
+        Some(Seeding::Density(density)),
+    );
+
+    let partition_tree = binary_partition_tree(graph, params.criterion_options())
+        .unwrap_or_else(|error| throw_str(&error.to_string()));
 
     (labels, partition_tree)
 }
 
-#[wasm_bindgen(getter_with_clone)]
+/// Same as [`hierarchical_segmentation_with_params`], snapped to a raster of existing
+/// parcels (e.g. a land registry): the superpixels are split along the parcels, and
+/// below `level` no region spans several parcels, see
+/// [`PartialPartitionTree::with_parcels`].
+pub fn hierarchical_segmentation_snapped(
+    img: Array3<u8>,
+    parcels: &Array2<usize>,
+    level: f64,
+    params: &SegmentationParams,
+) -> (Array2<usize>, PartitionTree) {
+    let (height, width, _) = img.dim();
+    if parcels.dim() != (height, width) {
+        throw_str("Parcel raster wrong shape");
+    }
+    let img = normalize_bands(&img, params.normalization);
+
+    // The superpixels are split after pruning, which could merge across parcels
+    let mut statistics = StatisticsBuffer::default();
+    let (labels, _) = build_superpixel_graph(
+        img.as_ref(),
+        params,
+        &mut SlicInfo::new(),
+        &mut statistics,
+        None,
+        None,
+    );
+    let (labels, region_parcels) = split_by_raster(&labels, parcels);
+    let graph = graph_with_params(img.as_ref(), &labels, params, &mut statistics);
+
+    let mut tree = PartialPartitionTree::new(graph)
+        .with_options(params.criterion_options())
+        .with_parcels(region_parcels, level);
+    tree.merge_until(None)
+        .unwrap_or_else(|error| throw_str(&error.to_string()));
+
+    (labels, tree.into_partition_tree())
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen(getter_with_clone))]
 #[derive(Clone, Debug)]
 pub struct Hierarchy {
     pub labels: Vec<usize>,
     pub parents: Vec<usize>,
     pub levels: Vec<f64>,
     pub max_level: f64,
+    /// Fingerprint of the image and parameters the hierarchy was built from, 0 if
+    /// unknown. See [`input_fingerprint`].
+    pub input_fingerprint: u64,
+    // Built from the labels at construction
+    leaf_pixels: LeafPixels,
 }
 
-#[wasm_bindgen]
+const HIERARCHY_MAGIC: &[u8; 4] = b"HLHI";
+const HIERARCHY_VERSION: u32 = 1;
+
+impl Hierarchy {
+    fn new(labels: &Array2<usize>, tree: PartitionTree) -> Self {
+        let max_level = tree.levels.iter().fold(0.0f64, |acc, l| acc.max(*l));
+
+        Self {
+            labels: labels_to_vec(labels),
+            parents: tree.parents,
+            levels: tree.levels,
+            max_level,
+            input_fingerprint: 0,
+            leaf_pixels: LeafPixels::new(labels),
+        }
+    }
+
+    fn with_input_fingerprint(mut self, input_fingerprint: u64) -> Self {
+        self.input_fingerprint = input_fingerprint;
+        self
+    }
+
+    /// Same hierarchy with another tree on the same leaves.
+    fn with_tree(&self, (parents, levels): (Vec<usize>, Vec<f64>)) -> Self {
+        Self {
+            labels: self.labels.clone(),
+            parents,
+            levels,
+            max_level: self.max_level,
+            input_fingerprint: self.input_fingerprint,
+            leaf_pixels: self.leaf_pixels.clone(),
+        }
+    }
+
+    /// Save the hierarchy, to be loaded with [`Hierarchy::from_bytes`]. The format is
+    /// the one of annotation sessions: magic bytes, version, input fingerprint, then
+    /// the labels, parents and levels as length-prefixed lists.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = HIERARCHY_MAGIC.to_vec();
+        bytes.extend(HIERARCHY_VERSION.to_le_bytes());
+        bytes.extend(self.input_fingerprint.to_le_bytes());
+        write_usizes(&mut bytes, &self.labels);
+        write_usizes(&mut bytes, &self.parents);
+        write_f64s(&mut bytes, &self.levels);
+
+        bytes
+    }
+
+    /// Load a hierarchy of an image of the given `(height, width)`, `None` if the
+    /// bytes are not a valid hierarchy of that size.
+    pub fn from_bytes(bytes: &[u8], shape: (usize, usize)) -> Option<Self> {
+        let mut reader = Reader::new(bytes);
+        if reader.take(4)? != HIERARCHY_MAGIC || reader.u32()? != HIERARCHY_VERSION {
+            return None;
+        }
+        let input_fingerprint = reader.u64()?;
+        let labels = reader.usizes()?;
+        let parents = reader.usizes()?;
+        let levels = reader.f64s()?;
+        if !reader.is_empty() || parents.len() != levels.len() {
+            return None;
+        }
+
+        // Parents always come after their children
+        let valid_parents = parents
+            .iter()
+            .enumerate()
+            .all(|(node, parent)| (node..parents.len()).contains(parent));
+        let leaves = leaf_count(&parents);
+        if !valid_parents || labels.iter().any(|label| *label >= leaves) {
+            return None;
+        }
+
+        let labels = Array2::from_shape_vec(shape, labels).ok()?;
+        let tree = PartitionTree {
+            parents,
+            levels,
+            merges: Vec::new(),
+        };
+
+        Some(Self::new(&labels, tree).with_input_fingerprint(input_fingerprint))
+    }
+
+    /// Pixels of each leaf.
+    pub fn leaf_pixel_index(&self) -> &LeafPixels {
+        &self.leaf_pixels
+    }
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+impl Hierarchy {
+    /// Memory held by the hierarchy, in bytes.
+    pub fn byte_size(&self) -> usize {
+        size_of::<Self>()
+            + size_of_val(self.labels.as_slice())
+            + size_of_val(self.parents.as_slice())
+            + size_of_val(self.levels.as_slice())
+            + self.leaf_pixels.byte_size()
+    }
+
+    /// Pixels of a leaf, as run-length encoded row-major indices: pairs of the first
+    /// pixel index and the length of each run.
+    pub fn leaf_pixels(&self, leaf: usize) -> Vec<usize> {
+        if leaf >= self.leaf_pixels.leaf_count() {
+            throw_str("Leaf out of range");
+        }
+
+        self.leaf_pixels
+            .runs(leaf)
+            .flat_map(|(start, length)| [start, length])
+            .collect()
+    }
+
+    /// Sparser hierarchy without the regions living less than `epsilon` (parent
+    /// level minus own level), their children being attached to their parent.
+    /// The leaves are unchanged. See [`simplify_by_lifetime`].
+    pub fn simplify_by_lifetime(&self, epsilon: f64) -> Hierarchy {
+        self.with_tree(simplify_by_lifetime(&self.parents, &self.levels, epsilon))
+    }
+
+    /// Hierarchy without unary nodes, nodes merged at the same level being merged
+    /// into n-ary nodes, e.g. for a dendrogram. The leaves are unchanged. See
+    /// [`compact`].
+    pub fn compact(&self) -> Hierarchy {
+        self.with_tree(compact(&self.parents, &self.levels))
+    }
+
+    /// Index of the lowest common ancestors of the nodes, e.g. to find at which
+    /// level two clicked regions merge.
+    pub fn lca_index(&self) -> LcaIndex {
+        LcaIndex::new(&self.parents, &self.levels)
+    }
+
+    /// Fingerprint of the leaves and tree, e.g. to check that an annotation session
+    /// was made on this hierarchy. See [`hierarchy_fingerprint`].
+    pub fn fingerprint(&self) -> u64 {
+        hierarchy_fingerprint(&self.labels, &self.parents, &self.levels)
+    }
+
+    /// Mask of the pixels of a node, one byte per pixel, 1 inside the node.
+    pub fn node_mask(&self, node: usize) -> Vec<u8> {
+        if node >= self.parents.len() {
+            throw_str("Node out of range");
+        }
+        let members = descendants(&self.parents, node);
+
+        self.labels
+            .iter()
+            .map(|leaf| members[*leaf] as u8)
+            .collect()
+    }
+
+    /// Leaf containing a pixel, given by its row-major index.
+    pub fn pixel_leaf(&self, pixel: usize) -> usize {
+        *self.labels.get(pixel).expect_throw("Pixel out of range")
+    }
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen(getter_with_clone))]
+#[derive(Clone, Debug)]
+pub struct DecodedImage {
+    pub data: Vec<u8>,
+    pub width: usize,
+    pub height: usize,
+    pub channels: usize,
+    /// Georeferencing of GeoTIFF images
+    pub geo_transform: Option<GeoTransform>,
+}
+
+/// Decode a PNG, JPEG or TIFF image into planar data, ready to be passed to the
+/// other functions of the module. All bands are kept, including the alpha band,
+/// as well as the georeferencing of GeoTIFFs.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+pub fn decode_image_wasm(bytes: &[u8]) -> DecodedImage {
+    let img = decode_image(bytes).expect_throw("Failed to decode image");
+    let (height, width, channels) = img.dim();
+
+    DecodedImage {
+        data: array_to_planar(img.view()),
+        width,
+        height,
+        channels,
+        geo_transform: GeoTransform::from_geotiff(bytes),
+    }
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
 pub fn build_hierarchy_wasm(
     data: &[u8],
     width: usize,
@@ -61,99 +649,1752 @@ pub fn build_hierarchy_wasm(
     channels: usize,
     n_clusters: usize,
 ) -> Hierarchy {
-    let mut array = Array3::from_shape_vec((channels, height, width), data.to_vec())
-        .expect_throw("Data doesn't have the right shape");
-
-    array.swap_axes(0, 1);
-    array.swap_axes(1, 2);
+    let array = array_from_planar(data, width, height, channels);
+    let fingerprint = input_fingerprint(&array, &SegmentationParams::new(n_clusters));
 
     let (labels, tree) = hierarchical_segmentation(array, n_clusters);
 
-    let labels = labels.as_standard_layout();
-    let labels = labels.as_slice().unwrap();
-    let labels = labels.to_vec();
+    Hierarchy::new(&labels, tree).with_input_fingerprint(fingerprint)
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+pub fn build_hierarchy_with_params_wasm(
+    data: &[u8],
+    width: usize,
+    height: usize,
+    channels: usize,
+    params: &SegmentationParams,
+) -> Hierarchy {
+    let array = array_from_planar(data, width, height, channels);
+    let fingerprint = input_fingerprint(&array, params);
 
-    let max_level = tree.levels.iter().fold(0.0f64, |acc, l| acc.max(*l));
+    let (labels, tree) = hierarchical_segmentation_with_params(array, params);
 
-    Hierarchy {
-        labels,
-        parents: tree.parents,
-        levels: tree.levels,
-        max_level,
-    }
+    Hierarchy::new(&labels, tree).with_input_fingerprint(fingerprint)
 }
 
-#[wasm_bindgen]
-pub fn cut_hierarchy_wasm(hierarchy: &Hierarchy, level: f64) -> Vec<usize> {
-    let levels = hierarchy.levels.iter().cloned().enumerate();
+/// Same as [`build_hierarchy_with_params_wasm`], with the superpixel statistics held
+/// in `buffer`. The buffer lives in the WASM memory and keeps its allocation from
+/// one call to the next, so that a worker segmenting many tiles does not grow and
+/// shrink the heap for each of them. See [`StatisticsBuffer`].
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+pub fn build_hierarchy_with_buffer_wasm(
+    data: &[u8],
+    width: usize,
+    height: usize,
+    channels: usize,
+    params: &SegmentationParams,
+    buffer: &mut StatisticsBuffer,
+) -> Hierarchy {
+    let array = array_from_planar(data, width, height, channels);
+    let fingerprint = input_fingerprint(&array, params);
 
-    let mut label_rewrites = HashMap::<usize, Vec<usize>>::new();
+    let (labels, tree) = hierarchical_segmentation_with_buffer(array, params, buffer);
 
-    for (i, l) in levels {
-        if l >= level {
-            break;
-        }
+    Hierarchy::new(&labels, tree).with_input_fingerprint(fingerprint)
+}
 
-        let parent = hierarchy.parents[i];
+/// Shadowed pixels of an image, one byte per pixel, 1 in the shadows, e.g. to check
+/// the mask before setting [`SegmentationParams::shadow_weight`]. The near infrared
+/// and elevation bands are taken from the parameters. See [`shadow_mask`].
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+pub fn shadow_mask_wasm(
+    data: &[u8],
+    width: usize,
+    height: usize,
+    channels: usize,
+    params: &SegmentationParams,
+) -> Vec<u8> {
+    let array = array_from_planar(data, width, height, channels);
+    let mask = shadow_mask(&array, params.nir_channel, params.elevation_channel);
 
-        let children = label_rewrites.remove(&i);
-        let parent_family = label_rewrites.entry(parent).or_insert_with(Vec::new);
+    mask.iter().map(|shadow| *shadow as u8).collect()
+}
 
-        parent_family.push(i);
-        if let Some(children) = children {
-            parent_family.extend(children);
-        }
-    }
+/// Superpixel count picked for an image when `n_clusters` is 0, to be shown to the
+/// user as a starting point. See [`auto_n_clusters`].
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+pub fn auto_n_clusters_wasm(
+    data: &[u8],
+    width: usize,
+    height: usize,
+    channels: usize,
+    params: &SegmentationParams,
+) -> usize {
+    let array = array_from_planar(data, width, height, channels);
 
-    let mut label_mappings = (0..hierarchy.parents.len()).collect::<Vec<_>>();
-    for (parent, children) in label_rewrites {
-        for child in children {
-            label_mappings[child] = parent;
-        }
-    }
+    auto_n_clusters(&normalize_bands(&array, params.normalization))
+}
 
-    let mut distinct = label_mappings.clone();
-    distinct.sort();
-    distinct.dedup();
+/// Parameters of a preset for an image of `width * height` pixels, to be adjusted
+/// before building the hierarchy. See [`Preset::params`].
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+pub fn preset_params_wasm(preset: Preset, width: usize, height: usize) -> SegmentationParams {
+    preset.params(width, height)
+}
 
-    console_log!("Distinct labels: {}", distinct.len());
+/// Outline smoothing of a preset, for the polygon exports.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+pub fn preset_smoothing_wasm(preset: Preset) -> Smoothing {
+    preset.smoothing()
+}
 
-    let labels = hierarchy
-        .labels
-        .iter()
-        .cloned()
-        .map(|l| label_mappings[l])
-        .collect::<Vec<_>>();
+/// Build the hierarchy with a boundary probability map of `width * height` values
+/// between 0 and 1 (e.g. from an edge detection network): regions separated by a
+/// likely boundary are merged later.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+pub fn build_hierarchy_with_boundary_wasm(
+    data: &[u8],
+    width: usize,
+    height: usize,
+    channels: usize,
+    boundary: Vec<f32>,
+    params: &SegmentationParams,
+) -> Hierarchy {
+    let array = array_from_planar(data, width, height, channels);
+    let fingerprint = auxiliary_fingerprint(
+        input_fingerprint(&array, params),
+        &le_bytes(boundary.iter().map(|value| value.to_le_bytes())),
+    );
+    let boundary =
+        Array2::from_shape_vec((height, width), boundary).expect_throw("Boundary map wrong shape");
 
-    labels
+    let (labels, tree) = hierarchical_segmentation_with_boundary(array, &boundary, params);
+
+    Hierarchy::new(&labels, tree).with_input_fingerprint(fingerprint)
 }
 
-#[wasm_bindgen]
-pub fn display_labels_wasm(
-    mut img: Vec<u8>,
+/// Build the hierarchy without ever merging across a barrier raster of
+/// `width * height` values (e.g. a rasterized road network), non-zero values being
+/// barriers. Pixels on barriers form their own regions, and the tree has several
+/// roots when barriers split the image.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+pub fn build_hierarchy_with_barriers_wasm(
+    data: &[u8],
     width: usize,
     height: usize,
-    labels: Vec<usize>,
-) -> Vec<u8> {
-    // Only take first 3 channels
-    img.truncate(width * height * 3);
+    channels: usize,
+    barrier: Vec<u8>,
+    params: &SegmentationParams,
+) -> Hierarchy {
+    let array = array_from_planar(data, width, height, channels);
+    let fingerprint = auxiliary_fingerprint(input_fingerprint(&array, params), &barrier);
+    let barrier = Array2::from_shape_vec((height, width), barrier)
+        .expect_throw("Barrier raster wrong shape")
+        .mapv(|value| value != 0);
 
-    let mut img = Array3::from_shape_vec((3, height, width), img).expect_throw("Img wrong shape");
+    let (labels, tree) = hierarchical_segmentation_with_barriers(array, &barrier, params);
 
-    img.swap_axes(0, 1);
-    img.swap_axes(1, 2);
+    Hierarchy::new(&labels, tree).with_input_fingerprint(fingerprint)
+}
 
-    let labels = Array2::from_shape_vec((height, width), labels).expect_throw("Labels wrong shape");
+/// Build the hierarchy with a seed density raster of `width * height` values (e.g.
+/// a gradient magnitude, or higher values on an area of interest): superpixels are
+/// smaller where the density is high.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+pub fn build_hierarchy_with_density_wasm(
+    data: &[u8],
+    width: usize,
+    height: usize,
+    channels: usize,
+    density: Vec<f32>,
+    params: &SegmentationParams,
+) -> Hierarchy {
+    let array = array_from_planar(data, width, height, channels);
+    let fingerprint = auxiliary_fingerprint(
+        input_fingerprint(&array, params),
+        &le_bytes(density.iter().map(|value| value.to_le_bytes())),
+    );
+    let density =
+        Array2::from_shape_vec((height, width), density).expect_throw("Density map wrong shape");
 
-    for (i, row) in labels.outer_iter().enumerate().take(height - 1) {
-        for (j, label) in row.iter().enumerate().take(width - 1) {
-            if label != &labels[[i + 1, j]] || label != &labels[[i, j + 1]] {
-                img[[i, j, 0]] = 0;
-                img[[i, j, 1]] = 0;
-                img[[i, j, 2]] = 0;
-            }
-        }
+    let (labels, tree) = hierarchical_segmentation_with_density(array, &density, params);
+
+    Hierarchy::new(&labels, tree).with_input_fingerprint(fingerprint)
+}
+
+/// Gradient magnitude of each pixel, the maximum over the bands, e.g. to build a
+/// seed density map for [`build_hierarchy_with_density_wasm`].
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+pub fn gradient_magnitude_wasm(
+    data: &[u8],
+    width: usize,
+    height: usize,
+    channels: usize,
+    operator: GradientOperator,
+) -> Vec<f32> {
+    let array = array_from_planar(data, width, height, channels);
+
+    gradient_magnitude(&array, operator).into_raw_vec()
+}
+
+/// Build the hierarchy snapped to a raster of `width * height` parcel identifiers
+/// (e.g. rasterized land registry parcels): below `level`, regions never cross
+/// parcel boundaries, above it the merges ignore them.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+pub fn build_hierarchy_snapped_wasm(
+    data: &[u8],
+    width: usize,
+    height: usize,
+    channels: usize,
+    parcels: Vec<u32>,
+    level: f64,
+    params: &SegmentationParams,
+) -> Hierarchy {
+    let array = array_from_planar(data, width, height, channels);
+    // The level is hashed first, as it changes the tree like the parcels
+    let fingerprint = auxiliary_fingerprint(
+        input_fingerprint(&array, params),
+        &le_bytes(
+            [level.to_le_bytes().to_vec()]
+                .into_iter()
+                .chain(parcels.iter().map(|parcel| parcel.to_le_bytes().to_vec())),
+        ),
+    );
+    let parcels = Array2::from_shape_vec((height, width), parcels)
+        .expect_throw("Parcel raster wrong shape")
+        .mapv(|parcel| parcel as usize);
+
+    let (labels, tree) = hierarchical_segmentation_snapped(array, &parcels, level, params);
+
+    Hierarchy::new(&labels, tree).with_input_fingerprint(fingerprint)
+}
+
+/// Build the alpha-tree of the superpixel graph: regions at level `α` are the
+/// connected components of the edges whose weight is lower or equal to `α`.
+///
+/// Much faster than [`build_hierarchy_wasm`], but less accurate.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+pub fn build_alpha_tree_wasm(
+    data: &[u8],
+    width: usize,
+    height: usize,
+    channels: usize,
+    n_clusters: usize,
+) -> Hierarchy {
+    let array = array_from_planar(data, width, height, channels);
+    let params = SegmentationParams::new(n_clusters);
+    // Tagged, so that it is not taken for the hierarchy of the same image and params
+    let fingerprint =
+        auxiliary_fingerprint(input_fingerprint(&array, &params), &alpha_tree_auxiliary());
+
+    let (labels, graph) = superpixel_graph(&array, &params);
+    let tree = alpha_tree(&graph);
+
+    Hierarchy::new(&labels, tree).with_input_fingerprint(fingerprint)
+}
+
+/// Build the component tree of one band of the image. Leaves are the pixels.
+///
+/// With an `index_channel`, the band is the normalized difference of `channel` and
+/// `index_channel`, e.g. the NDVI from the near infrared and red channels, see
+/// [`normalized_difference`]. With `max_tree`, the tree extracts bright structures
+/// instead of dark ones.
+#[allow(clippy::too_many_arguments)]
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+pub fn build_component_tree_wasm(
+    data: &[u8],
+    width: usize,
+    height: usize,
+    channels: usize,
+    channel: usize,
+    index_channel: Option<usize>,
+    max_tree: bool,
+) -> Hierarchy {
+    let array = array_from_planar(data, width, height, channels);
+    if channel >= channels || index_channel.is_some_and(|index| index >= channels) {
+        throw_str("Channel out of range");
     }
+    let fingerprint = auxiliary_fingerprint(
+        input_fingerprint(&array, &SegmentationParams::new(0)),
+        &component_tree_auxiliary(channel, index_channel, max_tree),
+    );
 
-    array_to_rgba_bitmap(img.view())
+    let kind = if max_tree {
+        ComponentTreeKind::MaxTree
+    } else {
+        ComponentTreeKind::MinTree
+    };
+    let tree = match index_channel {
+        Some(index_channel) => component_tree(
+            normalized_difference(array.view(), channel, index_channel).view(),
+            kind,
+        ),
+        None => component_tree(array.index_axis(Axis(2), channel), kind),
+    };
+
+    let labels = Array2::from_shape_fn((height, width), |(y, x)| y * width + x);
+
+    Hierarchy::new(&labels, tree).with_input_fingerprint(fingerprint)
+}
+
+/// Auxiliary input of [`build_alpha_tree_wasm`] in its fingerprint, to be passed to
+/// [`load_hierarchy_wasm`] along with `SegmentationParams::new(n_clusters)`.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+pub fn alpha_tree_auxiliary() -> Vec<u8> {
+    b"alpha tree".to_vec()
+}
+
+/// Auxiliary input of [`build_component_tree_wasm`] in its fingerprint, to be
+/// passed to [`load_hierarchy_wasm`] along with `SegmentationParams::new(0)`.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+pub fn component_tree_auxiliary(
+    channel: usize,
+    index_channel: Option<usize>,
+    max_tree: bool,
+) -> Vec<u8> {
+    let mut bytes = (channel as u64).to_le_bytes().to_vec();
+    // u64::MAX without an index channel
+    bytes.extend(index_channel.map_or(u64::MAX, |c| c as u64).to_le_bytes());
+    bytes.push(max_tree as u8);
+    bytes
+}
+
+/// Concatenated little-endian bytes of the values of an auxiliary raster.
+fn le_bytes<B: AsRef<[u8]>>(values: impl Iterator<Item = B>) -> Vec<u8> {
+    values.fold(Vec::new(), |mut bytes, value| {
+        bytes.extend_from_slice(value.as_ref());
+        bytes
+    })
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen(getter_with_clone))]
+#[derive(Clone, Debug)]
+pub struct RagMst {
+    pub labels: Vec<usize>,
+    pub sources: Vec<usize>,
+    pub targets: Vec<usize>,
+    pub weights: Vec<f64>,
+}
+
+/// Minimum spanning tree of the superpixel adjacency graph. Edge `i` links the
+/// superpixels `sources[i]` and `targets[i]`, edges are sorted by increasing weight.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+pub fn rag_mst_wasm(
+    data: &[u8],
+    width: usize,
+    height: usize,
+    channels: usize,
+    n_clusters: usize,
+) -> RagMst {
+    let array = array_from_planar(data, width, height, channels);
+
+    let (labels, graph) = superpixel_graph(&array, &SegmentationParams::new(n_clusters));
+    let labels = labels_to_vec(&labels);
+
+    let mst = minimum_spanning_tree(&graph);
+
+    let mut sources = Vec::with_capacity(mst.len());
+    let mut targets = Vec::with_capacity(mst.len());
+    let mut weights = Vec::with_capacity(mst.len());
+
+    for edge_id in mst {
+        let (a, b) = graph.edge_endpoints(edge_id).unwrap();
+        sources.push(a.index());
+        targets.push(b.index());
+        weights.push(graph[edge_id].weight);
+    }
+
+    RagMst {
+        labels,
+        sources,
+        targets,
+        weights,
+    }
+}
+
+/// Cut of a hierarchy, each pixel being labelled by its region's tree node.
+#[cfg_attr(feature = "wasm", wasm_bindgen(getter_with_clone))]
+#[derive(Clone, Debug)]
+pub struct CutResult {
+    pub labels: Vec<usize>,
+    pub n_regions: usize,
+    /// Tree nodes of the regions, in increasing order
+    pub region_node_ids: Vec<usize>,
+    /// Level the hierarchy was cut at
+    pub level: f64,
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+pub fn cut_hierarchy_wasm(hierarchy: &Hierarchy, level: f64) -> CutResult {
+    let label_mappings = cut_node_labels(&hierarchy.parents, &hierarchy.levels, level);
+
+    // Regions are the cut nodes of the leaves
+    let mut is_region = vec![false; label_mappings.len()];
+    for leaf in 0..leaf_count(&hierarchy.parents) {
+        is_region[label_mappings[leaf]] = true;
+    }
+    let regions = (0..is_region.len())
+        .filter(|node| is_region[*node])
+        .collect::<Vec<_>>();
+
+    console_log!("Distinct labels: {}", regions.len());
+
+    let labels = hierarchy
+        .labels
+        .iter()
+        .cloned()
+        .map(|l| label_mappings[l])
+        .collect::<Vec<_>>();
+
+    CutResult {
+        labels,
+        n_regions: regions.len(),
+        region_node_ids: regions,
+        level,
+    }
+}
+
+/// Label of each tree node in the cut at `level`: the node of the cut containing it.
+pub(crate) fn cut_node_labels(parents: &[usize], levels: &[f64], level: f64) -> Vec<usize> {
+    let levels = levels.iter().cloned().enumerate();
+
+    let mut label_rewrites = HashMap::<usize, Vec<usize>>::new();
+
+    for (i, l) in levels {
+        if l >= level {
+            break;
+        }
+
+        let parent = parents[i];
+
+        let children = label_rewrites.remove(&i);
+        let parent_family = label_rewrites.entry(parent).or_insert_with(Vec::new);
+
+        parent_family.push(i);
+        if let Some(children) = children {
+            parent_family.extend(children);
+        }
+    }
+
+    let mut label_mappings = (0..parents.len()).collect::<Vec<_>>();
+    for (parent, children) in label_rewrites {
+        for child in children {
+            label_mappings[child] = parent;
+        }
+    }
+
+    label_mappings
+}
+
+/// Render the saliency map of the hierarchy as a PNG: region borders are colored
+/// by the level at which the regions are merged.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+pub fn saliency_png_wasm(
+    hierarchy: &Hierarchy,
+    width: usize,
+    height: usize,
+    log_scale: bool,
+    colormap: Colormap,
+) -> Vec<u8> {
+    let labels = Array2::from_shape_vec((height, width), hierarchy.labels.clone())
+        .expect_throw("Labels wrong shape");
+
+    let saliency = saliency_map(&labels, &hierarchy.parents, &hierarchy.levels);
+
+    saliency_to_png(&saliency, log_scale, colormap)
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen(getter_with_clone))]
+#[derive(Clone, Debug)]
+pub struct HierarchyComparison {
+    pub disagreement: Vec<u8>,
+    pub variation_of_information: f64,
+    pub rand_index: f64,
+}
+
+/// Compare the cuts of two hierarchies of the same image having `n_regions`
+/// regions each. `disagreement` is `1` for the pixels whose regions do not match.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+pub fn compare_hierarchies_wasm(
+    h1: &Hierarchy,
+    h2: &Hierarchy,
+    width: usize,
+    height: usize,
+    n_regions: usize,
+) -> HierarchyComparison {
+    let cut = |hierarchy: &Hierarchy| {
+        let labels = Array2::from_shape_vec((height, width), hierarchy.labels.clone())
+            .expect_throw("Labels wrong shape");
+        let merges = leaf_count(&hierarchy.parents).saturating_sub(n_regions);
+        cut_by_merge_count(&labels, &hierarchy.parents, merges)
+    };
+
+    let comparison = compare_partitions(&cut(h1), &cut(h2));
+
+    console_log!(
+        "Variation of information: {}, Rand index: {}",
+        comparison.variation_of_information,
+        comparison.rand_index
+    );
+
+    HierarchyComparison {
+        disagreement: labels_to_vec(&comparison.disagreement),
+        variation_of_information: comparison.variation_of_information,
+        rand_index: comparison.rand_index,
+    }
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen(getter_with_clone))]
+#[derive(Clone, Debug)]
+pub struct RegionMatches {
+    /// Matched nodes, `nodes_a[i]` of the first hierarchy with `nodes_b[i]` of the second
+    pub nodes_a: Vec<usize>,
+    pub nodes_b: Vec<usize>,
+    pub ious: Vec<f64>,
+    /// Regions of the first cut with no match in the second one
+    pub disappeared: Vec<usize>,
+    /// Regions of the second cut with no match in the first one
+    pub appeared: Vec<usize>,
+}
+
+/// Track the regions of the cuts of two hierarchies of the same area, e.g. at two
+/// dates, at the given levels. See [`match_regions`].
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+pub fn match_regions_wasm(
+    h1: &Hierarchy,
+    h2: &Hierarchy,
+    width: usize,
+    height: usize,
+    level1: f64,
+    level2: f64,
+) -> RegionMatches {
+    let cut = |hierarchy: &Hierarchy, level: f64| {
+        let nodes = cut_node_labels(&hierarchy.parents, &hierarchy.levels, level);
+        Array2::from_shape_vec((height, width), hierarchy.labels.clone())
+            .expect_throw("Labels wrong shape")
+            .mapv(|leaf| nodes[leaf])
+    };
+
+    let matching = match_regions(&cut(h1, level1), &cut(h2, level2));
+    console_log!(
+        "Matched regions: {}, disappeared: {}, appeared: {}",
+        matching.matches.len(),
+        matching.disappeared.len(),
+        matching.appeared.len()
+    );
+
+    RegionMatches {
+        nodes_a: matching.matches.iter().map(|m| m.0).collect(),
+        nodes_b: matching.matches.iter().map(|m| m.1).collect(),
+        ious: matching.matches.iter().map(|m| m.2).collect(),
+        disappeared: matching.disappeared,
+        appeared: matching.appeared,
+    }
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen(getter_with_clone))]
+#[derive(Clone, Debug)]
+pub struct RegionChanges {
+    /// Change score of each region `nodes[i]` of the second cut, `Infinity` for
+    /// the appeared regions
+    pub nodes: Vec<usize>,
+    pub scores: Vec<f64>,
+    /// `1` for the changed pixels
+    pub mask: Vec<u8>,
+}
+
+/// Change detection between two `width * height` images of the same area at two
+/// dates, from the cuts of their hierarchies at the given levels. The regions whose
+/// band means moved by more than `threshold` are changed, see [`change_map`].
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[allow(clippy::too_many_arguments)]
+pub fn change_map_wasm(
+    h1: &Hierarchy,
+    h2: &Hierarchy,
+    data1: &[u8],
+    data2: &[u8],
+    width: usize,
+    height: usize,
+    channels: usize,
+    level1: f64,
+    level2: f64,
+    threshold: f64,
+) -> RegionChanges {
+    let cut = |hierarchy: &Hierarchy, level: f64| {
+        let nodes = cut_node_labels(&hierarchy.parents, &hierarchy.levels, level);
+        Array2::from_shape_vec((height, width), hierarchy.labels.clone())
+            .expect_throw("Labels wrong shape")
+            .mapv(|leaf| nodes[leaf])
+    };
+    let (cut1, cut2) = (cut(h1, level1), cut(h2, level2));
+    let stats1 = region_means(&cut1, &array_from_planar(data1, width, height, channels));
+    let stats2 = region_means(&cut2, &array_from_planar(data2, width, height, channels));
+
+    let changes = change_map(&cut1, &cut2, &stats1, &stats2, threshold);
+    console_log!(
+        "Changed pixels: {}",
+        changes.mask.iter().filter(|changed| **changed == 1).count()
+    );
+
+    let mut scores = changes.scores.into_iter().collect::<Vec<_>>();
+    scores.sort_unstable_by_key(|(node, _)| *node);
+    RegionChanges {
+        nodes: scores.iter().map(|(node, _)| *node).collect(),
+        scores: scores.iter().map(|(_, score)| *score).collect(),
+        mask: labels_to_vec(&changes.mask),
+    }
+}
+
+/// Render the cuts of a hierarchy of a `width * height` image at several levels into
+/// a single RGBA sprite sheet of `columns` tiles per row, each tile being the
+/// viewport, e.g. for a strip previewing the levels. See [`render_cut_sprite_sheet`].
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[allow(clippy::too_many_arguments)]
+pub fn render_cut_sprite_sheet_wasm(
+    hierarchy: &Hierarchy,
+    width: usize,
+    height: usize,
+    levels: &[f64],
+    columns: usize,
+    viewport: &Viewport,
+    style: CutStyle,
+    palette: &Palette,
+) -> Vec<u8> {
+    let labels = ArrayView2::from_shape((height, width), hierarchy.labels.as_slice())
+        .expect_throw("Labels wrong shape");
+
+    render_cut_sprite_sheet(
+        labels,
+        &hierarchy.parents,
+        &hierarchy.levels,
+        levels,
+        viewport,
+        columns,
+        style,
+        palette,
+    )
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen(getter_with_clone))]
+#[derive(Clone, Debug)]
+pub struct ViCurve {
+    pub levels: Vec<f64>,
+    pub vi: Vec<f64>,
+}
+
+/// Variation of information between each horizontal cut of the hierarchy and a
+/// reference partition of the image, e.g. an existing land-cover map.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+pub fn vi_curve_wasm(
+    hierarchy: &Hierarchy,
+    width: usize,
+    height: usize,
+    reference_labels: Vec<usize>,
+) -> ViCurve {
+    let labels = Array2::from_shape_vec((height, width), hierarchy.labels.clone())
+        .expect_throw("Labels wrong shape");
+    let reference = Array2::from_shape_vec((height, width), reference_labels)
+        .expect_throw("Reference labels wrong shape");
+
+    let curve = vi_curve(&labels, &hierarchy.parents, &hierarchy.levels, &reference);
+
+    ViCurve {
+        levels: curve.iter().map(|(level, _)| *level).collect(),
+        vi: curve.iter().map(|(_, vi)| *vi).collect(),
+    }
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen(getter_with_clone))]
+#[derive(Clone, Debug)]
+pub struct ScaleCandidates {
+    pub levels: Vec<f64>,
+    pub region_counts: Vec<usize>,
+    pub lifetimes: Vec<f64>,
+}
+
+/// Most salient regions of a hierarchy, best first.
+#[cfg_attr(feature = "wasm", wasm_bindgen(getter_with_clone))]
+#[derive(Clone, Debug)]
+pub struct TopRegions {
+    pub nodes: Vec<usize>,
+    pub scores: Vec<f64>,
+    /// Concatenated masks of the regions, one byte per pixel, 1 inside the region
+    pub masks: Vec<u8>,
+}
+
+/// Find the `k` most salient regions of the hierarchy, e.g. the largest and most
+/// persistent ones, to show the most obvious objects.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+pub fn top_regions_wasm(hierarchy: &Hierarchy, k: usize, score: SaliencyScore) -> TopRegions {
+    let attributes = NodeAttributes::new(
+        &hierarchy.parents,
+        &hierarchy.levels,
+        &hierarchy.leaf_pixels,
+    );
+    let regions = top_regions(&hierarchy.parents, &attributes, k, score);
+
+    let mut masks = Vec::with_capacity(regions.len() * hierarchy.labels.len());
+    for (node, _) in &regions {
+        masks.extend(hierarchy.node_mask(*node));
+    }
+
+    TopRegions {
+        nodes: regions.iter().map(|(node, _)| *node).collect(),
+        scores: regions.iter().map(|(_, score)| *score).collect(),
+        masks,
+    }
+}
+
+/// Grow a region from each user click, given as a row-major pixel index: the
+/// largest ancestor of the clicked leaf whose variance on the planar image stays
+/// lower or equal to `tolerance`. Returns one node per click.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+pub fn grow_from_seeds_wasm(
+    hierarchy: &Hierarchy,
+    img: &[u8],
+    width: usize,
+    height: usize,
+    seeds: Vec<usize>,
+    tolerance: f64,
+) -> Vec<usize> {
+    let channels = band_count(img, width, height);
+    let img = array_from_planar(img, width, height, channels);
+
+    let seed_leaves = seeds
+        .iter()
+        .map(|pixel| hierarchy.pixel_leaf(*pixel))
+        .collect::<Vec<_>>();
+    let variances = node_variances(&hierarchy.parents, &hierarchy.leaf_pixels, &img);
+
+    grow_from_seeds(&hierarchy.parents, &variances, &seed_leaves, tolerance)
+}
+
+/// Suggest levels at which to cut the hierarchy: the levels where the region
+/// count is the most stable, best first.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+pub fn stable_scales_wasm(hierarchy: &Hierarchy, max_candidates: usize) -> ScaleCandidates {
+    let candidates = stable_scales(&hierarchy.parents, &hierarchy.levels, max_candidates);
+
+    ScaleCandidates {
+        levels: candidates.iter().map(|c| c.level).collect(),
+        region_counts: candidates.iter().map(|c| c.region_count).collect(),
+        lifetimes: candidates.iter().map(|c| c.lifetime).collect(),
+    }
+}
+
+/// Ultrametric distance between two pixels given by their row-major index: the
+/// level at which their superpixels merge, 0 in the same superpixel and infinite
+/// when barriers separate them. Pixels closer than `t` are in the same region of
+/// the cut at `t`. `lca` is the [`Hierarchy::lca_index`] of the hierarchy, built
+/// once for all the queries.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+pub fn ultrametric_distance_wasm(
+    hierarchy: &Hierarchy,
+    lca: &LcaIndex,
+    pixel_a: usize,
+    pixel_b: usize,
+) -> f64 {
+    let (a, b) = (hierarchy.pixel_leaf(pixel_a), hierarchy.pixel_leaf(pixel_b));
+    if a == b {
+        return 0.;
+    }
+
+    lca.merge_level_of(a, b).unwrap_or(f64::INFINITY)
+}
+
+/// Magic wand selection from a clicked pixel, given by its row-major index: mask of
+/// the largest region containing it whose level is below `tolerance`, one byte per
+/// pixel. See [`flood_select`].
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+pub fn flood_select_wasm(hierarchy: &Hierarchy, pixel: usize, tolerance: f64) -> Vec<u8> {
+    let leaf = hierarchy.pixel_leaf(pixel);
+    let node = flood_select(&hierarchy.parents, &hierarchy.levels, leaf, tolerance);
+
+    hierarchy.node_mask(node)
+}
+
+/// Union of two selections of tree nodes, in canonical form: the largest nodes
+/// covering the same pixels. See [`selection_union`].
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+pub fn selection_union_wasm(hierarchy: &Hierarchy, a: Vec<usize>, b: Vec<usize>) -> Vec<usize> {
+    check_nodes(hierarchy, &a);
+    check_nodes(hierarchy, &b);
+    selection_union(&hierarchy.parents, &a, &b)
+}
+
+/// Intersection of two selections of tree nodes, in canonical form.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+pub fn selection_intersection_wasm(
+    hierarchy: &Hierarchy,
+    a: Vec<usize>,
+    b: Vec<usize>,
+) -> Vec<usize> {
+    check_nodes(hierarchy, &a);
+    check_nodes(hierarchy, &b);
+    selection_intersection(&hierarchy.parents, &a, &b)
+}
+
+/// Nodes selected in `a` but not in `b`, in canonical form.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+pub fn selection_difference_wasm(
+    hierarchy: &Hierarchy,
+    a: Vec<usize>,
+    b: Vec<usize>,
+) -> Vec<usize> {
+    check_nodes(hierarchy, &a);
+    check_nodes(hierarchy, &b);
+    selection_difference(&hierarchy.parents, &a, &b)
+}
+
+/// Split the selected nodes into their largest descendants below `level`.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+pub fn split_selection_by_level_wasm(
+    hierarchy: &Hierarchy,
+    selection: Vec<usize>,
+    level: f64,
+) -> Vec<usize> {
+    check_nodes(hierarchy, &selection);
+    split_selection_by_level(&hierarchy.parents, &hierarchy.levels, &selection, level)
+}
+
+/// Mask of the pixels of a selection of tree nodes, one byte per pixel, 1 inside.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+pub fn selection_mask_wasm(hierarchy: &Hierarchy, selection: Vec<usize>) -> Vec<u8> {
+    check_nodes(hierarchy, &selection);
+    let selected = selected_nodes(&hierarchy.parents, &selection);
+
+    hierarchy
+        .labels
+        .iter()
+        .map(|leaf| selected[*leaf] as u8)
+        .collect()
+}
+
+fn check_nodes(hierarchy: &Hierarchy, nodes: &[usize]) {
+    if nodes.iter().any(|node| *node >= hierarchy.parents.len()) {
+        throw_str("Node out of range");
+    }
+}
+
+/// Save a hierarchy, e.g. to cache it per tile, to be loaded with
+/// [`load_hierarchy_wasm`].
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+pub fn save_hierarchy_wasm(hierarchy: &Hierarchy) -> Vec<u8> {
+    hierarchy.to_bytes()
+}
+
+/// Load a hierarchy saved with [`save_hierarchy_wasm`], throwing if it was not
+/// built from this image and parameters. Hierarchies built with an auxiliary
+/// input are checked against its little-endian bytes in `auxiliary`, e.g. the
+/// bytes of the `Float32Array` of a boundary map, or the level then the parcels of
+/// a snapped hierarchy. Alpha-trees and component trees are checked against
+/// [`alpha_tree_auxiliary`] and [`component_tree_auxiliary`]. Hierarchies of
+/// unknown inputs, saved by older versions, are loaded without the check.
+#[allow(clippy::too_many_arguments)]
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+pub fn load_hierarchy_wasm(
+    bytes: &[u8],
+    data: &[u8],
+    width: usize,
+    height: usize,
+    channels: usize,
+    params: &SegmentationParams,
+    auxiliary: Option<Vec<u8>>,
+) -> Hierarchy {
+    let hierarchy =
+        Hierarchy::from_bytes(bytes, (height, width)).expect_throw("Invalid hierarchy file");
+
+    if hierarchy.input_fingerprint == 0 {
+        console_log!("Hierarchy of unknown inputs, not checked against the image");
+    } else {
+        let array = array_from_planar(data, width, height, channels);
+        let mut fingerprint = input_fingerprint(&array, params);
+        if let Some(auxiliary) = &auxiliary {
+            fingerprint = auxiliary_fingerprint(fingerprint, auxiliary);
+        }
+        if hierarchy.input_fingerprint != fingerprint {
+            throw_str("Hierarchy built from another image or other parameters");
+        }
+    }
+
+    hierarchy
+}
+
+/// Save an annotation session, to be loaded with [`load_session_wasm`].
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+pub fn save_session_wasm(session: &AnnotationSession) -> Vec<u8> {
+    session.to_bytes()
+}
+
+/// Load an annotation session saved on the same hierarchy, throwing if it was made
+/// on another one.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+pub fn load_session_wasm(bytes: &[u8], hierarchy: &Hierarchy) -> AnnotationSession {
+    let session = AnnotationSession::from_bytes(bytes)
+        .and_then(|session| {
+            if session.hierarchy_fingerprint == hierarchy.fingerprint() {
+                Ok(session)
+            } else {
+                Err(SessionError::HierarchyMismatch)
+            }
+        })
+        .unwrap_or_else(|error| throw_str(&error.to_string()));
+
+    console_log!(
+        "Loaded session: {} selected nodes, {} classified nodes",
+        session.selection.len(),
+        session.class_nodes.len()
+    );
+
+    session
+}
+
+/// Depth, node counts, level statistics and branching anomalies of the hierarchy,
+/// for debugging.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+pub fn hierarchy_summary_wasm(hierarchy: &Hierarchy) -> HierarchySummary {
+    hierarchy_summary(&hierarchy.parents, &hierarchy.levels)
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen(getter_with_clone))]
+#[derive(Clone, Debug)]
+pub struct PolygonRegions {
+    pub regions: Vec<usize>,
+    /// Fraction of the area of each region inside the polygon, 1 when fully inside
+    pub fractions: Vec<f64>,
+}
+
+/// Regions of a cut intersecting a polygon drawn by the user (e.g. a lasso), given
+/// as `[x0, y0, x1, y1, ...]` pixel coordinates. Pixels whose center is inside the
+/// polygon are counted.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+pub fn regions_in_polygon_wasm(
+    width: usize,
+    height: usize,
+    cut_labels: Vec<usize>,
+    polygon: Vec<f64>,
+) -> PolygonRegions {
+    let labels =
+        Array2::from_shape_vec((height, width), cut_labels).expect_throw("Labels wrong shape");
+    let points = polygon.chunks_exact(2);
+    if !points.remainder().is_empty() {
+        throw_str("Polygon coordinates must come in pairs");
+    }
+    let polygon = points.map(|point| (point[0], point[1])).collect::<Vec<_>>();
+
+    let regions = regions_in_polygon(&labels, &polygon);
+
+    PolygonRegions {
+        regions: regions.iter().map(|(region, _)| *region).collect(),
+        fractions: regions.iter().map(|(_, fraction)| *fraction).collect(),
+    }
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen(getter_with_clone))]
+#[derive(Clone, Debug)]
+pub struct CutAdjacency {
+    pub sources: Vec<usize>,
+    pub targets: Vec<usize>,
+    pub lengths: Vec<u32>,
+}
+
+/// Pairs of adjacent regions of a cut, with the length of their shared boundary
+/// in pixels. Regions `sources[i]` and `targets[i]` share a boundary of `lengths[i]`.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+pub fn cut_adjacency_wasm(width: usize, height: usize, labels: Vec<usize>) -> CutAdjacency {
+    let labels = Array2::from_shape_vec((height, width), labels).expect_throw("Labels wrong shape");
+
+    let adjacency = region_adjacency(&labels);
+
+    let mut sources = Vec::with_capacity(adjacency.len());
+    let mut targets = Vec::with_capacity(adjacency.len());
+    let mut lengths = Vec::with_capacity(adjacency.len());
+
+    for ((a, b), length) in adjacency {
+        sources.push(a);
+        targets.push(b);
+        lengths.push(length);
+    }
+
+    CutAdjacency {
+        sources,
+        targets,
+        lengths,
+    }
+}
+
+/// Resample the labels of a cut of a `width * height` image by `factor` along each
+/// axis, to `round(width * factor)` by `round(height * factor)` pixels, e.g. for
+/// overviews matching the full resolution segmentation. See [`resample_labels`].
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+pub fn resample_labels_wasm(
+    width: usize,
+    height: usize,
+    labels: Vec<usize>,
+    factor: f64,
+) -> Vec<usize> {
+    let labels = Array2::from_shape_vec((height, width), labels).expect_throw("Labels wrong shape");
+
+    labels_to_vec(&resample_labels(&labels, factor))
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen(getter_with_clone))]
+#[derive(Clone, Debug)]
+pub struct HoleRepair {
+    pub labels: Vec<usize>,
+    pub holes: Vec<usize>,
+    pub containers: Vec<usize>,
+    pub areas: Vec<usize>,
+}
+
+/// Merge the regions of a cut enclosed in a single other region, when their area
+/// is lower or equal to `max_area` pixels. Region `holes[i]`, of `areas[i]` pixels,
+/// was merged into `containers[i]`.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+pub fn fill_cut_holes_wasm(
+    width: usize,
+    height: usize,
+    labels: Vec<usize>,
+    max_area: usize,
+) -> HoleRepair {
+    let mut labels =
+        Array2::from_shape_vec((height, width), labels).expect_throw("Labels wrong shape");
+
+    let repairs = fill_enclosed_regions(&mut labels, max_area);
+
+    console_log!("Filled holes: {}", repairs.len());
+
+    HoleRepair {
+        labels: labels_to_vec(&labels),
+        holes: repairs.iter().map(|r| r.hole).collect(),
+        containers: repairs.iter().map(|r| r.container).collect(),
+        areas: repairs.iter().map(|r| r.area).collect(),
+    }
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen(getter_with_clone))]
+#[derive(Clone, Debug)]
+pub struct RegionMerges {
+    pub labels: Vec<usize>,
+    pub regions: Vec<usize>,
+    pub targets: Vec<usize>,
+    pub areas: Vec<f64>,
+}
+
+/// Merge the regions of a cut smaller than a minimum mapping unit of `min_area`,
+/// in squared units of the geotransform (e.g. m²), into their neighbor with the
+/// closest mean color. Without geotransform, areas are in pixels. Region
+/// `regions[i]`, of area `areas[i]`, was merged into `targets[i]`.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+pub fn enforce_minimum_mapping_unit_wasm(
+    img: Vec<u8>,
+    width: usize,
+    height: usize,
+    labels: Vec<usize>,
+    min_area: f64,
+    geo_transform: Option<GeoTransform>,
+) -> RegionMerges {
+    let channels = band_count(&img, width, height);
+    let img = array_from_planar(&img, width, height, channels);
+    let mut labels =
+        Array2::from_shape_vec((height, width), labels).expect_throw("Labels wrong shape");
+
+    let transform = geo_transform.unwrap_or_default();
+    let merges = merge_small_regions(&mut labels, &img, min_area, &transform);
+
+    RegionMerges {
+        labels: labels_to_vec(&labels),
+        regions: merges.iter().map(|m| m.region).collect(),
+        targets: merges.iter().map(|m| m.target).collect(),
+        areas: merges.iter().map(|m| m.area).collect(),
+    }
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen(getter_with_clone))]
+#[derive(Clone, Debug)]
+pub struct LabelAnchors {
+    pub regions: Vec<usize>,
+    pub xs: Vec<usize>,
+    pub ys: Vec<usize>,
+    pub radii: Vec<f64>,
+}
+
+/// Positions where the label of each region of a cut can be placed, far from the
+/// region border even for oddly shaped regions. The label of region `regions[i]`
+/// goes at `(xs[i], ys[i])`, with `radii[i]` pixels of free space around it.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+pub fn region_label_anchors_wasm(width: usize, height: usize, labels: Vec<usize>) -> LabelAnchors {
+    let labels = Array2::from_shape_vec((height, width), labels).expect_throw("Labels wrong shape");
+
+    let anchors = region_label_anchors(&labels);
+
+    LabelAnchors {
+        regions: anchors.iter().map(|a| a.region).collect(),
+        xs: anchors.iter().map(|a| a.x).collect(),
+        ys: anchors.iter().map(|a| a.y).collect(),
+        radii: anchors.iter().map(|a| a.radius).collect(),
+    }
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen(getter_with_clone))]
+#[derive(Clone, Debug)]
+pub struct RegionClasses {
+    pub regions: Vec<usize>,
+    pub classes: Vec<usize>,
+    pub purities: Vec<f64>,
+}
+
+/// Assign to each region of a cut its majority class in a per-pixel classification
+/// raster. `purities[i]` is the fraction of the pixels of region `regions[i]`
+/// having the class `classes[i]`.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+pub fn assign_classes_from_raster_wasm(
+    width: usize,
+    height: usize,
+    cut_labels: Vec<usize>,
+    class_raster: Vec<usize>,
+) -> RegionClasses {
+    let labels =
+        Array2::from_shape_vec((height, width), cut_labels).expect_throw("Labels wrong shape");
+    let classes = Array2::from_shape_vec((height, width), class_raster)
+        .expect_throw("Class raster wrong shape");
+
+    let regions = majority_classes(&labels, &classes);
+
+    RegionClasses {
+        regions: regions.iter().map(|r| r.region).collect(),
+        classes: regions.iter().map(|r| r.class).collect(),
+        purities: regions.iter().map(|r| r.purity).collect(),
+    }
+}
+
+/// Snap a per-pixel classification raster (e.g. a CNN semantic map) to the
+/// hierarchy: each region of the cut at `level` gets its majority class, regions
+/// less pure than `min_purity` being split into their children.
+/// Returns the class of each pixel.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+pub fn snap_classes_wasm(
+    hierarchy: &Hierarchy,
+    width: usize,
+    height: usize,
+    level: f64,
+    class_raster: Vec<usize>,
+    min_purity: f64,
+) -> Vec<usize> {
+    let leaf_labels = Array2::from_shape_vec((height, width), hierarchy.labels.clone())
+        .expect_throw("Labels wrong shape");
+    let classes = Array2::from_shape_vec((height, width), class_raster)
+        .expect_throw("Class raster wrong shape");
+
+    let snapped = snap_classes(
+        &leaf_labels,
+        &hierarchy.parents,
+        &hierarchy.levels,
+        level,
+        &classes,
+        min_purity,
+    );
+
+    labels_to_vec(&snapped)
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen(getter_with_clone))]
+#[derive(Clone, Debug)]
+pub struct RegionProbabilityMap {
+    pub regions: Vec<usize>,
+    /// Averaged probabilities, `n_classes` values per region
+    pub probabilities: Vec<f64>,
+    pub entropies: Vec<f64>,
+    pub n_classes: usize,
+}
+
+/// Average per-pixel class probability maps over each region of a cut.
+/// `probabilities` holds `n_classes` planar maps of `width * height` values.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+pub fn aggregate_probabilities_wasm(
+    width: usize,
+    height: usize,
+    cut_labels: Vec<usize>,
+    probabilities: Vec<f32>,
+    n_classes: usize,
+) -> RegionProbabilityMap {
+    let labels =
+        Array2::from_shape_vec((height, width), cut_labels).expect_throw("Labels wrong shape");
+    let probabilities = Array3::from_shape_vec((n_classes, height, width), probabilities)
+        .expect_throw("Probabilities wrong shape");
+
+    let regions = mean_probabilities(&labels, probabilities.view());
+
+    RegionProbabilityMap {
+        regions: regions.iter().map(|r| r.region).collect(),
+        probabilities: regions
+            .iter()
+            .flat_map(|r| r.probabilities.iter().cloned())
+            .collect(),
+        entropies: regions.iter().map(|r| r.entropy).collect(),
+        n_classes,
+    }
+}
+
+/// Compress a label map with a palette and run-length encoding, to reduce the size
+/// of the transfers between a worker and the main thread.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+pub fn compress_labels_wasm(labels: &[usize], width: usize, height: usize) -> CompressedLabels {
+    if labels.len() != width * height {
+        throw_str("Labels wrong shape");
+    }
+
+    compress_labels(labels, width, height)
+}
+
+/// Expand a label map compressed with [`compress_labels_wasm`], throwing if it is
+/// invalid, e.g. built in JS with `new CompressedLabels(...)` from corrupted arrays.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+pub fn decompress_labels_wasm(compressed: &CompressedLabels) -> Vec<usize> {
+    decompress_labels(compressed).unwrap_or_else(|| throw_str("Invalid compressed labels"))
+}
+
+/// Thumbnail of a node of the hierarchy, for a gallery of regions: the bounding box
+/// of the node is cropped from the planar image and downscaled so that its largest
+/// side is at most `max_size` pixels. Pixels outside of the node are transparent.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+pub fn node_thumbnail_wasm(
+    hierarchy: &Hierarchy,
+    node: usize,
+    img: &[u8],
+    width: usize,
+    height: usize,
+    max_size: usize,
+) -> Thumbnail {
+    if node >= hierarchy.parents.len() {
+        throw_str("Node out of range");
+    }
+
+    let channels = band_count(img, width, height);
+    let img = array_from_planar(img, width, height, channels);
+    let leaf_labels = Array2::from_shape_vec((height, width), hierarchy.labels.clone())
+        .expect_throw("Labels wrong shape");
+
+    node_thumbnail(
+        &leaf_labels,
+        &hierarchy.leaf_pixels,
+        &hierarchy.parents,
+        node,
+        &img,
+        max_size,
+    )
+    .expect_throw("Node has no pixel")
+}
+
+/// Render only a window of a cut of `width * height` labels into RGBA, at the scale
+/// of the viewport, so that panning a large segmentation does not render it whole.
+/// Regions are colored by their tree node with the palette. A `supersampling`
+/// factor of 2 or more smooths the borders, e.g. for screenshots.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+pub fn render_cut_window_wasm(
+    labels: &[usize],
+    width: usize,
+    height: usize,
+    viewport: &Viewport,
+    style: CutStyle,
+    palette: &Palette,
+    supersampling: usize,
+) -> Vec<u8> {
+    let labels = ArrayView2::from_shape((height, width), labels).expect_throw("Labels wrong shape");
+
+    render_cut_window(labels, viewport, style, palette, supersampling)
+}
+
+/// Render a window of the cut at `level` of a hierarchy of a `width * height` image
+/// into the final thematic map in RGBA, node `nodes[i]` being of class `classes[i]`.
+/// Regions are filled with the color and pattern of their class in the legend, transparent
+/// without a class, with their borders in black if `borders`.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[allow(clippy::too_many_arguments)]
+pub fn render_class_map_wasm(
+    hierarchy: &Hierarchy,
+    width: usize,
+    height: usize,
+    level: f64,
+    viewport: &Viewport,
+    nodes: Vec<usize>,
+    classes: Vec<usize>,
+    legend: &Legend,
+    borders: bool,
+) -> Vec<u8> {
+    if nodes.len() != classes.len() {
+        throw_str("One class per node expected");
+    }
+    let cut_nodes = cut_node_labels(&hierarchy.parents, &hierarchy.levels, level);
+    let labels = ArrayView2::from_shape((height, width), hierarchy.labels.as_slice())
+        .expect_throw("Labels wrong shape")
+        .mapv(|leaf| cut_nodes[leaf]);
+    let region_classes = nodes.into_iter().zip(classes).collect::<HashMap<_, _>>();
+
+    render_class_map(labels.view(), viewport, &region_classes, legend, borders)
+}
+
+/// Render the legend of a thematic map as a PNG strip of square swatches of
+/// `swatch_size` pixels, one per class, styled like the map. The class of each
+/// swatch and its style are given by [`Legend::to_json`].
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+pub fn render_legend_strip_wasm(legend: &Legend, swatch_size: usize) -> Vec<u8> {
+    let data = render_legend_strip(legend, swatch_size);
+    let width = data.len() / (swatch_size * 4).max(1);
+
+    rgba_to_png(data, width, swatch_size)
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen(getter_with_clone))]
+#[derive(Clone, Debug)]
+pub struct XyzTiles {
+    pub zooms: Vec<u32>,
+    pub xs: Vec<u32>,
+    pub ys: Vec<u32>,
+    pngs: Vec<Vec<u8>>,
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+impl XyzTiles {
+    /// PNG of tile `i`, at `zooms[i]/xs[i]/ys[i]`.
+    pub fn png(&self, i: usize) -> Vec<u8> {
+        self.pngs.get(i).expect_throw("Tile out of range").clone()
+    }
+}
+
+/// Render a cut of `width * height` labels into the 256x256 PNG tiles of the XYZ
+/// scheme covering it, for zoom levels `min_zoom` to `max_zoom`, at most 30. The
+/// geotransform must be in Web Mercator (EPSG:3857), or in Lambert-93 or WGS 84
+/// with the `proj` feature.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[allow(clippy::too_many_arguments)]
+pub fn cut_to_xyz_tiles_wasm(
+    labels: &[usize],
+    width: usize,
+    height: usize,
+    geo_transform: &GeoTransform,
+    min_zoom: u32,
+    max_zoom: u32,
+    style: CutStyle,
+    palette: &Palette,
+) -> XyzTiles {
+    let labels = ArrayView2::from_shape((height, width), labels).expect_throw("Labels wrong shape");
+    if max_zoom > MAX_ZOOM {
+        throw_str(&format!("Zoom levels above {MAX_ZOOM} are not supported"));
+    }
+
+    let tiles = cut_to_xyz_tiles(labels, geo_transform, min_zoom..=max_zoom, style, palette)
+        .expect_throw("Unsupported coordinate reference system");
+
+    XyzTiles {
+        zooms: tiles.iter().map(|tile| tile.z).collect(),
+        xs: tiles.iter().map(|tile| tile.x).collect(),
+        ys: tiles.iter().map(|tile| tile.y).collect(),
+        pngs: tiles.into_iter().map(|tile| tile.png).collect(),
+    }
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen(getter_with_clone))]
+#[derive(Clone, Debug)]
+pub struct VectorTiles {
+    pub zooms: Vec<u32>,
+    pub xs: Vec<u32>,
+    pub ys: Vec<u32>,
+    tiles: Vec<Vec<u8>>,
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+impl VectorTiles {
+    /// Protobuf of tile `i`, at `zooms[i]/xs[i]/ys[i]`.
+    pub fn tile(&self, i: usize) -> Vec<u8> {
+        self.tiles.get(i).expect_throw("Tile out of range").clone()
+    }
+}
+
+/// Encode a cut of `width * height` labels into the Mapbox Vector Tiles of the XYZ
+/// scheme covering it, for zoom levels `min_zoom` to `max_zoom`, at most 30, with a
+/// `regions` layer whose features have their tree node as id and `node` property.
+/// The geotransform must be in Web Mercator (EPSG:3857), or in Lambert-93 or
+/// WGS 84 with the `proj` feature.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+pub fn cut_to_vector_tiles_wasm(
+    labels: Vec<usize>,
+    width: usize,
+    height: usize,
+    geo_transform: &GeoTransform,
+    min_zoom: u32,
+    max_zoom: u32,
+    smoothing: Option<Smoothing>,
+) -> VectorTiles {
+    let labels = Array2::from_shape_vec((height, width), labels).expect_throw("Labels wrong shape");
+    if max_zoom > MAX_ZOOM {
+        throw_str(&format!("Zoom levels above {MAX_ZOOM} are not supported"));
+    }
+
+    let tiles = cut_to_vector_tiles(
+        &labels,
+        geo_transform,
+        min_zoom..=max_zoom,
+        smoothing.unwrap_or_default(),
+    )
+    .expect_throw("Unsupported coordinate reference system");
+
+    VectorTiles {
+        zooms: tiles.iter().map(|tile| tile.z).collect(),
+        xs: tiles.iter().map(|tile| tile.x).collect(),
+        ys: tiles.iter().map(|tile| tile.y).collect(),
+        tiles: tiles.into_iter().map(|tile| tile.data).collect(),
+    }
+}
+
+/// Export a cut of `width * height` labels as an SVG document, with one path per
+/// region following the pixel borders unless smoothed, colored by tree node with
+/// the palette.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+pub fn cut_to_svg_wasm(
+    labels: Vec<usize>,
+    width: usize,
+    height: usize,
+    style: CutStyle,
+    palette: &Palette,
+    smoothing: Option<Smoothing>,
+) -> String {
+    let labels = Array2::from_shape_vec((height, width), labels).expect_throw("Labels wrong shape");
+
+    cut_to_svg(&labels, style, palette, smoothing.unwrap_or_default())
+}
+
+/// Export a cut of `width * height` labels as GeoJSON. Coordinates are georeferenced
+/// by the transform, or in pixels with the Y axis pointing up without one.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+pub fn cut_to_geojson_wasm(
+    labels: Vec<usize>,
+    width: usize,
+    height: usize,
+    geo_transform: Option<GeoTransform>,
+    smoothing: Option<Smoothing>,
+) -> String {
+    let labels = Array2::from_shape_vec((height, width), labels).expect_throw("Labels wrong shape");
+    let transform = geo_transform.unwrap_or_default();
+
+    cut_to_geojson(&labels, &transform, smoothing.unwrap_or_default())
+}
+
+/// Export a cut of `width * height` labels as GeoJSON, reprojected from the
+/// coordinate reference system of the geotransform to `target_epsg`, e.g. 4326
+/// for WGS 84 or 3857 for Web Mercator.
+#[cfg(feature = "proj")]
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+pub fn cut_to_geojson_reprojected_wasm(
+    labels: Vec<usize>,
+    width: usize,
+    height: usize,
+    geo_transform: &GeoTransform,
+    target_epsg: u32,
+    smoothing: Option<Smoothing>,
+) -> String {
+    let labels = Array2::from_shape_vec((height, width), labels).expect_throw("Labels wrong shape");
+    let smoothing = smoothing.unwrap_or_default();
+
+    cut_to_geojson_reprojected(&labels, geo_transform, target_epsg, smoothing)
+        .expect_throw("Unsupported reprojection")
+}
+
+/// Export a cut of `width * height` labels as an ESRI Shapefile, with the class of
+/// the regions `regions[i]` being `classes[i]`. Coordinates are georeferenced by
+/// the transform, or in pixels with the Y axis pointing up without one.
+#[cfg(feature = "shapefile")]
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+pub fn cut_to_shapefile_wasm(
+    labels: Vec<usize>,
+    width: usize,
+    height: usize,
+    regions: Vec<usize>,
+    classes: Vec<usize>,
+    geo_transform: Option<GeoTransform>,
+    smoothing: Option<Smoothing>,
+) -> Shapefile {
+    let labels = Array2::from_shape_vec((height, width), labels).expect_throw("Labels wrong shape");
+    if regions.len() != classes.len() {
+        throw_str("One class per region expected");
+    }
+    let classes = regions.into_iter().zip(classes).collect();
+
+    let transform = geo_transform.unwrap_or_default();
+
+    cut_to_shapefile(&labels, &classes, &transform, smoothing.unwrap_or_default())
+}
+
+/// Export a cut of `width * height` labels as a GeoPackage, with the region
+/// polygons and the attributes of the hierarchy nodes. Coordinates are georeferenced
+/// by the transform, or in pixels with the Y axis pointing up without one.
+#[cfg(all(feature = "geopackage", feature = "wasm"))]
+#[wasm_bindgen]
+pub fn cut_to_geopackage_wasm(
+    labels: Vec<usize>,
+    width: usize,
+    height: usize,
+    hierarchy: &Hierarchy,
+    geo_transform: Option<GeoTransform>,
+    smoothing: Option<Smoothing>,
+) -> Vec<u8> {
+    let labels = Array2::from_shape_vec((height, width), labels).expect_throw("Labels wrong shape");
+    let now = String::from(js_sys::Date::new_0().to_iso_string());
+
+    let transform = geo_transform.unwrap_or_default();
+    let smoothing = smoothing.unwrap_or_default();
+
+    cut_to_geopackage(&labels, hierarchy, &transform, smoothing, &now)
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+pub fn display_labels_wasm(
+    img: Vec<u8>,
+    width: usize,
+    height: usize,
+    labels: Vec<usize>,
+) -> Vec<u8> {
+    let channels = band_count(&img, width, height);
+    let mut img = array_from_planar(&img, width, height, channels);
+
+    let labels = Array2::from_shape_vec((height, width), labels).expect_throw("Labels wrong shape");
+
+    for ((i, j), label) in labels.indexed_iter() {
+        let neighbors = [labels.get((i + 1, j)), labels.get((i, j + 1))];
+        if neighbors
+            .into_iter()
+            .flatten()
+            .any(|neighbor| neighbor != label)
+        {
+            img.slice_mut(s![i, j, ..]).fill(0);
+        }
+    }
+
+    array_to_rgba_bitmap(img.view())
+}
+
+/// Set the verbosity of the logs to the browser console, e.g. `Off` in production
+/// or `Trace` to follow the convergence of SLIC.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+pub fn set_log_level_wasm(level: LogLevel) {
+    set_log_level(level);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pipeline_channels() {
+        for channels in [1, 3, 4, 8, 12] {
+            // Four flat quadrants, with a different value on each band
+            let img = Array3::from_shape_fn((16, 16, channels), |(y, x, c)| {
+                ((y / 8 * 2 + x / 8) * 40 + c * 5) as u8
+            });
+
+            let labels = slic(16, 1, Some(1), &img).unwrap();
+            let graph = graph_from_labels(&img, &labels).unwrap();
+            assert!(graph
+                .node_weights()
+                .all(|node| node.values.len() == channels));
+
+            let num_nodes = graph.node_count();
+            let mut tree = PartialPartitionTree::new(graph);
+            tree.merge_until(None).unwrap();
+            let tree = tree.into_partition_tree();
+            assert_eq!(tree.parents.len(), 2 * num_nodes - 1);
+        }
+    }
+
+    #[test]
+    fn test_degenerate_sizes() {
+        for (height, width) in [(1, 1), (1, 9), (9, 1), (2, 2), (3, 40), (5, 9)] {
+            let pixels = height * width;
+            let img = Array3::from_shape_fn((height, width, 3), |(y, x, c)| {
+                ((y * 31 + x * 17 + c * 5) % 256) as u8
+            });
+
+            for n_clusters in [1, 2, 4, pixels - 1, pixels, pixels + 5]
+                .into_iter()
+                .filter(|n| *n > 0)
+            {
+                let (labels, tree) = hierarchical_segmentation(img.clone(), n_clusters);
+                let n_labels = labels.iter().max().unwrap() + 1;
+                assert_eq!(labels.dim(), (height, width));
+                assert!(n_labels <= pixels);
+                assert_eq!(tree.parents.len(), 2 * n_labels - 1);
+                if n_clusters >= pixels {
+                    // Each pixel is a superpixel
+                    assert_eq!(
+                        labels,
+                        Array2::from_shape_fn((height, width), |(y, x)| y * width + x)
+                    );
+                }
+
+                let hierarchy = Hierarchy::new(&labels, tree);
+                let rgba = display_labels_wasm(
+                    array_to_planar(img.view()),
+                    width,
+                    height,
+                    labels_to_vec(&labels),
+                );
+                assert_eq!(rgba.len(), 4 * pixels);
+                assert_eq!(cut_hierarchy_wasm(&hierarchy, f64::INFINITY).n_regions, 1);
+            }
+        }
+
+        // A single superpixel on a flat image, and two along a column of two halves
+        let (labels, _) = hierarchical_segmentation(Array3::zeros((6, 6, 1)), 1);
+        assert!(labels.iter().all(|label| *label == 0));
+        let column = Array3::from_shape_fn((40, 1, 1), |(y, _, _)| if y < 20 { 0 } else { 200 });
+        let (labels, _) = hierarchical_segmentation(column, 2);
+        assert_eq!(labels.iter().max(), Some(&1));
+        assert_ne!(labels[[0, 0]], labels[[39, 0]]);
+    }
+
+    #[test]
+    fn test_hierarchy_byte_size() {
+        let labels = Array2::from_shape_vec((1, 2), vec![0, 1]).unwrap();
+        let tree = PartitionTree {
+            parents: vec![2, 2, 2],
+            levels: vec![0., 0., 1.],
+            merges: vec![(0, 1, 1)],
+        };
+
+        let hierarchy = Hierarchy::new(&labels, tree);
+
+        assert_eq!(
+            hierarchy.byte_size(),
+            size_of::<Hierarchy>()
+                + 2 * size_of::<usize>()
+                + 3 * size_of::<usize>()
+                + 3 * 8
+                // Leaf pixel index: 3 offsets, 2 runs
+                + 7 * size_of::<usize>()
+        );
+    }
+
+    #[test]
+    fn test_cut_result() {
+        // Node 3 merges leaves 0 and 1 at level 1, node 4 the rest at level 2
+        let labels = Array2::from_shape_vec((2, 2), vec![0, 1, 2, 2]).unwrap();
+        let tree = PartitionTree {
+            parents: vec![3, 3, 4, 4, 4],
+            levels: vec![0., 0., 0., 1., 2.],
+            merges: Vec::new(),
+        };
+        let hierarchy = Hierarchy::new(&labels, tree);
+
+        // Nodes below the level are merged into their parent
+        let cut = cut_hierarchy_wasm(&hierarchy, 0.5);
+        assert_eq!(cut.labels, vec![3, 3, 4, 4]);
+        assert_eq!((cut.n_regions, cut.region_node_ids), (2, vec![3, 4]));
+        assert_eq!(cut.level, 0.5);
+    }
+
+    #[test]
+    fn test_hierarchy_bytes() {
+        let labels = Array2::from_shape_vec((2, 2), vec![0, 1, 2, 2]).unwrap();
+        let tree = PartitionTree {
+            parents: vec![3, 3, 4, 4, 4],
+            levels: vec![0., 0., 0., 1., 2.],
+            merges: Vec::new(),
+        };
+        let hierarchy = Hierarchy::new(&labels, tree).with_input_fingerprint(42);
+
+        let bytes = hierarchy.to_bytes();
+        let loaded = Hierarchy::from_bytes(&bytes, (2, 2)).unwrap();
+        assert_eq!(
+            (loaded.labels, loaded.parents, loaded.levels),
+            (hierarchy.labels, hierarchy.parents, hierarchy.levels)
+        );
+        assert_eq!(loaded.input_fingerprint, 42);
+        assert_eq!(loaded.leaf_pixels, hierarchy.leaf_pixels);
+
+        assert!(Hierarchy::from_bytes(&bytes, (1, 4)).is_some());
+        assert!(Hierarchy::from_bytes(&bytes, (2, 3)).is_none());
+        assert!(Hierarchy::from_bytes(&bytes[..bytes.len() - 1], (2, 2)).is_none());
+
+        // Unknown versions, including 0, are rejected
+        for version in [0u32, HIERARCHY_VERSION + 1] {
+            let mut bytes = bytes.clone();
+            bytes[4..8].copy_from_slice(&version.to_le_bytes());
+            assert!(Hierarchy::from_bytes(&bytes, (2, 2)).is_none());
+        }
+    }
+
+    #[test]
+    fn test_ultrametric_distance() {
+        // Leaves 0 and 1 merge at level 1, then with leaf 2 at level 2
+        let labels = Array2::from_shape_vec((2, 2), vec![0, 1, 2, 2]).unwrap();
+        let tree = PartitionTree {
+            parents: vec![3, 3, 4, 4, 4],
+            levels: vec![0., 0., 0., 1., 2.],
+            merges: Vec::new(),
+        };
+        let hierarchy = Hierarchy::new(&labels, tree);
+        let lca = hierarchy.lca_index();
+
+        assert_eq!(ultrametric_distance_wasm(&hierarchy, &lca, 0, 0), 0.);
+        assert_eq!(ultrametric_distance_wasm(&hierarchy, &lca, 2, 3), 0.);
+        assert_eq!(ultrametric_distance_wasm(&hierarchy, &lca, 0, 1), 1.);
+        assert_eq!(ultrametric_distance_wasm(&hierarchy, &lca, 3, 0), 2.);
+        assert!(
+            std::panic::catch_unwind(|| { ultrametric_distance_wasm(&hierarchy, &lca, 0, 4) })
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_builder_fingerprints() {
+        let (width, height) = (8, 6);
+        let data: Vec<u8> = (0..width * height * 3)
+            .map(|i| (i * 7 % 256) as u8)
+            .collect();
+        let params = SegmentationParams::new(4);
+        let boundary: Vec<f32> = (0..width * height).map(|i| (i % 5) as f32 / 4.).collect();
+
+        let hierarchy =
+            build_hierarchy_with_boundary_wasm(&data, width, height, 3, boundary.clone(), &params);
+        let boundary_bytes: Vec<u8> = boundary.iter().flat_map(|v| v.to_le_bytes()).collect();
+        let image_only = input_fingerprint(&array_from_planar(&data, width, height, 3), &params);
+        assert_ne!(hierarchy.input_fingerprint, image_only);
+        assert_eq!(
+            hierarchy.input_fingerprint,
+            auxiliary_fingerprint(image_only, &boundary_bytes)
+        );
+        let bytes = save_hierarchy_wasm(&hierarchy);
+        load_hierarchy_wasm(
+            &bytes,
+            &data,
+            width,
+            height,
+            3,
+            &params,
+            Some(boundary_bytes),
+        );
+
+        let tree = build_component_tree_wasm(&data, width, height, 3, 1, None, true);
+        assert_eq!(
+            tree.input_fingerprint,
+            auxiliary_fingerprint(
+                input_fingerprint(
+                    &array_from_planar(&data, width, height, 3),
+                    &SegmentationParams::new(0)
+                ),
+                &component_tree_auxiliary(1, None, true)
+            )
+        );
+        assert_ne!(
+            tree.input_fingerprint,
+            build_component_tree_wasm(&data, width, height, 3, 1, None, false).input_fingerprint
+        );
+        let index_tree = build_component_tree_wasm(&data, width, height, 3, 1, Some(0), true);
+        assert_ne!(tree.input_fingerprint, index_tree.input_fingerprint);
+        assert_eq!(index_tree.parents.len(), 2 * width * height - 1);
+        let alpha = build_alpha_tree_wasm(&data, width, height, 3, 4).input_fingerprint;
+        assert_ne!(alpha, 0);
+        assert_ne!(
+            alpha,
+            build_hierarchy_wasm(&data, width, height, 3, 4).input_fingerprint
+        );
+        assert_eq!(
+            alpha,
+            auxiliary_fingerprint(
+                input_fingerprint(&array_from_planar(&data, width, height, 3), &params),
+                &alpha_tree_auxiliary()
+            )
+        );
+    }
 }
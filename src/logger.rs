@@ -1,12 +1,190 @@
+use std::sync::atomic::{AtomicU8, Ordering};
+
+#[cfg(feature = "wasm")]
 use wasm_bindgen::prelude::wasm_bindgen;
 
+#[cfg(all(feature = "wasm", feature = "console", target_arch = "wasm32"))]
 #[wasm_bindgen]
 extern "C" {
     #[wasm_bindgen(js_namespace = console)]
     pub fn log(s: &str);
+
+    #[wasm_bindgen(js_namespace = console)]
+    fn time(label: &str);
+
+    #[wasm_bindgen(js_namespace = console, js_name = timeEnd)]
+    fn time_end(label: &str);
+}
+
+/// Log to the standard error output outside of JavaScript, e.g. in native tests.
+#[cfg(all(
+    feature = "console",
+    not(all(feature = "wasm", target_arch = "wasm32"))
+))]
+pub fn log(s: &str) {
+    eprintln!("{s}");
+}
+
+/// Logging is disabled without the `console` feature.
+#[cfg(not(feature = "console"))]
+pub fn log(_s: &str) {}
+
+/// Verbosity of the logs.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+pub enum LogLevel {
+    /// No logs, e.g. in production
+    Off,
+    /// Progress of the pipeline, sizes of the graphs and trees, and timings of the phases
+    #[default]
+    Info,
+    /// Also the details of each iteration, e.g. the residual of the SLIC iterations
+    Trace,
+}
+
+// Logs go to the JavaScript console by default, while native programs opt in to
+// the standard error output with `set_log_level`
+const DEFAULT_LOG_LEVEL: LogLevel = if cfg!(all(feature = "wasm", target_arch = "wasm32")) {
+    LogLevel::Info
+} else {
+    LogLevel::Off
+};
+
+static LOG_LEVEL: AtomicU8 = AtomicU8::new(DEFAULT_LOG_LEVEL as u8);
+
+/// Set the verbosity of the logs, for all the threads. Logs are at the
+/// [`LogLevel::Info`] level in WebAssembly, and off in native programs.
+pub fn set_log_level(level: LogLevel) {
+    LOG_LEVEL.store(level as u8, Ordering::Relaxed);
+}
+
+/// Whether messages of the given level are logged. Always `false` without the
+/// `console` feature.
+pub fn log_enabled(level: LogLevel) -> bool {
+    cfg!(feature = "console")
+        && level != LogLevel::Off
+        && level as u8 <= LOG_LEVEL.load(Ordering::Relaxed)
+}
+
+#[cfg(feature = "console")]
+thread_local! {
+    // Names of the spans in progress, from the outermost one
+    static SPANS: std::cell::RefCell<Vec<&'static str>> = const { std::cell::RefCell::new(Vec::new()) };
 }
 
+/// Timed phase of the pipeline, from its creation to its drop.
+///
+/// In the browser, spans are `console.time` labels, which devtools show in the
+/// timings track of the performance panel, aligned with the flamechart. Nested
+/// spans are named after their parents, e.g. `segmentation/slic`. Outside of
+/// JavaScript, the duration is logged when the span ends. Spans are logged at the
+/// [`LogLevel::Info`] level.
+pub struct Span {
+    // `None` when the logs are disabled
+    #[cfg(feature = "console")]
+    label: Option<String>,
+    #[cfg(all(
+        feature = "console",
+        not(all(feature = "wasm", target_arch = "wasm32"))
+    ))]
+    start: std::time::Instant,
+}
+
+impl Span {
+    pub fn enter(name: &'static str) -> Self {
+        #[cfg(feature = "console")]
+        {
+            let label = log_enabled(LogLevel::Info).then(|| {
+                SPANS.with_borrow_mut(|spans| {
+                    spans.push(name);
+                    spans.join("/")
+                })
+            });
+            #[cfg(all(feature = "wasm", target_arch = "wasm32"))]
+            if let Some(label) = &label {
+                time(label);
+            }
+
+            Self {
+                label,
+                #[cfg(not(all(feature = "wasm", target_arch = "wasm32")))]
+                start: std::time::Instant::now(),
+            }
+        }
+        #[cfg(not(feature = "console"))]
+        {
+            let _ = name;
+            Self {}
+        }
+    }
+}
+
+impl Drop for Span {
+    fn drop(&mut self) {
+        #[cfg(feature = "console")]
+        if let Some(label) = &self.label {
+            #[cfg(all(feature = "wasm", target_arch = "wasm32"))]
+            time_end(label);
+            #[cfg(not(all(feature = "wasm", target_arch = "wasm32")))]
+            log(&format!(
+                "{label}: {:.1} ms",
+                self.start.elapsed().as_secs_f64() * 1000.
+            ));
+
+            SPANS.with_borrow_mut(|spans| spans.pop());
+        }
+    }
+}
+
+/// Log a message at the [`LogLevel::Info`] level.
 #[macro_export]
 macro_rules! console_log {
-    ($($t:tt)*) => ($crate::logger::log(&format_args!($($t)*).to_string()))
+    ($($t:tt)*) => {
+        if $crate::logger::log_enabled($crate::logger::LogLevel::Info) {
+            $crate::logger::log(&format_args!($($t)*).to_string())
+        }
+    };
+}
+
+/// Log a message at the [`LogLevel::Trace`] level.
+#[macro_export]
+macro_rules! console_trace {
+    ($($t:tt)*) => {
+        if $crate::logger::log_enabled($crate::logger::LogLevel::Trace) {
+            $crate::logger::log(&format_args!($($t)*).to_string())
+        }
+    };
+}
+
+#[cfg(all(test, feature = "console"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_nested_spans() {
+        // Native logs are off until enabled
+        assert!(!log_enabled(LogLevel::Info));
+        set_log_level(LogLevel::Info);
+
+        let outer = Span::enter("segmentation");
+        let inner = Span::enter("slic");
+        assert_eq!(inner.label.as_deref(), Some("segmentation/slic"));
+        drop(inner);
+
+        assert_eq!(
+            Span::enter("graph").label.as_deref(),
+            Some("segmentation/graph")
+        );
+        drop(outer);
+        assert_eq!(Span::enter("merge").label.as_deref(), Some("merge"));
+
+        // Spans are silenced with the other logs
+        assert!(!log_enabled(LogLevel::Trace));
+        set_log_level(LogLevel::Off);
+        assert!(!log_enabled(LogLevel::Info));
+        assert_eq!(Span::enter("merge").label, None);
+        set_log_level(LogLevel::Trace);
+        assert!(log_enabled(LogLevel::Trace));
+        set_log_level(DEFAULT_LOG_LEVEL);
+    }
 }
@@ -0,0 +1,82 @@
+use js_sys::{ArrayBuffer, WebAssembly};
+use wasm_bindgen::{prelude::*, JsCast};
+
+#[cfg(feature = "shapefile")]
+use crate::Shapefile;
+
+use crate::{
+    AnnotationSession, CompressedLabels, CutAdjacency, CutResult, DecodedImage, GeoTransform,
+    Hierarchy, HierarchyComparison, HierarchyPyramid, HierarchySummary, HoleRepair, IntegralImage,
+    LabelAnchors, LabelOverviews, LcaIndex, Legend, Palette, PolygonRegions, PyramidLevel, RagMst,
+    RegionChanges, RegionClasses, RegionMatches, RegionMerges, RegionProbabilityMap,
+    ScaleCandidates, SegmentationSession, SlicReport, Smoothing, StatisticsBuffer, Thumbnail,
+    TileSequence, TopRegions, VectorTiles, ViCurve, Viewport, XyzTiles,
+};
+
+/// Current size of the WASM linear memory, in bytes. It never shrinks, but
+/// memory released with `dispose()` is reused by the next allocations.
+#[wasm_bindgen]
+pub fn wasm_memory_bytes() -> usize {
+    wasm_bindgen::memory()
+        .unchecked_into::<WebAssembly::Memory>()
+        .buffer()
+        .unchecked_into::<ArrayBuffer>()
+        .byte_length() as usize
+}
+
+/// Add a `dispose()` method to WASM-exposed structs, releasing their memory
+/// without waiting for the garbage collector to run the finalizer.
+macro_rules! impl_dispose {
+    ($($name:ty),* $(,)?) => {
+        $(
+            #[wasm_bindgen]
+            impl $name {
+                /// Release the memory held by this object. It cannot be used afterwards.
+                pub fn dispose(self) {}
+            }
+        )*
+    };
+}
+
+impl_dispose!(
+    AnnotationSession,
+    CompressedLabels,
+    CutAdjacency,
+    CutResult,
+    DecodedImage,
+    GeoTransform,
+    Hierarchy,
+    HierarchyComparison,
+    HierarchyPyramid,
+    HierarchySummary,
+    HoleRepair,
+    IntegralImage,
+    LabelAnchors,
+    LabelOverviews,
+    LcaIndex,
+    Legend,
+    Palette,
+    PolygonRegions,
+    PyramidLevel,
+    RagMst,
+    RegionChanges,
+    RegionClasses,
+    RegionMatches,
+    RegionMerges,
+    RegionProbabilityMap,
+    ScaleCandidates,
+    SegmentationSession,
+    SlicReport,
+    Smoothing,
+    StatisticsBuffer,
+    Thumbnail,
+    TileSequence,
+    TopRegions,
+    VectorTiles,
+    ViCurve,
+    Viewport,
+    XyzTiles,
+);
+
+#[cfg(feature = "shapefile")]
+impl_dispose!(Shapefile);
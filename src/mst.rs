@@ -0,0 +1,71 @@
+use petgraph::unionfind::UnionFind;
+
+use crate::graph::{SuperpixelEdgeIndex, SuperpixelGraph};
+
+/// Compute the minimum spanning tree of the region adjacency graph with
+/// Kruskal's algorithm. Returns the edges of the tree sorted by increasing weight.
+///
+/// If the graph is not connected, the minimum spanning forest is returned.
+pub fn minimum_spanning_tree(graph: &SuperpixelGraph) -> Vec<SuperpixelEdgeIndex> {
+    let mut edges = graph.edge_indices().collect::<Vec<_>>();
+    // A total order, so that NaN weights are sorted last instead of panicking
+    edges.sort_by(|a, b| graph[*a].weight.total_cmp(&graph[*b].weight));
+
+    let mut components = UnionFind::<usize>::new(graph.node_count());
+
+    edges
+        .into_iter()
+        .filter(|edge_id| {
+            let (a, b) = graph.edge_endpoints(*edge_id).unwrap();
+            components.union(a.index(), b.index())
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use ndarray::{Array2, Array3};
+
+    use super::*;
+    use crate::graph::{graph_from_labels, SuperpixelNodeIndex};
+
+    fn edge(graph: &SuperpixelGraph, a: usize, b: usize) -> SuperpixelEdgeIndex {
+        graph
+            .find_edge(SuperpixelNodeIndex::new(a), SuperpixelNodeIndex::new(b))
+            .unwrap()
+    }
+
+    #[test]
+    fn test_minimum_spanning_tree() {
+        // 0 1
+        // 2 3
+        let labels = Array2::from_shape_vec((2, 2), vec![0, 1, 2, 3]).unwrap();
+        let mut graph = graph_from_labels(&Array3::zeros((2, 2, 1)), &labels).unwrap();
+        for (a, b, weight) in [(0, 1, 1.), (0, 2, 2.), (1, 3, 3.), (2, 3, 4.)] {
+            let edge = edge(&graph, a, b);
+            graph[edge].weight = weight;
+        }
+
+        let tree = minimum_spanning_tree(&graph);
+        assert_eq!(tree.len(), graph.node_count() - 1);
+        assert_eq!(tree.iter().map(|e| graph[*e].weight).sum::<f64>(), 6.);
+
+        // A NaN weight is sorted last
+        let heaviest = edge(&graph, 1, 3);
+        graph[heaviest].weight = f64::NAN;
+        let tree = minimum_spanning_tree(&graph);
+        assert_eq!(tree.iter().map(|e| graph[*e].weight).sum::<f64>(), 7.);
+    }
+
+    #[test]
+    fn test_minimum_spanning_forest() {
+        // Two components, 0 1 and 2 3, once the edge between 1 and 2 is removed
+        let labels = Array2::from_shape_vec((1, 4), vec![0, 1, 2, 3]).unwrap();
+        let mut graph = graph_from_labels(&Array3::zeros((1, 4, 1)), &labels).unwrap();
+        let bridge = edge(&graph, 1, 2);
+        graph.remove_edge(bridge);
+
+        let forest = minimum_spanning_tree(&graph);
+        assert_eq!(forest.len(), graph.node_count() - 2);
+    }
+}
@@ -0,0 +1,461 @@
+//! Mapbox Vector Tiles (MVT 2.1) of the regions of a cut, rendered by OpenLayers,
+//! Leaflet or MapLibre as vector layers that can be styled and hovered.
+//!
+//! Each tile has a single `regions` layer with one polygon feature per region,
+//! whose id and `node` property are its tree node.
+
+use std::ops::RangeInclusive;
+
+use ndarray::Array2;
+
+#[cfg(feature = "proj")]
+use crate::proj::transformer;
+use crate::{
+    geo::GeoTransform,
+    smooth::{smooth_outlines, Smoothing},
+    tiles::{MAX_ZOOM, WEB_MERCATOR_EXTENT},
+};
+
+/// Size of the tiles in their integer coordinates.
+pub const MVT_EXTENT: u32 = 4096;
+/// Margin around the tiles in which the polygons are kept, hiding the clipping
+/// lines along the tile edges.
+const MVT_BUFFER: f64 = 64.;
+const LAYER_NAME: &str = "regions";
+
+/// Vector tile of the XYZ scheme, encoded as protobuf.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VectorTile {
+    pub z: u32,
+    pub x: u32,
+    pub y: u32,
+    pub data: Vec<u8>,
+}
+
+/// Polygon in Web Mercator, its first ring being the outer one.
+struct Polygon {
+    rings: Vec<Vec<[f64; 2]>>,
+    bbox: [f64; 4],
+}
+
+/// Encode a cut into the Web Mercator (EPSG:3857) vector tiles covering the image
+/// for each zoom level. Tiles without any region are skipped.
+///
+/// The geotransform must be in Web Mercator, or with the `proj` feature in any
+/// coordinate reference system it supports. Returns `None` otherwise, or if a zoom
+/// level is above [`MAX_ZOOM`].
+pub fn cut_to_vector_tiles(
+    labels: &Array2<usize>,
+    transform: &GeoTransform,
+    zooms: RangeInclusive<u32>,
+    smoothing: Smoothing,
+) -> Option<Vec<VectorTile>> {
+    if *zooms.end() > MAX_ZOOM {
+        return None;
+    }
+    let crs = transform.crs_epsg?;
+    #[cfg(feature = "proj")]
+    let to_mercator = transformer(crs, 3857)?;
+    #[cfg(not(feature = "proj"))]
+    let to_mercator = (crs == 3857).then_some(|point: (f64, f64)| point)?;
+
+    let regions = smooth_outlines(labels, smoothing)
+        .into_iter()
+        .map(|(label, rings)| {
+            let polygons = polygons(rings, |(x, y)| {
+                let (x, y) = to_mercator(transform.apply(x, y));
+                [x, y]
+            });
+            (label, polygons)
+        })
+        .collect::<Vec<_>>();
+
+    let mut bbox = [
+        f64::INFINITY,
+        f64::INFINITY,
+        f64::NEG_INFINITY,
+        f64::NEG_INFINITY,
+    ];
+    for polygon in regions.iter().flat_map(|(_, polygons)| polygons) {
+        bbox = union(bbox, polygon.bbox);
+    }
+    if bbox[0] > bbox[2] {
+        return Some(Vec::new());
+    }
+
+    let mut tiles = Vec::new();
+    for z in zooms {
+        let count = 1u32 << z;
+        let tile_length = 2. * WEB_MERCATOR_EXTENT / f64::from(count);
+        let tile_index =
+            |offset: f64| ((offset / tile_length).floor().max(0.) as u32).min(count - 1);
+        let x_range =
+            tile_index(bbox[0] + WEB_MERCATOR_EXTENT)..=tile_index(bbox[2] + WEB_MERCATOR_EXTENT);
+        let y_range =
+            tile_index(WEB_MERCATOR_EXTENT - bbox[3])..=tile_index(WEB_MERCATOR_EXTENT - bbox[1]);
+
+        for y in y_range {
+            for x in x_range.clone() {
+                let left = -WEB_MERCATOR_EXTENT + f64::from(x) * tile_length;
+                let top = WEB_MERCATOR_EXTENT - f64::from(y) * tile_length;
+                let scale = f64::from(MVT_EXTENT) / tile_length;
+                let margin = MVT_BUFFER / scale;
+                let tile_bbox = [
+                    left - margin,
+                    top - tile_length - margin,
+                    left + tile_length + margin,
+                    top + margin,
+                ];
+
+                let mut features = Vec::new();
+                for (label, polygons) in &regions {
+                    let geometry = polygons
+                        .iter()
+                        .filter(|polygon| intersects(polygon.bbox, tile_bbox))
+                        .flat_map(|polygon| {
+                            tile_polygon(polygon, |[mx, my]| {
+                                [(mx - left) * scale, (top - my) * scale]
+                            })
+                        })
+                        .collect::<Vec<_>>();
+                    if !geometry.is_empty() {
+                        features.push((*label, encode_geometry(&geometry)));
+                    }
+                }
+
+                if !features.is_empty() {
+                    tiles.push(VectorTile {
+                        z,
+                        x,
+                        y,
+                        data: encode_tile(&features),
+                    });
+                }
+            }
+        }
+    }
+
+    Some(tiles)
+}
+
+/// Group the rings of a region into polygons, each hole going to the smallest outer
+/// ring around it. Outer rings have a positive area in image coordinates.
+fn polygons(rings: Vec<Vec<(f64, f64)>>, project: impl Fn((f64, f64)) -> [f64; 2]) -> Vec<Polygon> {
+    let (outers, holes): (Vec<_>, Vec<_>) = rings
+        .into_iter()
+        .filter(|ring| ring.len() >= 3)
+        .map(|ring| ring.into_iter().map(|(x, y)| [x, y]).collect::<Vec<_>>())
+        .partition(|ring| signed_area(ring) > 0.);
+
+    let mut polygons = outers
+        .into_iter()
+        .map(|ring| vec![ring])
+        .collect::<Vec<_>>();
+    for hole in holes {
+        // The middle of an edge of the hole never lies on another ring of the region
+        let [x0, y0] = hole[0];
+        let [x1, y1] = hole[1];
+        let point = [(x0 + x1) / 2., (y0 + y1) / 2.];
+        let outer = polygons
+            .iter_mut()
+            .filter(|rings| contains(&rings[0], point))
+            .min_by(|a, b| signed_area(&a[0]).total_cmp(&signed_area(&b[0])));
+        if let Some(rings) = outer {
+            rings.push(hole);
+        }
+    }
+
+    polygons
+        .into_iter()
+        .map(|rings| {
+            let rings = rings
+                .into_iter()
+                .map(|ring| ring.into_iter().map(|[x, y]| project((x, y))).collect())
+                .collect::<Vec<Vec<_>>>();
+            let bbox = rings.iter().flatten().fold(
+                [
+                    f64::INFINITY,
+                    f64::INFINITY,
+                    f64::NEG_INFINITY,
+                    f64::NEG_INFINITY,
+                ],
+                |bbox, [x, y]| union(bbox, [*x, *y, *x, *y]),
+            );
+            Polygon { rings, bbox }
+        })
+        .collect()
+}
+
+/// Rings of a polygon in the integer coordinates of a tile, clipped to the tile and
+/// its buffer. Outer rings are clockwise on screen, with a positive area, and holes
+/// counter-clockwise, as required by the specification. Empty when the outer ring
+/// is clipped out.
+fn tile_polygon(polygon: &Polygon, to_tile: impl Fn([f64; 2]) -> [f64; 2]) -> Vec<Vec<[i64; 2]>> {
+    let mut rings = Vec::new();
+    for (i, ring) in polygon.rings.iter().enumerate() {
+        let ring = ring.iter().map(|point| to_tile(*point)).collect::<Vec<_>>();
+        let clipped = clip_ring(ring, -MVT_BUFFER, f64::from(MVT_EXTENT) + MVT_BUFFER);
+
+        let mut ring = clipped
+            .into_iter()
+            .map(|[x, y]| [x.round() as i64, y.round() as i64])
+            .collect::<Vec<_>>();
+        ring.dedup();
+        while ring.len() > 1 && ring.first() == ring.last() {
+            ring.pop();
+        }
+
+        let area = integer_area(&ring);
+        if area == 0. {
+            if i == 0 {
+                return Vec::new();
+            }
+            continue;
+        }
+        if (area > 0.) != (i == 0) {
+            ring.reverse();
+        }
+        rings.push(ring);
+    }
+
+    rings
+}
+
+/// Clip a ring to the square `[min, max]²` (Sutherland-Hodgman).
+fn clip_ring(mut ring: Vec<[f64; 2]>, min: f64, max: f64) -> Vec<[f64; 2]> {
+    for (axis, bound, below) in [
+        (0, min, false),
+        (0, max, true),
+        (1, min, false),
+        (1, max, true),
+    ] {
+        let inside = |point: &[f64; 2]| (point[axis] <= bound) == below || point[axis] == bound;
+
+        let mut clipped = Vec::with_capacity(ring.len() + 4);
+        for (i, current) in ring.iter().enumerate() {
+            let next = &ring[(i + 1) % ring.len()];
+            if inside(current) {
+                clipped.push(*current);
+            }
+            if inside(current) != inside(next) {
+                let t = (bound - current[axis]) / (next[axis] - current[axis]);
+                let mut crossing = [
+                    current[0] + t * (next[0] - current[0]),
+                    current[1] + t * (next[1] - current[1]),
+                ];
+                crossing[axis] = bound;
+                clipped.push(crossing);
+            }
+        }
+        ring = clipped;
+    }
+
+    ring
+}
+
+/// Area of a ring by the shoelace formula, positive for clockwise rings when the
+/// y axis points down.
+fn signed_area(ring: &[[f64; 2]]) -> f64 {
+    let n = ring.len();
+    (0..n)
+        .map(|i| {
+            let ([x0, y0], [x1, y1]) = (ring[i], ring[(i + 1) % n]);
+            x0 * y1 - x1 * y0
+        })
+        .sum::<f64>()
+        / 2.
+}
+
+fn integer_area(ring: &[[i64; 2]]) -> f64 {
+    signed_area(
+        &ring
+            .iter()
+            .map(|[x, y]| [*x as f64, *y as f64])
+            .collect::<Vec<_>>(),
+    )
+}
+
+/// Whether a point is inside a ring, by the even-odd rule.
+fn contains(ring: &[[f64; 2]], [x, y]: [f64; 2]) -> bool {
+    let n = ring.len();
+    let mut inside = false;
+    for i in 0..n {
+        let ([x0, y0], [x1, y1]) = (ring[i], ring[(i + 1) % n]);
+        if (y0 > y) != (y1 > y) && x < x0 + (y - y0) * (x1 - x0) / (y1 - y0) {
+            inside = !inside;
+        }
+    }
+
+    inside
+}
+
+fn union(a: [f64; 4], b: [f64; 4]) -> [f64; 4] {
+    [
+        a[0].min(b[0]),
+        a[1].min(b[1]),
+        a[2].max(b[2]),
+        a[3].max(b[3]),
+    ]
+}
+
+fn intersects(a: [f64; 4], b: [f64; 4]) -> bool {
+    a[0] <= b[2] && b[0] <= a[2] && a[1] <= b[3] && b[1] <= a[3]
+}
+
+/// Geometry commands of polygon rings: a move to the first point, lines to the next
+/// ones and a close path, with zigzag encoded offsets from the previous point.
+fn encode_geometry(rings: &[Vec<[i64; 2]>]) -> Vec<u32> {
+    const MOVE_TO: u32 = 1;
+    const LINE_TO: u32 = 2;
+    const CLOSE_PATH: u32 = 7;
+    let command = |id: u32, count: usize| id | (count as u32) << 3;
+    let zigzag = |value: i64| ((value << 1) ^ (value >> 63)) as u32;
+
+    let mut geometry = Vec::new();
+    let mut cursor = [0, 0];
+    for ring in rings {
+        for (i, point) in ring.iter().enumerate() {
+            match i {
+                0 => geometry.push(command(MOVE_TO, 1)),
+                1 => geometry.push(command(LINE_TO, ring.len() - 1)),
+                _ => {}
+            }
+            geometry.push(zigzag(point[0] - cursor[0]));
+            geometry.push(zigzag(point[1] - cursor[1]));
+            cursor = *point;
+        }
+        geometry.push(command(CLOSE_PATH, 1));
+    }
+
+    geometry
+}
+
+/// Tile message with a single layer of polygon features, given as their tree node
+/// and geometry commands.
+fn encode_tile(features: &[(usize, Vec<u32>)]) -> Vec<u8> {
+    const POLYGON: u64 = 3;
+
+    let mut layer = Vec::new();
+    write_varint_field(&mut layer, 15, 2);
+    write_bytes_field(&mut layer, 1, LAYER_NAME.as_bytes());
+    for (i, (label, geometry)) in features.iter().enumerate() {
+        let mut feature = Vec::new();
+        write_varint_field(&mut feature, 1, *label as u64);
+        // Key 0 is `node`, and value `i` the node of feature `i`
+        write_bytes_field(&mut feature, 2, &packed(&[0, i as u32]));
+        write_varint_field(&mut feature, 3, POLYGON);
+        write_bytes_field(&mut feature, 4, &packed(geometry));
+        write_bytes_field(&mut layer, 2, &feature);
+    }
+    write_bytes_field(&mut layer, 3, b"node");
+    for (label, _) in features {
+        let mut value = Vec::new();
+        write_varint_field(&mut value, 5, *label as u64);
+        write_bytes_field(&mut layer, 4, &value);
+    }
+    write_varint_field(&mut layer, 5, u64::from(MVT_EXTENT));
+
+    let mut tile = Vec::new();
+    write_bytes_field(&mut tile, 3, &layer);
+    tile
+}
+
+fn packed(values: &[u32]) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    for value in values {
+        write_varint(&mut bytes, u64::from(*value));
+    }
+    bytes
+}
+
+fn write_varint(bytes: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        bytes.push((value as u8 & 0x7f) | 0x80);
+        value >>= 7;
+    }
+    bytes.push(value as u8);
+}
+
+fn write_varint_field(bytes: &mut Vec<u8>, field: u64, value: u64) {
+    write_varint(bytes, field << 3);
+    write_varint(bytes, value);
+}
+
+fn write_bytes_field(bytes: &mut Vec<u8>, field: u64, value: &[u8]) {
+    write_varint(bytes, field << 3 | 2);
+    write_varint(bytes, value.len() as u64);
+    bytes.extend_from_slice(value);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encode_geometry() {
+        // Example of the specification: a 3x3 square then a 1x1 hole
+        let rings = vec![
+            vec![[3, 6], [8, 12], [20, 34]],
+            vec![[0, 0], [1, 0], [1, 1]],
+        ];
+        assert_eq!(
+            encode_geometry(&rings),
+            vec![9, 6, 12, 18, 10, 12, 24, 44, 15, 9, 39, 67, 18, 2, 0, 0, 2, 15]
+        );
+    }
+
+    #[test]
+    fn test_clip_ring() {
+        let square = vec![[-10., -10.], [10., -10.], [10., 10.], [-10., 10.]];
+        let clipped = clip_ring(square, 0., 20.);
+        assert_eq!(signed_area(&clipped), 100.);
+        assert!(clipped.iter().all(|[x, y]| *x >= 0. && *y >= 0.));
+    }
+
+    #[test]
+    fn test_cut_to_vector_tiles() {
+        // Image covering the north-west quarter of the world, with a one pixel hole
+        let mut labels = Array2::zeros((4, 4));
+        labels[[1, 1]] = 1;
+        let pixel_length = WEB_MERCATOR_EXTENT / 4.;
+        let transform = GeoTransform::new(
+            -WEB_MERCATOR_EXTENT,
+            WEB_MERCATOR_EXTENT,
+            pixel_length,
+            pixel_length,
+            Some(3857),
+        );
+
+        let tiles = cut_to_vector_tiles(&labels, &transform, 0..=0, Smoothing::none()).unwrap();
+        assert_eq!(tiles.len(), 1);
+        let data = &tiles[0].data;
+        // A single layer, holding the features then the keys and values
+        assert_eq!(data[0], 3 << 3 | 2);
+        assert!(data.windows(7).any(|bytes| bytes == b"regions"));
+        assert!(data.windows(4).any(|bytes| bytes == b"node"));
+
+        // Region 0 has its outer ring and the hole, in a quarter of the tile
+        let polygons = polygons(
+            smooth_outlines(&labels, Smoothing::none()).remove(0).1,
+            |(x, y)| [x, y],
+        );
+        assert_eq!(polygons.len(), 1);
+        assert_eq!(polygons[0].rings.len(), 2);
+        let rings = tile_polygon(&polygons[0], |[x, y]| [x * 512., y * 512.]);
+        assert_eq!(rings.len(), 2);
+        assert_eq!(integer_area(&rings[0]), 2048. * 2048.);
+        assert_eq!(integer_area(&rings[1]), -512. * 512.);
+
+        assert!(
+            cut_to_vector_tiles(&labels, &GeoTransform::default(), 0..=0, Smoothing::none())
+                .is_none()
+        );
+        assert!(cut_to_vector_tiles(
+            &labels,
+            &transform,
+            MAX_ZOOM..=MAX_ZOOM + 2,
+            Smoothing::none()
+        )
+        .is_none());
+    }
+}
@@ -0,0 +1,218 @@
+use std::borrow::Cow;
+
+use ndarray::{s, Array2, Array3, ArrayViewMut2, Axis, Zip};
+#[cfg(feature = "wasm")]
+use wasm_bindgen::prelude::wasm_bindgen;
+
+use crate::slic_helpers::div_ceil;
+
+/// Fraction of the pixels saturated at each end of a band by the percentile stretch.
+const STRETCH_PERCENTILE: f64 = 0.02;
+/// Number of standard deviations mapped to each half of the output range by the z-score.
+const ZSCORE_RANGE: f64 = 3.;
+/// Number of CLAHE tiles along each axis of the image.
+const CLAHE_TILES: usize = 8;
+/// CLAHE clip limit, relative to the average number of pixels per histogram bin.
+const CLAHE_CLIP_LIMIT: f64 = 2.;
+
+/// Normalization applied to the bands of the image before clustering.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Normalization {
+    #[default]
+    None,
+    /// Stretch each band between its 2nd and 98th percentiles
+    PercentileStretch,
+    /// Center each band on its mean, with ±3 standard deviations mapped to the full range
+    ZScore,
+    /// Contrast limited adaptive histogram equalization of the luminance
+    Clahe,
+}
+
+/// Normalize the bands of a `(height, width, channels)` image.
+pub fn normalize_bands(img: &Array3<u8>, normalization: Normalization) -> Cow<'_, Array3<u8>> {
+    let mut img = match normalization {
+        Normalization::None => return Cow::Borrowed(img),
+        _ => img.clone(),
+    };
+
+    match normalization {
+        Normalization::None => {}
+        Normalization::PercentileStretch => {
+            for band in img.axis_iter_mut(Axis(2)) {
+                percentile_stretch(band);
+            }
+        }
+        Normalization::ZScore => {
+            for band in img.axis_iter_mut(Axis(2)) {
+                zscore(band);
+            }
+        }
+        Normalization::Clahe => clahe_luminance(&mut img),
+    }
+
+    Cow::Owned(img)
+}
+
+fn histogram<'a>(values: impl Iterator<Item = &'a u8>) -> [usize; 256] {
+    let mut histogram = [0; 256];
+    for value in values {
+        histogram[*value as usize] += 1;
+    }
+    histogram
+}
+
+fn percentile_stretch(mut band: ArrayViewMut2<u8>) {
+    let histogram = histogram(band.iter());
+    let saturated = (band.len() as f64 * STRETCH_PERCENTILE) as usize;
+
+    let mut count = 0;
+    let low = histogram
+        .iter()
+        .position(|h| {
+            count += h;
+            count > saturated
+        })
+        .unwrap_or(0);
+
+    count = 0;
+    let high = 255
+        - histogram
+            .iter()
+            .rev()
+            .position(|h| {
+                count += h;
+                count > saturated
+            })
+            .unwrap_or(0);
+
+    if high <= low {
+        return;
+    }
+
+    let scale = 255. / (high - low) as f64;
+    band.mapv_inplace(|v| ((v as f64 - low as f64) * scale).round().clamp(0., 255.) as u8);
+}
+
+fn zscore(mut band: ArrayViewMut2<u8>) {
+    let n = band.len() as f64;
+    let mean = band.iter().map(|v| *v as f64).sum::<f64>() / n;
+    let variance = band.iter().map(|v| (*v as f64 - mean).powi(2)).sum::<f64>() / n;
+    let std = variance.sqrt();
+
+    if std == 0. {
+        return;
+    }
+
+    band.mapv_inplace(|v| {
+        let z = (v as f64 - mean) / std;
+        ((z + ZSCORE_RANGE) / (2. * ZSCORE_RANGE) * 255.)
+            .round()
+            .clamp(0., 255.) as u8
+    });
+}
+
+/// Apply CLAHE on the luminance of the image. With at least 3 bands, the
+/// luminance is the mean of the first 3 and they are scaled by the luminance
+/// gain, other bands are left untouched. Otherwise the first band is equalized.
+fn clahe_luminance(img: &mut Array3<u8>) {
+    let (height, width, channels) = img.dim();
+
+    if channels < 3 {
+        let equalized = clahe(&img.index_axis(Axis(2), 0).to_owned());
+        img.index_axis_mut(Axis(2), 0).assign(&equalized);
+        return;
+    }
+
+    let luminance = Array2::from_shape_fn((height, width), |(y, x)| {
+        let sum = img[[y, x, 0]] as u32 + img[[y, x, 1]] as u32 + img[[y, x, 2]] as u32;
+        (sum as f64 / 3.).round() as u8
+    });
+    let equalized = clahe(&luminance);
+
+    Zip::from(img.lanes_mut(Axis(2)))
+        .and(&luminance)
+        .and(&equalized)
+        .for_each(|mut pixel, &l, &e| {
+            let gain = if l == 0 { 1. } else { e as f64 / l as f64 };
+            for c in 0..3 {
+                pixel[c] = (pixel[c] as f64 * gain).round().clamp(0., 255.) as u8;
+            }
+        });
+}
+
+fn clahe(band: &Array2<u8>) -> Array2<u8> {
+    let (height, width) = band.dim();
+    let tile_height = div_ceil(height, CLAHE_TILES.min(height));
+    let tile_width = div_ceil(width, CLAHE_TILES.min(width));
+    let tiles_y = div_ceil(height, tile_height);
+    let tiles_x = div_ceil(width, tile_width);
+
+    // Equalization mapping of each tile
+    let mut mappings = Vec::with_capacity(tiles_y * tiles_x);
+    for ty in 0..tiles_y {
+        for tx in 0..tiles_x {
+            let tile = band.slice(s![
+                ty * tile_height..((ty + 1) * tile_height).min(height),
+                tx * tile_width..((tx + 1) * tile_width).min(width)
+            ]);
+            let mut histogram = histogram(tile.iter());
+
+            // Clip the histogram and redistribute the excess uniformly
+            let clip_limit = ((CLAHE_CLIP_LIMIT * tile.len() as f64 / 256.) as usize).max(1);
+            let mut excess = 0;
+            for h in histogram.iter_mut() {
+                if *h > clip_limit {
+                    excess += *h - clip_limit;
+                    *h = clip_limit;
+                }
+            }
+            for (i, h) in histogram.iter_mut().enumerate() {
+                *h += excess / 256 + usize::from(i < excess % 256);
+            }
+
+            let mut mapping = [0u8; 256];
+            let mut cdf = 0;
+            for (value, h) in histogram.iter().enumerate() {
+                cdf += h;
+                mapping[value] = (cdf as f64 * 255. / tile.len() as f64).round() as u8;
+            }
+            mappings.push(mapping);
+        }
+    }
+
+    // Position of a pixel between the centers of the two closest tiles along an axis
+    let interpolation = |position: usize, tile_size: usize, tiles: usize| {
+        let f = ((position as f64 + 0.5) / tile_size as f64 - 0.5).max(0.);
+        let t0 = (f.floor() as usize).min(tiles - 1);
+        let t1 = (t0 + 1).min(tiles - 1);
+        (t0, t1, (f - t0 as f64).clamp(0., 1.))
+    };
+
+    Array2::from_shape_fn((height, width), |(y, x)| {
+        let (ty0, ty1, wy) = interpolation(y, tile_height, tiles_y);
+        let (tx0, tx1, wx) = interpolation(x, tile_width, tiles_x);
+        let value = band[[y, x]] as usize;
+        let map = |ty: usize, tx: usize| mappings[ty * tiles_x + tx][value] as f64;
+
+        let top = map(ty0, tx0) * (1. - wx) + map(ty0, tx1) * wx;
+        let bottom = map(ty1, tx0) * (1. - wx) + map(ty1, tx1) * wx;
+        (top * (1. - wy) + bottom * wy).round() as u8
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_percentile_stretch() {
+        // Values squeezed between 100 and 149
+        let img = Array3::from_shape_fn((10, 10, 1), |(y, x, _)| (100 + (y * 10 + x) / 2) as u8);
+
+        let stretched = normalize_bands(&img, Normalization::PercentileStretch);
+
+        assert_eq!(*stretched.iter().min().unwrap(), 0);
+        assert_eq!(*stretched.iter().max().unwrap(), 255);
+    }
+}
@@ -0,0 +1,201 @@
+use ndarray::{s, Array1, Array3};
+#[cfg(feature = "wasm")]
+use wasm_bindgen::prelude::wasm_bindgen;
+
+use crate::{
+    console_log,
+    gradient::{gradient_magnitude, GradientOperator},
+    graph::{FidelityModel, FidelityParams, Wrap},
+    hierarchy::CriterionOptions,
+    normalize::Normalization,
+    seed::SeedGrid,
+};
+
+/// Parameters of the hierarchical segmentation.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Clone, Copy, Debug)]
+pub struct SegmentationParams {
+    /// Approximate number of superpixels computed by SLIC, 0 to pick it from the
+    /// image, see [`auto_n_clusters`]
+    pub n_clusters: usize,
+    /// SLIC compactness, between 1 and 20: higher values give more regular
+    /// superpixels, lower ones follow the colors more closely
+    pub compactness: u8,
+    /// Number of SLIC iterations, the maximum one when `slic_threshold` is set
+    pub slic_iterations: u8,
+    /// Stop SLIC when the mean displacement of the superpixel centers, in pixels,
+    /// falls below this value
+    pub slic_threshold: Option<f64>,
+    /// Layout of the initial superpixel centers
+    pub seed_grid: SeedGrid,
+    /// Passes moving the border pixels of the superpixels to the neighbor of closest
+    /// color after SLIC, see [`refine_boundaries`](crate::refine_boundaries)
+    pub adherence_sweeps: u8,
+    /// Model used for the data fidelity of the regions
+    pub fidelity: FidelityModel,
+    /// Band containing an elevation raster (DSM), if any
+    pub elevation_channel: Option<usize>,
+    /// Near infrared band, if any, used to tell shadows from dark vegetation
+    pub nir_channel: Option<usize>,
+    /// Weight of the radiometric bands in the data fidelity
+    pub color_weight: f64,
+    /// Weight of the elevation band in the data fidelity
+    pub height_weight: f64,
+    /// Weight λ_shape of the region perimeters against the data fidelity, see
+    /// [`FidelityParams::shape_weight`]
+    pub shape_weight: f64,
+    /// Normalization of the bands applied before clustering
+    pub normalization: Normalization,
+    /// Weight of the boundary probability map in the edge weights, when one is given
+    pub boundary_weight: f64,
+    /// Reduction, between 0 and 1, of the edge weights inside shadows so that
+    /// shadowed objects are not fragmented, 0 to ignore the shadows. See
+    /// [`shadow_mask`](crate::shadow_mask)
+    pub shadow_weight: f64,
+    /// Divide the merge criterion by the length of the shared border
+    pub normalize_by_boundary: bool,
+    /// Neighboring superpixels whose edge weight is below this value are merged
+    /// before building the tree
+    pub prune_tolerance: f64,
+    /// Round the levels to fixed point, for hierarchies more stable across targets,
+    /// see [`PartialPartitionTree::with_fixed_point`](crate::PartialPartitionTree::with_fixed_point)
+    pub fixed_point: bool,
+    /// Regions smaller than this area, in pixels, are merged by color only before
+    /// the perimeter term is taken into account, 0 to always take it into account.
+    /// See [`PartialPartitionTree::with_fine_stage`](crate::PartialPartitionTree::with_fine_stage)
+    pub fine_area: u32,
+    /// Height of the pixels over their width, e.g. for scanned maps sampled at
+    /// different resolutions along each axis: distances in SLIC and perimeters are
+    /// measured in pixel widths. See [`GeoTransform::pixel_aspect`](crate::GeoTransform::pixel_aspect)
+    pub pixel_aspect: f64,
+    /// Edges of the image treated as adjacent, e.g. the left and right edges of a
+    /// 360° panorama, so that regions continue across them
+    pub wrap: Wrap,
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+impl SegmentationParams {
+    #[cfg_attr(feature = "wasm", wasm_bindgen(constructor))]
+    pub fn new(n_clusters: usize) -> Self {
+        Self {
+            n_clusters,
+            compactness: 1,
+            slic_iterations: 1,
+            slic_threshold: None,
+            seed_grid: SeedGrid::Square,
+            adherence_sweeps: 0,
+            fidelity: FidelityModel::default(),
+            elevation_channel: None,
+            nir_channel: None,
+            color_weight: 1.,
+            height_weight: 1.,
+            shape_weight: 1.,
+            normalization: Normalization::default(),
+            boundary_weight: 1.,
+            shadow_weight: 0.,
+            normalize_by_boundary: false,
+            prune_tolerance: 0.,
+            fixed_point: false,
+            fine_area: 0,
+            pixel_aspect: 1.,
+            wrap: Wrap::None,
+        }
+    }
+}
+
+impl SegmentationParams {
+    /// Data fidelity settings for an image with the given number of bands.
+    ///
+    /// The elevation band is weighted separately from the radiometric bands, as
+    /// its variance does not scale like the radiometry.
+    pub(crate) fn fidelity_params(&self, channels: usize) -> FidelityParams {
+        let channel_weights = Array1::from_shape_fn(channels, |c| {
+            if self.elevation_channel == Some(c) {
+                self.height_weight
+            } else {
+                self.color_weight
+            }
+        });
+
+        FidelityParams {
+            model: self.fidelity,
+            channel_weights,
+            shape_weight: self.shape_weight,
+            pixel_aspect: self.pixel_aspect,
+            wrap: self.wrap,
+        }
+    }
+
+    /// Same parameters with the superpixel count picked from the image if it is 0.
+    pub(crate) fn resolved(&self, image: &Array3<u8>) -> Self {
+        if self.n_clusters != 0 {
+            return *self;
+        }
+
+        let n_clusters = auto_n_clusters(image);
+        console_log!("Automatic superpixel count: {}", n_clusters);
+
+        Self {
+            n_clusters,
+            ..*self
+        }
+    }
+
+    /// Options of the merge criterion.
+    pub(crate) fn criterion_options(&self) -> CriterionOptions {
+        CriterionOptions {
+            normalize_by_boundary: self.normalize_by_boundary,
+            fixed_point: self.fixed_point,
+            fine_area: self.fine_area,
+            shadow_weight: self.shadow_weight,
+        }
+    }
+}
+
+/// Pixels per superpixel picked by [`auto_n_clusters`] on an image of average detail.
+const AUTO_PIXELS_PER_CLUSTER: f64 = 400.;
+/// Mean gradient magnitude of an image of average detail, in grey levels per pixel.
+const AUTO_REFERENCE_GRADIENT: f64 = 8.;
+/// Pixels sampled at most to measure the detail.
+const AUTO_SAMPLES: usize = 1 << 18;
+
+/// Superpixel count suited to an image, from its size and detail: one superpixel
+/// per 400 pixels on an image of average detail, up to twice more on detailed
+/// images and half as many on flat ones.
+///
+/// The detail is the mean gradient magnitude, measured on a subsampled image so
+/// that the estimation stays quick.
+pub fn auto_n_clusters(image: &Array3<u8>) -> usize {
+    let (height, width, _) = image.dim();
+    let pixels = width * height;
+    if pixels == 0 {
+        return 1;
+    }
+
+    let step = ((pixels as f64 / AUTO_SAMPLES as f64).sqrt().ceil() as usize).max(1);
+    let sampled = image.slice(s![..;step, ..;step, ..]).to_owned();
+    let gradient = gradient_magnitude(&sampled, GradientOperator::Sobel);
+    // Per pixel of the full image
+    let mean = gradient.mean().unwrap_or_default() as f64 / step as f64;
+
+    let detail = (mean / AUTO_REFERENCE_GRADIENT).sqrt().clamp(0.5, 2.);
+    let n_clusters = (pixels as f64 / AUTO_PIXELS_PER_CLUSTER * detail).round() as usize;
+
+    n_clusters.clamp(1, (pixels / 4).max(1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_auto_n_clusters() {
+        let flat = Array3::from_elem((200, 200, 3), 100);
+        assert_eq!(auto_n_clusters(&flat), 50);
+
+        let stripes = Array3::from_shape_fn((200, 200, 3), |(_, x, _)| (x % 4 * 60) as u8);
+        assert_eq!(auto_n_clusters(&stripes), 200);
+
+        assert_eq!(auto_n_clusters(&Array3::zeros((2, 2, 1))), 1);
+    }
+}
@@ -0,0 +1,100 @@
+use std::mem::size_of_val;
+
+use ndarray::Array2;
+
+/// Pixels of each leaf of a label map, stored as runs of consecutive row-major
+/// pixel indices, so per-region operations do not have to scan the whole map.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct LeafPixels {
+    // Runs of leaf `i` are `offsets[i]..offsets[i + 1]`
+    offsets: Vec<usize>,
+    starts: Vec<usize>,
+    lengths: Vec<usize>,
+}
+
+impl LeafPixels {
+    pub fn new(labels: &Array2<usize>) -> Self {
+        let n_leaves = labels.iter().max().map_or(0, |max| max + 1);
+        let mut runs = vec![Vec::new(); n_leaves];
+
+        let width = labels.dim().1;
+        for (y, row) in labels.outer_iter().enumerate() {
+            let mut start = 0;
+            for x in 1..=width {
+                if x == width || row[x] != row[start] {
+                    runs[row[start]].push((y * width + start, x - start));
+                    start = x;
+                }
+            }
+        }
+
+        let mut offsets = Vec::with_capacity(n_leaves + 1);
+        let mut starts = Vec::new();
+        let mut lengths = Vec::new();
+        offsets.push(0);
+        for leaf_runs in runs {
+            for (start, length) in leaf_runs {
+                starts.push(start);
+                lengths.push(length);
+            }
+            offsets.push(starts.len());
+        }
+
+        Self {
+            offsets,
+            starts,
+            lengths,
+        }
+    }
+
+    pub fn leaf_count(&self) -> usize {
+        self.offsets.len().saturating_sub(1)
+    }
+
+    /// Runs of the pixels of a leaf, as `(first pixel index, length)` pairs.
+    pub fn runs(&self, leaf: usize) -> impl Iterator<Item = (usize, usize)> + '_ {
+        let range = self.offsets[leaf]..self.offsets[leaf + 1];
+        self.starts[range.clone()]
+            .iter()
+            .cloned()
+            .zip(self.lengths[range].iter().cloned())
+    }
+
+    /// Row-major indices of the pixels of a leaf.
+    pub fn pixels(&self, leaf: usize) -> impl Iterator<Item = usize> + '_ {
+        self.runs(leaf)
+            .flat_map(|(start, length)| start..start + length)
+    }
+
+    /// Number of pixels of a leaf.
+    pub fn area(&self, leaf: usize) -> usize {
+        self.runs(leaf).map(|(_, length)| length).sum()
+    }
+
+    /// Memory held by the index, in bytes.
+    pub fn byte_size(&self) -> usize {
+        size_of_val(self.offsets.as_slice())
+            + size_of_val(self.starts.as_slice())
+            + size_of_val(self.lengths.as_slice())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_leaf_pixels() {
+        // 0 0 1
+        // 0 0 1
+        // 2 2 2
+        let labels = Array2::from_shape_vec((3, 3), vec![0, 0, 1, 0, 0, 1, 2, 2, 2]).unwrap();
+
+        let index = LeafPixels::new(&labels);
+
+        assert_eq!(index.leaf_count(), 3);
+        assert_eq!(index.runs(0).collect::<Vec<_>>(), vec![(0, 2), (3, 2)]);
+        assert_eq!(index.pixels(1).collect::<Vec<_>>(), vec![2, 5]);
+        assert_eq!(index.area(2), 3);
+    }
+}
@@ -0,0 +1,87 @@
+use std::collections::HashMap;
+
+use ndarray::Array2;
+
+/// Spans of the pixels whose center is inside a polygon, with the even-odd rule, as
+/// `(y, x_start, x_end)` with `x_end` excluded. Vertices are `(x, y)` pixel coordinates,
+/// `(0, 0)` being the top-left corner of the image.
+pub fn rasterize_polygon(
+    polygon: &[(f64, f64)],
+    width: usize,
+    height: usize,
+) -> Vec<(usize, usize, usize)> {
+    let mut spans = Vec::new();
+    let mut crossings = Vec::new();
+
+    for y in 0..height {
+        let center = y as f64 + 0.5;
+
+        crossings.clear();
+        for (i, &(x0, y0)) in polygon.iter().enumerate() {
+            let (x1, y1) = polygon[(i + 1) % polygon.len()];
+            if (y0 <= center) != (y1 <= center) {
+                crossings.push(x0 + (center - y0) * (x1 - x0) / (y1 - y0));
+            }
+        }
+        crossings.sort_by(|a, b| a.partial_cmp(b).unwrap());
+
+        for pair in crossings.chunks_exact(2) {
+            // First and last pixels whose center is between the crossings
+            let start = (pair[0] - 0.5).ceil().clamp(0., width as f64) as usize;
+            let end = (pair[1] - 0.5).ceil().clamp(0., width as f64) as usize;
+            if start < end {
+                spans.push((y, start, end));
+            }
+        }
+    }
+
+    spans
+}
+
+/// Regions of a cut intersecting a polygon, with the fraction of their area inside
+/// it, sorted by region. Regions fully inside the polygon have a fraction of 1.
+pub fn regions_in_polygon(labels: &Array2<usize>, polygon: &[(f64, f64)]) -> Vec<(usize, f64)> {
+    let (height, width) = labels.dim();
+
+    let mut inside = HashMap::<usize, usize>::new();
+    for (y, start, end) in rasterize_polygon(polygon, width, height) {
+        for x in start..end {
+            *inside.entry(labels[[y, x]]).or_insert(0) += 1;
+        }
+    }
+
+    let mut areas = HashMap::<usize, usize>::new();
+    for label in labels.iter().filter(|label| inside.contains_key(label)) {
+        *areas.entry(*label).or_insert(0) += 1;
+    }
+
+    let mut regions = inside
+        .into_iter()
+        .map(|(label, count)| (label, count as f64 / areas[&label] as f64))
+        .collect::<Vec<_>>();
+    regions.sort_by_key(|(label, _)| *label);
+
+    regions
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_regions_in_polygon() {
+        // Left half is region 0, right half region 1
+        let labels = Array2::from_shape_fn((4, 4), |(_, x)| usize::from(x >= 2));
+
+        // Square covering the left half and one column of the right half
+        let polygon = [(0., 0.), (3., 0.), (3., 4.), (0., 4.)];
+        assert_eq!(
+            rasterize_polygon(&polygon, 4, 4),
+            vec![(0, 0, 3), (1, 0, 3), (2, 0, 3), (3, 0, 3)]
+        );
+        assert_eq!(
+            regions_in_polygon(&labels, &polygon),
+            vec![(0, 1.), (1, 0.5)]
+        );
+    }
+}
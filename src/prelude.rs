@@ -0,0 +1,34 @@
+//! Items of the `wasm-bindgen` prelude used across the crate. Without the `wasm`
+//! feature, errors thrown to JS become panics.
+
+#[cfg(feature = "wasm")]
+pub use wasm_bindgen::{prelude::*, throw_str};
+
+#[cfg(not(feature = "wasm"))]
+pub use native::*;
+
+#[cfg(not(feature = "wasm"))]
+mod native {
+    use std::fmt::Debug;
+
+    /// Native counterpart of [`wasm_bindgen::UnwrapThrowExt`].
+    pub trait UnwrapThrowExt<T> {
+        fn expect_throw(self, message: &str) -> T;
+    }
+
+    impl<T> UnwrapThrowExt<T> for Option<T> {
+        fn expect_throw(self, message: &str) -> T {
+            self.expect(message)
+        }
+    }
+
+    impl<T, E: Debug> UnwrapThrowExt<T> for Result<T, E> {
+        fn expect_throw(self, message: &str) -> T {
+            self.expect(message)
+        }
+    }
+
+    pub fn throw_str(message: &str) -> ! {
+        panic!("{message}")
+    }
+}
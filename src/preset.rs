@@ -0,0 +1,88 @@
+#[cfg(feature = "wasm")]
+use wasm_bindgen::prelude::wasm_bindgen;
+
+use crate::{normalize::Normalization, params::SegmentationParams, smooth::Smoothing};
+
+/// Parameters suited to common IGN imagery products, as a starting point for users
+/// unfamiliar with the parameters.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[allow(non_camel_case_types)]
+pub enum Preset {
+    /// BD ORTHO® at 20 cm, RGB or IRC: buildings, roads and single trees
+    OrthoHR20cm,
+    /// SPOT 6/7 pansharpened at 1.5 m: blocks, fields and forest stands
+    Spot6,
+    /// Sentinel-2 at 10 m: agricultural parcels and land cover
+    Sentinel2_10m,
+}
+
+impl Preset {
+    /// Superpixels per megapixel of the image.
+    pub fn clusters_per_megapixel(self) -> f64 {
+        match self {
+            // About 500 px, 20 m², per superpixel
+            Preset::OrthoHR20cm => 2000.,
+            // About 250 px, 0.06 ha
+            Preset::Spot6 => 4000.,
+            // About 150 px, 1.5 ha, below the size of most parcels
+            Preset::Sentinel2_10m => 6500.,
+        }
+    }
+
+    /// Segmentation parameters for an image of `width * height` pixels, with at least
+    /// 4 pixels per superpixel.
+    pub fn params(self, width: usize, height: usize) -> SegmentationParams {
+        let pixels = width * height;
+        let n_clusters = (self.clusters_per_megapixel() * pixels as f64 / 1e6).round() as usize;
+        let mut params = SegmentationParams::new(n_clusters.clamp(1, (pixels / 4).max(1)));
+
+        match self {
+            // Man-made objects with straight borders, favoring compact regions
+            Preset::OrthoHR20cm => {
+                params.compactness = 8;
+                params.adherence_sweeps = 1;
+                params.normalize_by_boundary = true;
+            }
+            Preset::Spot6 => {
+                params.compactness = 4;
+                params.normalization = Normalization::PercentileStretch;
+                params.normalize_by_boundary = true;
+            }
+            // Irregular parcels at a coarse resolution, following the colors closely
+            Preset::Sentinel2_10m => {
+                params.compactness = 2;
+                params.slic_iterations = 5;
+                params.slic_threshold = Some(0.5);
+                params.normalization = Normalization::PercentileStretch;
+            }
+        }
+
+        params
+    }
+
+    /// Smoothing of the outlines in the polygon exports.
+    pub fn smoothing(self) -> Smoothing {
+        match self {
+            Preset::OrthoHR20cm => Smoothing::none(),
+            Preset::Spot6 => Smoothing::chaikin(2),
+            Preset::Sentinel2_10m => Smoothing::spline(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_preset_params() {
+        let params = Preset::Spot6.params(2000, 1000);
+        assert_eq!(params.n_clusters, 8000);
+        assert_eq!(params.normalization, Normalization::PercentileStretch);
+
+        // Capped for tiny images
+        assert_eq!(Preset::Sentinel2_10m.params(2, 2).n_clusters, 1);
+        assert_eq!(Preset::OrthoHR20cm.params(0, 0).n_clusters, 1);
+    }
+}
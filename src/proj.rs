@@ -0,0 +1,183 @@
+use std::f64::consts::{FRAC_PI_2, FRAC_PI_4};
+
+// GRS 80 ellipsoid of RGF93, considered equal to WGS 84 at the meter level
+const SEMI_MAJOR_AXIS: f64 = 6_378_137.;
+const FLATTENING: f64 = 1. / 298.257_222_101;
+
+// Lambert-93 conic projection
+const LAMBERT93_LAT_1: f64 = 44.;
+const LAMBERT93_LAT_2: f64 = 49.;
+const LAMBERT93_LAT_0: f64 = 46.5;
+const LAMBERT93_LON_0: f64 = 3.;
+const LAMBERT93_X_0: f64 = 700_000.;
+const LAMBERT93_Y_0: f64 = 6_600_000.;
+
+// Latitude bound of the square Web Mercator world
+const WEB_MERCATOR_MAX_LAT: f64 = 85.051_128_779_806_59;
+
+/// Coordinate reference systems supported by the reprojection.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Crs {
+    /// RGF93 / Lambert-93
+    Lambert93,
+    /// WGS 84 longitude and latitude, in degrees
+    Wgs84,
+    /// WGS 84 / Pseudo-Mercator
+    WebMercator,
+}
+
+impl Crs {
+    fn from_epsg(code: u32) -> Option<Self> {
+        match code {
+            2154 => Some(Crs::Lambert93),
+            4326 => Some(Crs::Wgs84),
+            3857 => Some(Crs::WebMercator),
+            _ => None,
+        }
+    }
+
+    /// Longitude and latitude in radians of a point.
+    fn unproject(self, (x, y): (f64, f64)) -> (f64, f64) {
+        match self {
+            Crs::Lambert93 => Lambert::lambert93().inverse(x, y),
+            Crs::Wgs84 => (x.to_radians(), y.to_radians()),
+            Crs::WebMercator => (
+                x / SEMI_MAJOR_AXIS,
+                FRAC_PI_2 - 2. * (-y / SEMI_MAJOR_AXIS).exp().atan(),
+            ),
+        }
+    }
+
+    /// Coordinates of a point given by its longitude and latitude in radians.
+    fn project(self, (lon, lat): (f64, f64)) -> (f64, f64) {
+        match self {
+            Crs::Lambert93 => Lambert::lambert93().forward(lon, lat),
+            Crs::Wgs84 => (lon.to_degrees(), lat.to_degrees()),
+            Crs::WebMercator => {
+                let max_lat = WEB_MERCATOR_MAX_LAT.to_radians();
+                let lat = lat.clamp(-max_lat, max_lat);
+                (
+                    SEMI_MAJOR_AXIS * lon,
+                    SEMI_MAJOR_AXIS * (FRAC_PI_4 + lat / 2.).tan().ln(),
+                )
+            }
+        }
+    }
+}
+
+/// Transformation of points from one coordinate reference system to another,
+/// given by their EPSG codes. Returns `None` unless both are among Lambert-93
+/// (2154), WGS 84 (4326) and Web Mercator (3857).
+///
+/// WGS 84 coordinates are `(longitude, latitude)`, in degrees.
+pub fn transformer(from: u32, to: u32) -> Option<impl Fn((f64, f64)) -> (f64, f64)> {
+    let (from, to) = (Crs::from_epsg(from)?, Crs::from_epsg(to)?);
+
+    Some(move |point| {
+        if from == to {
+            point
+        } else {
+            to.project(from.unproject(point))
+        }
+    })
+}
+
+/// Lambert conformal conic projection with two standard parallels, on the GRS 80
+/// ellipsoid.
+struct Lambert {
+    e: f64,
+    n: f64,
+    /// `a * F` of the EPSG formulas
+    scale: f64,
+    r0: f64,
+    lon0: f64,
+    x0: f64,
+    y0: f64,
+}
+
+impl Lambert {
+    fn lambert93() -> Self {
+        let e = (FLATTENING * (2. - FLATTENING)).sqrt();
+        let m = |lat: f64| lat.cos() / (1. - (e * lat.sin()).powi(2)).sqrt();
+        let t = |lat: f64| isometric_t(lat, e);
+
+        let (lat1, lat2) = (LAMBERT93_LAT_1.to_radians(), LAMBERT93_LAT_2.to_radians());
+        let n = (m(lat1).ln() - m(lat2).ln()) / (t(lat1).ln() - t(lat2).ln());
+        let scale = SEMI_MAJOR_AXIS * m(lat1) / (n * t(lat1).powf(n));
+
+        Self {
+            e,
+            n,
+            scale,
+            r0: scale * t(LAMBERT93_LAT_0.to_radians()).powf(n),
+            lon0: LAMBERT93_LON_0.to_radians(),
+            x0: LAMBERT93_X_0,
+            y0: LAMBERT93_Y_0,
+        }
+    }
+
+    fn forward(&self, lon: f64, lat: f64) -> (f64, f64) {
+        let r = self.scale * isometric_t(lat, self.e).powf(self.n);
+        let theta = self.n * (lon - self.lon0);
+
+        (
+            self.x0 + r * theta.sin(),
+            self.y0 + self.r0 - r * theta.cos(),
+        )
+    }
+
+    fn inverse(&self, x: f64, y: f64) -> (f64, f64) {
+        let (dx, dy) = (x - self.x0, self.r0 - (y - self.y0));
+        let r = dx.hypot(dy);
+        let t = (r / self.scale).powf(1. / self.n);
+        let lon = dx.atan2(dy) / self.n + self.lon0;
+
+        // Fixed point iteration on the latitude, converging in a few steps
+        let mut lat = FRAC_PI_2 - 2. * t.atan();
+        for _ in 0..10 {
+            let sin = self.e * lat.sin();
+            lat = FRAC_PI_2 - 2. * (t * ((1. - sin) / (1. + sin)).powf(self.e / 2.)).atan();
+        }
+
+        (lon, lat)
+    }
+}
+
+/// `t` function of the conformal latitude, as defined in the EPSG formulas.
+fn isometric_t(lat: f64, e: f64) -> f64 {
+    let sin = e * lat.sin();
+    (FRAC_PI_4 - lat / 2.).tan() / ((1. - sin) / (1. + sin)).powf(e / 2.)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close((x, y): (f64, f64), (ex, ey): (f64, f64), tolerance: f64) {
+        assert!(
+            (x - ex).abs() < tolerance && (y - ey).abs() < tolerance,
+            "{:?} != {:?}",
+            (x, y),
+            (ex, ey)
+        );
+    }
+
+    #[test]
+    fn test_transformer() {
+        let to_lambert = transformer(4326, 2154).unwrap();
+        let to_wgs84 = transformer(2154, 4326).unwrap();
+
+        // Origin of the projection
+        assert_close(to_lambert((3., 46.5)), (700_000., 6_600_000.), 1e-6);
+
+        let paris = (2.351_5, 48.856_7);
+        assert_close(to_wgs84(to_lambert(paris)), paris, 1e-9);
+
+        let to_mercator = transformer(4326, 3857).unwrap();
+        assert_close(to_mercator((180., 0.)), (20_037_508.342_789_244, 0.), 1e-6);
+        // Poles are clamped to the bounds of the square world
+        assert!(to_mercator((0., 90.)).1.is_finite());
+
+        assert!(transformer(2154, 32631).is_none());
+    }
+}
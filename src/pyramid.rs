@@ -0,0 +1,208 @@
+use std::collections::HashMap;
+
+use crate::prelude::*;
+use ndarray::{s, Array2, Array3};
+
+use crate::{
+    build_superpixel_graph, console_log,
+    hierarchy::binary_partition_tree,
+    normalize::{normalize_bands, Normalization},
+    params::SegmentationParams,
+    slic::SlicInfo,
+    slic_helpers::div_ceil,
+    statistics::StatisticsBuffer,
+    utils::array_from_planar,
+    Hierarchy,
+};
+
+/// Downsampling factors of the levels of the pyramid, from the coarsest one.
+const PYRAMID_FACTORS: [usize; 3] = [4, 2, 1];
+
+/// Downsample an image by averaging blocks of `factor * factor` pixels. Blocks on
+/// the right and bottom borders may be smaller.
+pub fn downsample(img: &Array3<u8>, factor: usize) -> Array3<u8> {
+    let (height, width, channels) = img.dim();
+    if factor <= 1 {
+        return img.clone();
+    }
+
+    Array3::from_shape_fn(
+        (div_ceil(height, factor), div_ceil(width, factor), channels),
+        |(y, x, c)| {
+            let block = img.slice(s![
+                y * factor..((y + 1) * factor).min(height),
+                x * factor..((x + 1) * factor).min(width),
+                c
+            ]);
+            let sum = block.iter().map(|v| *v as u32).sum::<u32>();
+            (sum as f64 / block.len() as f64).round() as u8
+        },
+    )
+}
+
+/// Map each leaf of a fine level to the leaf of a coarser level covering most of
+/// its pixels. The coarse level is `ratio` times smaller along each axis.
+pub fn coarse_leaf_mapping(
+    fine_labels: &Array2<usize>,
+    coarse_labels: &Array2<usize>,
+    ratio: usize,
+) -> Vec<usize> {
+    let n_leaves = fine_labels.iter().max().map_or(0, |max| max + 1);
+    let mut overlaps = vec![HashMap::<usize, usize>::new(); n_leaves];
+
+    for ((y, x), &leaf) in fine_labels.indexed_iter() {
+        let coarse = coarse_labels[[y / ratio, x / ratio]];
+        *overlaps[leaf].entry(coarse).or_insert(0) += 1;
+    }
+
+    overlaps
+        .into_iter()
+        .map(|overlap| {
+            overlap
+                .into_iter()
+                .max_by(|(leaf_a, count_a), (leaf_b, count_b)| {
+                    count_a.cmp(count_b).then(leaf_b.cmp(leaf_a))
+                })
+                .map_or(0, |(leaf, _)| leaf)
+        })
+        .collect()
+}
+
+/// Hierarchy computed on a downsampled image.
+#[cfg_attr(feature = "wasm", wasm_bindgen(getter_with_clone))]
+#[derive(Clone, Debug)]
+pub struct PyramidLevel {
+    /// Downsampling factor of the image
+    pub factor: usize,
+    pub width: usize,
+    pub height: usize,
+    pub hierarchy: Hierarchy,
+    /// Leaf of the previous (coarser) level covering most of each leaf, empty for
+    /// the first level
+    pub coarse_leaves: Vec<usize>,
+}
+
+/// Builds hierarchies at 1/4, 1/2 and full resolution, one level per call, so
+/// that coarse previews can be shown while the finer levels are computed.
+///
+/// The superpixel count is scaled with the image area at each level.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+pub struct HierarchyPyramid {
+    image: Array3<u8>,
+    params: SegmentationParams,
+    slic_info: SlicInfo<f64, usize>,
+    statistics: StatisticsBuffer,
+    next: usize,
+    previous: Option<(Array2<usize>, usize)>,
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+impl HierarchyPyramid {
+    #[cfg_attr(feature = "wasm", wasm_bindgen(constructor))]
+    pub fn new(
+        data: &[u8],
+        width: usize,
+        height: usize,
+        channels: usize,
+        params: &SegmentationParams,
+    ) -> Self {
+        let image = array_from_planar(data, width, height, channels);
+        Self::from_array(image, params)
+    }
+
+    /// Whether all the levels have been built.
+    pub fn is_complete(&self) -> bool {
+        self.next >= PYRAMID_FACTORS.len()
+    }
+
+    /// Build the next level, from the coarsest to the full resolution.
+    pub fn build_next(&mut self) -> PyramidLevel {
+        let factor = *PYRAMID_FACTORS
+            .get(self.next)
+            .expect_throw("All the pyramid levels are built");
+        self.next += 1;
+
+        let image = downsample(&self.image, factor);
+        let (height, width, _) = image.dim();
+
+        let params = SegmentationParams {
+            n_clusters: (self.params.n_clusters / (factor * factor)).max(1),
+            ..self.params
+        };
+
+        console_log!("Computing pyramid level 1/{}...", factor);
+
+        let (labels, graph) = build_superpixel_graph(
+            &image,
+            &params,
+            &mut self.slic_info,
+            &mut self.statistics,
+            None,
+            None,
+        );
+        let tree = binary_partition_tree(graph, params.criterion_options())
+            .unwrap_or_else(|error| throw_str(&error.to_string()));
+
+        let coarse_leaves = match &self.previous {
+            Some((coarse_labels, coarse_factor)) => {
+                coarse_leaf_mapping(&labels, coarse_labels, coarse_factor / factor)
+            }
+            None => Vec::new(),
+        };
+
+        let hierarchy = Hierarchy::new(&labels, tree);
+        self.previous = Some((labels, factor));
+
+        PyramidLevel {
+            factor,
+            width,
+            height,
+            hierarchy,
+            coarse_leaves,
+        }
+    }
+}
+
+impl HierarchyPyramid {
+    pub fn from_array(image: Array3<u8>, params: &SegmentationParams) -> Self {
+        let image = match params.normalization {
+            Normalization::None => image,
+            _ => normalize_bands(&image, params.normalization).into_owned(),
+        };
+
+        Self {
+            params: params.resolved(&image),
+            image,
+            slic_info: SlicInfo::new(),
+            statistics: StatisticsBuffer::default(),
+            next: 0,
+            previous: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_downsample() {
+        let img = Array3::from_shape_fn((5, 4, 1), |(y, _, _)| (y * 10) as u8);
+
+        let small = downsample(&img, 2);
+
+        assert_eq!(small.dim(), (3, 2, 1));
+        assert_eq!(small[[0, 0, 0]], 5);
+        // Last block only has the last row
+        assert_eq!(small[[2, 1, 0]], 40);
+    }
+
+    #[test]
+    fn test_coarse_leaf_mapping() {
+        let coarse = Array2::from_shape_vec((1, 2), vec![0, 1]).unwrap();
+        // Leaf 0 mostly lies in the first coarse pixel, leaf 1 in the second
+        let fine = Array2::from_shape_vec((2, 4), vec![0, 0, 0, 1, 0, 0, 1, 1]).unwrap();
+
+        assert_eq!(coarse_leaf_mapping(&fine, &coarse, 2), vec![0, 1]);
+    }
+}
@@ -0,0 +1,130 @@
+//! Python bindings of the core pipeline, returning numpy arrays.
+//!
+//! Arrays are exchanged as contiguous buffers rather than `ndarray` views, so the
+//! bindings do not depend on the `ndarray` version of the `numpy` crate.
+
+use ndarray::{Array2, Array3};
+use numpy::{
+    Element, PyArray1, PyArray2, PyArrayMethods, PyReadonlyArray1, PyReadonlyArray2,
+    PyReadonlyArray3, PyUntypedArrayMethods,
+};
+use pyo3::{exceptions::PyValueError, prelude::*, types::PyDict};
+
+use crate::{
+    attributes::NodeAttributes, cut_node_labels, hierarchical_segmentation_with_params,
+    pixels::LeafPixels, utils::labels_to_vec, SegmentationParams,
+};
+
+/// Python module of the `python` feature.
+#[pymodule]
+fn hierarchy_labellisation(module: &Bound<'_, PyModule>) -> PyResult<()> {
+    module.add_function(wrap_pyfunction!(hierarchical_segmentation, module)?)?;
+    module.add_function(wrap_pyfunction!(cut, module)?)?;
+    module.add_function(wrap_pyfunction!(node_attributes, module)?)?;
+
+    Ok(())
+}
+
+/// Segment a `(height, width, channels)` image into `n_clusters` superpixels and
+/// build their hierarchy. Returns the superpixel labels of the pixels, and the
+/// parents and levels of the tree nodes.
+#[pyfunction]
+#[allow(clippy::type_complexity)]
+fn hierarchical_segmentation<'py>(
+    py: Python<'py>,
+    image: PyReadonlyArray3<'py, u8>,
+    n_clusters: usize,
+) -> PyResult<(
+    Bound<'py, PyArray2<usize>>,
+    Bound<'py, PyArray1<usize>>,
+    Bound<'py, PyArray1<f64>>,
+)> {
+    let shape = <[usize; 3]>::try_from(image.shape()).unwrap();
+    let img = Array3::from_shape_vec(shape, contiguous(&image)?.to_vec()).unwrap();
+    let params = SegmentationParams::new(n_clusters);
+
+    let (labels, tree) = py.detach(|| hierarchical_segmentation_with_params(img, &params));
+
+    Ok((
+        to_numpy_2d(py, labels)?,
+        PyArray1::from_vec(py, tree.parents),
+        PyArray1::from_vec(py, tree.levels),
+    ))
+}
+
+/// Labels of the pixels in the cut of the hierarchy at `level`, each region being
+/// labelled by its tree node.
+#[pyfunction]
+fn cut<'py>(
+    py: Python<'py>,
+    labels: PyReadonlyArray2<'py, usize>,
+    parents: PyReadonlyArray1<'py, usize>,
+    levels: PyReadonlyArray1<'py, f64>,
+    level: f64,
+) -> PyResult<Bound<'py, PyArray2<usize>>> {
+    let (parents, levels) = tree(&parents, &levels)?;
+    let node_labels = cut_node_labels(parents, levels, level);
+
+    let shape = <[usize; 2]>::try_from(labels.shape()).unwrap();
+    let cut = contiguous(&labels)?
+        .iter()
+        .map(|label| node_labels.get(*label).copied())
+        .collect::<Option<Vec<_>>>()
+        .ok_or_else(|| PyValueError::new_err("Label out of range"))?;
+
+    to_numpy_2d(py, Array2::from_shape_vec(shape, cut).unwrap())
+}
+
+/// Attributes of the tree nodes, as a dict of arrays indexed by node: `areas` in
+/// pixels, and `lifetimes`.
+#[pyfunction]
+fn node_attributes<'py>(
+    py: Python<'py>,
+    labels: PyReadonlyArray2<'py, usize>,
+    parents: PyReadonlyArray1<'py, usize>,
+    levels: PyReadonlyArray1<'py, f64>,
+) -> PyResult<Bound<'py, PyDict>> {
+    let (parents, levels) = tree(&parents, &levels)?;
+    let shape = <[usize; 2]>::try_from(labels.shape()).unwrap();
+    let labels = Array2::from_shape_vec(shape, contiguous(&labels)?.to_vec()).unwrap();
+    if labels.iter().any(|label| *label >= parents.len()) {
+        return Err(PyValueError::new_err("Label out of range"));
+    }
+
+    let attributes = NodeAttributes::new(parents, levels, &LeafPixels::new(&labels));
+
+    let dict = PyDict::new(py);
+    dict.set_item("areas", PyArray1::from_vec(py, attributes.areas))?;
+    dict.set_item("lifetimes", PyArray1::from_vec(py, attributes.lifetimes))?;
+    Ok(dict)
+}
+
+fn contiguous<'a, T: Element, D: numpy::ndarray::Dimension>(
+    array: &'a numpy::PyReadonlyArray<'_, T, D>,
+) -> PyResult<&'a [T]> {
+    array
+        .as_slice()
+        .map_err(|_| PyValueError::new_err("Array not contiguous"))
+}
+
+/// Parents and levels of a partition tree, checked to be consistent.
+fn tree<'a>(
+    parents: &'a PyReadonlyArray1<'_, usize>,
+    levels: &'a PyReadonlyArray1<'_, f64>,
+) -> PyResult<(&'a [usize], &'a [f64])> {
+    let (parents, levels) = (contiguous(parents)?, contiguous(levels)?);
+    if parents.len() != levels.len() || parents.iter().any(|parent| *parent >= parents.len()) {
+        return Err(PyValueError::new_err("Invalid partition tree"));
+    }
+
+    Ok((parents, levels))
+}
+
+fn to_numpy_2d<T: Element + Clone>(
+    py: Python<'_>,
+    array: Array2<T>,
+) -> PyResult<Bound<'_, PyArray2<T>>> {
+    let (height, width) = array.dim();
+
+    PyArray1::from_vec(py, labels_to_vec(&array)).reshape([height, width])
+}
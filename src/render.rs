@@ -0,0 +1,710 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt::Write,
+};
+
+use crate::prelude::*;
+use ndarray::{Array2, ArrayView2};
+
+use crate::cut_node_labels;
+
+/// Window of a raster to render, `(x, y)` being its top-left corner in pixels.
+/// The rendered image has `round(width * scale) x round(height * scale)` pixels.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Viewport {
+    pub x: f64,
+    pub y: f64,
+    pub width: f64,
+    pub height: f64,
+    pub scale: f64,
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+impl Viewport {
+    #[cfg_attr(feature = "wasm", wasm_bindgen(constructor))]
+    pub fn new(x: f64, y: f64, width: f64, height: f64, scale: f64) -> Self {
+        Self {
+            x,
+            y,
+            width,
+            height,
+            scale,
+        }
+    }
+}
+
+impl Viewport {
+    /// Size of the rendered image, as `(width, height)`.
+    pub(crate) fn output_size(&self) -> (usize, usize) {
+        let size = |length: f64| (length * self.scale).round().max(0.) as usize;
+        (size(self.width), size(self.height))
+    }
+}
+
+/// How the regions of a cut are rendered.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CutStyle {
+    /// Each region filled with its color
+    #[default]
+    Fill,
+    /// Region borders in black, transparent elsewhere
+    Borders,
+    FillWithBorders,
+}
+
+/// Colors of the regions in the previews, keyed on the id of their tree node so a
+/// region keeps its color while the cut level changes.
+///
+/// Colors are derived from a hash of the node id, unless the node is assigned a
+/// class with a color in the palette.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Palette {
+    class_colors: HashMap<usize, [u8; 3]>,
+    node_classes: HashMap<usize, usize>,
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+impl Palette {
+    #[cfg_attr(feature = "wasm", wasm_bindgen(constructor))]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn set_class_color(&mut self, class: usize, r: u8, g: u8, b: u8) {
+        self.class_colors.insert(class, [r, g, b]);
+    }
+
+    /// Assign a class to each node, `nodes[i]` being of class `classes[i]`.
+    pub fn assign_classes(&mut self, nodes: Vec<usize>, classes: Vec<usize>) {
+        if nodes.len() != classes.len() {
+            throw_str("One class per node expected");
+        }
+        self.node_classes.extend(nodes.into_iter().zip(classes));
+    }
+
+    pub fn clear_classes(&mut self) {
+        self.node_classes.clear();
+    }
+
+    /// RGB color of a node.
+    pub fn node_color(&self, node: usize) -> Vec<u8> {
+        self.color(node).to_vec()
+    }
+}
+
+impl Palette {
+    pub fn color(&self, node: usize) -> [u8; 3] {
+        self.node_classes
+            .get(&node)
+            .and_then(|class| self.class_colors.get(class))
+            .copied()
+            .unwrap_or_else(|| hashed_color(node))
+    }
+}
+
+/// Deterministic color of a node, spread over the RGB cube by hashing its id.
+fn hashed_color(node: usize) -> [u8; 3] {
+    let hash = (node as u64 ^ 0x5bd1_e995).wrapping_mul(0x9e37_79b9_7f4a_7c15);
+    let bytes = hash.to_le_bytes();
+    [bytes[5] | 0x40, bytes[6] | 0x40, bytes[7] | 0x40]
+}
+
+/// Fill of the regions of a class in a thematic map.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FillPattern {
+    #[default]
+    Solid,
+    /// Lines going up to the right
+    DiagonalHatch,
+    Dots,
+}
+
+impl FillPattern {
+    fn name(self) -> &'static str {
+        match self {
+            FillPattern::Solid => "solid",
+            FillPattern::DiagonalHatch => "diagonal_hatch",
+            FillPattern::Dots => "dots",
+        }
+    }
+
+    /// Whether the rendered pixel `(tx, ty)` is painted, the pattern repeating every
+    /// `spacing` pixels.
+    fn covers(self, tx: usize, ty: usize, spacing: usize) -> bool {
+        let spacing = spacing.max(2);
+        match self {
+            FillPattern::Solid => true,
+            FillPattern::DiagonalHatch => (tx + ty).is_multiple_of(spacing),
+            FillPattern::Dots => {
+                let offset = |t: usize| (t % spacing).abs_diff(spacing / 2);
+                offset(tx) + offset(ty) <= spacing / 4
+            }
+        }
+    }
+}
+
+/// RGBA colors of the classes of a thematic map, with optional pattern fills for
+/// color-blind safe and printable maps.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Legend {
+    colors: BTreeMap<usize, [u8; 4]>,
+    /// Pattern and spacing in pixels of the classes not filled with a solid color
+    patterns: HashMap<usize, (FillPattern, usize)>,
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+impl Legend {
+    #[cfg_attr(feature = "wasm", wasm_bindgen(constructor))]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Legend of the classes `0..n`, the color of class `i` being `rgba[4 * i..4 * i + 4]`.
+    pub fn from_rgba(rgba: &[u8]) -> Self {
+        if !rgba.len().is_multiple_of(4) {
+            throw_str("Four bytes per class expected");
+        }
+        Self {
+            colors: rgba
+                .chunks_exact(4)
+                .map(|color| [color[0], color[1], color[2], color[3]])
+                .enumerate()
+                .collect(),
+            patterns: HashMap::new(),
+        }
+    }
+
+    pub fn set_class_color(&mut self, class: usize, r: u8, g: u8, b: u8, a: u8) {
+        self.colors.insert(class, [r, g, b, a]);
+    }
+
+    /// Fill the regions of a class with a pattern in its color, repeating every
+    /// `spacing` rendered pixels, the rest of the regions being transparent.
+    pub fn set_class_pattern(&mut self, class: usize, pattern: FillPattern, spacing: usize) {
+        if pattern == FillPattern::Solid {
+            self.patterns.remove(&class);
+        } else {
+            self.patterns.insert(class, (pattern, spacing));
+        }
+    }
+
+    /// Classes with a color, sorted.
+    pub fn classes(&self) -> Vec<usize> {
+        self.colors.keys().copied().collect()
+    }
+
+    /// Style of each class as JSON, e.g.
+    /// `{"1":{"color":"#008000ff","pattern":"diagonal_hatch","spacing":8}}`.
+    pub fn to_json(&self) -> String {
+        let mut json = String::from("{");
+        for (i, (class, [r, g, b, a])) in self.colors.iter().enumerate() {
+            let (pattern, spacing) = self.pattern(*class);
+            if i > 0 {
+                json.push(',');
+            }
+            write!(
+                json,
+                r##""{class}":{{"color":"#{r:02x}{g:02x}{b:02x}{a:02x}","pattern":"{}","spacing":{spacing}}}"##,
+                pattern.name()
+            )
+            .unwrap();
+        }
+        json.push('}');
+
+        json
+    }
+}
+
+impl Legend {
+    pub fn color(&self, class: usize) -> Option<[u8; 4]> {
+        self.colors.get(&class).copied()
+    }
+
+    pub fn pattern(&self, class: usize) -> (FillPattern, usize) {
+        self.patterns
+            .get(&class)
+            .copied()
+            .unwrap_or((FillPattern::Solid, 1))
+    }
+
+    /// Color of the rendered pixel `(tx, ty)` of a region of a class, with its pattern.
+    pub fn fill(&self, class: usize, tx: usize, ty: usize) -> Option<[u8; 4]> {
+        let (pattern, spacing) = self.pattern(class);
+        self.color(class)
+            .filter(|_| pattern.covers(tx, ty, spacing))
+    }
+}
+
+/// Render a window of a cut into RGBA, sampling the nearest pixel of the labels for
+/// each rendered pixel. Labels are the tree nodes of the regions, colored with the
+/// palette. Pixels outside of the raster are transparent.
+///
+/// With a `supersampling` factor above 1, the window is rendered that many times
+/// larger and downscaled with a box filter, smoothing the region borders.
+pub fn render_cut_window(
+    labels: ArrayView2<usize>,
+    viewport: &Viewport,
+    style: CutStyle,
+    palette: &Palette,
+    supersampling: usize,
+) -> Vec<u8> {
+    let (out_width, out_height) = viewport.output_size();
+    let factor = supersampling.max(1);
+
+    let data = render_labels(
+        labels,
+        viewport,
+        (out_width * factor, out_height * factor),
+        style,
+        palette,
+    );
+
+    if factor == 1 {
+        data
+    } else {
+        downscale_rgba(&data, out_width * factor, factor)
+    }
+}
+
+/// Render the cuts of a hierarchy at several levels side by side into a single
+/// RGBA sprite sheet, e.g. for a strip previewing the levels. `labels` are the
+/// leaves of the tree.
+///
+/// Each cut is a tile of the size of the viewport, the tiles filling rows of
+/// `columns` tiles in the order of `cut_levels`. The labels are sampled once for
+/// all the tiles.
+#[allow(clippy::too_many_arguments)]
+pub fn render_cut_sprite_sheet(
+    labels: ArrayView2<usize>,
+    parents: &[usize],
+    levels: &[f64],
+    cut_levels: &[f64],
+    viewport: &Viewport,
+    columns: usize,
+    style: CutStyle,
+    palette: &Palette,
+) -> Vec<u8> {
+    let (tile_width, tile_height) = viewport.output_size();
+    let columns = columns.clamp(1, cut_levels.len().max(1));
+    let rows = cut_levels.len().div_ceil(columns);
+    let sheet_width = columns * tile_width;
+
+    // Leaf sampled by each rendered pixel, one past the right and bottom edges
+    let (source_rows, source_columns) =
+        sample_positions(labels.dim(), viewport, (tile_width, tile_height));
+    let leaves = Array2::from_shape_fn((tile_height + 1, tile_width + 1), |(ty, tx)| {
+        Some(labels[[source_rows[ty]?, source_columns[tx]?]])
+    });
+
+    let mut sheet = vec![0; sheet_width * rows * tile_height * 4];
+    if sheet.is_empty() {
+        return sheet;
+    }
+    for (i, level) in cut_levels.iter().enumerate() {
+        let nodes = cut_node_labels(parents, levels, *level);
+        let tile_labels = leaves.mapv(|leaf| leaf.map_or(0, |leaf| nodes[leaf]));
+        let tile = render_sampled(
+            tile_labels.view(),
+            (tile_width, tile_height),
+            |tx, ty| leaves[[ty, tx]].map(|_| [ty, tx]),
+            style,
+            palette,
+        );
+
+        let (x, y) = (i % columns * tile_width, i / columns * tile_height);
+        for (ty, row) in tile.chunks_exact(tile_width * 4).enumerate() {
+            let start = ((y + ty) * sheet_width + x) * 4;
+            sheet[start..start + row.len()].copy_from_slice(row);
+        }
+    }
+
+    sheet
+}
+
+/// Render a window of a cut into a thematic map in RGBA, each region being filled
+/// with the color and pattern of its class in the legend, with the region borders in black if
+/// `borders`. `region_classes` maps the labels to their class. Regions without a
+/// class, or of a class missing from the legend, are transparent.
+pub fn render_class_map(
+    labels: ArrayView2<usize>,
+    viewport: &Viewport,
+    region_classes: &HashMap<usize, usize>,
+    legend: &Legend,
+    borders: bool,
+) -> Vec<u8> {
+    let out_size = viewport.output_size();
+    let (rows, columns) = sample_positions(labels.dim(), viewport, out_size);
+
+    render_sampled_with(
+        labels,
+        out_size,
+        |tx, ty| Some([rows[ty]?, columns[tx]?]),
+        borders,
+        |label, tx, ty| legend.fill(*region_classes.get(&label)?, tx, ty),
+    )
+}
+
+/// Render the legend as a strip of square swatches of `swatch_size` pixels, one per
+/// class in the order of [`Legend::classes`], filled like the regions of
+/// [`render_class_map`] and outlined in black. Swatches are `swatch_size / 4`
+/// pixels apart, so the RGBA strip of `n` classes is
+/// `n * swatch_size + (n - 1) * (swatch_size / 4)` pixels wide and `swatch_size` high.
+pub fn render_legend_strip(legend: &Legend, swatch_size: usize) -> Vec<u8> {
+    let classes = legend.classes();
+    let gap = swatch_size / 4;
+    let width = (classes.len() * (swatch_size + gap)).saturating_sub(gap);
+
+    let mut data = vec![0; width * swatch_size * 4];
+    for (i, class) in classes.iter().enumerate() {
+        let x0 = i * (swatch_size + gap);
+        for ty in 0..swatch_size {
+            for tx in 0..swatch_size {
+                let outline = tx == 0 || ty == 0 || tx + 1 == swatch_size || ty + 1 == swatch_size;
+                let color = if outline {
+                    Some([0, 0, 0, 255])
+                } else {
+                    legend.fill(*class, tx, ty)
+                };
+
+                if let Some(color) = color {
+                    let j = (ty * width + x0 + tx) * 4;
+                    data[j..j + 4].copy_from_slice(&color);
+                }
+            }
+        }
+    }
+
+    data
+}
+
+/// Source row and column of the labels sampled by each rendered row and column of
+/// a viewport, including one past the bottom and right edges, `None` outside.
+fn sample_positions(
+    (height, width): (usize, usize),
+    viewport: &Viewport,
+    (out_width, out_height): (usize, usize),
+) -> (Vec<Option<usize>>, Vec<Option<usize>>) {
+    let source = |start: f64, length: f64, i: usize, out_size: usize, size: usize| {
+        let position = (start + (i as f64 + 0.5) * length / out_size as f64).floor();
+        (position >= 0. && position < size as f64).then_some(position as usize)
+    };
+    let rows = (0..=out_height)
+        .map(|ty| source(viewport.y, viewport.height, ty, out_height, height))
+        .collect();
+    let columns = (0..=out_width)
+        .map(|tx| source(viewport.x, viewport.width, tx, out_width, width))
+        .collect();
+
+    (rows, columns)
+}
+
+/// Render a viewport at the given output size, as `(width, height)`.
+fn render_labels(
+    labels: ArrayView2<usize>,
+    viewport: &Viewport,
+    (out_width, out_height): (usize, usize),
+    style: CutStyle,
+    palette: &Palette,
+) -> Vec<u8> {
+    let (rows, columns) = sample_positions(labels.dim(), viewport, (out_width, out_height));
+
+    render_sampled(
+        labels,
+        (out_width, out_height),
+        |tx, ty| Some([rows[ty]?, columns[tx]?]),
+        style,
+        palette,
+    )
+}
+
+/// Render labels at the given output size, as `(width, height)`, `source` giving
+/// the `[y, x]` pixel of the labels sampled by each rendered pixel `(tx, ty)`, if any.
+/// It is also called one pixel past the right and bottom edges, to find borders.
+pub(crate) fn render_sampled(
+    labels: ArrayView2<usize>,
+    out_size: (usize, usize),
+    source: impl Fn(usize, usize) -> Option<[usize; 2]>,
+    style: CutStyle,
+    palette: &Palette,
+) -> Vec<u8> {
+    let fill = |label: usize, _, _| match style {
+        CutStyle::Borders => None,
+        CutStyle::Fill | CutStyle::FillWithBorders => {
+            let [r, g, b] = palette.color(label);
+            Some([r, g, b, 255])
+        }
+    };
+
+    render_sampled_with(labels, out_size, source, style != CutStyle::Fill, fill)
+}
+
+/// Same as [`render_sampled`], with the RGBA color of each rendered pixel `(tx, ty)`
+/// of a region given by `fill(label, tx, ty)`, and opaque black borders if `borders`.
+fn render_sampled_with(
+    labels: ArrayView2<usize>,
+    (out_width, out_height): (usize, usize),
+    source: impl Fn(usize, usize) -> Option<[usize; 2]>,
+    borders: bool,
+    fill: impl Fn(usize, usize, usize) -> Option<[u8; 4]>,
+) -> Vec<u8> {
+    let row_labels = |ty: usize| {
+        (0..=out_width)
+            .map(|tx| source(tx, ty).map(|pixel| labels[pixel]))
+            .collect::<Vec<_>>()
+    };
+
+    let mut data = vec![0; out_width * out_height * 4];
+    let mut next_row = row_labels(0);
+    for ty in 0..out_height {
+        let row = std::mem::replace(&mut next_row, row_labels(ty + 1));
+
+        for tx in 0..out_width {
+            let label = match row[tx] {
+                Some(label) => label,
+                None => continue,
+            };
+
+            // Borders are one rendered pixel wide, whatever the scale
+            let differs = |other: Option<usize>| matches!(other, Some(other) if other != label);
+            let on_border = borders && (differs(row[tx + 1]) || differs(next_row[tx]));
+
+            let color = if on_border {
+                Some([0, 0, 0, 255])
+            } else {
+                fill(label, tx, ty)
+            };
+
+            if let Some(color) = color {
+                let i = (ty * out_width + tx) * 4;
+                data[i..i + 4].copy_from_slice(&color);
+            }
+        }
+    }
+
+    data
+}
+
+/// Downscale an RGBA image by an integer factor, averaging each block of pixels
+/// weighted by their opacity.
+fn downscale_rgba(data: &[u8], width: usize, factor: usize) -> Vec<u8> {
+    // An empty window has no rows, whose count is deduced from the width
+    if width / factor == 0 || data.is_empty() {
+        return Vec::new();
+    }
+    let height = data.len() / (width * 4);
+    let (out_width, out_height) = (width / factor, height / factor);
+
+    let mut output = vec![0; out_width * out_height * 4];
+    for ty in 0..out_height {
+        for tx in 0..out_width {
+            let mut sums = [0usize; 4];
+            for y in ty * factor..(ty + 1) * factor {
+                for x in tx * factor..(tx + 1) * factor {
+                    let pixel = &data[(y * width + x) * 4..(y * width + x + 1) * 4];
+                    let alpha = usize::from(pixel[3]);
+                    for c in 0..3 {
+                        sums[c] += usize::from(pixel[c]) * alpha;
+                    }
+                    sums[3] += alpha;
+                }
+            }
+
+            let i = (ty * out_width + tx) * 4;
+            for c in 0..3 {
+                output[i + c] = sums[c].checked_div(sums[3]).unwrap_or(0) as u8;
+            }
+            output[i + 3] = (sums[3] / (factor * factor)) as u8;
+        }
+    }
+
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use ndarray::Array2;
+
+    use super::*;
+
+    #[test]
+    fn test_render_cut_window() {
+        // Left half is region 0, right half region 1
+        let labels = Array2::from_shape_fn((4, 4), |(_, x)| usize::from(x >= 2));
+
+        // Right half zoomed twice, overflowing the raster by one pixel
+        let viewport = Viewport::new(2., 0., 3., 1., 2.);
+        let palette = Palette::new();
+        let data = render_cut_window(labels.view(), &viewport, CutStyle::Fill, &palette, 1);
+
+        assert_eq!(data.len(), 6 * 2 * 4);
+        assert_eq!(&data[..3], &palette.color(1));
+        assert_eq!(data[3], 255);
+        assert_eq!(data[4 * 4 + 3], 0);
+
+        let data = render_cut_window(
+            labels.view(),
+            &Viewport::new(0., 0., 4., 1., 1.),
+            CutStyle::Borders,
+            &palette,
+            1,
+        );
+        let alpha = data.chunks(4).map(|pixel| pixel[3]).collect::<Vec<_>>();
+        assert_eq!(alpha, vec![0, 255, 0, 0]);
+    }
+
+    #[test]
+    fn test_supersampling() {
+        // Vertical border in the middle of a single pixel wide viewport
+        let labels = Array2::from_shape_fn((2, 4), |(_, x)| usize::from(x >= 2));
+        let viewport = Viewport::new(1., 0., 2., 1., 0.5);
+
+        let data = render_cut_window(
+            labels.view(),
+            &viewport,
+            CutStyle::Borders,
+            &Palette::new(),
+            2,
+        );
+        // Half of the supersampled pixels are on the border
+        assert_eq!(data, vec![0, 0, 0, 127]);
+
+        // Windows less than half a pixel wide or high are empty
+        for (width, height) in [(0.1, 1.), (1., 0.1), (0., 0.)] {
+            let viewport = Viewport::new(0., 0., width, height, 1.);
+            let data =
+                render_cut_window(labels.view(), &viewport, CutStyle::Fill, &Palette::new(), 3);
+            assert!(data.is_empty());
+        }
+    }
+
+    #[test]
+    fn test_sprite_sheet() {
+        // Leaves 0 and 1 merged by node 2 at level 1
+        let labels = Array2::from_shape_fn((1, 2), |(_, x)| x);
+        let (parents, levels) = (vec![2, 2, 2], vec![0., 0., 1.]);
+        let palette = Palette::new();
+
+        let sheet = render_cut_sprite_sheet(
+            labels.view(),
+            &parents,
+            &levels,
+            &[0., 2., 0.],
+            &Viewport::new(0., 0., 2., 1., 1.),
+            2,
+            CutStyle::Fill,
+            &palette,
+        );
+
+        // Two rows of two tiles of 2x1 pixels, the last tile empty
+        let colors = sheet
+            .chunks(4)
+            .map(|pixel| (pixel[3] == 255).then(|| [pixel[0], pixel[1], pixel[2]]))
+            .collect::<Vec<_>>();
+        let color = |node| Some(palette.color(node));
+        assert_eq!(
+            colors,
+            vec![
+                color(0),
+                color(1),
+                color(2),
+                color(2),
+                color(0),
+                color(1),
+                None,
+                None
+            ]
+        );
+    }
+
+    #[test]
+    fn test_palette() {
+        let mut palette = Palette::new();
+        assert_eq!(palette.color(42), Palette::new().color(42));
+        assert_ne!(palette.color(42), palette.color(43));
+
+        palette.set_class_color(1, 255, 0, 0);
+        palette.assign_classes(vec![42], vec![1]);
+        assert_eq!(palette.color(42), [255, 0, 0]);
+
+        palette.clear_classes();
+        assert_eq!(palette.color(42), hashed_color(42));
+    }
+
+    #[test]
+    fn test_render_class_map() {
+        // Regions 0 and 1 of class 5, region 2 without a class
+        let labels = Array2::from_shape_vec((1, 4), vec![0, 1, 1, 2]).unwrap();
+        let classes = HashMap::from([(0, 5), (1, 5)]);
+        let mut legend = Legend::new();
+        legend.set_class_color(5, 0, 128, 0, 200);
+
+        let viewport = Viewport::new(0., 0., 4., 1., 1.);
+        let data = render_class_map(labels.view(), &viewport, &classes, &legend, false);
+        assert_eq!(
+            data,
+            [[0, 128, 0, 200], [0, 128, 0, 200], [0, 128, 0, 200], [0; 4]].concat()
+        );
+
+        // Borders between regions, even of the same class
+        let data = render_class_map(labels.view(), &viewport, &classes, &legend, true);
+        assert_eq!(&data[..8], &[0, 0, 0, 255, 0, 128, 0, 200]);
+        assert_eq!(&data[8..12], &[0, 0, 0, 255]);
+
+        assert_eq!(
+            Legend::from_rgba(&[1, 2, 3, 4, 5, 6, 7, 8]).color(1),
+            Some([5, 6, 7, 8])
+        );
+    }
+
+    #[test]
+    fn test_fill_patterns() {
+        let labels = Array2::zeros((4, 4));
+        let classes = HashMap::from([(0, 1)]);
+        let mut legend = Legend::from_rgba(&[0, 0, 0, 0, 255, 0, 0, 255]);
+        legend.set_class_pattern(1, FillPattern::DiagonalHatch, 2);
+
+        let viewport = Viewport::new(0., 0., 4., 4., 1.);
+        let data = render_class_map(labels.view(), &viewport, &classes, &legend, false);
+        let painted = data
+            .chunks(4)
+            .map(|pixel| pixel[3] == 255)
+            .collect::<Vec<_>>();
+        // Checkerboard of diagonal lines two pixels apart
+        assert!(painted
+            .iter()
+            .enumerate()
+            .all(|(i, painted)| *painted == ((i / 4 + i % 4) % 2 == 0)));
+
+        legend.set_class_pattern(1, FillPattern::Dots, 4);
+        let data = render_class_map(labels.view(), &viewport, &classes, &legend, false);
+        assert_eq!(data.chunks(4).filter(|pixel| pixel[3] == 255).count(), 5);
+        assert_eq!(&data[(2 * 4 + 2) * 4..][..4], &[255, 0, 0, 255]);
+
+        legend.set_class_pattern(1, FillPattern::Solid, 0);
+        assert_eq!(legend, Legend::from_rgba(&[0, 0, 0, 0, 255, 0, 0, 255]));
+    }
+
+    #[test]
+    fn test_legend_strip() {
+        let mut legend = Legend::from_rgba(&[255, 0, 0, 255, 0, 0, 255, 128]);
+        legend.set_class_pattern(1, FillPattern::Dots, 8);
+
+        let data = render_legend_strip(&legend, 4);
+        // Two swatches of 4 pixels, one pixel apart
+        assert_eq!(data.len(), 9 * 4 * 4);
+        let pixel = |x: usize, y: usize| &data[(y * 9 + x) * 4..][..4];
+        assert_eq!(pixel(0, 0), &[0, 0, 0, 255]);
+        assert_eq!(pixel(1, 1), &[255, 0, 0, 255]);
+        assert_eq!(pixel(4, 1), &[0; 4]);
+
+        assert_eq!(
+            legend.to_json(),
+            r##"{"0":{"color":"#ff0000ff","pattern":"solid","spacing":1},"1":{"color":"#0000ff80","pattern":"dots","spacing":8}}"##
+        );
+    }
+}
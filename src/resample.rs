@@ -0,0 +1,165 @@
+use std::collections::HashMap;
+
+use crate::prelude::*;
+use ndarray::{s, Array2};
+
+use crate::{slic_helpers::div_ceil, utils::labels_to_vec};
+
+/// Downsampling factors of the overviews, from the finest one.
+const OVERVIEW_FACTORS: [usize; 3] = [2, 4, 8];
+
+/// Resample a label map by `factor` along each axis, e.g. `0.5` for half its
+/// width and height, keeping at least one pixel.
+///
+/// When downsampling, each pixel gets the majority label of the block of pixels it
+/// covers, ties going to the label at the center of the block, so thin regions do
+/// not drift. When upsampling, each pixel gets the label of the nearest source
+/// pixel center, so the boundaries stay in place instead of shifting by half a
+/// pixel.
+pub fn resample_labels(labels: &Array2<usize>, factor: f64) -> Array2<usize> {
+    let (height, width) = labels.dim();
+    let size = |len: usize| ((len as f64 * factor).round() as usize).max(1);
+    let (new_height, new_width) = (size(height), size(width));
+    if labels.is_empty() || (new_height, new_width) == (height, width) {
+        return labels.clone();
+    }
+
+    // Source pixels covered by pixel `i` of an axis of `new_len` pixels
+    let block = |i: usize, len: usize, new_len: usize| {
+        let start = i * len / new_len;
+        let end = div_ceil((i + 1) * len, new_len).max(start + 1);
+        (start, end, (2 * i + 1) * len / (2 * new_len))
+    };
+
+    let mut counts = HashMap::new();
+    Array2::from_shape_fn((new_height, new_width), |(y, x)| {
+        let (y0, y1, center_y) = block(y, height, new_height);
+        let (x0, x1, center_x) = block(x, width, new_width);
+        let center = labels[[center_y, center_x]];
+        if (y1 - y0) * (x1 - x0) == 1 {
+            return center;
+        }
+
+        counts.clear();
+        for label in labels.slice(s![y0..y1, x0..x1]) {
+            *counts.entry(*label).or_insert(0) += 1;
+        }
+        let center_count = counts[&center];
+        counts
+            .iter()
+            .filter(|(_, count)| **count > center_count)
+            .max_by_key(|(label, count)| (**count, usize::MAX - **label))
+            .map_or(center, |(label, _)| *label)
+    })
+}
+
+/// Downsampled copies of the labels of a cut at 1/2, 1/4 and 1/8 resolution, built
+/// by majority vote with [`resample_labels`], for the zoomed out display of
+/// segmentations of large tiles.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+pub struct LabelOverviews {
+    /// Overviews as `(factor, labels)`, from the finest one
+    overviews: Vec<(usize, Array2<usize>)>,
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+impl LabelOverviews {
+    /// Overviews of the labels of a `width * height` image.
+    #[cfg_attr(feature = "wasm", wasm_bindgen(constructor))]
+    pub fn new(width: usize, height: usize, labels: Vec<usize>) -> Self {
+        let labels =
+            Array2::from_shape_vec((height, width), labels).expect_throw("Labels wrong shape");
+        Self::from_array(&labels)
+    }
+
+    /// Downsampling factors of the overviews, from the finest one.
+    pub fn factors(&self) -> Vec<usize> {
+        self.overviews.iter().map(|(factor, _)| *factor).collect()
+    }
+
+    /// Factor of the coarsest overview with at least one pixel per screen pixel at
+    /// `zoom` screen pixels per image pixel, `1` when the full resolution is needed.
+    pub fn factor_for_zoom(&self, zoom: f64) -> usize {
+        self.overviews
+            .iter()
+            .rev()
+            .map(|(factor, _)| *factor)
+            .find(|factor| *factor as f64 * zoom <= 1.)
+            .unwrap_or(1)
+    }
+
+    pub fn width(&self, factor: usize) -> usize {
+        self.overview(factor).ncols()
+    }
+
+    pub fn height(&self, factor: usize) -> usize {
+        self.overview(factor).nrows()
+    }
+
+    /// Labels of the overview at the given factor, row by row.
+    pub fn labels(&self, factor: usize) -> Vec<usize> {
+        labels_to_vec(self.overview(factor))
+    }
+}
+
+impl LabelOverviews {
+    pub fn from_array(labels: &Array2<usize>) -> Self {
+        Self {
+            overviews: OVERVIEW_FACTORS
+                .iter()
+                .map(|factor| (*factor, resample_labels(labels, 1. / *factor as f64)))
+                .collect(),
+        }
+    }
+
+    /// Labels of the overview at the given factor, if there is one.
+    pub fn level(&self, factor: usize) -> Option<&Array2<usize>> {
+        self.overviews
+            .iter()
+            .find(|(f, _)| *f == factor)
+            .map(|(_, labels)| labels)
+    }
+
+    fn overview(&self, factor: usize) -> &Array2<usize> {
+        self.level(factor)
+            .expect_throw("No overview at this factor")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ndarray::array;
+
+    use super::*;
+
+    #[test]
+    fn test_resample_labels() {
+        let labels = array![[0, 0, 1, 1], [0, 2, 1, 1], [3, 3, 3, 1], [3, 3, 3, 3]];
+
+        assert_eq!(resample_labels(&labels, 0.5), array![[0, 1], [3, 3]]);
+        assert_eq!(resample_labels(&labels, 0.25), array![[3]]);
+
+        let upsampled = resample_labels(&array![[0, 1], [2, 3]], 2.);
+        assert_eq!(
+            upsampled,
+            array![[0, 0, 1, 1], [0, 0, 1, 1], [2, 2, 3, 3], [2, 2, 3, 3]]
+        );
+        // Back to the original after a round trip
+        assert_eq!(resample_labels(&upsampled, 0.5), array![[0, 1], [2, 3]]);
+    }
+
+    #[test]
+    fn test_label_overviews() {
+        let labels = Array2::from_shape_fn((16, 12), |(y, x)| y / 8 * 2 + x / 6);
+        let overviews = LabelOverviews::from_array(&labels);
+
+        assert_eq!(overviews.factors(), vec![2, 4, 8]);
+        assert_eq!(overviews.level(4).unwrap().dim(), (4, 3));
+        assert_eq!(overviews.level(8), Some(&array![[0, 1], [2, 3]]));
+        assert_eq!(overviews.level(3), None);
+
+        assert_eq!(overviews.factor_for_zoom(2.), 1);
+        assert_eq!(overviews.factor_for_zoom(0.3), 2);
+        assert_eq!(overviews.factor_for_zoom(0.01), 8);
+    }
+}
@@ -0,0 +1,157 @@
+use std::collections::{HashMap, HashSet};
+
+use ndarray::{Array2, Array3};
+#[cfg(feature = "wasm")]
+use wasm_bindgen::prelude::wasm_bindgen;
+
+use crate::utils::{array_to_image, image_to_png};
+
+/// Samples of the viridis colormap, evenly spaced between 0 and 1.
+const VIRIDIS: [[u8; 3]; 9] = [
+    [68, 1, 84],
+    [71, 44, 122],
+    [59, 81, 139],
+    [44, 113, 142],
+    [33, 144, 141],
+    [39, 173, 129],
+    [92, 200, 99],
+    [170, 220, 50],
+    [253, 231, 37],
+];
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Colormap {
+    #[default]
+    Grayscale,
+    Viridis,
+}
+
+impl Colormap {
+    /// Color of a value between 0 and 1.
+    pub fn color(&self, t: f64) -> [u8; 3] {
+        let t = t.clamp(0., 1.);
+        match self {
+            Colormap::Grayscale => [(t * 255.).round() as u8; 3],
+            Colormap::Viridis => {
+                let position = t * (VIRIDIS.len() - 1) as f64;
+                let i = (position.floor() as usize).min(VIRIDIS.len() - 2);
+                let w = position - i as f64;
+
+                let mut color = [0; 3];
+                for (c, value) in color.iter_mut().enumerate() {
+                    let low = VIRIDIS[i][c] as f64;
+                    let high = VIRIDIS[i + 1][c] as f64;
+                    *value = (low + w * (high - low)).round() as u8;
+                }
+                color
+            }
+        }
+    }
+}
+
+/// Level at which two nodes of the tree are merged, which is the level of their
+/// lowest common ancestor. Infinite if they are never merged.
+fn merge_level(parents: &[usize], levels: &[f64], a: usize, b: usize) -> f64 {
+    let mut ancestors = HashSet::new();
+    let mut node = a;
+    loop {
+        ancestors.insert(node);
+        if parents[node] == node {
+            break;
+        }
+        node = parents[node];
+    }
+
+    let mut node = b;
+    loop {
+        if ancestors.contains(&node) {
+            return levels[node];
+        }
+        if parents[node] == node {
+            return f64::INFINITY;
+        }
+        node = parents[node];
+    }
+}
+
+/// Compute the saliency map (ultrametric contour map) of a hierarchy: each pixel
+/// on the border of two leaves gets the level at which they are merged, other
+/// pixels are `0`. Borders are drawn on the top-left side, as in the preview.
+pub fn saliency_map(labels: &Array2<usize>, parents: &[usize], levels: &[f64]) -> Array2<f64> {
+    let (height, width) = labels.dim();
+    let mut saliency = Array2::zeros((height, width));
+    let mut cache = HashMap::<(usize, usize), f64>::new();
+
+    for ((y, x), &label) in labels.indexed_iter() {
+        // Loop over the neighbors (right and bottom)
+        for (dy, dx) in [(0, 1), (1, 0)] {
+            if let Some(&n_label) = labels.get((y + dy, x + dx)) {
+                if n_label != label {
+                    let pair = (label.min(n_label), label.max(n_label));
+                    let level = *cache
+                        .entry(pair)
+                        .or_insert_with(|| merge_level(parents, levels, pair.0, pair.1));
+
+                    let value: &mut f64 = &mut saliency[[y, x]];
+                    *value = value.max(level);
+                }
+            }
+        }
+    }
+
+    saliency
+}
+
+/// Render a saliency map as a PNG. Levels are divided by the highest finite level,
+/// after a `log(1 + level)` scaling if `log_scale` is set.
+pub fn saliency_to_png(saliency: &Array2<f64>, log_scale: bool, colormap: Colormap) -> Vec<u8> {
+    let scale = |level: f64| if log_scale { level.ln_1p() } else { level };
+
+    let max_level = saliency
+        .iter()
+        .filter(|level| level.is_finite())
+        .fold(0.0f64, |acc, level| acc.max(scale(*level)));
+
+    let (height, width) = saliency.dim();
+    let mut img = Array3::zeros((height, width, 3));
+
+    for ((y, x), level) in saliency.indexed_iter() {
+        let t = if !level.is_finite() {
+            1.
+        } else if max_level > 0. {
+            scale(*level) / max_level
+        } else {
+            0.
+        };
+
+        for (c, value) in colormap.color(t).into_iter().enumerate() {
+            img[[y, x, c]] = value;
+        }
+    }
+
+    image_to_png(array_to_image(img.view()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_saliency_map() {
+        // 0 0 1
+        // 0 0 1
+        // 2 2 2
+        let labels = Array2::from_shape_vec((3, 3), vec![0, 0, 1, 0, 0, 1, 2, 2, 2]).unwrap();
+        // 0 and 1 merge at level 1, then with 2 at level 5
+        let parents = vec![3, 3, 4, 4, 4];
+        let levels = vec![0., 0., 0., 1., 5.];
+
+        let saliency = saliency_map(&labels, &parents, &levels);
+
+        assert_eq!(saliency[[0, 1]], 1.);
+        assert_eq!(saliency[[1, 1]], 5.);
+        assert_eq!(saliency[[1, 2]], 5.);
+        assert_eq!(saliency[[0, 0]], 0.);
+    }
+}
@@ -1,17 +1,33 @@
 use crate::slic_helpers::{distance_pixel, div_ceil, get_pixel};
+use crate::utils::Rect;
 
-use ndarray::{s, Array1, Array3};
+use ndarray::{s, Array1, Array2, Array3};
 use simple_clustering::error::{ScError, SeedErrorKind};
 use simple_clustering::Superpixel;
 
 use num_traits::ToPrimitive;
+#[cfg(feature = "wasm")]
+use wasm_bindgen::prelude::wasm_bindgen;
+
+/// Layout of the initial superpixel centers.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SeedGrid {
+    #[default]
+    Square,
+    /// Odd rows shifted by half a column, so that superpixels tile as hexagons
+    /// rather than squares on homogeneous areas
+    Hexagonal,
+}
 
 /// Initialize the superpixel seed centers.
 ///
-/// `width`, `height`, `s`, and `k` must not be `0`.
+/// `width`, `height`, `s`, and `k` must not be `0`. Images thinner than `s` get a
+/// single row or column of seeds, centered across it.
 pub fn init_seeds<T: Copy>(
     s: u32,
     k: u32,
+    grid: SeedGrid,
     image: &Array3<T>,
     seeds: &mut Vec<Superpixel<Array1<T>>>,
 ) -> Result<(), ScError> {
@@ -19,7 +35,8 @@ pub fn init_seeds<T: Copy>(
     let width = image.shape()[1] as u32;
     let height = image.shape()[0] as u32;
     let s = s;
-    let half_s = div_ceil(s, 2);
+    // Center of the first cell of `s` pixels, within it
+    let half_s = div_ceil(s, 2).min(s - 1);
     let mut x_seeds = div_ceil(width, s);
     let mut y_seeds = div_ceil(height, s);
 
@@ -31,14 +48,8 @@ pub fn init_seeds<T: Copy>(
         y_seeds -= 1;
     }
 
-    // If the seed count is larger than k, reduce until we're below (we may add
-    // seeds in the "enforce connectivity" step later for SLIC)
-    while u64::from(x_seeds) * u64::from(y_seeds) > u64::from(k) {
-        x_seeds -= 1;
-        y_seeds -= 1;
-    }
-
-    // Edge case for very small image sizes where no clusters would be produced
+    // Edge case for images thinner than the interval, seeded along a single row
+    // or column
     if x_seeds == 0 {
         x_seeds += 1;
     }
@@ -46,6 +57,17 @@ pub fn init_seeds<T: Copy>(
         y_seeds += 1;
     }
 
+    // If the seed count is larger than k, reduce until we're below (we may add
+    // seeds in the "enforce connectivity" step later for SLIC)
+    while u64::from(x_seeds) * u64::from(y_seeds) > u64::from(k) {
+        if x_seeds > 1 {
+            x_seeds -= 1;
+        }
+        if y_seeds > 1 {
+            y_seeds -= 1;
+        }
+    }
+
     // Error correction for spreading the seeds out more evenly along rows/cols
     let x_correction = (f64::from(width) - f64::from(x_seeds) * f64::from(s)) / f64::from(x_seeds);
     let y_correction = (f64::from(height) - f64::from(y_seeds) * f64::from(s)) / f64::from(y_seeds);
@@ -61,17 +83,24 @@ pub fn init_seeds<T: Copy>(
         let y_correct = (f64::from(ydx) * y_correction)
             .to_u32()
             .ok_or("Could not convert Y correction")?;
+        // Half the column spacing on odd rows of a hexagonal grid
+        let x_offset = match grid {
+            SeedGrid::Hexagonal if ydx % 2 == 1 && x_seeds > 1 => {
+                (f64::from(s) + x_correction) / 2.
+            }
+            _ => 0.,
+        };
         for xdx in 0..x_seeds {
-            let x_correct = (f64::from(xdx) * x_correction)
+            let x_correct = (f64::from(xdx) * x_correction + x_offset)
                 .to_u32()
                 .ok_or("Could not convert X correction")?;
             let x = xdx
                 .saturating_mul(s)
-                .saturating_add(half_s)
+                .saturating_add(if half_s < width { half_s } else { width / 2 })
                 .saturating_add(x_correct);
             let y = ydx
                 .saturating_mul(s)
-                .saturating_add(half_s)
+                .saturating_add(if half_s < height { half_s } else { height / 2 })
                 .saturating_add(y_correct);
             let i = usize::try_from(
                 u64::from(y)
@@ -92,6 +121,131 @@ pub fn init_seeds<T: Copy>(
     Ok(())
 }
 
+/// Initialize seeds at the centers of a previous run, as `(x, y)`, e.g. on the
+/// previous tile of a sequence shifted to this one, so that the superpixels stay
+/// stable. The seeds of the square grid are added in the cells of `s` pixels
+/// without a previous center, e.g. where the tiles do not overlap.
+pub fn init_seeds_warm<T: Copy>(
+    s: u32,
+    k: u32,
+    centers: &[(u32, u32)],
+    image: &Array3<T>,
+    seeds: &mut Vec<Superpixel<Array1<T>>>,
+) -> Result<(), ScError> {
+    init_seeds(s, k, SeedGrid::Square, image, seeds)?;
+
+    let width = image.shape()[1] as u32;
+    let height = image.shape()[0] as u32;
+    let columns = div_ceil(width, s) as usize;
+    let cell = |x: u32, y: u32| (y / s) as usize * columns + (x / s) as usize;
+
+    let mut covered = vec![false; columns * div_ceil(height, s) as usize];
+    let centers = centers
+        .iter()
+        .filter(|(x, y)| *x < width && *y < height)
+        .collect::<Vec<_>>();
+    for (x, y) in &centers {
+        covered[cell(*x, *y)] = true;
+    }
+
+    seeds.retain(|seed| !covered[cell(seed.x, seed.y)]);
+    seeds.extend(centers.into_iter().map(|&(x, y)| Superpixel {
+        data: image.slice(s![y as i32, x as i32, ..]).to_owned(),
+        x,
+        y,
+    }));
+
+    Ok(())
+}
+
+/// Initialize seed centers spaced according to a density raster of the image size,
+/// e.g. a gradient magnitude or an area of interest: about `k` seeds are placed in
+/// proportion to the density, so that superpixels are smaller where it is high.
+///
+/// Cells of `4 * s` pixels are split in quadrants while they are expected to hold
+/// 2.5 seeds or more, closer to the 4 seeds of the quadrants than to 1, and each
+/// remaining cell gets a seed at its center. A uniform
+/// density gives the square grid. The interval around each seed, the side of its
+/// cell, is pushed to `intervals`.
+pub fn init_seeds_density<T: Copy>(
+    s: u32,
+    k: u32,
+    density: &Array2<f32>,
+    image: &Array3<T>,
+    seeds: &mut Vec<Superpixel<Array1<T>>>,
+    intervals: &mut Vec<u32>,
+) -> Result<(), ScError> {
+    let (height, width) = (image.shape()[0], image.shape()[1]);
+    if density.dim() != (height, width) {
+        return Err("Density map wrong shape".into());
+    }
+
+    // Summed-area table of the density, negative values counting as zero
+    let mut integral = Array2::<f64>::zeros((height + 1, width + 1));
+    for ((y, x), value) in density.indexed_iter() {
+        integral[[y + 1, x + 1]] =
+            f64::from(value.max(0.)) + integral[[y, x + 1]] + integral[[y + 1, x]]
+                - integral[[y, x]];
+    }
+    let total = integral[[height, width]];
+    if total <= 0. {
+        init_seeds(s, k, SeedGrid::Square, image, seeds)?;
+        intervals.clear();
+        intervals.resize(seeds.len(), s);
+        return Ok(());
+    }
+
+    seeds.clear();
+    intervals.clear();
+    let side = s.saturating_mul(4) as usize;
+    let mut cells = Vec::new();
+    for y in (0..height).step_by(side) {
+        for x in (0..width).step_by(side) {
+            cells.push(Rect {
+                x,
+                y,
+                width: side.min(width - x),
+                height: side.min(height - y),
+            });
+            while let Some(cell) = cells.pop() {
+                let mass = integral[[cell.y + cell.height, cell.x + cell.width]]
+                    - integral[[cell.y, cell.x + cell.width]]
+                    - integral[[cell.y + cell.height, cell.x]]
+                    + integral[[cell.y, cell.x]];
+                let expected = f64::from(k) * mass / total;
+
+                if expected >= 2.5 && cell.width >= 2 && cell.height >= 2 {
+                    let (half_width, half_height) = (cell.width / 2, cell.height / 2);
+                    // Pushed in reverse, so that the top-left quadrant is seeded first
+                    for (dy, h) in [(half_height, cell.height - half_height), (0, half_height)] {
+                        for (dx, w) in [(half_width, cell.width - half_width), (0, half_width)] {
+                            cells.push(Rect {
+                                x: cell.x + dx,
+                                y: cell.y + dy,
+                                width: w,
+                                height: h,
+                            });
+                        }
+                    }
+                } else {
+                    let (cx, cy) = (cell.x + cell.width / 2, cell.y + cell.height / 2);
+                    seeds.push(Superpixel {
+                        data: image.slice(s![cy, cx, ..]).to_owned(),
+                        x: u32::try_from(cx).or(Err("Could not convert seed position"))?,
+                        y: u32::try_from(cy).or(Err("Could not convert seed position"))?,
+                    });
+                    intervals.push(
+                        u32::try_from(cell.width.max(cell.height))
+                            .or(Err("Could not convert seed interval"))?,
+                    );
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
 /// Find the lowest gradient in a 3x3 neighborhood for a seed.
 ///
 /// This step minimizes the chance that a noisy pixel is chosen as a seed.
@@ -140,3 +294,116 @@ pub fn perturb(seed: &mut Superpixel<Array1<u8>>, image: &Array3<u8>) -> Result<
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hexagonal_seeds() {
+        let image = Array3::<u8>::zeros((12, 12, 1));
+        let mut seeds = Vec::new();
+        let positions = |seeds: &Vec<Superpixel<Array1<u8>>>| {
+            seeds
+                .iter()
+                .map(|seed| (seed.x, seed.y))
+                .collect::<Vec<_>>()
+        };
+
+        init_seeds(4, 9, SeedGrid::Square, &image, &mut seeds).unwrap();
+        assert_eq!(positions(&seeds)[3..6], [(2, 6), (6, 6), (10, 6)]);
+
+        // The last seed of the shifted row falls outside the image
+        init_seeds(4, 9, SeedGrid::Hexagonal, &image, &mut seeds).unwrap();
+        assert_eq!(
+            positions(&seeds),
+            [
+                (2, 2),
+                (6, 2),
+                (10, 2),
+                (4, 6),
+                (8, 6),
+                (2, 10),
+                (6, 10),
+                (10, 10)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_thin_image_seeds() {
+        let mut seeds = Vec::new();
+        let positions = |seeds: &Vec<Superpixel<Array1<u8>>>| {
+            seeds
+                .iter()
+                .map(|seed| (seed.x, seed.y))
+                .collect::<Vec<_>>()
+        };
+
+        // A single row of seeds, along the middle of the image
+        let image = Array3::<u8>::zeros((1, 40, 1));
+        init_seeds(4, 10, SeedGrid::Hexagonal, &image, &mut seeds).unwrap();
+        assert_eq!(
+            positions(&seeds),
+            (0..10).map(|i| (4 * i + 2, 0)).collect::<Vec<_>>()
+        );
+        let image = Array3::<u8>::zeros((3, 1, 1));
+        init_seeds(2, 1, SeedGrid::Hexagonal, &image, &mut seeds).unwrap();
+        assert_eq!(positions(&seeds), [(0, 1)]);
+
+        // One seed per pixel
+        let image = Array3::<u8>::zeros((2, 2, 1));
+        init_seeds(1, 4, SeedGrid::Square, &image, &mut seeds).unwrap();
+        assert_eq!(positions(&seeds), [(0, 0), (1, 0), (0, 1), (1, 1)]);
+    }
+
+    #[test]
+    fn test_warm_seeds() {
+        let image = Array3::<u8>::zeros((12, 12, 1));
+        let mut seeds = Vec::new();
+
+        // Previous centers in the left two thirds, one of them outside the image
+        let centers = [(1, 1), (5, 2), (1, 9), (5, 10), (13, 1)];
+        init_seeds_warm(4, 9, &centers, &image, &mut seeds).unwrap();
+        let mut positions = seeds
+            .iter()
+            .map(|seed| (seed.x, seed.y))
+            .collect::<Vec<_>>();
+        positions.sort();
+        assert_eq!(
+            positions,
+            [
+                (1, 1),
+                (1, 9),
+                (2, 6),
+                (5, 2),
+                (5, 10),
+                (6, 6),
+                (10, 2),
+                (10, 6),
+                (10, 10)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_density_seeds() {
+        let image = Array3::<u8>::zeros((16, 16, 1));
+        let mut seeds = Vec::new();
+        let mut intervals = Vec::new();
+
+        // Uniform density: the square grid
+        let density = Array2::ones((16, 16));
+        init_seeds_density(4, 16, &density, &image, &mut seeds, &mut intervals).unwrap();
+        assert_eq!(seeds.len(), 16);
+        assert_eq!((seeds[3].x, seeds[3].y), (6, 6));
+        assert!(intervals.iter().all(|interval| *interval == 4));
+
+        // All the density on the left half: small superpixels there, large ones elsewhere
+        let density = Array2::from_shape_fn((16, 16), |(_, x)| if x < 8 { 1. } else { 0. });
+        init_seeds_density(4, 16, &density, &image, &mut seeds, &mut intervals).unwrap();
+        let left = seeds.iter().filter(|seed| seed.x < 8).count();
+        assert_eq!((left, seeds.len() - left), (8, 2));
+        assert_eq!(intervals.iter().max(), Some(&8));
+    }
+}
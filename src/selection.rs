@@ -0,0 +1,133 @@
+//! Set operations on selections of regions, kept as sets of tree nodes rather than
+//! pixel masks.
+//!
+//! Selections are returned in canonical form: sorted nodes, none of them being a
+//! descendant of another, and no node having all of its children selected.
+
+use crate::cut::leaf_count;
+
+/// Flag the nodes of a partition tree that are in the selection or descendants of
+/// a selected node.
+pub fn selected_nodes(parents: &[usize], selection: &[usize]) -> Vec<bool> {
+    let mut selected = vec![false; parents.len()];
+    for &node in selection {
+        selected[node] = true;
+    }
+
+    // Parents always come after their children
+    for node in (0..parents.len()).rev() {
+        selected[node] |= selected[parents[node]];
+    }
+
+    selected
+}
+
+/// Canonical selection of the leaves flagged in `selected`, the flags of the
+/// internal nodes being ignored.
+fn canonical(parents: &[usize], mut selected: Vec<bool>) -> Vec<usize> {
+    let leaves = leaf_count(parents);
+
+    // Number of children, and of fully selected children, of each node
+    let mut children = vec![0; parents.len()];
+    let mut full_children = vec![0; parents.len()];
+    for node in 0..parents.len() {
+        if node >= leaves {
+            selected[node] = children[node] > 0 && full_children[node] == children[node];
+        }
+
+        let parent = parents[node];
+        if parent != node {
+            children[parent] += 1;
+            full_children[parent] += usize::from(selected[node]);
+        }
+    }
+
+    (0..parents.len())
+        .filter(|&node| {
+            let parent = parents[node];
+            selected[node] && (parent == node || !selected[parent])
+        })
+        .collect()
+}
+
+/// Regions selected in `a` or in `b`.
+pub fn selection_union(parents: &[usize], a: &[usize], b: &[usize]) -> Vec<usize> {
+    let mut selected = selected_nodes(parents, a);
+    for (selected, in_b) in selected.iter_mut().zip(selected_nodes(parents, b)) {
+        *selected |= in_b;
+    }
+
+    canonical(parents, selected)
+}
+
+/// Regions selected in both `a` and `b`.
+pub fn selection_intersection(parents: &[usize], a: &[usize], b: &[usize]) -> Vec<usize> {
+    let mut selected = selected_nodes(parents, a);
+    for (selected, in_b) in selected.iter_mut().zip(selected_nodes(parents, b)) {
+        *selected &= in_b;
+    }
+
+    canonical(parents, selected)
+}
+
+/// Regions selected in `a` but not in `b`.
+pub fn selection_difference(parents: &[usize], a: &[usize], b: &[usize]) -> Vec<usize> {
+    let mut selected = selected_nodes(parents, a);
+    for (selected, in_b) in selected.iter_mut().zip(selected_nodes(parents, b)) {
+        *selected &= !in_b;
+    }
+
+    canonical(parents, selected)
+}
+
+/// Split the selected regions into their largest descendants whose level is below
+/// `level`, e.g. to edit a coarse selection at a finer scale. Regions already
+/// below it are kept. The result is sorted, but not canonical.
+pub fn split_selection_by_level(
+    parents: &[usize],
+    levels: &[f64],
+    selection: &[usize],
+    level: f64,
+) -> Vec<usize> {
+    let selected = selected_nodes(parents, selection);
+
+    (0..parents.len())
+        .filter(|&node| {
+            let parent = parents[node];
+            selected[node]
+                && levels[node] < level
+                && (parent == node || !selected[parent] || levels[parent] >= level)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_selection_operations() {
+        // Node 4 merges leaves 0 and 1, node 5 leaves 2 and 3, node 6 both
+        let parents = vec![4, 4, 5, 5, 6, 6, 6];
+        let levels = vec![0., 0., 0., 0., 1., 2., 3.];
+
+        // Selecting both children selects the parent
+        assert_eq!(selection_union(&parents, &[0], &[1, 2]), vec![2, 4]);
+        assert_eq!(selection_union(&parents, &[4], &[5]), vec![6]);
+        assert_eq!(selection_intersection(&parents, &[6], &[0, 5]), vec![0, 5]);
+        assert_eq!(selection_difference(&parents, &[6], &[1]), vec![0, 5]);
+        assert_eq!(
+            selection_difference(&parents, &[4], &[6]),
+            Vec::<usize>::new()
+        );
+
+        assert_eq!(
+            split_selection_by_level(&parents, &levels, &[6], 1.5),
+            vec![2, 3, 4]
+        );
+        assert_eq!(
+            split_selection_by_level(&parents, &levels, &[4], 5.),
+            vec![4]
+        );
+    }
+}
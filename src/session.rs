@@ -0,0 +1,317 @@
+use std::mem::{size_of, size_of_val};
+
+use crate::prelude::*;
+use ndarray::{Array2, Array3};
+
+use crate::{
+    build_superpixel_graph, console_log, cut_hierarchy_wasm,
+    fingerprint::{image_fingerprint, params_fingerprint},
+    geo::GeoTransform,
+    graph::{patch_graph, SuperpixelEdge, SuperpixelGraph, SuperpixelNode},
+    hierarchy::binary_partition_tree,
+    normalize::{normalize_bands, Normalization},
+    params::SegmentationParams,
+    slic::{distance_map, refine_superpixels_local, SlicInfo},
+    statistics::StatisticsBuffer,
+    utils::{array_from_planar, decode_image, Rect},
+    CutResult, Hierarchy,
+};
+
+/// Convergence of the last SLIC run, see [`SegmentationParams::slic_threshold`].
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Clone, Copy, Debug)]
+pub struct SlicReport {
+    pub iterations: usize,
+    /// Mean displacement of the superpixel centers in the last iteration, in pixels
+    pub residual: f64,
+}
+
+/// Segmentation state kept across calls, so that changing a parameter only
+/// recomputes what depends on it.
+///
+/// The normalized image is kept, changing the superpixel count re-runs SLIC with
+/// the same buffers, local edits only refine the superpixels around them, and
+/// cutting the hierarchy only runs the cutter.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+pub struct SegmentationSession {
+    image: Array3<u8>,
+    // Of the image before normalization, as passed to the session
+    image_fingerprint: u64,
+    params: SegmentationParams,
+    slic_info: SlicInfo<f64, usize>,
+    statistics: StatisticsBuffer,
+    superpixels: Option<(Array2<usize>, SuperpixelGraph)>,
+    // Superpixels refined locally, the hierarchy no longer follows from the inputs
+    refined: bool,
+    hierarchy: Option<Hierarchy>,
+    geo_transform: GeoTransform,
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+impl SegmentationSession {
+    #[cfg_attr(feature = "wasm", wasm_bindgen(constructor))]
+    pub fn new(
+        data: &[u8],
+        width: usize,
+        height: usize,
+        channels: usize,
+        params: &SegmentationParams,
+    ) -> Self {
+        let image = array_from_planar(data, width, height, channels);
+        Self::from_array(image, params)
+    }
+
+    /// Create a session from a PNG, JPEG or TIFF image, keeping the georeferencing
+    /// of GeoTIFFs.
+    pub fn from_encoded(bytes: &[u8], params: &SegmentationParams) -> Self {
+        let image = decode_image(bytes).expect_throw("Failed to decode image");
+        let mut session = Self::from_array(image, params);
+        if let Some(transform) = GeoTransform::from_geotiff(bytes) {
+            session.geo_transform = transform;
+        }
+
+        session
+    }
+
+    /// Georeferencing of the image, to be passed to the exports.
+    pub fn geo_transform(&self) -> GeoTransform {
+        self.geo_transform
+    }
+
+    pub fn set_geo_transform(&mut self, transform: GeoTransform) {
+        self.geo_transform = transform;
+    }
+
+    pub fn width(&self) -> usize {
+        self.image.dim().1
+    }
+
+    pub fn height(&self) -> usize {
+        self.image.dim().0
+    }
+
+    pub fn channels(&self) -> usize {
+        self.image.dim().2
+    }
+
+    /// Superpixel count, the one picked from the image if it was set to 0.
+    pub fn n_clusters(&self) -> usize {
+        self.params.n_clusters
+    }
+
+    /// Change the superpixel count, 0 to pick it from the image. The hierarchy is
+    /// recomputed on next access.
+    pub fn set_n_clusters(&mut self, n_clusters: usize) {
+        let n_clusters = SegmentationParams {
+            n_clusters,
+            ..self.params
+        }
+        .resolved(&self.image)
+        .n_clusters;
+
+        if n_clusters != self.params.n_clusters {
+            self.params.n_clusters = n_clusters;
+            self.superpixels = None;
+            self.hierarchy = None;
+        }
+    }
+
+    /// Convergence of SLIC, computing the superpixels if needed.
+    pub fn slic_report(&mut self) -> SlicReport {
+        self.compute_superpixels();
+
+        SlicReport {
+            iterations: self.slic_info.iterations,
+            residual: self.slic_info.residual,
+        }
+    }
+
+    /// Distance of each pixel to the center of its superpixel in the last SLIC run,
+    /// in row-major order, computing the superpixels if needed. See
+    /// [`distance_map`](crate::slic::distance_map).
+    pub fn slic_distances(&mut self) -> Vec<f32> {
+        let (height, width) = (self.height(), self.width());
+        self.compute_superpixels();
+
+        distance_map(&self.slic_info, (height, width))
+            .iter()
+            .map(|distance| *distance as f32)
+            .collect()
+    }
+
+    /// Hierarchy of the image, computed on first access.
+    pub fn hierarchy(&mut self) -> Hierarchy {
+        self.compute_hierarchy().clone()
+    }
+
+    /// Re-run SLIC only inside the given rectangle extended by `margin` pixels, e.g.
+    /// after the user edited the regions there, and patch the superpixel graph.
+    /// The hierarchy is recomputed on next access.
+    pub fn refine_superpixels_local(
+        &mut self,
+        x: usize,
+        y: usize,
+        width: usize,
+        height: usize,
+        margin: usize,
+    ) {
+        self.compute_superpixels();
+        let (labels, graph) = self.superpixels.as_mut().unwrap();
+        let old_labels = labels.clone();
+
+        let rect = Rect {
+            x,
+            y,
+            width,
+            height,
+        };
+        let window = refine_superpixels_local(&self.image, labels, rect, margin, 1, 1);
+        patch_graph(graph, &self.image, &old_labels, labels, window);
+
+        self.refined = true;
+        self.hierarchy = None;
+    }
+
+    /// Memory held by the session, in bytes.
+    pub fn byte_size(&self) -> usize {
+        size_of::<Self>()
+            + self.image.len()
+            + self.slic_info.distances.capacity() * size_of::<f64>()
+            + self.slic_info.labels.capacity() * size_of::<usize>()
+            + self.statistics.byte_size()
+            - size_of::<StatisticsBuffer>()
+            + self.superpixels.as_ref().map_or(0, |(labels, graph)| {
+                size_of_val(labels.as_slice().unwrap_or_default())
+                    + graph.node_count() * size_of::<SuperpixelNode>()
+                    + graph.edge_count() * size_of::<SuperpixelEdge>()
+            })
+            + self.hierarchy.as_ref().map_or(0, Hierarchy::byte_size)
+    }
+
+    /// Cut the hierarchy at the given level, computing it if needed.
+    pub fn cut(&mut self, level: f64) -> CutResult {
+        cut_hierarchy_wasm(self.compute_hierarchy(), level)
+    }
+}
+
+impl SegmentationSession {
+    pub fn from_array(image: Array3<u8>, params: &SegmentationParams) -> Self {
+        let image_fingerprint = image_fingerprint(&image);
+        let image = match params.normalization {
+            Normalization::None => image,
+            _ => normalize_bands(&image, params.normalization).into_owned(),
+        };
+
+        Self {
+            params: params.resolved(&image),
+            image,
+            image_fingerprint,
+            slic_info: SlicInfo::new(),
+            statistics: StatisticsBuffer::default(),
+            superpixels: None,
+            refined: false,
+            hierarchy: None,
+            geo_transform: GeoTransform::default(),
+        }
+    }
+
+    fn compute_superpixels(&mut self) -> &mut (Array2<usize>, SuperpixelGraph) {
+        if self.superpixels.is_none() {
+            console_log!(
+                "Computing superpixels for {} clusters...",
+                self.params.n_clusters
+            );
+
+            self.superpixels = Some(build_superpixel_graph(
+                &self.image,
+                &self.params,
+                &mut self.slic_info,
+                &mut self.statistics,
+                None,
+                None,
+            ));
+            self.refined = false;
+        }
+
+        self.superpixels.as_mut().unwrap()
+    }
+
+    fn compute_hierarchy(&mut self) -> &Hierarchy {
+        if self.hierarchy.is_none() {
+            let criterion = self.params.criterion_options();
+            let (labels, graph) = self.compute_superpixels();
+            let labels = labels.clone();
+            let tree = binary_partition_tree(graph.clone(), criterion)
+                .unwrap_or_else(|error| throw_str(&error.to_string()));
+
+            let fingerprint = if self.refined {
+                0
+            } else {
+                params_fingerprint(self.image_fingerprint, &self.params)
+            };
+            self.hierarchy =
+                Some(Hierarchy::new(&labels, tree).with_input_fingerprint(fingerprint));
+        }
+
+        self.hierarchy.as_ref().unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::build_hierarchy_with_params_wasm;
+
+    fn test_image() -> (Vec<u8>, usize, usize) {
+        let (width, height) = (24, 16);
+        // Two flat halves with some texture, on three planar bands
+        let data = (0..3 * width * height)
+            .map(|i| {
+                let x = i % width;
+                (if x < width / 2 { 40 } else { 200 } + (i * 7) % 13) as u8
+            })
+            .collect();
+
+        (data, width, height)
+    }
+
+    #[test]
+    fn test_session_hierarchy() {
+        let (data, width, height) = test_image();
+        let params = SegmentationParams::new(12);
+
+        let mut session = SegmentationSession::new(&data, width, height, 3, &params);
+        let hierarchy = session.hierarchy();
+        let expected = build_hierarchy_with_params_wasm(&data, width, height, 3, &params);
+
+        assert_eq!(hierarchy.labels, expected.labels);
+        assert_eq!(hierarchy.parents, expected.parents);
+        assert_eq!(hierarchy.levels, expected.levels);
+        assert_eq!(hierarchy.input_fingerprint, expected.input_fingerprint);
+        assert_ne!(hierarchy.input_fingerprint, 0);
+    }
+
+    #[test]
+    fn test_session_invalidation() {
+        let (data, width, height) = test_image();
+        let mut session =
+            SegmentationSession::new(&data, width, height, 3, &SegmentationParams::new(12));
+        session.hierarchy();
+
+        // The same superpixel count keeps the cache, another one drops it
+        session.set_n_clusters(12);
+        assert!(session.hierarchy.is_some() && session.superpixels.is_some());
+        session.set_n_clusters(6);
+        assert!(session.hierarchy.is_none() && session.superpixels.is_none());
+        assert_ne!(session.hierarchy().input_fingerprint, 0);
+
+        // Local refinements no longer follow from the inputs
+        session.refine_superpixels_local(2, 2, 4, 4, 2);
+        assert!(session.hierarchy.is_none());
+        assert_eq!(session.hierarchy().input_fingerprint, 0);
+
+        // Recomputing the superpixels restores the fingerprint
+        session.set_n_clusters(12);
+        assert_ne!(session.hierarchy().input_fingerprint, 0);
+    }
+}
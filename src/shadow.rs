@@ -0,0 +1,102 @@
+use ndarray::{Array2, Array3};
+use petgraph::graph::NodeIndex;
+
+use crate::graph::{graph_wrap, SuperpixelGraph};
+
+/// Luminance of the visible bands below which a pixel is taken as shadowed.
+const SHADOW_LUMINANCE: f32 = 60.;
+/// Near infrared value below which a dark pixel is taken as shadowed, as sunlit
+/// vegetation stays bright in the near infrared.
+const SHADOW_NIR: f32 = 80.;
+
+/// Shadowed pixels of a `(height, width, channels)` image: dark in the visible
+/// bands and, when there is one, in the near infrared band.
+///
+/// The visible bands are the bands other than the near infrared and elevation
+/// ones. Their luminance is computed as in Rec. 601 from the first three of them,
+/// taken as red, green and blue, or as their mean when there are fewer.
+pub fn shadow_mask(
+    img: &Array3<u8>,
+    nir_channel: Option<usize>,
+    elevation_channel: Option<usize>,
+) -> Array2<bool> {
+    let (height, width, channels) = img.dim();
+    let visible = (0..channels)
+        .filter(|c| Some(*c) != nir_channel && Some(*c) != elevation_channel)
+        .collect::<Vec<_>>();
+    if visible.is_empty() {
+        return Array2::from_elem((height, width), false);
+    }
+
+    Array2::from_shape_fn((height, width), |(y, x)| {
+        let value = |c: usize| img[[y, x, c]] as f32;
+        let luminance = match visible[..] {
+            [r, g, b, ..] => 0.299 * value(r) + 0.587 * value(g) + 0.114 * value(b),
+            _ => visible.iter().map(|c| value(*c)).sum::<f32>() / visible.len() as f32,
+        };
+
+        luminance < SHADOW_LUMINANCE && nir_channel.is_none_or(|c| value(c) < SHADOW_NIR)
+    })
+}
+
+/// Count the pairs of neighboring pixels inside shadows along the borders of the
+/// superpixels, see [`SuperpixelEdge::mean_shadow`](crate::SuperpixelEdge::mean_shadow).
+pub fn add_shadows(graph: &mut SuperpixelGraph, labels: &Array2<usize>, shadows: &Array2<bool>) {
+    let wrap = graph_wrap(graph);
+    for ((y, x), label) in labels.indexed_iter() {
+        let i = NodeIndex::new(*label);
+
+        // Loop over the neighbors (right and bottom)
+        for (neighbor, _) in wrap.forward_neighbors((y, x), labels.dim()) {
+            let n_label = &labels[neighbor];
+            if n_label != label && shadows[[y, x]] && shadows[neighbor] {
+                let j = NodeIndex::new(*n_label);
+                let edge = graph.find_edge(i, j).unwrap();
+                graph[edge].shadow += 1;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ndarray::array;
+
+    use super::*;
+    use crate::graph::graph_from_labels;
+
+    #[test]
+    fn test_shadows() {
+        // RGB and near infrared: dark pixels in the left column, the bottom one
+        // bright in the near infrared like vegetation
+        let img = Array3::from_shape_vec(
+            (2, 2, 4),
+            vec![
+                20, 30, 20, 10, 200, 200, 200, 150, //
+                20, 30, 20, 150, 200, 200, 200, 150,
+            ],
+        )
+        .unwrap();
+
+        assert_eq!(
+            shadow_mask(&img, None, None),
+            array![[true, false], [true, false]]
+        );
+        assert_eq!(
+            shadow_mask(&img, Some(3), None),
+            array![[true, false], [false, false]]
+        );
+
+        // Both pixels of the border between regions 0 and 1 are shadowed
+        let labels = array![[0, 1], [2, 2]];
+        let mut graph = graph_from_labels(&img, &labels).unwrap();
+        add_shadows(&mut graph, &labels, &array![[true, true], [false, false]]);
+        let edge = |a, b| {
+            graph
+                .find_edge(NodeIndex::new(a), NodeIndex::new(b))
+                .unwrap()
+        };
+        assert_eq!(graph[edge(0, 1)].mean_shadow(), 1.);
+        assert_eq!(graph[edge(0, 2)].mean_shadow(), 0.);
+    }
+}
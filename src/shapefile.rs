@@ -0,0 +1,285 @@
+use std::collections::HashMap;
+
+use ndarray::Array2;
+#[cfg(feature = "wasm")]
+use wasm_bindgen::prelude::wasm_bindgen;
+
+use crate::{
+    geo::GeoTransform,
+    smooth::{smooth_outlines, Smoothing},
+    vector::region_measures,
+};
+
+/// ESRI Shapefile of the regions of a cut, as the contents of its three files.
+#[cfg_attr(feature = "wasm", wasm_bindgen(getter_with_clone))]
+#[derive(Clone, Debug)]
+pub struct Shapefile {
+    pub shp: Vec<u8>,
+    pub shx: Vec<u8>,
+    pub dbf: Vec<u8>,
+}
+
+const POLYGON: i32 = 5;
+const NUMBER_WIDTH: usize = 10;
+const REAL_WIDTH: usize = 19;
+const REAL_DECIMALS: usize = 3;
+
+/// Write a cut as a Shapefile with one polygon per region, and the `REGION` id,
+/// `CLASS`, `AREA` and `PERIMETER` attributes in the DBF, the measures being in the
+/// units of the geotransform. Regions without a class have an empty `CLASS`.
+///
+/// Pixel corners are mapped to coordinates with the geotransform.
+pub fn cut_to_shapefile(
+    labels: &Array2<usize>,
+    classes: &HashMap<usize, usize>,
+    transform: &GeoTransform,
+    smoothing: Smoothing,
+) -> Shapefile {
+    let project = |(x, y): (f64, f64)| transform.apply(x, y);
+    // Outer rings are clockwise in world coordinates unless the transform mirrors them
+    let flipped = transform.pixel_width * transform.pixel_height < 0.;
+
+    let outlines = smooth_outlines(labels, smoothing);
+
+    let mut records = Vec::with_capacity(outlines.len());
+    for (_, rings) in &outlines {
+        let rings = rings
+            .iter()
+            .map(|ring| {
+                let mut points = ring
+                    .iter()
+                    .map(|vertex| project(*vertex))
+                    .collect::<Vec<_>>();
+                if flipped {
+                    points.reverse();
+                }
+                // Rings are closed by repeating their first point
+                points.push(points[0]);
+                points
+            })
+            .collect::<Vec<_>>();
+        records.push(polygon_record(&rings));
+    }
+
+    let bbox = records
+        .iter()
+        .map(|(bbox, _)| *bbox)
+        .reduce(|a, b| {
+            [
+                a[0].min(b[0]),
+                a[1].min(b[1]),
+                a[2].max(b[2]),
+                a[3].max(b[3]),
+            ]
+        })
+        .unwrap_or([0.; 4]);
+
+    let shp_length = 100
+        + records
+            .iter()
+            .map(|(_, content)| 8 + content.len())
+            .sum::<usize>();
+    let mut shp = file_header(shp_length, bbox);
+    let mut shx = file_header(100 + 8 * records.len(), bbox);
+
+    for (i, (_, content)) in records.iter().enumerate() {
+        shx.extend(((shp.len() / 2) as i32).to_be_bytes());
+        shx.extend(((content.len() / 2) as i32).to_be_bytes());
+
+        shp.extend((i as i32 + 1).to_be_bytes());
+        shp.extend(((content.len() / 2) as i32).to_be_bytes());
+        shp.extend(content);
+    }
+
+    let rows = region_measures(labels, transform)
+        .into_iter()
+        .map(|(label, measures)| {
+            [
+                Some(label as f64),
+                classes.get(&label).map(|class| *class as f64),
+                Some(measures.area),
+                Some(measures.perimeter),
+            ]
+        })
+        .collect::<Vec<_>>();
+    let fields = [
+        ("REGION", 0),
+        ("CLASS", 0),
+        ("AREA", REAL_DECIMALS),
+        ("PERIMETER", REAL_DECIMALS),
+    ];
+    let dbf = dbf_table(&fields, &rows);
+
+    Shapefile { shp, shx, dbf }
+}
+
+/// Header shared by the `.shp` and `.shx` files, lengths being in bytes.
+fn file_header(length: usize, bbox: [f64; 4]) -> Vec<u8> {
+    let mut header = Vec::with_capacity(length);
+    header.extend(9994i32.to_be_bytes());
+    header.extend([0; 20]);
+    header.extend(((length / 2) as i32).to_be_bytes());
+    header.extend(1000i32.to_le_bytes());
+    header.extend(POLYGON.to_le_bytes());
+    for value in bbox {
+        header.extend(value.to_le_bytes());
+    }
+    // No Z or M range
+    header.extend([0; 32]);
+
+    header
+}
+
+/// Bounding box and content of a polygon record.
+fn polygon_record(rings: &[Vec<(f64, f64)>]) -> ([f64; 4], Vec<u8>) {
+    let points = rings.iter().flatten();
+    let mut bbox = [
+        f64::INFINITY,
+        f64::INFINITY,
+        f64::NEG_INFINITY,
+        f64::NEG_INFINITY,
+    ];
+    for (x, y) in points.clone() {
+        bbox = [
+            bbox[0].min(*x),
+            bbox[1].min(*y),
+            bbox[2].max(*x),
+            bbox[3].max(*y),
+        ];
+    }
+
+    let mut content = Vec::new();
+    content.extend(POLYGON.to_le_bytes());
+    for value in bbox {
+        content.extend(value.to_le_bytes());
+    }
+    content.extend((rings.len() as i32).to_le_bytes());
+    content.extend((points.clone().count() as i32).to_le_bytes());
+
+    let mut start = 0;
+    for ring in rings {
+        content.extend((start as i32).to_le_bytes());
+        start += ring.len();
+    }
+    for (x, y) in points {
+        content.extend(x.to_le_bytes());
+        content.extend(y.to_le_bytes());
+    }
+
+    (bbox, content)
+}
+
+/// dBASE III table of numeric fields, given by their name and decimal count. Fields
+/// without decimals are integers. `None` values are left empty.
+fn dbf_table<const N: usize>(fields: &[(&str, usize); N], rows: &[[Option<f64>; N]]) -> Vec<u8> {
+    let width = |decimals: usize| {
+        if decimals == 0 {
+            NUMBER_WIDTH
+        } else {
+            REAL_WIDTH
+        }
+    };
+    let header_length = 32 + 32 * N + 1;
+    let record_length = 1 + fields
+        .iter()
+        .map(|(_, decimals)| width(*decimals))
+        .sum::<usize>();
+
+    let mut dbf = Vec::with_capacity(header_length + record_length * rows.len() + 1);
+    dbf.push(0x03);
+    // Last update date left empty
+    dbf.extend([0; 3]);
+    dbf.extend((rows.len() as u32).to_le_bytes());
+    dbf.extend((header_length as u16).to_le_bytes());
+    dbf.extend((record_length as u16).to_le_bytes());
+    dbf.extend([0; 20]);
+
+    for (name, decimals) in fields {
+        let mut descriptor = [0; 32];
+        descriptor[..name.len()].copy_from_slice(name.as_bytes());
+        descriptor[11] = b'N';
+        descriptor[16] = width(*decimals) as u8;
+        descriptor[17] = *decimals as u8;
+        dbf.extend(descriptor);
+    }
+    dbf.push(0x0d);
+
+    for row in rows {
+        // Record not deleted
+        dbf.push(b' ');
+        for (value, (_, decimals)) in row.iter().zip(fields) {
+            let text = value
+                .map(|value| format!("{value:.decimals$}"))
+                .unwrap_or_default();
+            dbf.extend(format!("{text:>width$}", width = width(*decimals)).bytes());
+        }
+    }
+    dbf.push(0x1a);
+
+    dbf
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vector::Ring;
+
+    /// Rings of a polygon record, read back from its content.
+    fn read_rings(content: &[u8]) -> Vec<Ring> {
+        let int =
+            |offset: usize| i32::from_le_bytes(content[offset..offset + 4].try_into().unwrap());
+        let float =
+            |offset: usize| f64::from_le_bytes(content[offset..offset + 8].try_into().unwrap());
+
+        let (n_parts, n_points) = (int(36) as usize, int(40) as usize);
+        let mut starts = (0..n_parts)
+            .map(|i| int(44 + 4 * i) as usize)
+            .collect::<Vec<_>>();
+        starts.push(n_points);
+        let points_offset = 44 + 4 * n_parts;
+
+        starts
+            .windows(2)
+            .map(|range| {
+                (range[0]..range[1])
+                    .map(|i| {
+                        let offset = points_offset + 16 * i;
+                        (float(offset) as usize, float(offset + 8) as usize)
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_cut_to_shapefile() {
+        // Region 1 is a single pixel hole in region 0
+        let mut labels = Array2::zeros((3, 3));
+        labels[[1, 1]] = 1;
+        let classes = HashMap::from([(1, 7)]);
+
+        // Pixel coordinates with the Y axis pointing down
+        let transform = GeoTransform::new(0., 0., 1., -1., None);
+        let shapefile = cut_to_shapefile(&labels, &classes, &transform, Smoothing::none());
+
+        assert_eq!(&shapefile.shp[..4], &9994i32.to_be_bytes());
+        let shp_words = i32::from_be_bytes(shapefile.shp[24..28].try_into().unwrap());
+        assert_eq!(shp_words as usize * 2, shapefile.shp.len());
+        assert_eq!(shapefile.shx.len(), 100 + 2 * 8);
+
+        // Second record, after its 8 bytes header
+        let offset = i32::from_be_bytes(shapefile.shx[108..112].try_into().unwrap()) as usize * 2;
+        let rings = read_rings(&shapefile.shp[offset + 8..]);
+        // Clockwise with the Y axis pointing down
+        assert_eq!(rings, vec![vec![(1, 2), (2, 2), (2, 1), (1, 1), (1, 2)]]);
+
+        // Header, 4 field descriptors and terminator, then the records
+        let records = &shapefile.dbf[32 + 4 * 32 + 1..];
+        let record_length = 1 + 2 * 10 + 2 * 19;
+        assert_eq!(&records[..21], b"          0          ");
+        let second = &records[record_length..2 * record_length];
+        assert_eq!(&second[..21], b"          1         7");
+        assert_eq!(&second[21..40], b"              1.000");
+        assert_eq!(&second[40..], b"              4.000");
+    }
+}
@@ -0,0 +1,172 @@
+//! Vectorized color distances of the SLIC assignment, with the `simd` feature.
+//!
+//! WebAssembly uses simd128 when built with `-C target-feature=+simd128`, and x86-64
+//! SSSE3 and SSE4.1 when the CPU has them, detected at runtime. The distances are
+//! computed on integers, so they are exactly the scalar ones.
+
+/// Distances of the first pixels of a row of interleaved bands to a center pixel,
+/// four pixels at a time for images of 3 or 4 bands. Returns the number of pixels
+/// done, the others being left to the scalar code.
+pub(crate) fn distance_pixels_prefix(row: &[u8], center: &[u8], distances: &mut Vec<f32>) -> usize {
+    if !matches!(center.len(), 3 | 4) {
+        return 0;
+    }
+
+    #[cfg(all(target_arch = "wasm32", target_feature = "simd128"))]
+    {
+        wasm::distance_pixels(row, center, distances)
+    }
+    #[cfg(target_arch = "x86_64")]
+    {
+        if is_x86_feature_detected!("ssse3") && is_x86_feature_detected!("sse4.1") {
+            // SAFETY: the CPU supports the target features of the function
+            unsafe { x86::distance_pixels(row, center, distances) }
+        } else {
+            0
+        }
+    }
+    #[cfg(not(any(
+        all(target_arch = "wasm32", target_feature = "simd128"),
+        target_arch = "x86_64"
+    )))]
+    {
+        let _ = (row, distances);
+        0
+    }
+}
+
+/// Byte shuffle spreading 4 RGB pixels to 4 bytes each, the fourth one being zeroed.
+#[cfg(any(
+    all(target_arch = "wasm32", target_feature = "simd128"),
+    target_arch = "x86_64"
+))]
+const RGB_TO_RGBX: [u8; 16] = [0, 1, 2, 0x80, 3, 4, 5, 0x80, 6, 7, 8, 0x80, 9, 10, 11, 0x80];
+
+/// Center pixel repeated for two pixels, as 16-bit lanes, zero-padded to 4 bands.
+#[cfg(any(
+    all(target_arch = "wasm32", target_feature = "simd128"),
+    target_arch = "x86_64"
+))]
+fn center_lanes(center: &[u8]) -> [i16; 8] {
+    let mut lanes = [0; 8];
+    for (i, value) in center.iter().enumerate() {
+        lanes[i] = i16::from(*value);
+        lanes[4 + i] = i16::from(*value);
+    }
+    lanes
+}
+
+#[cfg(all(target_arch = "wasm32", target_feature = "simd128"))]
+mod wasm {
+    use core::arch::wasm32::*;
+
+    use super::{center_lanes, RGB_TO_RGBX};
+
+    pub fn distance_pixels(row: &[u8], center: &[u8], distances: &mut Vec<f32>) -> usize {
+        let channels = center.len();
+        let lanes = center_lanes(center);
+        // SAFETY: both arrays are 16 bytes long
+        let (center, shuffle) = unsafe {
+            (
+                v128_load(lanes.as_ptr() as *const v128),
+                v128_load(RGB_TO_RGBX.as_ptr() as *const v128),
+            )
+        };
+
+        let mut done = 0;
+        // Each step loads 16 bytes, more than 4 RGB pixels
+        while (done * channels) + 16 <= row.len() {
+            // SAFETY: the 16 bytes are in the row
+            let mut pixels = unsafe { v128_load(row[done * channels..].as_ptr() as *const v128) };
+            if channels == 3 {
+                pixels = i8x16_swizzle(pixels, shuffle);
+            }
+
+            let low = i16x8_sub(u16x8_extend_low_u8x16(pixels), center);
+            let high = i16x8_sub(u16x8_extend_high_u8x16(pixels), center);
+            // Sums of the squares of pairs of bands, then of both pairs of each pixel
+            let (low, high) = (i32x4_dot_i16x8(low, low), i32x4_dot_i16x8(high, high));
+            let sums = i32x4_add(
+                i32x4_shuffle::<0, 2, 4, 6>(low, high),
+                i32x4_shuffle::<1, 3, 5, 7>(low, high),
+            );
+
+            let sums = f32x4_convert_i32x4(sums);
+            distances.extend([
+                f32x4_extract_lane::<0>(sums),
+                f32x4_extract_lane::<1>(sums),
+                f32x4_extract_lane::<2>(sums),
+                f32x4_extract_lane::<3>(sums),
+            ]);
+            done += 4;
+        }
+
+        done
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+mod x86 {
+    use std::arch::x86_64::*;
+
+    use super::{center_lanes, RGB_TO_RGBX};
+
+    #[target_feature(enable = "ssse3,sse4.1")]
+    pub unsafe fn distance_pixels(row: &[u8], center: &[u8], distances: &mut Vec<f32>) -> usize {
+        let channels = center.len();
+        let lanes = center_lanes(center);
+        let center = _mm_loadu_si128(lanes.as_ptr() as *const __m128i);
+        let shuffle = _mm_loadu_si128(RGB_TO_RGBX.as_ptr() as *const __m128i);
+
+        let mut done = 0;
+        // Each step loads 16 bytes, more than 4 RGB pixels
+        while (done * channels) + 16 <= row.len() {
+            let mut pixels = _mm_loadu_si128(row[done * channels..].as_ptr() as *const __m128i);
+            if channels == 3 {
+                pixels = _mm_shuffle_epi8(pixels, shuffle);
+            }
+
+            let low = _mm_sub_epi16(_mm_cvtepu8_epi16(pixels), center);
+            let high = _mm_sub_epi16(_mm_cvtepu8_epi16(_mm_srli_si128::<8>(pixels)), center);
+            // Sums of the squares of pairs of bands, then of both pairs of each pixel
+            let sums = _mm_hadd_epi32(_mm_madd_epi16(low, low), _mm_madd_epi16(high, high));
+
+            let mut values = [0f32; 4];
+            _mm_storeu_ps(values.as_mut_ptr(), _mm_cvtepi32_ps(sums));
+            distances.extend(values);
+            done += 4;
+        }
+
+        done
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::slic_helpers::distance_pixels;
+
+    #[test]
+    fn test_distance_pixels() {
+        for channels in 1..=5 {
+            let row = (0..23 * channels)
+                .map(|i| (i * 37 % 256) as u8)
+                .collect::<Vec<_>>();
+            let center = (0..channels).map(|c| (c * 90) as u8).collect::<Vec<_>>();
+
+            let mut distances = Vec::new();
+            distance_pixels(&row, &center, &mut distances);
+
+            let expected = row
+                .chunks(channels)
+                .map(|pixel| {
+                    pixel
+                        .iter()
+                        .zip(&center)
+                        .map(|(a, b)| (f32::from(*a) - f32::from(*b)).powi(2))
+                        .sum::<f32>()
+                })
+                .collect::<Vec<_>>();
+            assert_eq!(distances, expected);
+        }
+    }
+}
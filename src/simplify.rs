@@ -0,0 +1,113 @@
+use crate::cut::leaf_count;
+
+/// Remove the internal nodes whose lifetime, the level of their parent minus their
+/// own level, is lower than `epsilon`: their children are attached to their parent.
+///
+/// The leaves and the roots are kept, so the tree may no longer be binary. The kept
+/// nodes keep their relative order, parents still coming after their children.
+/// Returns the new parents and levels.
+pub fn simplify_by_lifetime(
+    parents: &[usize],
+    levels: &[f64],
+    epsilon: f64,
+) -> (Vec<usize>, Vec<f64>) {
+    let leaves = leaf_count(parents);
+
+    remove_nodes(parents, levels, |node| {
+        let parent = parents[node];
+        node < leaves || parent == node || levels[parent] - levels[node] >= epsilon
+    })
+}
+
+/// Compact a partition tree, e.g. after a pruning, to send a smaller dendrogram:
+/// internal nodes at the same level as their parent are merged into it, making
+/// n-ary nodes, then the internal nodes left with a single child are removed.
+///
+/// The leaves and the roots are kept, as in [`simplify_by_lifetime`]. Returns the
+/// new parents and levels.
+pub fn compact(parents: &[usize], levels: &[f64]) -> (Vec<usize>, Vec<f64>) {
+    let leaves = leaf_count(parents);
+    let (parents, levels) = remove_nodes(parents, levels, |node| {
+        let parent = parents[node];
+        node < leaves || parent == node || levels[parent] != levels[node]
+    });
+
+    // Removing a node with a single child does not change the count of its parent
+    let mut children = vec![0; parents.len()];
+    for (node, &parent) in parents.iter().enumerate() {
+        if parent != node {
+            children[parent] += 1;
+        }
+    }
+    remove_nodes(&parents, &levels, |node| {
+        node < leaves || parents[node] == node || children[node] != 1
+    })
+}
+
+/// Remove the nodes that are not `kept`, attaching their children to their closest
+/// kept ancestor. Roots must be kept.
+fn remove_nodes(
+    parents: &[usize],
+    levels: &[f64],
+    kept: impl Fn(usize) -> bool,
+) -> (Vec<usize>, Vec<f64>) {
+    // Closest kept ancestor of each node, itself included
+    let mut representatives = (0..parents.len()).collect::<Vec<_>>();
+    for node in (0..parents.len()).rev() {
+        if !kept(node) {
+            representatives[node] = representatives[parents[node]];
+        }
+    }
+
+    let mut new_indices = vec![usize::MAX; parents.len()];
+    let mut new_parents = Vec::new();
+    let mut new_levels = Vec::new();
+    for node in (0..parents.len()).filter(|node| kept(*node)) {
+        new_indices[node] = new_parents.len();
+        new_parents.push(node);
+        new_levels.push(levels[node]);
+    }
+
+    // Kept ancestors come after the node, so their new index is known
+    for (new_node, parent) in new_parents.iter_mut().enumerate() {
+        let node = *parent;
+        *parent = if parents[node] == node {
+            new_node
+        } else {
+            new_indices[representatives[parents[node]]]
+        };
+    }
+
+    (new_parents, new_levels)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_simplify_by_lifetime() {
+        // Node 4 merges leaves 0 and 1 just before node 5 adds leaf 2
+        let parents = vec![4, 4, 5, 6, 5, 6, 6];
+        let levels = vec![0., 0., 0., 0., 1., 1.1, 5.];
+
+        let (parents, levels) = simplify_by_lifetime(&parents, &levels, 0.5);
+
+        assert_eq!(parents, vec![4, 4, 4, 5, 5, 5]);
+        assert_eq!(levels, vec![0., 0., 0., 0., 1.1, 5.]);
+    }
+
+    #[test]
+    fn test_compact() {
+        // Node 5 merges leaves 0 and 1, node 6 has the single child 5 (e.g. after a
+        // pruning), and node 7 merges it with leaves 2 and 3 through node 4 at the
+        // same level
+        let parents = vec![5, 5, 4, 4, 7, 6, 7, 7];
+        let levels = vec![0., 0., 0., 0., 2., 1., 1.5, 2.];
+
+        let (parents, levels) = compact(&parents, &levels);
+
+        assert_eq!(parents, vec![4, 4, 5, 5, 5, 5]);
+        assert_eq!(levels, vec![0., 0., 0., 0., 1., 2.]);
+    }
+}
@@ -1,22 +1,33 @@
-use crate::seed::{init_seeds, perturb};
+use std::collections::{BTreeSet, HashMap};
+
+use crate::seed::{init_seeds, init_seeds_density, init_seeds_warm, perturb, SeedGrid};
 use crate::slic_helpers::{
-    calculate_grid_interval, distance_pixel, distance_s, distance_xy, get_in_bounds,
+    calculate_grid_interval, distance_pixels, distance_s, distance_xy, get_in_bounds,
     get_mut_in_bounds, m_div_s,
 };
 
 use simple_clustering::error::ScError;
 
-use ndarray::{s, Array1, Array2, Array3};
+use crate::{console_trace, utils::Rect};
+
+use ndarray::{s, Array1, Array2, Array3, Zip};
 use num_traits::ToPrimitive;
 
 /// Information for tracking image pixels' nearest superpixel cluster and
 /// distance to that cluster during SLIC.
 #[derive(Debug, Clone)]
-struct SlicInfo<T, U> {
+pub(crate) struct SlicInfo<T, U> {
     /// Vector of nearest superpixel distances.
     pub distances: Vec<T>,
     /// Vector of nearest superpixel labels.
     pub labels: Vec<U>,
+    /// Number of iterations performed by the last run.
+    pub iterations: usize,
+    /// Mean displacement of the superpixel centers, in pixels, during the last
+    /// iteration of the last run.
+    pub residual: f64,
+    /// Superpixel centers at the end of the last run, as `(x, y)`.
+    pub centers: Vec<(u32, u32)>,
 }
 
 impl<T, U> SlicInfo<T, U> {
@@ -34,10 +45,24 @@ impl<T, U> Default for SlicInfo<T, U> {
         Self {
             distances: Vec::new(),
             labels: Vec::new(),
+            iterations: 0,
+            residual: 0.,
+            centers: Vec::new(),
         }
     }
 }
 
+/// Initial layout of the SLIC superpixel centers.
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum Seeding<'a> {
+    /// Regular grid with the SLIC interval
+    Grid(SeedGrid),
+    /// Spacing following a density raster, see [`init_seeds_density`]
+    Density(&'a Array2<f32>),
+    /// Centers of a previous run, as `(x, y)`, see [`init_seeds_warm`]
+    Warm(&'a [(u32, u32)]),
+}
+
 /// Struct used for accumulating and calculating superpixel clusters in SLIC.
 #[derive(Debug, Clone, Copy)]
 struct SlicUpdate<T> {
@@ -76,7 +101,8 @@ impl<T: Default> Default for SlicUpdate<T> {
 ///
 /// `iter` will default to `10` if `None` is supplied.
 ///
-/// `k` must not be `0`.
+/// `k` must not be `0`, and each pixel is a superpixel when it is at least the
+/// pixel count.
 /// `m` is clamped to be between `1` and `20`.
 /// `width` and `height` must not be `0`.
 ///
@@ -90,6 +116,98 @@ impl<T: Default> Default for SlicUpdate<T> {
 /// on Pattern Analysis and Machine Intelligence, vol. 34, num. 11, p. 2274 – 2282,
 /// May 2012.*
 pub fn slic(k: u32, m: u8, iter: Option<u8>, image: &Array3<u8>) -> Result<Array2<usize>, ScError> {
+    slic_with_info(
+        k,
+        m,
+        iter,
+        None,
+        Seeding::Grid(SeedGrid::Square),
+        1.,
+        image,
+        &mut SlicInfo::new(),
+    )
+}
+
+/// Calculate SLIC, also returning the distance of each pixel to the center of its
+/// superpixel, see [`distance_map`].
+pub fn slic_with_distances(
+    k: u32,
+    m: u8,
+    iter: Option<u8>,
+    image: &Array3<u8>,
+) -> Result<(Array2<usize>, Array2<f64>), ScError> {
+    let mut info = SlicInfo::new();
+    let labels = slic_with_info(
+        k,
+        m,
+        iter,
+        None,
+        Seeding::Grid(SeedGrid::Square),
+        1.,
+        image,
+        &mut info,
+    )?;
+    let distances = distance_map(&info, labels.dim());
+
+    Ok((labels, distances))
+}
+
+/// Calculate SLIC with superpixels smaller where `density`, a raster of the image
+/// size, is high, e.g. a gradient magnitude or an area of interest. See
+/// [`init_seeds_density`].
+pub fn slic_with_density(
+    k: u32,
+    m: u8,
+    iter: Option<u8>,
+    density: &Array2<f32>,
+    image: &Array3<u8>,
+) -> Result<Array2<usize>, ScError> {
+    slic_with_info(
+        k,
+        m,
+        iter,
+        None,
+        Seeding::Density(density),
+        1.,
+        image,
+        &mut SlicInfo::new(),
+    )
+}
+
+/// Distance `D` of each pixel to the center it was assigned to in the last SLIC
+/// iteration, combining the color and spatial distances as in the SLIC paper. High
+/// values hint at boundaries, or at a compactness `m` too low when they are spread
+/// everywhere. Pixels relabelled to enforce connectivity keep their distance to
+/// their first center.
+pub(crate) fn distance_map(
+    info: &SlicInfo<f64, usize>,
+    (height, width): (usize, usize),
+) -> Array2<f64> {
+    Array2::from_shape_fn((height, width), |(y, x)| {
+        info.distances[y * width + x].sqrt()
+    })
+}
+
+/// Calculate SLIC, reusing the buffers of `info` from a previous call.
+///
+/// With a `threshold`, `iter` is the maximum number of iterations: SLIC stops as
+/// soon as the residual, the mean displacement of the superpixel centers in pixels,
+/// falls below it. The iterations performed and the final residual are stored in
+/// `info`. The initial centers are laid out according to `seeding`, the search
+/// window and compactness of each superpixel following its initial interval.
+/// Vertical offsets count `pixel_aspect` times more than horizontal ones in the
+/// spatial distance, for pixels taller than wide.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn slic_with_info(
+    k: u32,
+    m: u8,
+    iter: Option<u8>,
+    threshold: Option<f64>,
+    seeding: Seeding<'_>,
+    pixel_aspect: f64,
+    image: &Array3<u8>,
+    info: &mut SlicInfo<f64, usize>,
+) -> Result<Array2<usize>, ScError> {
     let width = image.shape()[1] as u32;
     let height = image.shape()[0] as u32;
     let size = (width * height) as usize;
@@ -105,34 +223,66 @@ pub fn slic(k: u32, m: u8, iter: Option<u8>, image: &Array3<u8>) -> Result<Array
         return Err(ScError::InvalidImageDimension);
     }
 
-    match u64::from(k).cmp(&(u64::from(width) * u64::from(height))) {
-        std::cmp::Ordering::Less => {}
-        std::cmp::Ordering::Equal | std::cmp::Ordering::Greater => {
-            return Err(ScError::InvalidSuperpixelCount);
-        }
+    // As many superpixels as pixels or more: each pixel is a superpixel
+    if u64::from(k) >= u64::from(width) * u64::from(height) {
+        info.iterations = 0;
+        info.residual = 0.;
+        info.centers = (0..height)
+            .flat_map(|y| (0..width).map(move |x| (x, y)))
+            .collect();
+        info.distances.clear();
+        info.distances.resize(size, 0.);
+        info.labels.clear();
+        info.labels.extend(0..size);
+        return Ok(
+            Array2::from_shape_vec((height as usize, width as usize), info.labels.clone()).unwrap(),
+        );
     }
 
     // Calculate S
     let s = calculate_grid_interval(width, height, k)
         .to_u32()
         .ok_or(ScError::InvalidGridInterval)?;
+    // Images thinner than the interval are seeded along a single row or column,
+    // spaced so that the search windows still cover them
+    let grid_s = s;
+    let s = if s > width.min(height) {
+        (width.max(height) / k).max(1)
+    } else {
+        s
+    };
 
     if s == 0 {
         return Err(ScError::ZeroGridInterval);
     }
 
-    let m_s_term = m_div_s(f64::from(m), f64::from(s));
-
     // Init seeds and shuffle them to a hopefully non-noisy pixel
     let mut clusters = Vec::new();
-    init_seeds(s, k, image, &mut clusters)?;
+    let mut intervals = Vec::new();
+    match seeding {
+        Seeding::Grid(grid) => {
+            init_seeds(s, k, grid, image, &mut clusters)?;
+            intervals.resize(clusters.len(), s);
+        }
+        Seeding::Density(density) => {
+            init_seeds_density(s, k, density, image, &mut clusters, &mut intervals)?;
+        }
+        Seeding::Warm(centers) => {
+            init_seeds_warm(s, k, centers, image, &mut clusters)?;
+            intervals.resize(clusters.len(), s);
+        }
+    }
 
-    for seed in &mut clusters {
-        perturb(seed, image)?;
+    // Seeds closer than 3 pixels would be moved onto each other
+    for (seed, s) in clusters.iter_mut().zip(&intervals) {
+        if *s >= 3 {
+            perturb(seed, image)?;
+        }
     }
 
     // Bookkeeping for tracking pixel clusters and updating cluster centers
-    let mut info = SlicInfo::<f64, usize>::new();
+    info.distances.clear();
+    info.labels.clear();
     info.distances.try_reserve_exact(size)?;
     info.labels.try_reserve_exact(size)?;
     info.distances.extend((0..size).map(|_| f64::INFINITY));
@@ -142,12 +292,30 @@ pub fn slic(k: u32, m: u8, iter: Option<u8>, image: &Array3<u8>) -> Result<Array
     updates.try_reserve_exact(clusters.len())?;
     updates.extend((0..clusters.len()).map(|_| SlicUpdate::new()));
 
+    info.iterations = 0;
+    info.residual = 0.;
+    let mut row_distances = Vec::new();
+
     for _ in 0..iter {
         // Search a pixel area of 2S x 2S size and match cluster centers to
         // pixels with the lowest distance measure
         for (center_index, center) in clusters.iter().enumerate() {
+            let s = intervals[center_index];
+            let m_s_term = m_div_s(f64::from(m), f64::from(s));
+            let center_data = center.data.as_standard_layout();
+            let x_start = center.x.saturating_sub(s);
+            let x_end = center.x.saturating_add(s).min(width);
             for y in center.y.saturating_sub(s)..center.y.saturating_add(s).min(height) {
-                for x in center.x.saturating_sub(s)..center.x.saturating_add(s).min(width) {
+                // Color distances of the whole row at once, vectorized with the `simd` feature
+                let row = image.slice(s![y as usize, x_start as usize..x_end as usize, ..]);
+                let row = row.as_standard_layout();
+                distance_pixels(
+                    row.as_slice().unwrap(),
+                    center_data.as_slice().unwrap(),
+                    &mut row_distances,
+                );
+
+                for x in x_start..x_end {
                     let idx = usize::try_from(
                         u64::from(y)
                             .saturating_mul(u64::from(width))
@@ -155,14 +323,12 @@ pub fn slic(k: u32, m: u8, iter: Option<u8>, image: &Array3<u8>) -> Result<Array
                     )
                     .or(Err("Index out of bounds for finding new neighbors"))?;
 
-                    let pixel = image.slice(s![y as i32, x as i32, ..]);
-
                     let distance = distance_s(
                         m_s_term,
-                        distance_pixel(pixel, center.data.view()),
+                        f64::from(row_distances[(x - x_start) as usize]),
                         distance_xy(
-                            (f64::from(x), f64::from(y)),
-                            (f64::from(center.x), f64::from(center.y)),
+                            (f64::from(x), f64::from(y) * pixel_aspect),
+                            (f64::from(center.x), f64::from(center.y) * pixel_aspect),
                         ),
                     );
 
@@ -213,21 +379,45 @@ pub fn slic(k: u32, m: u8, iter: Option<u8>, image: &Array3<u8>) -> Result<Array
             }
         }
 
+        let mut displacement = 0.;
         for (update, center) in updates.iter_mut().zip(&mut clusters) {
             if update.count == 0.0 {
                 continue;
             }
-            center.data = (update.data.clone() / update.count).mapv(|e| e as u8);
-            center.x = (update.x / update.count)
+            let x = (update.x / update.count)
                 .to_u32()
                 .ok_or("Update X out of bounds")?;
-            center.y = (update.y / update.count)
+            let y = (update.y / update.count)
                 .to_u32()
                 .ok_or("Update Y out of bounds")?;
+            displacement += distance_xy(
+                (f64::from(x), f64::from(y)),
+                (f64::from(center.x), f64::from(center.y)),
+            )
+            .sqrt();
+
+            center.data = (update.data.clone() / update.count).mapv(|e| e as u8);
+            center.x = x;
+            center.y = y;
             *update = SlicUpdate::new();
         }
+
+        info.iterations += 1;
+        info.residual = displacement / clusters.len() as f64;
+        console_trace!(
+            "SLIC iteration {}: residual {:.3}",
+            info.iterations,
+            info.residual
+        );
+        if matches!(threshold, Some(threshold) if info.residual < threshold) {
+            break;
+        }
     }
+    info.centers = clusters.iter().map(|center| (center.x, center.y)).collect();
 
+    // Superpixels are only merged when much smaller than the smallest interval, or
+    // than the grid interval on thin images, where they are narrower than long
+    let s = intervals.iter().copied().min().unwrap_or(s).min(grid_s);
     enforce_connectivity(width, height, s, &mut info.labels)?;
 
     let res = Array2::from_shape_vec(
@@ -239,6 +429,234 @@ pub fn slic(k: u32, m: u8, iter: Option<u8>, image: &Array3<u8>) -> Result<Array
     Ok(res)
 }
 
+/// Re-run the SLIC assignment inside `rect` extended by `margin` pixels, e.g. after
+/// an edit, instead of segmenting the whole image again.
+///
+/// Pixels of the window are reassigned among the superpixels present in it, whose
+/// labels are kept; pixels outside of the window are left untouched. `m` is the
+/// compactness and `iter` the number of iterations, as in [`slic`]. Returns the
+/// window, within the image.
+pub fn refine_superpixels_local(
+    image: &Array3<u8>,
+    labels: &mut Array2<usize>,
+    rect: Rect,
+    margin: usize,
+    m: u8,
+    iter: u8,
+) -> Rect {
+    let (height, width, channels) = image.dim();
+    let window = rect.expanded(margin, width, height);
+
+    let mut candidates = BTreeSet::new();
+    for y in window.y..window.y + window.height {
+        for x in window.x..window.x + window.width {
+            candidates.insert(labels[[y, x]]);
+        }
+    }
+    let candidates = candidates.into_iter().collect::<Vec<_>>();
+    let index = candidates
+        .iter()
+        .enumerate()
+        .map(|(i, label)| (*label, i))
+        .collect::<HashMap<_, _>>();
+
+    // Sums of the values and positions of the candidates, outside of the window
+    let mut outside = vec![SlicUpdate::<Array1<f64>>::new(); candidates.len()];
+    for ((y, x), label) in labels.indexed_iter() {
+        if let (Some(&i), false) = (index.get(label), window.contains(x, y)) {
+            accumulate(&mut outside[i], image, x, y);
+        }
+    }
+
+    let n_superpixels = labels.iter().max().map_or(1, |max| max + 1);
+    let s = calculate_grid_interval(width as u32, height as u32, n_superpixels as u32);
+    let m_s_term = m_div_s(f64::from(m.clamp(1, 20)), s);
+
+    let mut centers = Vec::new();
+    for _ in 0..iter.max(1) {
+        // Update the centers from the current assignment
+        let mut updates = outside.clone();
+        for y in window.y..window.y + window.height {
+            for x in window.x..window.x + window.width {
+                accumulate(&mut updates[index[&labels[[y, x]]]], image, x, y);
+            }
+        }
+        let counts = updates
+            .iter()
+            .map(|update| update.count)
+            .collect::<Vec<_>>();
+        centers = updates
+            .into_iter()
+            .map(|update| {
+                let count = update.count.max(1.);
+                let data = if update.data.is_empty() {
+                    Array1::zeros(channels)
+                } else {
+                    update.data / count
+                };
+                (data, update.x / count, update.y / count)
+            })
+            .collect::<Vec<_>>();
+
+        for y in window.y..window.y + window.height {
+            for x in window.x..window.x + window.width {
+                let pixel = image.slice(s![y, x, ..]);
+                let distance = |(data, cx, cy): &(Array1<f64>, f64, f64)| {
+                    let d_lab = Zip::from(pixel)
+                        .and(data)
+                        .fold(0., |acc, &p, &c| acc + (p as f64 - c).powi(2));
+                    distance_s(
+                        m_s_term,
+                        d_lab,
+                        distance_xy((x as f64, y as f64), (*cx, *cy)),
+                    )
+                };
+
+                // Only the centers within 2S are searched, as in SLIC
+                let best = centers
+                    .iter()
+                    .enumerate()
+                    .filter(|(i, (_, cx, cy))| {
+                        counts[*i] > 0.
+                            && distance_xy((x as f64, y as f64), (*cx, *cy)) <= 4. * s * s
+                    })
+                    .min_by(|(_, a), (_, b)| distance(a).partial_cmp(&distance(b)).unwrap());
+
+                if let Some((i, _)) = best {
+                    labels[[y, x]] = candidates[i];
+                }
+            }
+        }
+    }
+
+    // Superpixels left without any pixel get back the pixel closest to their center
+    let mut remaining = outside
+        .iter()
+        .map(|update| update.count)
+        .collect::<Vec<_>>();
+    for y in window.y..window.y + window.height {
+        for x in window.x..window.x + window.width {
+            remaining[index[&labels[[y, x]]]] += 1.;
+        }
+    }
+    for (i, _) in remaining
+        .iter()
+        .enumerate()
+        .filter(|(_, count)| **count == 0.)
+    {
+        let (_, cx, cy) = &centers[i];
+        let x = (cx.round() as usize).clamp(window.x, window.x + window.width - 1);
+        let y = (cy.round() as usize).clamp(window.y, window.y + window.height - 1);
+        labels[[y, x]] = candidates[i];
+    }
+
+    window
+}
+
+/// Improve the adherence of the superpixels to thin structures (e.g. roads) by
+/// reassigning their border pixels to the neighboring superpixel with the closest
+/// mean color, in `sweeps` passes over the image.
+///
+/// A pixel is only moved when its superpixel stays 4-connected around it.
+pub fn refine_boundaries(image: &Array3<u8>, labels: &mut Array2<usize>, sweeps: u8) {
+    let (height, width, channels) = image.dim();
+    let n_labels = labels.iter().max().map_or(0, |max| max + 1);
+
+    for _ in 0..sweeps {
+        let mut means = Array2::<f64>::zeros((n_labels, channels));
+        let mut counts = vec![0f64; n_labels];
+        for ((y, x), label) in labels.indexed_iter() {
+            let mut sum = means.row_mut(*label);
+            sum += &image.slice(s![y, x, ..]).mapv(f64::from);
+            counts[*label] += 1.;
+        }
+        for (mut mean, count) in means.outer_iter_mut().zip(&counts) {
+            mean /= count.max(1.);
+        }
+
+        for y in 0..height {
+            for x in 0..width {
+                let label = labels[[y, x]];
+                let pixel = image.slice(s![y, x, ..]);
+                let distance = |other: usize| {
+                    Zip::from(pixel)
+                        .and(means.row(other))
+                        .fold(0., |acc, &p, &m| acc + (f64::from(p) - m).powi(2))
+                };
+
+                let best = [(0, -1), (1, 0), (0, 1), (-1, 0)]
+                    .into_iter()
+                    .filter_map(|(dx, dy)| neighbor(labels, x, y, dx, dy))
+                    .filter(|other| *other != label)
+                    .map(|other| (other, distance(other)))
+                    .min_by(|(_, a), (_, b)| a.partial_cmp(b).unwrap());
+
+                if let Some((other, other_distance)) = best {
+                    if other_distance < distance(label) && is_simple(labels, x, y) {
+                        labels[[y, x]] = other;
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Label of a neighbor of a pixel, `None` outside of the image.
+fn neighbor(labels: &Array2<usize>, x: usize, y: usize, dx: isize, dy: isize) -> Option<usize> {
+    let (x, y) = (x.checked_add_signed(dx)?, y.checked_add_signed(dy)?);
+    labels.get([y, x]).copied()
+}
+
+/// Whether removing a pixel from its region keeps the region 4-connected around it:
+/// the pixels of the region in its 8-neighborhood, taken around it, form a single
+/// run touching its 4-neighbors.
+fn is_simple(labels: &Array2<usize>, x: usize, y: usize) -> bool {
+    const RING: [(isize, isize); 8] = [
+        (0, -1),
+        (1, -1),
+        (1, 0),
+        (1, 1),
+        (0, 1),
+        (-1, 1),
+        (-1, 0),
+        (-1, -1),
+    ];
+    let label = labels[[y, x]];
+    let inside = RING.map(|(dx, dy)| neighbor(labels, x, y, dx, dy) == Some(label));
+
+    // Runs of the region around the pixel, with whether they touch a 4-neighbor
+    let mut runs = 0;
+    for start in 0..RING.len() {
+        if !inside[start] || inside[(start + RING.len() - 1) % RING.len()] {
+            continue;
+        }
+        let touches_edge = (start..start + RING.len())
+            .take_while(|i| inside[i % RING.len()])
+            .any(|i| i % 2 == 0);
+        if touches_edge {
+            runs += 1;
+        }
+    }
+    // A region surrounding the pixel is a single run without start
+    if inside.iter().all(|inside| *inside) {
+        runs = 1;
+    }
+
+    runs == 1
+}
+
+fn accumulate(update: &mut SlicUpdate<Array1<f64>>, image: &Array3<u8>, x: usize, y: usize) {
+    let pixel = image.slice(s![y, x, ..]).mapv(f64::from);
+    if update.data.is_empty() {
+        update.data = pixel;
+    } else {
+        update.data += &pixel;
+    }
+    update.x += x as f64;
+    update.y += y as f64;
+    update.count += 1.;
+}
+
 // Relabel disjoint labels to the largest, nearest neighbor cluster.
 fn enforce_connectivity(
     width: u32,
@@ -355,3 +773,91 @@ fn enforce_connectivity(
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_refine_superpixels_local() {
+        // The edge of the image is at x = 3, the one of the superpixels at x = 2
+        let image = Array3::from_shape_fn((4, 6, 1), |(_, x, _)| if x < 3 { 0 } else { 200 });
+        let mut labels = Array2::from_shape_fn((4, 6), |(_, x)| usize::from(x >= 2));
+
+        let rect = Rect {
+            x: 2,
+            y: 0,
+            width: 1,
+            height: 4,
+        };
+        let window = refine_superpixels_local(&image, &mut labels, rect, 1, 1, 2);
+
+        assert_eq!(window.x, 1);
+        assert_eq!(window.width, 3);
+        assert_eq!(
+            labels,
+            Array2::from_shape_fn((4, 6), |(_, x)| usize::from(x >= 3))
+        );
+    }
+
+    #[test]
+    fn test_refine_boundaries() {
+        // The road is at x = 4, but assigned to the superpixel on its left
+        let image = Array3::from_shape_fn((6, 8, 1), |(_, x, _)| if x < 4 { 0 } else { 200 });
+        let mut labels = Array2::from_shape_fn((6, 8), |(_, x)| usize::from(x >= 5));
+
+        refine_boundaries(&image, &mut labels, 1);
+
+        assert_eq!(
+            labels,
+            Array2::from_shape_fn((6, 8), |(_, x)| usize::from(x >= 4))
+        );
+    }
+
+    #[test]
+    fn test_slic_with_distances() {
+        let m = 10;
+        let image = Array3::zeros((12, 12, 1));
+
+        let (labels, distances) = slic_with_distances(9, m, Some(2), &image).unwrap();
+
+        assert_eq!(distances.dim(), labels.dim());
+        // Flat image: only the spatial distance, within S * sqrt(2) scaled by m / S
+        let max = f64::from(m) * 2f64.sqrt();
+        assert!(distances.iter().all(|d| (0. ..=max).contains(d)));
+    }
+
+    #[test]
+    fn test_slic_convergence() {
+        let image = Array3::from_shape_fn((24, 24, 1), |(y, x, _)| ((x / 6 + y / 6) * 40) as u8);
+
+        let mut info = SlicInfo::new();
+        slic_with_info(
+            16,
+            10,
+            Some(20),
+            Some(0.5),
+            Seeding::Grid(SeedGrid::Square),
+            1.,
+            &image,
+            &mut info,
+        )
+        .unwrap();
+        assert!(info.iterations < 20);
+        assert!(info.residual < 0.5);
+
+        // Without threshold, all the iterations are performed
+        slic_with_info(
+            16,
+            10,
+            Some(3),
+            None,
+            Seeding::Grid(SeedGrid::Square),
+            1.,
+            &image,
+            &mut info,
+        )
+        .unwrap();
+        assert_eq!(info.iterations, 3);
+    }
+}
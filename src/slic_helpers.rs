@@ -24,6 +24,33 @@ pub fn distance_pixel(lhs: ArrayView1<u8>, rhs: ArrayView1<u8>) -> f64 {
     sum as f64
 }
 
+/// Calculate the distance between each pixel of a row of interleaved bands and a
+/// center pixel, as [`distance_pixel`] does. Vectorized with the `simd` feature.
+pub fn distance_pixels(row: &[u8], center: &[u8], distances: &mut Vec<f32>) {
+    distances.clear();
+    if center.is_empty() {
+        return;
+    }
+
+    #[cfg(feature = "simd")]
+    let done = crate::simd::distance_pixels_prefix(row, center, distances);
+    #[cfg(not(feature = "simd"))]
+    let done = 0;
+
+    distances.extend(
+        row[done * center.len()..]
+            .chunks_exact(center.len())
+            .map(|pixel| {
+                let mut sum = 0.0;
+                for (value, center) in pixel.iter().zip(center) {
+                    let diff = *value as f32 - *center as f32;
+                    sum += diff * diff;
+                }
+                sum
+            }),
+    );
+}
+
 /// Calculate the distance between two two-dimensional points.
 #[inline]
 pub fn distance_xy<T: Float>(lhs: (T, T), rhs: (T, T)) -> T {
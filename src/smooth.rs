@@ -0,0 +1,304 @@
+use ndarray::Array2;
+#[cfg(feature = "wasm")]
+use wasm_bindgen::prelude::wasm_bindgen;
+
+use crate::vector::{region_outlines, Ring};
+
+// Points sampled on each span of the spline
+const SPLINE_SAMPLES: usize = 4;
+
+/// Outline of a region in pixel coordinates, possibly between the pixel corners.
+pub type SmoothRing = Vec<(f64, f64)>;
+
+/// Smoothing of the region outlines in the polygon exports, e.g. for natural objects
+/// like forests or water. Unsmoothed outlines follow the pixel borders, which suits
+/// buildings.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Smoothing {
+    kind: SmoothingKind,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+enum SmoothingKind {
+    #[default]
+    None,
+    Chaikin(usize),
+    Spline,
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+impl Smoothing {
+    pub fn none() -> Self {
+        Self::default()
+    }
+
+    /// Chaikin corner cutting, each iteration cutting the corners of the previous one.
+    pub fn chaikin(iterations: usize) -> Self {
+        Self {
+            kind: SmoothingKind::Chaikin(iterations),
+        }
+    }
+
+    /// Cubic B-spline, with the outline vertices as control points.
+    pub fn spline() -> Self {
+        Self {
+            kind: SmoothingKind::Spline,
+        }
+    }
+}
+
+/// Outlines of the regions of a cut like [`region_outlines`], smoothed.
+///
+/// The vertices where three regions meet and the vertices on the image border are
+/// kept, and the borders between them are smoothed the same way from both sides, so
+/// neighboring regions still share their borders.
+pub fn smooth_outlines(
+    labels: &Array2<usize>,
+    smoothing: Smoothing,
+) -> Vec<(usize, Vec<SmoothRing>)> {
+    region_outlines(labels)
+        .into_iter()
+        .map(|(label, rings)| {
+            let rings = rings
+                .iter()
+                .map(|ring| smooth_ring(labels, ring, smoothing))
+                .collect();
+            (label, rings)
+        })
+        .collect()
+}
+
+fn smooth_ring(labels: &Array2<usize>, ring: &Ring, smoothing: Smoothing) -> SmoothRing {
+    let points = ring
+        .iter()
+        .map(|(x, y)| (*x as f64, *y as f64))
+        .collect::<Vec<_>>();
+    if smoothing.kind == SmoothingKind::None {
+        return points;
+    }
+
+    let pinned = ring
+        .iter()
+        .map(|vertex| is_pinned(labels, *vertex))
+        .collect::<Vec<_>>();
+    let start = match pinned.iter().position(|pinned| *pinned) {
+        Some(start) => start,
+        None => return smooth_closed(&points, smoothing.kind),
+    };
+
+    // Smooth each chain between two pinned vertices, starting from a pinned one
+    let n = points.len();
+    let mut smoothed = Vec::new();
+    let mut chain = vec![points[start]];
+    for i in 1..=n {
+        let j = (start + i) % n;
+        chain.push(points[j]);
+        if pinned[j] {
+            let smoothed_chain = smooth_open(&chain, smoothing.kind);
+            smoothed.extend_from_slice(&smoothed_chain[..smoothed_chain.len() - 1]);
+            chain = vec![points[j]];
+        }
+    }
+
+    smoothed
+}
+
+/// Whether a corner of the pixel grid is on the image border, or between pixels of
+/// more than two regions or of two regions touching diagonally.
+fn is_pinned(labels: &Array2<usize>, (x, y): (usize, usize)) -> bool {
+    let (height, width) = labels.dim();
+    if x == 0 || y == 0 || x == width || y == height {
+        return true;
+    }
+
+    let [a, b, c, d] = [
+        labels[[y - 1, x - 1]],
+        labels[[y - 1, x]],
+        labels[[y, x]],
+        labels[[y, x - 1]],
+    ];
+    let diagonal = a == c && b == d && a != b;
+    let mut distinct = vec![a, b, c, d];
+    distinct.sort_unstable();
+    distinct.dedup();
+
+    diagonal || distinct.len() > 2
+}
+
+/// Smooth a closed ring. Rings are smoothed from their smallest vertex in a fixed
+/// direction, so that a hole and the island filling it get exactly the same points.
+fn smooth_closed(points: &[(f64, f64)], kind: SmoothingKind) -> SmoothRing {
+    let n = points.len();
+    let start = (0..n)
+        .min_by(|a, b| points[*a].partial_cmp(&points[*b]).unwrap())
+        .unwrap();
+    let mut canonical = points[start..]
+        .iter()
+        .chain(&points[..start])
+        .copied()
+        .collect::<Vec<_>>();
+    let reversed = canonical[n - 1] < canonical[1];
+    if reversed {
+        canonical[1..].reverse();
+    }
+
+    let mut smoothed = smooth_canonical_closed(&canonical, kind);
+    if reversed {
+        smoothed.reverse();
+    }
+    smoothed
+}
+
+fn smooth_canonical_closed(points: &[(f64, f64)], kind: SmoothingKind) -> SmoothRing {
+    let n = points.len();
+    match kind {
+        SmoothingKind::None => points.to_vec(),
+        SmoothingKind::Chaikin(iterations) => {
+            let mut points = points.to_vec();
+            for _ in 0..iterations {
+                points = (0..points.len())
+                    .flat_map(|i| cut_corner(points[i], points[(i + 1) % points.len()]))
+                    .collect();
+            }
+            points
+        }
+        SmoothingKind::Spline => (0..n)
+            .flat_map(|i| {
+                let control = [
+                    points[(i + n - 1) % n],
+                    points[i],
+                    points[(i + 1) % n],
+                    points[(i + 2) % n],
+                ];
+                sample_span(control)
+            })
+            .collect(),
+    }
+}
+
+/// Smooth a polyline, keeping its end points. Polylines are smoothed in a fixed
+/// direction, so that a border shared by two regions gets exactly the same points.
+fn smooth_open(points: &[(f64, f64)], kind: SmoothingKind) -> SmoothRing {
+    let n = points.len();
+    if n < 3 {
+        return points.to_vec();
+    }
+
+    let (first, last) = (points[0], points[n - 1]);
+    if last < first || (last == first && points[n - 2] < points[1]) {
+        let reversed = points.iter().rev().copied().collect::<Vec<_>>();
+        let mut smoothed = smooth_canonical_open(&reversed, kind);
+        smoothed.reverse();
+        return smoothed;
+    }
+    smooth_canonical_open(points, kind)
+}
+
+fn smooth_canonical_open(points: &[(f64, f64)], kind: SmoothingKind) -> SmoothRing {
+    let n = points.len();
+    match kind {
+        SmoothingKind::None => points.to_vec(),
+        SmoothingKind::Chaikin(iterations) => {
+            let mut points = points.to_vec();
+            for _ in 0..iterations {
+                let mut cut = vec![points[0]];
+                cut.extend(
+                    points
+                        .windows(2)
+                        .flat_map(|pair| cut_corner(pair[0], pair[1])),
+                );
+                cut.push(points[points.len() - 1]);
+                points = cut;
+            }
+            points
+        }
+        SmoothingKind::Spline => {
+            // Mirrored phantom points make the curve go through the end points
+            let mirror = |a: (f64, f64), b: (f64, f64)| (2. * a.0 - b.0, 2. * a.1 - b.1);
+            let mut control = vec![mirror(points[0], points[1])];
+            control.extend_from_slice(points);
+            control.push(mirror(points[n - 1], points[n - 2]));
+
+            let mut smoothed = control
+                .windows(4)
+                .flat_map(|window| sample_span([window[0], window[1], window[2], window[3]]))
+                .collect::<Vec<_>>();
+            smoothed.push(points[n - 1]);
+            smoothed
+        }
+    }
+}
+
+/// Points at a quarter and three quarters of a segment.
+fn cut_corner(a: (f64, f64), b: (f64, f64)) -> [(f64, f64); 2] {
+    [
+        (0.75 * a.0 + 0.25 * b.0, 0.75 * a.1 + 0.25 * b.1),
+        (0.25 * a.0 + 0.75 * b.0, 0.25 * a.1 + 0.75 * b.1),
+    ]
+}
+
+/// Points of a span of a uniform cubic B-spline, from its start included to its
+/// end excluded.
+fn sample_span(control: [(f64, f64); 4]) -> Vec<(f64, f64)> {
+    (0..SPLINE_SAMPLES)
+        .map(|i| {
+            let t = i as f64 / SPLINE_SAMPLES as f64;
+            let weights = [
+                (1. - t).powi(3) / 6.,
+                (3. * t.powi(3) - 6. * t.powi(2) + 4.) / 6.,
+                (-3. * t.powi(3) + 3. * t.powi(2) + 3. * t + 1.) / 6.,
+                t.powi(3) / 6.,
+            ];
+            control
+                .iter()
+                .zip(weights)
+                .fold((0., 0.), |(x, y), (point, weight)| {
+                    (x + weight * point.0, y + weight * point.1)
+                })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Points of a ring, in a canonical order to compare rings going in opposite
+    /// directions.
+    fn sorted(ring: &SmoothRing) -> Vec<(u64, u64)> {
+        let mut points = ring
+            .iter()
+            .map(|(x, y)| (x.to_bits(), y.to_bits()))
+            .collect::<Vec<_>>();
+        points.sort_unstable();
+        points
+    }
+
+    #[test]
+    fn test_smooth_outlines() {
+        // Region 1 is a 2x2 square hole in region 0, region 2 a notch on the border
+        let mut labels = Array2::zeros((4, 5));
+        labels.slice_mut(ndarray::s![1..3, 1..3]).fill(1);
+        labels[[0, 4]] = 2;
+
+        for smoothing in [Smoothing::chaikin(2), Smoothing::spline()] {
+            let outlines = smooth_outlines(&labels, smoothing);
+
+            let (_, outer) = &outlines[0];
+            // The image corners are kept
+            assert!(outer[0].contains(&(0., 0.)));
+            assert!(outer[0].contains(&(0., 4.)));
+            // The hole of region 0 is the outline of region 1
+            assert_eq!(sorted(&outer[1]), sorted(&outlines[1].1[0]));
+            assert!(outer[1].len() > 4);
+            assert!(!outer[1].contains(&(1., 1.)));
+        }
+
+        let outlines = smooth_outlines(&labels, Smoothing::none());
+        assert_eq!(
+            outlines[1].1[0],
+            vec![(1., 1.), (3., 1.), (3., 3.), (1., 3.)]
+        );
+    }
+}
@@ -0,0 +1,478 @@
+//! Minimal writer of SQLite database files, enough to create read-only exports
+//! (e.g. GeoPackages) without a SQLite engine, which is unavailable in WASM.
+//!
+//! Tables are written at once with their rows, as table b-trees. Indexes are
+//! limited to a single page, which is enough for metadata tables.
+
+const PAGE_SIZE: usize = 4096;
+// Size of the database header at the start of the first page
+const HEADER_SIZE: usize = 100;
+
+const INDEX_INTERIOR: u8 = 0x02;
+const TABLE_INTERIOR: u8 = 0x05;
+const INDEX_LEAF: u8 = 0x0a;
+const TABLE_LEAF: u8 = 0x0d;
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Value {
+    Null,
+    Integer(i64),
+    Real(f64),
+    Text(String),
+    Blob(Vec<u8>),
+}
+
+impl From<&str> for Value {
+    fn from(text: &str) -> Self {
+        Value::Text(text.to_string())
+    }
+}
+
+impl From<i64> for Value {
+    fn from(value: i64) -> Self {
+        Value::Integer(value)
+    }
+}
+
+impl From<f64> for Value {
+    fn from(value: f64) -> Self {
+        Value::Real(value)
+    }
+}
+
+/// Database being written, kept in memory.
+pub(crate) struct Database {
+    // Page `i + 1` is `pages[i]`, the first one being written last with the schema
+    pages: Vec<Vec<u8>>,
+    // Rows of the `sqlite_master` table: type, name, table name, root page and SQL
+    schema: Vec<Vec<Value>>,
+    application_id: u32,
+    user_version: u32,
+}
+
+impl Database {
+    pub fn new(application_id: u32, user_version: u32) -> Self {
+        Self {
+            pages: vec![Vec::new()],
+            schema: Vec::new(),
+            application_id,
+            user_version,
+        }
+    }
+
+    /// Create a table from its SQL definition and its rows, as `(rowid, values)`
+    /// sorted by rowid. An `INTEGER PRIMARY KEY` column is an alias of the rowid,
+    /// and must be stored as `Null` in the values.
+    pub fn create_table(&mut self, name: &str, sql: &str, rows: &[(i64, Vec<Value>)]) {
+        assert!(
+            rows.windows(2).all(|w| w[0].0 < w[1].0),
+            "Rows must be sorted by rowid"
+        );
+
+        let cells = rows
+            .iter()
+            .map(|(rowid, values)| (*rowid, self.table_cell(*rowid, values)))
+            .collect::<Vec<_>>();
+        let root = self.write_table_btree(cells, None);
+
+        self.schema
+            .push(schema_row("table", name, name, root, Value::from(sql)));
+    }
+
+    /// Create an automatic index of a `UNIQUE` or `PRIMARY KEY` constraint, from the
+    /// indexed values of each row followed by its rowid. The entries must fit in one page.
+    pub fn create_auto_index(&mut self, table: &str, number: usize, mut entries: Vec<Vec<Value>>) {
+        entries.sort_by(|a, b| compare_records(a, b));
+
+        let cells = entries
+            .iter()
+            .map(|entry| {
+                let payload = record(entry);
+                assert!(
+                    payload.len() <= max_local(INDEX_LEAF),
+                    "Index entry too large"
+                );
+                let mut cell = varint(payload.len() as u64);
+                cell.extend(payload);
+                cell
+            })
+            .collect::<Vec<_>>();
+        assert!(fits(&cells, 8, PAGE_SIZE), "Index too large");
+
+        let root = self.pages.len() + 1;
+        self.pages.push(serialize_page(INDEX_LEAF, &cells, None, 0));
+
+        let name = format!("sqlite_autoindex_{table}_{number}");
+        self.schema
+            .push(schema_row("index", &name, table, root, Value::Null));
+    }
+
+    pub fn into_bytes(mut self) -> Vec<u8> {
+        let schema = std::mem::take(&mut self.schema);
+        let cells = schema
+            .iter()
+            .enumerate()
+            .map(|(i, values)| (i as i64 + 1, self.table_cell(i as i64 + 1, values)))
+            .collect::<Vec<_>>();
+        self.write_table_btree(cells, Some(1));
+
+        let page_count = self.pages.len() as u32;
+        let header = &mut self.pages[0][..HEADER_SIZE];
+        header[..16].copy_from_slice(b"SQLite format 3\0");
+        header[16..18].copy_from_slice(&(PAGE_SIZE as u16).to_be_bytes());
+        // File format versions, reserved space, payload fractions
+        header[18..24].copy_from_slice(&[1, 1, 0, 64, 32, 32]);
+        // File change counter
+        header[24..28].copy_from_slice(&1u32.to_be_bytes());
+        header[28..32].copy_from_slice(&page_count.to_be_bytes());
+        // Schema cookie and schema format
+        header[40..44].copy_from_slice(&1u32.to_be_bytes());
+        header[44..48].copy_from_slice(&4u32.to_be_bytes());
+        // UTF-8 text
+        header[56..60].copy_from_slice(&1u32.to_be_bytes());
+        header[60..64].copy_from_slice(&self.user_version.to_be_bytes());
+        header[68..72].copy_from_slice(&self.application_id.to_be_bytes());
+        // Version valid for the file change counter, and SQLite version
+        header[92..96].copy_from_slice(&1u32.to_be_bytes());
+        header[96..100].copy_from_slice(&3_040_000u32.to_be_bytes());
+
+        self.pages.concat()
+    }
+
+    /// Leaf cell of a table b-tree, spilling the end of large payloads into overflow pages.
+    fn table_cell(&mut self, rowid: i64, values: &[Value]) -> Vec<u8> {
+        let payload = record(values);
+
+        let mut cell = varint(payload.len() as u64);
+        cell.extend(varint(rowid as u64));
+
+        let local = local_size(payload.len());
+        cell.extend(&payload[..local]);
+        if local < payload.len() {
+            let first_overflow = self.pages.len() + 1;
+            let chunks = payload[local..].chunks(PAGE_SIZE - 4).collect::<Vec<_>>();
+            for (i, chunk) in chunks.iter().enumerate() {
+                let next = if i + 1 < chunks.len() {
+                    first_overflow + i + 1
+                } else {
+                    0
+                };
+                let mut page = (next as u32).to_be_bytes().to_vec();
+                page.extend(*chunk);
+                page.resize(PAGE_SIZE, 0);
+                self.pages.push(page);
+            }
+            cell.extend((first_overflow as u32).to_be_bytes());
+        }
+
+        cell
+    }
+
+    /// Write a table b-tree from its leaf cells sorted by rowid, and return its root
+    /// page. The root is written on `root` if given, after the database header for
+    /// the first page.
+    fn write_table_btree(&mut self, cells: Vec<(i64, Vec<u8>)>, root: Option<usize>) -> usize {
+        let root_offset = if root == Some(1) { HEADER_SIZE } else { 0 };
+        // Every page has the capacity of the root, so a single page can become the root
+        let capacity = PAGE_SIZE - root_offset;
+
+        // Pages of the current level, with their largest rowid
+        let mut level = pack(cells, 8, capacity, 1)
+            .into_iter()
+            .map(|cells| {
+                let max_rowid = cells.last().map_or(0, |(rowid, _)| *rowid);
+                (TABLE_LEAF, cells, None, max_rowid)
+            })
+            .collect::<Vec<_>>();
+
+        loop {
+            if level.len() == 1 {
+                let (kind, cells, right, _) = level.pop().unwrap();
+                let cells = cells.into_iter().map(|(_, cell)| cell).collect::<Vec<_>>();
+                let page = serialize_page(kind, &cells, right, root_offset);
+                return self.place_root(page, root);
+            }
+
+            let mut children = Vec::with_capacity(level.len());
+            for (kind, cells, right, max_rowid) in level {
+                let cells = cells.into_iter().map(|(_, cell)| cell).collect::<Vec<_>>();
+                self.pages.push(serialize_page(kind, &cells, right, 0));
+                children.push((max_rowid, self.pages.len()));
+            }
+
+            // The last child of each interior page is its right-most pointer
+            let interior_cells = children
+                .iter()
+                .map(|(rowid, page)| {
+                    let mut cell = (*page as u32).to_be_bytes().to_vec();
+                    cell.extend(varint(*rowid as u64));
+                    (*rowid, cell)
+                })
+                .collect::<Vec<_>>();
+            level = pack(interior_cells, 12, capacity, 2)
+                .into_iter()
+                .map(|mut cells| {
+                    let (max_rowid, last) = cells.pop().unwrap();
+                    let right = u32::from_be_bytes(last[..4].try_into().unwrap());
+                    (TABLE_INTERIOR, cells, Some(right), max_rowid)
+                })
+                .collect();
+        }
+    }
+
+    fn place_root(&mut self, page: Vec<u8>, root: Option<usize>) -> usize {
+        match root {
+            Some(root) => {
+                self.pages[root - 1] = page;
+                root
+            }
+            None => {
+                self.pages.push(page);
+                self.pages.len()
+            }
+        }
+    }
+}
+
+fn schema_row(kind: &str, name: &str, table: &str, root: usize, sql: Value) -> Vec<Value> {
+    vec![
+        Value::from(kind),
+        Value::from(name),
+        Value::from(table),
+        Value::Integer(root as i64),
+        sql,
+    ]
+}
+
+/// Split cells into pages of the given capacity, with a page header of `header` bytes
+/// and at least `min_cells` cells per page when there are enough cells. Interior
+/// pages need two cells, their last one being moved to the header.
+fn pack<T>(
+    cells: Vec<(T, Vec<u8>)>,
+    header: usize,
+    capacity: usize,
+    min_cells: usize,
+) -> Vec<Vec<(T, Vec<u8>)>> {
+    let mut pages = vec![Vec::new()];
+    let mut used = header;
+
+    for cell in cells {
+        let size = cell.1.len() + 2;
+        if used + size > capacity && pages.last().unwrap().len() >= min_cells {
+            pages.push(Vec::new());
+            used = header;
+        }
+        used += size;
+        pages.last_mut().unwrap().push(cell);
+    }
+
+    // Borrow cells from the previous page, which has room to spare
+    let n = pages.len();
+    while n > 1 && pages[n - 1].len() < min_cells {
+        let cell = pages[n - 2].pop().unwrap();
+        pages[n - 1].insert(0, cell);
+    }
+
+    pages
+}
+
+fn fits(cells: &[Vec<u8>], header: usize, capacity: usize) -> bool {
+    header + cells.iter().map(|cell| cell.len() + 2).sum::<usize>() <= capacity
+}
+
+/// Page with its header at `offset`, cell pointers after it, and cells at its end.
+fn serialize_page(kind: u8, cells: &[Vec<u8>], right: Option<u32>, offset: usize) -> Vec<u8> {
+    let mut page = vec![0; PAGE_SIZE];
+
+    let mut content_start = PAGE_SIZE;
+    let header_size = if right.is_some() { 12 } else { 8 };
+    for (i, cell) in cells.iter().enumerate() {
+        content_start -= cell.len();
+        page[content_start..content_start + cell.len()].copy_from_slice(cell);
+
+        let pointer = offset + header_size + 2 * i;
+        page[pointer..pointer + 2].copy_from_slice(&(content_start as u16).to_be_bytes());
+    }
+
+    page[offset] = kind;
+    page[offset + 3..offset + 5].copy_from_slice(&(cells.len() as u16).to_be_bytes());
+    page[offset + 5..offset + 7].copy_from_slice(&(content_start as u16).to_be_bytes());
+    if let Some(right) = right {
+        page[offset + 8..offset + 12].copy_from_slice(&right.to_be_bytes());
+    }
+
+    page
+}
+
+/// Largest payload stored in a cell without overflow.
+fn max_local(kind: u8) -> usize {
+    match kind {
+        TABLE_LEAF => PAGE_SIZE - 35,
+        INDEX_LEAF | INDEX_INTERIOR => (PAGE_SIZE - 12) * 64 / 255 - 23,
+        _ => unreachable!(),
+    }
+}
+
+/// Bytes of a table leaf payload stored in the cell, the rest going to overflow pages.
+fn local_size(payload: usize) -> usize {
+    if payload <= max_local(TABLE_LEAF) {
+        return payload;
+    }
+
+    let min_local = (PAGE_SIZE - 12) * 32 / 255 - 23;
+    let local = min_local + (payload - min_local) % (PAGE_SIZE - 4);
+    if local <= max_local(TABLE_LEAF) {
+        local
+    } else {
+        min_local
+    }
+}
+
+/// Record of values: a header of serial types followed by the values.
+fn record(values: &[Value]) -> Vec<u8> {
+    let mut types = Vec::new();
+    let mut body = Vec::new();
+
+    for value in values {
+        let serial_type = match value {
+            Value::Null => 0,
+            Value::Integer(0) => 8,
+            Value::Integer(1) => 9,
+            Value::Integer(value) => {
+                let (serial_type, size) = match *value {
+                    -0x80..=0x7f => (1, 1),
+                    -0x8000..=0x7fff => (2, 2),
+                    -0x80_0000..=0x7f_ffff => (3, 3),
+                    -0x8000_0000..=0x7fff_ffff => (4, 4),
+                    -0x8000_0000_0000..=0x7fff_ffff_ffff => (5, 6),
+                    _ => (6, 8),
+                };
+                body.extend(&value.to_be_bytes()[8 - size..]);
+                serial_type
+            }
+            Value::Real(value) => {
+                body.extend(value.to_be_bytes());
+                7
+            }
+            Value::Text(text) => {
+                body.extend(text.as_bytes());
+                13 + 2 * text.len() as u64
+            }
+            Value::Blob(blob) => {
+                body.extend(blob);
+                12 + 2 * blob.len() as u64
+            }
+        };
+        types.extend(varint(serial_type));
+    }
+
+    // The header size includes its own varint
+    let mut header_size = types.len() + 1;
+    while types.len() + varint(header_size as u64).len() != header_size {
+        header_size = types.len() + varint(header_size as u64).len();
+    }
+
+    let mut record = varint(header_size as u64);
+    record.extend(types);
+    record.extend(body);
+    record
+}
+
+/// Order of index entries with the binary collation: NULL, numbers, text, blobs.
+fn compare_records(a: &[Value], b: &[Value]) -> std::cmp::Ordering {
+    fn rank(value: &Value) -> u8 {
+        match value {
+            Value::Null => 0,
+            Value::Integer(_) | Value::Real(_) => 1,
+            Value::Text(_) => 2,
+            Value::Blob(_) => 3,
+        }
+    }
+
+    for (a, b) in a.iter().zip(b) {
+        let ordering = match (a, b) {
+            (Value::Integer(a), Value::Integer(b)) => a.cmp(b),
+            (Value::Text(a), Value::Text(b)) => a.as_bytes().cmp(b.as_bytes()),
+            (Value::Blob(a), Value::Blob(b)) => a.cmp(b),
+            _ => rank(a).cmp(&rank(b)),
+        };
+        if ordering.is_ne() {
+            return ordering;
+        }
+    }
+
+    a.len().cmp(&b.len())
+}
+
+/// Variable-length big-endian integer of 1 to 9 bytes.
+fn varint(value: u64) -> Vec<u8> {
+    if value > 0x00ff_ffff_ffff_ffff {
+        // The 9th byte holds 8 bits
+        let mut bytes = Vec::with_capacity(9);
+        let high = value >> 8;
+        for i in (0..8).rev() {
+            bytes.push(((high >> (7 * i)) & 0x7f) as u8 | 0x80);
+        }
+        bytes.push(value as u8);
+        return bytes;
+    }
+
+    let mut bytes = vec![(value & 0x7f) as u8];
+    let mut rest = value >> 7;
+    while rest > 0 {
+        bytes.push((rest & 0x7f) as u8 | 0x80);
+        rest >>= 7;
+    }
+    bytes.reverse();
+    bytes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_varint() {
+        assert_eq!(varint(0), vec![0]);
+        assert_eq!(varint(0x7f), vec![0x7f]);
+        assert_eq!(varint(0x80), vec![0x81, 0x00]);
+        assert_eq!(varint(u64::MAX).len(), 9);
+    }
+
+    #[test]
+    fn test_record() {
+        let values = [
+            Value::Null,
+            Value::Integer(1),
+            Value::Integer(300),
+            Value::from("ab"),
+        ];
+        assert_eq!(
+            record(&values),
+            vec![5, 0, 9, 2, 17, 0x01, 0x2c, b'a', b'b']
+        );
+    }
+
+    #[test]
+    fn test_database_layout() {
+        let mut database = Database::new(0, 0);
+        // Enough large rows to need overflow pages and interior pages
+        let rows = (0..100)
+            .map(|i| (i, vec![Value::Null, Value::Blob(vec![i as u8; 5000])]))
+            .collect::<Vec<_>>();
+        database.create_table(
+            "t",
+            "CREATE TABLE t (id INTEGER PRIMARY KEY, b BLOB)",
+            &rows,
+        );
+
+        let bytes = database.into_bytes();
+
+        assert_eq!(&bytes[..16], b"SQLite format 3\0");
+        assert_eq!(bytes.len() % PAGE_SIZE, 0);
+        let page_count = u32::from_be_bytes(bytes[28..32].try_into().unwrap());
+        assert_eq!(page_count as usize, bytes.len() / PAGE_SIZE);
+        // Schema on the first page, as a single leaf
+        assert_eq!(bytes[HEADER_SIZE], TABLE_LEAF);
+    }
+}
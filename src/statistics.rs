@@ -0,0 +1,216 @@
+use std::{mem::size_of, sync::Arc};
+
+use ndarray::{Array1, ArrayView1};
+#[cfg(feature = "wasm")]
+use wasm_bindgen::prelude::wasm_bindgen;
+
+use crate::{
+    graph::{FidelityParams, SpatialMoments, SuperpixelNode},
+    plef::Plef,
+};
+
+/// Statistics of the superpixels accumulated while building the graph, stored as
+/// one flat array per statistic instead of small arrays in each node.
+///
+/// Passing the same buffer to successive builds, e.g. the tiles processed by a
+/// worker, reuses its allocation: it holds the largest statistics needed so far,
+/// and can be reserved up front with [`StatisticsBuffer::new`]. The statistics are
+/// copied into the nodes of the graph once all the pixels are added.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Clone, Debug, Default)]
+pub struct StatisticsBuffer {
+    channels: usize,
+    areas: Vec<u32>,
+    // Sums of x, y, x², xy and y² of each superpixel
+    moments: Vec<[u64; 5]>,
+    // Sums over the pixels of each superpixel and channel, superpixel-major
+    values: Vec<u64>,
+    values_sq: Vec<u64>,
+    values_x: Vec<u64>,
+    values_y: Vec<u64>,
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+impl StatisticsBuffer {
+    /// Buffer reserved for `superpixels` superpixels of `channels` bands.
+    #[cfg_attr(feature = "wasm", wasm_bindgen(constructor))]
+    pub fn new(superpixels: usize, channels: usize) -> Self {
+        let len = superpixels * channels;
+
+        Self {
+            channels,
+            areas: Vec::with_capacity(superpixels),
+            moments: Vec::with_capacity(superpixels),
+            values: Vec::with_capacity(len),
+            values_sq: Vec::with_capacity(len),
+            values_x: Vec::with_capacity(len),
+            values_y: Vec::with_capacity(len),
+        }
+    }
+
+    /// Memory held by the buffer, in bytes.
+    pub fn byte_size(&self) -> usize {
+        size_of::<Self>()
+            + self.areas.capacity() * size_of::<u32>()
+            + self.moments.capacity() * size_of::<[u64; 5]>()
+            + (self.values.capacity()
+                + self.values_sq.capacity()
+                + self.values_x.capacity()
+                + self.values_y.capacity())
+                * size_of::<u64>()
+    }
+}
+
+impl StatisticsBuffer {
+    /// Empty statistics for `superpixels` superpixels, keeping the allocation.
+    pub(crate) fn reset(&mut self, superpixels: usize, channels: usize) {
+        let len = superpixels * channels;
+        self.channels = channels;
+
+        self.areas.clear();
+        self.areas.resize(superpixels, 0);
+        self.moments.clear();
+        self.moments.resize(superpixels, [0; 5]);
+        for sums in [
+            &mut self.values,
+            &mut self.values_sq,
+            &mut self.values_x,
+            &mut self.values_y,
+        ] {
+            sums.clear();
+            sums.resize(len, 0);
+        }
+    }
+
+    /// Add a pixel at `(x, y)` to a superpixel.
+    pub(crate) fn add_pixel(
+        &mut self,
+        superpixel: usize,
+        pixel: ArrayView1<u8>,
+        x: usize,
+        y: usize,
+    ) {
+        let (x, y) = (x as u64, y as u64);
+        self.areas[superpixel] += 1;
+
+        let moments = &mut self.moments[superpixel];
+        for (sum, value) in moments.iter_mut().zip([x, y, x * x, x * y, y * y]) {
+            *sum += value;
+        }
+
+        let start = superpixel * self.channels;
+        for (c, value) in pixel.iter().enumerate() {
+            let value = u64::from(*value);
+            self.values[start + c] += value;
+            self.values_sq[start + c] += value * value;
+            self.values_x[start + c] += value * x;
+            self.values_y[start + c] += value * y;
+        }
+    }
+
+    /// Node of a superpixel with its statistics, its perimeter left to 0.
+    pub(crate) fn node(&self, superpixel: usize, fidelity: Arc<FidelityParams>) -> SuperpixelNode {
+        let range = superpixel * self.channels..(superpixel + 1) * self.channels;
+        let sums = |values: &[u64]| Array1::from(values[range.clone()].to_vec());
+        let [sum_x, sum_y, sum_xx, sum_xy, sum_yy] = self.moments[superpixel];
+
+        SuperpixelNode::new(
+            self.areas[superpixel],
+            0,
+            0,
+            sums(&self.values),
+            sums(&self.values_sq),
+            SpatialMoments {
+                sum_x,
+                sum_y,
+                sum_xx,
+                sum_xy,
+                sum_yy,
+                values_x: sums(&self.values_x),
+                values_y: sums(&self.values_y),
+            },
+            fidelity,
+            Plef::init(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ndarray::{array, s, Array2, Array3};
+
+    use super::*;
+    use crate::graph::{graph_from_labels, graph_from_labels_with_buffer, FidelityModel};
+
+    #[test]
+    fn test_node_statistics() {
+        // 0 0 1
+        // 0 0 1
+        // 2 2 2
+        let labels = Array2::from_shape_vec((3, 3), vec![0, 0, 1, 0, 0, 1, 2, 2, 2]).unwrap();
+        let img = Array3::from_shape_vec((3, 3, 3), (0..27).collect()).unwrap();
+
+        let mut statistics = StatisticsBuffer::new(3, 3);
+        statistics.reset(3, 3);
+        for ((y, x), label) in labels.indexed_iter() {
+            statistics.add_pixel(*label, img.slice(s![y, x, ..]), x, y);
+        }
+        let fidelity = Arc::new(FidelityParams::new(FidelityModel::default(), 3));
+        let node = statistics.node(1, fidelity);
+
+        // Pixels (2, 0) and (2, 1)
+        assert_eq!(node.area, 2);
+        assert_eq!(node.values, array![21, 23, 25]);
+        assert_eq!(node.values_sq, array![261, 305, 353]);
+        let moments = &node.moments;
+        assert_eq!(
+            [
+                moments.sum_x,
+                moments.sum_y,
+                moments.sum_xx,
+                moments.sum_xy,
+                moments.sum_yy
+            ],
+            [4, 1, 8, 2, 1]
+        );
+        assert_eq!(moments.values_x, array![42, 46, 50]);
+        assert_eq!(moments.values_y, array![15, 16, 17]);
+    }
+
+    #[test]
+    fn test_reused_buffer() {
+        let large_labels = Array2::from_shape_fn((8, 8), |(y, x)| (y / 2) * 4 + x / 2);
+        let large_img = Array3::from_shape_fn((8, 8, 2), |(y, x, c)| (y * 8 + x + c) as u8);
+        let labels = Array2::from_shape_fn((4, 6), |(y, x)| usize::from(x >= 3) + 2 * (y / 2));
+        let img = Array3::from_shape_fn((4, 6, 2), |(y, x, c)| (y * 6 + x * c) as u8);
+
+        let fidelity = || FidelityParams::new(FidelityModel::default(), 2);
+        let mut statistics = StatisticsBuffer::default();
+        graph_from_labels_with_buffer(&large_img, &large_labels, fidelity(), &mut statistics)
+            .unwrap();
+        let byte_size = statistics.byte_size();
+
+        let graph =
+            graph_from_labels_with_buffer(&img, &labels, fidelity(), &mut statistics).unwrap();
+        let expected = graph_from_labels(&img, &labels).unwrap();
+
+        // Left from the larger image and not reallocated
+        assert_eq!(statistics.byte_size(), byte_size);
+
+        assert_eq!(graph.node_count(), expected.node_count());
+        for (node, expected) in graph.node_weights().zip(expected.node_weights()) {
+            assert_eq!(node.area, expected.area);
+            assert_eq!(node.perimeter, expected.perimeter);
+            assert_eq!(node.values, expected.values);
+            assert_eq!(node.values_sq, expected.values_sq);
+            assert_eq!(node.moments.sum_xy, expected.moments.sum_xy);
+            assert_eq!(node.moments.values_x, expected.moments.values_x);
+            assert_eq!(node.moments.values_y, expected.moments.values_y);
+        }
+        assert_eq!(graph.edge_count(), expected.edge_count());
+        for (edge, expected) in graph.edge_weights().zip(expected.edge_weights()) {
+            assert_eq!(edge.length, expected.length);
+            assert_eq!(edge.weight, expected.weight);
+        }
+    }
+}
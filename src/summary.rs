@@ -0,0 +1,89 @@
+#[cfg(feature = "wasm")]
+use wasm_bindgen::prelude::wasm_bindgen;
+
+use crate::cut::leaf_count;
+
+/// Shape of a partition tree, for debugging and quality dashboards.
+#[cfg_attr(feature = "wasm", wasm_bindgen(getter_with_clone))]
+#[derive(Clone, Debug, PartialEq)]
+pub struct HierarchySummary {
+    /// Largest number of edges from a leaf to its root
+    pub depth: usize,
+    pub n_leaves: usize,
+    pub n_internal: usize,
+    /// Several roots when barriers split the image
+    pub n_roots: usize,
+    /// Statistics of the levels of the internal nodes, zero when there are none
+    pub min_level: f64,
+    pub max_level: f64,
+    pub median_level: f64,
+    /// Internal nodes without exactly 2 children, e.g. after
+    /// [`simplify_by_lifetime`](crate::simplify_by_lifetime)
+    pub branching_anomalies: Vec<usize>,
+}
+
+/// Summarize the shape of a partition tree.
+pub fn hierarchy_summary(parents: &[usize], levels: &[f64]) -> HierarchySummary {
+    let n_leaves = leaf_count(parents);
+
+    // Parents always come after their children
+    let mut heights = vec![0; parents.len()];
+    let mut children = vec![0; parents.len()];
+    for (node, &parent) in parents.iter().enumerate() {
+        if parent != node {
+            heights[parent] = heights[parent].max(heights[node] + 1);
+            children[parent] += 1;
+        }
+    }
+    let roots = (0..parents.len()).filter(|node| parents[*node] == *node);
+
+    let mut internal_levels = levels[n_leaves..].to_vec();
+    internal_levels.sort_by(f64::total_cmp);
+    let median_level = match internal_levels.len() {
+        0 => 0.,
+        n if n % 2 == 0 => (internal_levels[n / 2 - 1] + internal_levels[n / 2]) / 2.,
+        n => internal_levels[n / 2],
+    };
+
+    HierarchySummary {
+        depth: roots.clone().map(|root| heights[root]).max().unwrap_or(0),
+        n_leaves,
+        n_internal: parents.len() - n_leaves,
+        n_roots: roots.count(),
+        min_level: internal_levels.first().copied().unwrap_or(0.),
+        max_level: internal_levels.last().copied().unwrap_or(0.),
+        median_level,
+        branching_anomalies: (n_leaves..parents.len())
+            .filter(|node| children[*node] != 2)
+            .collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hierarchy_summary() {
+        // Node 3 merges leaves 0 and 1, node 4 merges node 3 and leaf 2
+        let summary = hierarchy_summary(&[3, 3, 4, 4, 4], &[0., 0., 0., 1., 3.]);
+        assert_eq!(
+            summary,
+            HierarchySummary {
+                depth: 2,
+                n_leaves: 3,
+                n_internal: 2,
+                n_roots: 1,
+                min_level: 1.,
+                max_level: 3.,
+                median_level: 2.,
+                branching_anomalies: Vec::new(),
+            }
+        );
+
+        // Node 3 removed by a simplification
+        let summary = hierarchy_summary(&[3, 3, 3, 3], &[0., 0., 0., 3.]);
+        assert_eq!(summary.depth, 1);
+        assert_eq!(summary.branching_anomalies, vec![3]);
+    }
+}
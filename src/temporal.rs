@@ -0,0 +1,197 @@
+use std::collections::HashMap;
+
+use crate::prelude::*;
+use ndarray::{Array2, Array3};
+
+use crate::{
+    build_superpixel_graph, console_log, cut_node_labels,
+    hierarchy::{PartialPartitionTree, PartitionTree},
+    normalize::normalize_bands,
+    params::SegmentationParams,
+    slic::{Seeding, SlicInfo},
+    statistics::StatisticsBuffer,
+    utils::array_from_planar,
+    Hierarchy,
+};
+
+/// Segmentation of a sequence of overlapping tiles, or of the same area at several
+/// dates, kept stable from one tile to the next for change tracking.
+///
+/// SLIC starts from the superpixel centers of the previous tile, and the regions
+/// of the previous hierarchy cut at `prior_level` are merge priors: below that
+/// level, no region spans several of them, see
+/// [`PartialPartitionTree::with_parcels`].
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+pub struct TileSequence {
+    params: SegmentationParams,
+    prior_level: f64,
+    previous: Option<PreviousTile>,
+    // Reused from one tile to the next
+    statistics: StatisticsBuffer,
+}
+
+struct PreviousTile {
+    centers: Vec<(u32, u32)>,
+    // Regions of the cut at the prior level
+    regions: Array2<usize>,
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+impl TileSequence {
+    #[cfg_attr(feature = "wasm", wasm_bindgen(constructor))]
+    pub fn new(params: &SegmentationParams, prior_level: f64) -> Self {
+        Self {
+            params: *params,
+            prior_level,
+            previous: None,
+            statistics: StatisticsBuffer::default(),
+        }
+    }
+
+    /// Segment the next tile, whose top-left corner is `(dx, dy)` pixels away from
+    /// the one of the previous tile, `(0, 0)` for another date of the same tile.
+    pub fn next(
+        &mut self,
+        data: &[u8],
+        width: usize,
+        height: usize,
+        channels: usize,
+        dx: i32,
+        dy: i32,
+    ) -> Hierarchy {
+        let image = array_from_planar(data, width, height, channels);
+        let (labels, tree) = self.next_array(image, (dx, dy));
+
+        Hierarchy::new(&labels, tree)
+    }
+
+    /// Start a new sequence, the next tile being segmented without priors.
+    pub fn reset(&mut self) {
+        self.previous = None;
+    }
+}
+
+impl TileSequence {
+    /// Same as [`TileSequence::next`] on an image, returning the leaf labels and the tree.
+    pub fn next_array(
+        &mut self,
+        image: Array3<u8>,
+        (dx, dy): (i32, i32),
+    ) -> (Array2<usize>, PartitionTree) {
+        let img = normalize_bands(&image, self.params.normalization);
+        let params = self.params.resolved(&img);
+
+        let shift = |value: u32, offset: i32| value.checked_add_signed(offset.checked_neg()?);
+        let centers = self.previous.as_ref().map(|previous| {
+            previous
+                .centers
+                .iter()
+                .filter_map(|(x, y)| Some((shift(*x, dx)?, shift(*y, dy)?)))
+                .collect::<Vec<_>>()
+        });
+
+        let mut slic_info = SlicInfo::new();
+        let (labels, graph) = build_superpixel_graph(
+            img.as_ref(),
+            &params,
+            &mut slic_info,
+            &mut self.statistics,
+            None,
+            centers.as_deref().map(Seeding::Warm),
+        );
+
+        let mut tree = PartialPartitionTree::new(graph).with_options(params.criterion_options());
+        if let Some(previous) = &self.previous {
+            let priors = prior_regions(&labels, &previous.regions, (dx, dy));
+            tree = tree.with_parcels(priors, self.prior_level);
+        }
+        tree.merge_until(None)
+            .unwrap_or_else(|error| throw_str(&error.to_string()));
+        let tree = tree.into_partition_tree();
+
+        let nodes = cut_node_labels(&tree.parents, &tree.levels, self.prior_level);
+        self.previous = Some(PreviousTile {
+            centers: slic_info.centers,
+            regions: labels.mapv(|leaf| nodes[leaf]),
+        });
+
+        (labels, tree)
+    }
+}
+
+/// Region of the previous tile covering most of each superpixel, `usize::MAX` for
+/// the superpixels outside of the previous tile, which are left unconstrained.
+fn prior_regions(
+    labels: &Array2<usize>,
+    previous: &Array2<usize>,
+    (dx, dy): (i32, i32),
+) -> Vec<usize> {
+    let n_labels = labels.iter().max().map_or(0, |max| max + 1);
+    let mut counts = vec![HashMap::<usize, usize>::new(); n_labels];
+
+    for ((y, x), label) in labels.indexed_iter() {
+        let source = (
+            (y as i64 + i64::from(dy)).try_into(),
+            (x as i64 + i64::from(dx)).try_into(),
+        );
+        if let (Ok(y), Ok(x)) = source {
+            if let Some(region) = previous.get((y, x)) {
+                *counts[*label].entry(*region).or_default() += 1;
+            }
+        }
+    }
+
+    let priors = counts
+        .into_iter()
+        .map(|counts| {
+            counts
+                .into_iter()
+                // Ties broken by region, for reproducible trees
+                .max_by_key(|(region, count)| (*count, usize::MAX - region))
+                .map_or(usize::MAX, |(region, _)| region)
+        })
+        .collect::<Vec<_>>();
+    console_log!(
+        "Superpixels with a prior: {}",
+        priors.iter().filter(|prior| **prior != usize::MAX).count()
+    );
+
+    priors
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tile_sequence() {
+        // Four flat quadrants, and the same image shifted by 4 pixels
+        let quadrants = |shift: usize| {
+            Array3::from_shape_fn((16, 16, 1), |(y, x, _)| {
+                ((y / 8 * 2 + (x + shift) / 8 % 2) * 60) as u8
+            })
+        };
+        let mut sequence = TileSequence::new(&SegmentationParams::new(16), 1.);
+
+        let (first_labels, first) = sequence.next_array(quadrants(0), (0, 0));
+        let first_nodes = cut_node_labels(&first.parents, &first.levels, 1.);
+        let first_regions = first_labels.mapv(|leaf| first_nodes[leaf]);
+
+        let (labels, tree) = sequence.next_array(quadrants(4), (4, 0));
+        assert_eq!(labels.dim(), (16, 16));
+        assert_eq!(tree.parents.len(), tree.levels.len());
+
+        // Below the prior level, regions do not span several previous regions
+        let nodes = cut_node_labels(&tree.parents, &tree.levels, 1.);
+        let mut previous_of = HashMap::new();
+        for ((y, x), leaf) in labels.indexed_iter() {
+            if x + 4 < 16 {
+                let previous = first_regions[[y, x + 4]];
+                assert_eq!(
+                    *previous_of.entry(nodes[*leaf]).or_insert(previous),
+                    previous
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,111 @@
+use ndarray::{Array2, Array3};
+#[cfg(feature = "wasm")]
+use wasm_bindgen::prelude::wasm_bindgen;
+
+use crate::{pixels::LeafPixels, utils::display_rgb};
+
+/// RGBA image of a region of the hierarchy.
+#[cfg_attr(feature = "wasm", wasm_bindgen(getter_with_clone))]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Thumbnail {
+    /// RGBA pixels, transparent outside of the region
+    pub data: Vec<u8>,
+    pub width: usize,
+    pub height: usize,
+}
+
+/// Flag the nodes of a partition tree that are descendants of `node`, itself included.
+pub fn descendants(parents: &[usize], node: usize) -> Vec<bool> {
+    let mut members = vec![false; parents.len()];
+    members[node] = true;
+
+    // Parents always come after their children
+    for i in (0..node).rev() {
+        let parent = parents[i];
+        members[i] = parent != i && members[parent];
+    }
+
+    members
+}
+
+/// Crop the bounding box of a node of the hierarchy from a `(height, width, channels)`
+/// image, and downscale it so that its largest side is at most `max_size` pixels.
+///
+/// Pixels outside of the node are transparent. Returns `None` if the node has no pixel.
+pub fn node_thumbnail(
+    leaf_labels: &Array2<usize>,
+    leaf_pixels: &LeafPixels,
+    parents: &[usize],
+    node: usize,
+    img: &Array3<u8>,
+    max_size: usize,
+) -> Option<Thumbnail> {
+    let members = descendants(parents, node);
+    let image_width = leaf_labels.dim().1;
+
+    let (mut min_y, mut min_x, mut max_y, mut max_x) = (usize::MAX, usize::MAX, 0, 0);
+    for leaf in (0..leaf_pixels.leaf_count()).filter(|leaf| members[*leaf]) {
+        for (start, length) in leaf_pixels.runs(leaf) {
+            let (y, x) = (start / image_width, start % image_width);
+            min_y = min_y.min(y);
+            min_x = min_x.min(x);
+            max_y = max_y.max(y);
+            max_x = max_x.max(x + length - 1);
+        }
+    }
+    if min_y == usize::MAX {
+        return None;
+    }
+
+    let (crop_height, crop_width) = (max_y - min_y + 1, max_x - min_x + 1);
+    let scale = (max_size.max(1) as f64 / crop_height.max(crop_width) as f64).min(1.);
+    let height = ((crop_height as f64 * scale).round() as usize).max(1);
+    let width = ((crop_width as f64 * scale).round() as usize).max(1);
+
+    let mut data = vec![0; width * height * 4];
+    for ty in 0..height {
+        for tx in 0..width {
+            // Nearest pixel of the crop
+            let y = min_y + (ty * crop_height + crop_height / 2) / height;
+            let x = min_x + (tx * crop_width + crop_width / 2) / width;
+
+            if members[leaf_labels[[y, x]]] {
+                let i = (ty * width + tx) * 4;
+                data[i..i + 3].copy_from_slice(&display_rgb(img.slice(ndarray::s![y, x, ..])));
+                data[i + 3] = 255;
+            }
+        }
+    }
+
+    Some(Thumbnail {
+        data,
+        width,
+        height,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_node_thumbnail() {
+        // 0 0 1
+        // 0 0 1
+        // 2 2 2
+        let leaf_labels = Array2::from_shape_vec((3, 3), vec![0, 0, 1, 0, 0, 1, 2, 2, 2]).unwrap();
+        let parents = vec![3, 3, 4, 4, 4];
+        let leaf_pixels = LeafPixels::new(&leaf_labels);
+        let img = Array3::from_elem((3, 3, 1), 100);
+
+        // Leaf 2 is the bottom row
+        let thumbnail = node_thumbnail(&leaf_labels, &leaf_pixels, &parents, 2, &img, 10).unwrap();
+        assert_eq!((thumbnail.width, thumbnail.height), (3, 1));
+        assert_eq!(&thumbnail.data[..4], &[100, 100, 100, 255]);
+
+        // Node 3 covers the top two rows, downscaled to 2x1
+        let thumbnail = node_thumbnail(&leaf_labels, &leaf_pixels, &parents, 3, &img, 2).unwrap();
+        assert_eq!((thumbnail.width, thumbnail.height), (2, 1));
+        assert!(thumbnail.data.chunks(4).all(|pixel| pixel[3] == 255));
+    }
+}
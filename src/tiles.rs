@@ -0,0 +1,190 @@
+use std::ops::RangeInclusive;
+
+use ndarray::ArrayView2;
+
+#[cfg(feature = "proj")]
+use crate::proj::transformer;
+use crate::{
+    geo::GeoTransform,
+    render::{render_sampled, CutStyle, Palette},
+    utils::rgba_to_png,
+};
+
+pub const TILE_SIZE: usize = 256;
+/// Deepest zoom level of the tiles, whose indices fit in a `u32`.
+pub const MAX_ZOOM: u32 = 30;
+
+// Half the side of the square Web Mercator world, in meters
+pub(crate) const WEB_MERCATOR_EXTENT: f64 = 20_037_508.342_789_244;
+// Points sampled on each side of the image to find its extent
+const EDGE_SAMPLES: usize = 16;
+
+/// PNG tile of the XYZ scheme used by web maps.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct XyzTile {
+    pub z: u32,
+    pub x: u32,
+    pub y: u32,
+    pub png: Vec<u8>,
+}
+
+/// Render a cut into the 256x256 Web Mercator (EPSG:3857) tiles covering the image
+/// for each zoom level, as served to Leaflet or OpenLayers. Tiles outside of the
+/// image are skipped, and tile pixels outside of it are transparent.
+///
+/// The geotransform must be in Web Mercator, or with the `proj` feature in any
+/// coordinate reference system it supports. Returns `None` otherwise, or if a zoom
+/// level is above [`MAX_ZOOM`].
+pub fn cut_to_xyz_tiles(
+    labels: ArrayView2<usize>,
+    transform: &GeoTransform,
+    zooms: RangeInclusive<u32>,
+    style: CutStyle,
+    palette: &Palette,
+) -> Option<Vec<XyzTile>> {
+    if *zooms.end() > MAX_ZOOM {
+        return None;
+    }
+    let crs = transform.crs_epsg?;
+    #[cfg(feature = "proj")]
+    let (to_mercator, from_mercator) = (transformer(crs, 3857)?, transformer(3857, crs)?);
+    #[cfg(not(feature = "proj"))]
+    let (to_mercator, from_mercator) = {
+        let identity = (crs == 3857).then_some(|point: (f64, f64)| point)?;
+        (identity, identity)
+    };
+
+    // Extent of the image in Web Mercator, sampled along its reprojected edges
+    let (height, width) = labels.dim();
+    let (width_f, height_f) = (width as f64, height as f64);
+    let mut bbox = [
+        f64::INFINITY,
+        f64::INFINITY,
+        f64::NEG_INFINITY,
+        f64::NEG_INFINITY,
+    ];
+    for i in 0..=EDGE_SAMPLES {
+        let t = i as f64 / EDGE_SAMPLES as f64;
+        for (x, y) in [
+            (t * width_f, 0.),
+            (t * width_f, height_f),
+            (0., t * height_f),
+            (width_f, t * height_f),
+        ] {
+            let (x, y) = to_mercator(transform.apply(x, y));
+            bbox = [
+                bbox[0].min(x),
+                bbox[1].min(y),
+                bbox[2].max(x),
+                bbox[3].max(y),
+            ];
+        }
+    }
+
+    let mut tiles = Vec::new();
+    for z in zooms {
+        let count = 1u32 << z;
+        let tile_length = 2. * WEB_MERCATOR_EXTENT / f64::from(count);
+        let tile_index =
+            |offset: f64| ((offset / tile_length).floor().max(0.) as u32).min(count - 1);
+        let x_range =
+            tile_index(bbox[0] + WEB_MERCATOR_EXTENT)..=tile_index(bbox[2] + WEB_MERCATOR_EXTENT);
+        let y_range =
+            tile_index(WEB_MERCATOR_EXTENT - bbox[3])..=tile_index(WEB_MERCATOR_EXTENT - bbox[1]);
+
+        for y in y_range {
+            for x in x_range.clone() {
+                // Pixel of the labels sampled by the center of each tile pixel
+                let pixel_length = tile_length / TILE_SIZE as f64;
+                let source = |tx: usize, ty: usize| {
+                    let point = (
+                        -WEB_MERCATOR_EXTENT
+                            + f64::from(x) * tile_length
+                            + (tx as f64 + 0.5) * pixel_length,
+                        WEB_MERCATOR_EXTENT
+                            - f64::from(y) * tile_length
+                            - (ty as f64 + 0.5) * pixel_length,
+                    );
+                    let (cx, cy) = from_mercator(point);
+                    let (px, py) = transform.invert(cx, cy);
+                    (px >= 0. && py >= 0. && px < width_f && py < height_f)
+                        .then_some([py as usize, px as usize])
+                };
+
+                let data = render_sampled(labels, (TILE_SIZE, TILE_SIZE), source, style, palette);
+                if data.chunks_exact(4).all(|pixel| pixel[3] == 0) {
+                    continue;
+                }
+
+                tiles.push(XyzTile {
+                    z,
+                    x,
+                    y,
+                    png: rgba_to_png(data, TILE_SIZE, TILE_SIZE),
+                });
+            }
+        }
+    }
+
+    Some(tiles)
+}
+
+#[cfg(test)]
+mod tests {
+    use ndarray::Array2;
+
+    use super::*;
+    use crate::utils::decode_image;
+
+    #[test]
+    fn test_cut_to_xyz_tiles() {
+        // Image covering the north-west quarter of the world, split in two regions
+        let labels = Array2::from_shape_fn((4, 4), |(_, x)| usize::from(x >= 2));
+        let pixel_length = WEB_MERCATOR_EXTENT / 4.;
+        let transform = GeoTransform::new(
+            -WEB_MERCATOR_EXTENT,
+            WEB_MERCATOR_EXTENT,
+            pixel_length,
+            pixel_length,
+            Some(3857),
+        );
+        let palette = Palette::new();
+
+        let tiles =
+            cut_to_xyz_tiles(labels.view(), &transform, 0..=1, CutStyle::Fill, &palette).unwrap();
+
+        let positions = tiles
+            .iter()
+            .map(|tile| (tile.z, tile.x, tile.y))
+            .collect::<Vec<_>>();
+        assert_eq!(positions, vec![(0, 0, 0), (1, 0, 0)]);
+
+        // The zoom 1 tile is the image, with region 0 on its left half
+        let tile = decode_image(&tiles[1].png).unwrap();
+        assert_eq!(tile.dim(), (TILE_SIZE, TILE_SIZE, 4));
+        let color = palette.color(0);
+        assert_eq!(tile[[10, 10, 0]], color[0]);
+        assert_eq!(tile[[10, 10, 3]], 255);
+
+        // Only the north-west quarter of the zoom 0 tile is covered
+        let tile = decode_image(&tiles[0].png).unwrap();
+        assert_eq!(tile[[200, 200, 3]], 0);
+
+        assert!(cut_to_xyz_tiles(
+            labels.view(),
+            &GeoTransform::default(),
+            0..=1,
+            CutStyle::Fill,
+            &palette
+        )
+        .is_none());
+        assert!(cut_to_xyz_tiles(
+            labels.view(),
+            &transform,
+            MAX_ZOOM..=MAX_ZOOM + 2,
+            CutStyle::Fill,
+            &palette
+        )
+        .is_none());
+    }
+}
@@ -1,8 +1,110 @@
 use std::io::Cursor;
 
-use image::{ImageBuffer, ImageOutputFormat, Rgb};
-use ndarray::{s, ArrayView3};
-use wasm_bindgen::UnwrapThrowExt;
+use crate::prelude::{throw_str, UnwrapThrowExt};
+use image::{ImageBuffer, ImageOutputFormat, ImageResult, Rgb, Rgba};
+use ndarray::{s, Array2, Array3, ArrayView1, ArrayView3};
+
+/// Rectangle of pixels, `(x, y)` being its top-left corner.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rect {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+}
+
+impl Rect {
+    /// Extend the rectangle by `margin` pixels on each side, within an image of the given size.
+    pub fn expanded(&self, margin: usize, width: usize, height: usize) -> Self {
+        let x = self.x.saturating_sub(margin).min(width);
+        let y = self.y.saturating_sub(margin).min(height);
+        let right = (self.x + self.width + margin).min(width);
+        let bottom = (self.y + self.height + margin).min(height);
+
+        Self {
+            x,
+            y,
+            width: right.saturating_sub(x),
+            height: bottom.saturating_sub(y),
+        }
+    }
+
+    pub fn contains(&self, x: usize, y: usize) -> bool {
+        (self.x..self.x + self.width).contains(&x) && (self.y..self.y + self.height).contains(&y)
+    }
+}
+
+/// Band count of planar image data of `width * height` pixels, deduced from its
+/// length. Throws on an empty image or data shorter than one band.
+pub(crate) fn band_count(data: &[u8], width: usize, height: usize) -> usize {
+    let pixels = match width.checked_mul(height) {
+        Some(pixels) if pixels > 0 => pixels,
+        _ => throw_str("Image dimensions must be positive"),
+    };
+    if data.len() < pixels {
+        throw_str("Data doesn't have the right shape");
+    }
+
+    data.len() / pixels
+}
+
+/// Convert planar image data (one band after the other) into a `(height, width, channels)` array.
+pub(crate) fn array_from_planar(
+    data: &[u8],
+    width: usize,
+    height: usize,
+    channels: usize,
+) -> Array3<u8> {
+    let mut array = Array3::from_shape_vec((channels, height, width), data.to_vec())
+        .expect_throw("Data doesn't have the right shape");
+
+    array.swap_axes(0, 1);
+    array.swap_axes(1, 2);
+
+    array
+}
+
+/// Convert a `(height, width, channels)` array into planar data (one band after the other).
+pub(crate) fn array_to_planar(input: ArrayView3<u8>) -> Vec<u8> {
+    input.permuted_axes([2, 0, 1]).iter().cloned().collect()
+}
+
+/// Decode a PNG, JPEG or TIFF image into a `(height, width, channels)` array.
+///
+/// All bands are kept, including the alpha band. Images with more than 8 bits
+/// per sample are converted to 8 bits.
+pub(crate) fn decode_image(bytes: &[u8]) -> ImageResult<Array3<u8>> {
+    let img = image::load_from_memory(bytes)?;
+    let width = img.width() as usize;
+    let height = img.height() as usize;
+
+    let (channels, data) = match img.color().channel_count() {
+        1 => (1, img.into_luma8().into_raw()),
+        2 => (2, img.into_luma_alpha8().into_raw()),
+        3 => (3, img.into_rgb8().into_raw()),
+        _ => (4, img.into_rgba8().into_raw()),
+    };
+
+    Ok(Array3::from_shape_vec((height, width, channels), data)
+        .expect_throw("Decoded image doesn't have the right shape"))
+}
+
+/// Flatten a label map in row-major order.
+pub(crate) fn labels_to_vec<T: Clone>(labels: &Array2<T>) -> Vec<T> {
+    let labels = labels.as_standard_layout();
+    let labels = labels.as_slice().unwrap();
+    labels.to_vec()
+}
+
+/// Color of a pixel for display: its first 3 bands, or its first band in gray
+/// levels if it has less than 3.
+pub(crate) fn display_rgb(pixel: ArrayView1<u8>) -> [u8; 3] {
+    if pixel.len() >= 3 {
+        [pixel[0], pixel[1], pixel[2]]
+    } else {
+        [pixel[0]; 3]
+    }
+}
 
 pub(crate) fn array_to_image(input: ArrayView3<u8>) -> ImageBuffer<Rgb<u8>, Vec<u8>> {
     let (height, width, _channels) = input.dim();
@@ -10,7 +112,7 @@ pub(crate) fn array_to_image(input: ArrayView3<u8>) -> ImageBuffer<Rgb<u8>, Vec<
 
     for (y, row) in input.outer_iter().enumerate() {
         for (x, pixel) in row.outer_iter().enumerate() {
-            let pixel = Rgb([pixel[0], pixel[1], pixel[2]]);
+            let pixel = Rgb(display_rgb(pixel));
             output.put_pixel(x as u32, y as u32, pixel);
         }
     }
@@ -26,6 +128,17 @@ pub(crate) fn image_to_png(img: ImageBuffer<Rgb<u8>, Vec<u8>>) -> Vec<u8> {
     buffer
 }
 
+pub(crate) fn rgba_to_png(data: Vec<u8>, width: usize, height: usize) -> Vec<u8> {
+    let img = ImageBuffer::<Rgba<u8>, _>::from_raw(width as u32, height as u32, data)
+        .expect_throw("RGBA data wrong size");
+
+    let mut buffer = Vec::new();
+    img.write_to(&mut Cursor::new(&mut buffer), ImageOutputFormat::Png)
+        .expect_throw("Failed to write to png");
+
+    buffer
+}
+
 pub(crate) fn array_to_rgba_bitmap(input: ArrayView3<u8>) -> Vec<u8> {
     let (height, width, _channels) = input.dim();
 
@@ -34,11 +147,58 @@ pub(crate) fn array_to_rgba_bitmap(input: ArrayView3<u8>) -> Vec<u8> {
     for (y, row) in input.outer_iter().enumerate() {
         for (x, pixel) in row.outer_iter().enumerate() {
             let i = (y * width + x) as usize;
-            output[i * 4] = pixel[0];
-            output[i * 4 + 1] = pixel[1];
-            output[i * 4 + 2] = pixel[2];
+            output[i * 4..i * 4 + 3].copy_from_slice(&display_rgb(pixel));
         }
     }
 
     output
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_band_count() {
+        assert_eq!(band_count(&[0; 24], 4, 2), 3);
+        assert_eq!(band_count(&[0; 25], 4, 2), 3);
+
+        for (len, width, height) in [(8, 0, 2), (8, 4, 0), (4, 4, 2), (8, usize::MAX, 2)] {
+            let data = vec![0; len];
+            assert!(std::panic::catch_unwind(|| band_count(&data, width, height)).is_err());
+        }
+    }
+
+    #[test]
+    fn test_rgba_bitmap_channels() {
+        for channels in [1, 3, 4, 8, 12] {
+            let img =
+                Array3::from_shape_fn((2, 3, channels), |(y, x, c)| (y * 30 + x * 10 + c) as u8);
+
+            let bitmap = array_to_rgba_bitmap(img.view());
+
+            assert_eq!(bitmap.len(), 2 * 3 * 4);
+            // Pixel (1, 2)
+            let pixel = &bitmap[(3 + 2) * 4..(3 + 2) * 4 + 4];
+            if channels >= 3 {
+                assert_eq!(pixel, [50, 51, 52, 255]);
+            } else {
+                assert_eq!(pixel, [50, 50, 50, 255]);
+            }
+        }
+    }
+
+    #[test]
+    fn test_decode_png() {
+        let img = Array3::from_shape_fn((4, 5, 3), |(y, x, c)| (y * 50 + x * 10 + c) as u8);
+        let png = image_to_png(array_to_image(img.view()));
+
+        let decoded = decode_image(&png).unwrap();
+
+        assert_eq!(decoded, img);
+        assert_eq!(
+            array_from_planar(&array_to_planar(decoded.view()), 5, 4, 3),
+            img
+        );
+    }
+}
@@ -0,0 +1,351 @@
+use std::{collections::HashMap, fmt::Write};
+
+use ndarray::Array2;
+
+#[cfg(feature = "proj")]
+use crate::proj::transformer;
+use crate::{
+    geo::GeoTransform,
+    render::{CutStyle, Palette},
+    smooth::{smooth_outlines, Smoothing},
+};
+
+/// Closed outline of a region, as the corners of the pixel grid it goes through,
+/// `(x, y)` being the top-left corner of pixel `(x, y)`.
+pub type Ring = Vec<(usize, usize)>;
+
+/// Oriented border between two corners of the pixel grid.
+type Border = ((usize, usize), (usize, usize));
+
+/// Outlines of the regions of a cut, sorted by region, following the pixel borders.
+///
+/// Outer rings are clockwise in image coordinates (y pointing down) and holes
+/// counter-clockwise, so the rings of a region can be filled with the non-zero rule.
+/// Only the corners of the outlines are kept: they exactly match the pixels, and
+/// neighboring regions share their borders.
+pub fn region_outlines(labels: &Array2<usize>) -> Vec<(usize, Vec<Ring>)> {
+    let (height, width) = labels.dim();
+
+    // Borders of each region, oriented with the region on their right
+    let mut borders = HashMap::<usize, Vec<Border>>::new();
+    for ((y, x), &label) in labels.indexed_iter() {
+        let differs = |ny: Option<usize>, nx: Option<usize>| match (ny, nx) {
+            (Some(ny), Some(nx)) if ny < height && nx < width => labels[[ny, nx]] != label,
+            _ => true,
+        };
+
+        let region = borders.entry(label).or_default();
+        if differs(y.checked_sub(1), Some(x)) {
+            region.push(((x, y), (x + 1, y)));
+        }
+        if differs(Some(y), Some(x + 1)) {
+            region.push(((x + 1, y), (x + 1, y + 1)));
+        }
+        if differs(Some(y + 1), Some(x)) {
+            region.push(((x + 1, y + 1), (x, y + 1)));
+        }
+        if differs(Some(y), x.checked_sub(1)) {
+            region.push(((x, y + 1), (x, y)));
+        }
+    }
+
+    let mut outlines = borders
+        .into_iter()
+        .map(|(label, edges)| (label, link_rings(&edges)))
+        .collect::<Vec<_>>();
+    outlines.sort_by_key(|(label, _)| *label);
+
+    outlines
+}
+
+/// Link oriented border edges into closed rings, dropping collinear vertices.
+fn link_rings(edges: &[Border]) -> Vec<Ring> {
+    let mut outgoing = HashMap::<(usize, usize), Vec<(usize, usize)>>::new();
+    for (from, to) in edges {
+        outgoing.entry(*from).or_default().push(*to);
+    }
+
+    let mut rings = Vec::new();
+    for (start, _) in edges {
+        if outgoing[start].is_empty() {
+            continue;
+        }
+
+        let mut ring = Vec::new();
+        let mut vertex = *start;
+        loop {
+            ring.push(vertex);
+            vertex = outgoing.get_mut(&vertex).unwrap().pop().unwrap();
+            if vertex == *start {
+                break;
+            }
+        }
+
+        rings.push(remove_collinear(ring));
+    }
+
+    rings
+}
+
+/// Keep only the corners of an axis-aligned ring.
+fn remove_collinear(ring: Ring) -> Ring {
+    let n = ring.len();
+    (0..n)
+        .filter(|i| {
+            let (prev, vertex, next) = (ring[(i + n - 1) % n], ring[*i], ring[(i + 1) % n]);
+            !((prev.0 == vertex.0 && vertex.0 == next.0)
+                || (prev.1 == vertex.1 && vertex.1 == next.1))
+        })
+        .map(|i| ring[i])
+        .collect()
+}
+
+/// Area and perimeter of a region, in the units of a geotransform.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RegionMeasures {
+    pub area: f64,
+    pub perimeter: f64,
+}
+
+/// Area and perimeter of the regions of a cut, sorted by region, in the squared
+/// units and units of the coordinate reference system of the geotransform. Pixels
+/// may be non-square: horizontal borders are as long as a pixel is wide, and
+/// vertical borders as long as it is high.
+pub fn region_measures(
+    labels: &Array2<usize>,
+    transform: &GeoTransform,
+) -> Vec<(usize, RegionMeasures)> {
+    let (height, width) = labels.dim();
+
+    // Pixel count, and horizontal and vertical border lengths in pixels
+    let mut counts = HashMap::<usize, [usize; 3]>::new();
+    for ((y, x), &label) in labels.indexed_iter() {
+        let differs = |ny: Option<usize>, nx: Option<usize>| match (ny, nx) {
+            (Some(ny), Some(nx)) if ny < height && nx < width => labels[[ny, nx]] != label,
+            _ => true,
+        };
+        let horizontal =
+            differs(y.checked_sub(1), Some(x)) as usize + differs(Some(y + 1), Some(x)) as usize;
+        let vertical =
+            differs(Some(y), x.checked_sub(1)) as usize + differs(Some(y), Some(x + 1)) as usize;
+
+        let count = counts.entry(label).or_default();
+        count[0] += 1;
+        count[1] += horizontal;
+        count[2] += vertical;
+    }
+
+    let mut measures = counts
+        .into_iter()
+        .map(|(label, [pixels, horizontal, vertical])| {
+            let measures = RegionMeasures {
+                area: pixels as f64 * transform.pixel_area(),
+                perimeter: horizontal as f64 * transform.pixel_width.abs()
+                    + vertical as f64 * transform.pixel_height.abs(),
+            };
+            (label, measures)
+        })
+        .collect::<Vec<_>>();
+    measures.sort_by_key(|(label, _)| *label);
+
+    measures
+}
+
+/// Export a cut as an SVG document, with one path per region whose id is
+/// `region-<label>`. Regions are filled with their palette color, and the borders
+/// stroked in black, depending on the style.
+pub fn cut_to_svg(
+    labels: &Array2<usize>,
+    style: CutStyle,
+    palette: &Palette,
+    smoothing: Smoothing,
+) -> String {
+    let (height, width) = labels.dim();
+
+    let mut svg = String::new();
+    writeln!(
+        svg,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{width}" height="{height}" viewBox="0 0 {width} {height}">"#
+    )
+    .unwrap();
+
+    for (label, rings) in smooth_outlines(labels, smoothing) {
+        let mut path = String::new();
+        for ring in rings {
+            for (i, (x, y)) in ring.into_iter().enumerate() {
+                let command = if i == 0 { 'M' } else { 'L' };
+                write!(path, "{command}{x} {y}").unwrap();
+            }
+            path.push('Z');
+        }
+
+        let [r, g, b] = palette.color(label);
+        let fill = match style {
+            CutStyle::Borders => "none".to_string(),
+            _ => format!("#{r:02x}{g:02x}{b:02x}"),
+        };
+        let stroke = match style {
+            CutStyle::Fill => r#"stroke="none""#,
+            _ => r#"stroke="black" stroke-width="1" vector-effect="non-scaling-stroke""#,
+        };
+
+        writeln!(
+            svg,
+            r#"<path id="region-{label}" d="{path}" fill="{fill}" fill-rule="nonzero" {stroke}/>"#
+        )
+        .unwrap();
+    }
+
+    svg.push_str("</svg>\n");
+    svg
+}
+
+/// Export a cut as a GeoJSON feature collection, with one polygon per region whose
+/// `node` property is its tree node, along with its `area` and `perimeter` in the
+/// units of the geotransform. Pixel corners are mapped to coordinates with the
+/// geotransform.
+pub fn cut_to_geojson(
+    labels: &Array2<usize>,
+    transform: &GeoTransform,
+    smoothing: Smoothing,
+) -> String {
+    write_geojson(labels, transform, smoothing, transform.crs_epsg, |point| {
+        point
+    })
+}
+
+/// Export a cut as GeoJSON like [`cut_to_geojson`], reprojected from the
+/// coordinate reference system of the geotransform to the target one. Returns
+/// `None` if the reprojection is not supported.
+#[cfg(feature = "proj")]
+pub fn cut_to_geojson_reprojected(
+    labels: &Array2<usize>,
+    transform: &GeoTransform,
+    target_epsg: u32,
+    smoothing: Smoothing,
+) -> Option<String> {
+    let reproject = transformer(transform.crs_epsg?, target_epsg)?;
+    Some(write_geojson(
+        labels,
+        transform,
+        smoothing,
+        Some(target_epsg),
+        reproject,
+    ))
+}
+
+fn write_geojson(
+    labels: &Array2<usize>,
+    transform: &GeoTransform,
+    smoothing: Smoothing,
+    crs_epsg: Option<u32>,
+    reproject: impl Fn((f64, f64)) -> (f64, f64),
+) -> String {
+    // Outer rings must be counter-clockwise in world coordinates, they are clockwise
+    // unless the transform mirrors them
+    let flipped = transform.pixel_width * transform.pixel_height > 0.;
+
+    let mut json = String::from(r#"{"type":"FeatureCollection","#);
+    // WGS 84 is the default, other systems are named with the pre-RFC 7946 member
+    if let Some(code) = crs_epsg.filter(|code| *code != 4326) {
+        write!(
+            json,
+            r#""crs":{{"type":"name","properties":{{"name":"urn:ogc:def:crs:EPSG::{code}"}}}},"#
+        )
+        .unwrap();
+    }
+    json.push_str("\"features\":[\n");
+
+    let outlines = smooth_outlines(labels, smoothing);
+    let measures = region_measures(labels, transform);
+    for (i, ((label, rings), (_, measures))) in outlines.into_iter().zip(measures).enumerate() {
+        let mut coordinates = Vec::new();
+        for mut ring in rings {
+            if flipped {
+                ring.reverse();
+            }
+            ring.push(ring[0]);
+
+            let positions = ring
+                .into_iter()
+                .map(|(x, y)| {
+                    let (x, y) = reproject(transform.apply(x, y));
+                    format!("[{x},{y}]")
+                })
+                .collect::<Vec<_>>();
+            coordinates.push(format!("[{}]", positions.join(",")));
+        }
+
+        if i > 0 {
+            json.push_str(",\n");
+        }
+        write!(
+            json,
+            r#"{{"type":"Feature","properties":{{"node":{label},"area":{},"perimeter":{}}},"geometry":{{"type":"Polygon","coordinates":[{}]}}}}"#,
+            measures.area,
+            measures.perimeter,
+            coordinates.join(",")
+        )
+        .unwrap();
+    }
+
+    json.push_str("\n]}\n");
+    json
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_region_measures() {
+        // Region 1 is the bottom-right 2x1 corner of a 3x3 image
+        let mut labels = Array2::zeros((3, 3));
+        labels[[2, 1]] = 1;
+        labels[[2, 2]] = 1;
+        // Pixels 2m wide and 0.5m high
+        let transform = GeoTransform::new(0., 0., 2., 0.5, Some(2154));
+
+        let measures = region_measures(&labels, &transform);
+
+        assert_eq!(measures.len(), 2);
+        assert_eq!(
+            measures[1],
+            (
+                1,
+                RegionMeasures {
+                    area: 2.,
+                    perimeter: 4. * 2. + 2. * 0.5
+                }
+            )
+        );
+        assert_eq!(measures[0].1.area, 7.);
+        assert_eq!(measures[0].1.perimeter, 6. * 2. + 6. * 0.5);
+    }
+
+    #[test]
+    fn test_region_outlines() {
+        // Region 1 is a single pixel hole in region 0
+        let mut labels = Array2::zeros((3, 3));
+        labels[[1, 1]] = 1;
+
+        let outlines = region_outlines(&labels);
+
+        assert_eq!(outlines.len(), 2);
+        let (label, rings) = &outlines[0];
+        assert_eq!(*label, 0);
+        assert_eq!(rings.len(), 2);
+        assert_eq!(rings[0], vec![(0, 0), (3, 0), (3, 3), (0, 3)]);
+        assert_eq!(rings[1].len(), 4);
+        assert_eq!(outlines[1].1, vec![vec![(1, 1), (2, 1), (2, 2), (1, 2)]]);
+
+        let svg = cut_to_svg(&labels, CutStyle::Fill, &Palette::new(), Smoothing::none());
+        assert!(svg.contains(r#"<path id="region-1" d="M1 1L2 1L2 2L1 2Z""#));
+
+        // Counter-clockwise with the Y axis pointing up
+        let json = cut_to_geojson(&labels, &GeoTransform::default(), Smoothing::none());
+        assert!(json.contains(r#""coordinates":[[[1,-2],[2,-2],[2,-1],[1,-1],[1,-2]]]"#));
+        assert!(!json.contains("crs"));
+        assert!(json.contains(r#""properties":{"node":1,"area":1,"perimeter":4}"#));
+    }
+}
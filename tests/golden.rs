@@ -0,0 +1,131 @@
+//! Regression tests against the hierarchies of small reference images.
+//!
+//! Each image of `tests/data` has a `.golden` file with its superpixel labels, and
+//! the parents and levels of its tree. Set `UPDATE_GOLDEN=1` to rewrite them after
+//! an intended change of the results.
+
+use std::{collections::HashMap, env, fs, path::PathBuf};
+
+use hierarchy_labellisation::{build_hierarchy_wasm, decode_image_wasm, Hierarchy};
+
+// Relative tolerance on the levels, for floating-point differences between targets
+const LEVEL_TOLERANCE: f64 = 1e-9;
+
+/// Reference image and its number of superpixels.
+const CASES: [(&str, usize); 3] = [("quadrants", 16), ("disk", 12), ("stripes", 20)];
+
+struct Golden {
+    labels: Vec<usize>,
+    parents: Vec<usize>,
+    levels: Vec<f64>,
+}
+
+impl Golden {
+    fn from_hierarchy(hierarchy: &Hierarchy) -> Self {
+        Self {
+            labels: hierarchy.labels.clone(),
+            parents: hierarchy.parents.clone(),
+            levels: hierarchy.levels.clone(),
+        }
+    }
+
+    fn to_text(&self, width: usize) -> String {
+        let join = |values: &[String]| values.join(" ");
+        let labels = self
+            .labels
+            .chunks(width)
+            .map(|row| join(&row.iter().map(ToString::to_string).collect::<Vec<_>>()))
+            .collect::<Vec<_>>()
+            .join("\n");
+        let parents = self
+            .parents
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>();
+        // Debug formatting round-trips the floats exactly
+        let levels = self
+            .levels
+            .iter()
+            .map(|l| format!("{l:?}"))
+            .collect::<Vec<_>>();
+
+        format!(
+            "labels\n{labels}\nparents\n{}\nlevels\n{}\n",
+            join(&parents),
+            join(&levels)
+        )
+    }
+
+    fn parse(text: &str) -> Self {
+        // Values of each section, after its name
+        let mut sections = HashMap::<&str, Vec<&str>>::new();
+        let mut section = "";
+        for line in text.lines() {
+            if line.starts_with(|c: char| c.is_ascii_alphabetic()) {
+                section = line;
+            } else {
+                sections
+                    .entry(section)
+                    .or_default()
+                    .extend(line.split_whitespace());
+            }
+        }
+        let values = |name: &str| sections.get(name).cloned().unwrap_or_default();
+
+        Self {
+            labels: values("labels")
+                .iter()
+                .map(|v| v.parse().unwrap())
+                .collect(),
+            parents: values("parents")
+                .iter()
+                .map(|v| v.parse().unwrap())
+                .collect(),
+            levels: values("levels")
+                .iter()
+                .map(|v| v.parse().unwrap())
+                .collect(),
+        }
+    }
+}
+
+fn data_path(file: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("data")
+        .join(file)
+}
+
+#[test]
+fn test_golden_hierarchies() {
+    let update = env::var_os("UPDATE_GOLDEN").is_some();
+
+    for (name, n_clusters) in CASES {
+        let bytes = fs::read(data_path(&format!("{name}.png"))).unwrap();
+        let image = decode_image_wasm(&bytes);
+        let hierarchy = build_hierarchy_wasm(
+            &image.data,
+            image.width,
+            image.height,
+            image.channels,
+            n_clusters,
+        );
+        let actual = Golden::from_hierarchy(&hierarchy);
+
+        let golden_path = data_path(&format!("{name}.golden"));
+        if update {
+            fs::write(&golden_path, actual.to_text(image.width)).unwrap();
+            continue;
+        }
+        let expected = Golden::parse(&fs::read_to_string(&golden_path).unwrap());
+
+        assert_eq!(actual.labels, expected.labels, "{name}: superpixels differ");
+        assert_eq!(actual.parents, expected.parents, "{name}: trees differ");
+        for (node, (a, e)) in actual.levels.iter().zip(&expected.levels).enumerate() {
+            assert!(
+                (a - e).abs() <= LEVEL_TOLERANCE * e.abs().max(1.),
+                "{name}: level of node {node} is {a} instead of {e}"
+            );
+        }
+    }
+}